@@ -0,0 +1,140 @@
+//! Benchmarks serving calls of a simple function using [`wrpc_runtime_wasmtime::call`]
+//! with and without a [`ValPool`] and a [`BufferPool`].
+//!
+//! The amount of allocations performed by a single call is printed before each benchmark
+//! is run.
+
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Context as _;
+use criterion::Criterion;
+use tokio::io::{AsyncRead, ReadBuf};
+use wasmtime::component::{Component, Linker};
+use wasmtime::Engine;
+use wrpc_runtime_wasmtime::{call, BufferPool, ValPool};
+
+use common::{new_store, Discard};
+
+mod common;
+
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+/// [System] allocator counting allocations
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Parameters `(40, 2)` of `add`
+const PARAMS: &[u8] = &[40, 2];
+
+/// Reader of encoded parameters
+struct Params(Cursor<&'static [u8]>);
+
+impl AsyncRead for Params {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl wrpc_transport::Index<Self> for Params {
+    fn index(&self, _: &[usize]) -> anyhow::Result<Self> {
+        anyhow::bail!("indexing not supported")
+    }
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut c = Criterion::default().configure_from_args();
+    let rt = tokio::runtime::Runtime::new().context("failed to build runtime")?;
+    let mut config = wasmtime::Config::new();
+    config.async_support(true);
+    let engine = Engine::new(&config)?;
+    let component = Component::new(
+        &engine,
+        r#"(component
+            (core module $m
+                (func (export "add") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    i32.add)
+            )
+            (core instance $i (instantiate $m))
+            (func (export "add") (param "a" u32) (param "b" u32) (result u32)
+                (canon lift (core func $i "add")))
+        )"#,
+    )?;
+    let host_resources = HashMap::default();
+
+    let mut group = c.benchmark_group("call add");
+    for (name, vals, buffers) in [
+        ("vec", None, None),
+        ("val pool", Some(ValPool::default()), None),
+        ("buffer pool", None, Some(BufferPool::default())),
+        (
+            "val and buffer pool",
+            Some(ValPool::default()),
+            Some(BufferPool::default()),
+        ),
+    ] {
+        let mut store = new_store(&engine);
+        store.data_mut().wrpc.vals = vals;
+        store.data_mut().wrpc.buffers = buffers;
+        let instance =
+            rt.block_on(Linker::new(&engine).instantiate_async(&mut store, &component))?;
+        let func = instance
+            .get_func(&mut store, "add")
+            .context("function `add` not found")?;
+        let ty = func.ty(&store);
+        let params_ty: Vec<_> = ty.params().map(|(_, ty)| ty).collect();
+        let results_ty: Vec<_> = ty.results().collect();
+        let mut call_add = || {
+            rt.block_on(call(
+                &mut store,
+                Params(Cursor::new(PARAMS)),
+                Discard,
+                &[],
+                &host_resources,
+                params_ty.iter(),
+                &results_ty,
+                func,
+            ))
+            .expect("failed to call `add`");
+        };
+        // populate the pools, if any
+        call_add();
+        let allocs = ALLOCS.load(Ordering::Relaxed);
+        call_add();
+        let allocs = ALLOCS.load(Ordering::Relaxed) - allocs;
+        println!("calling `add` using {name} performed {allocs} allocations");
+        group.bench_function(name, |b| b.iter(&mut call_add));
+    }
+    group.finish();
+    c.final_summary();
+    Ok(())
+}
@@ -0,0 +1,112 @@
+//! Fixtures shared by the [`wrpc_runtime_wasmtime`] benchmarks
+
+// not every benchmark uses every fixture
+#![allow(dead_code)]
+
+use core::num::NonZeroUsize;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use tokio::io::{empty, sink, AsyncWrite, Empty, Sink};
+use wasmtime::component::ResourceTable;
+use wasmtime::{Engine, Store};
+use wrpc_runtime_wasmtime::{
+    BufferPool, SharedResourceTable, ValPool, WrpcCtx, WrpcCtxView, WrpcView,
+    DEFAULT_INPUT_STREAM_CHUNK_SIZE,
+};
+use wrpc_transport::frame::Oneshot;
+
+pub type Client = Oneshot<Empty, Sink>;
+
+pub struct Ctx {
+    pub table: ResourceTable,
+    pub wrpc: WrpcCtxImpl,
+}
+
+pub struct WrpcCtxImpl {
+    pub client: Client,
+    pub shared_resources: SharedResourceTable,
+    pub input_stream_chunk_size: NonZeroUsize,
+    pub vals: Option<ValPool>,
+    pub buffers: Option<BufferPool>,
+}
+
+impl WrpcCtx<Client> for WrpcCtxImpl {
+    fn context(&self) {}
+
+    fn client(&self) -> &Client {
+        &self.client
+    }
+
+    fn shared_resources(&mut self) -> &mut SharedResourceTable {
+        &mut self.shared_resources
+    }
+
+    fn input_stream_chunk_size(&self) -> NonZeroUsize {
+        self.input_stream_chunk_size
+    }
+
+    fn val_pool(&self) -> Option<&ValPool> {
+        self.vals.as_ref()
+    }
+
+    fn buffer_pool(&self) -> Option<&BufferPool> {
+        self.buffers.as_ref()
+    }
+}
+
+impl WrpcView for Ctx {
+    type Invoke = Client;
+
+    fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke> {
+        WrpcCtxView {
+            ctx: &mut self.wrpc,
+            table: &mut self.table,
+        }
+    }
+}
+
+/// Returns a [Store] using the default configuration of [WrpcCtx] with a client, which is
+/// never used
+pub fn new_store(engine: &Engine) -> Store<Ctx> {
+    Store::new(
+        engine,
+        Ctx {
+            table: ResourceTable::default(),
+            wrpc: WrpcCtxImpl {
+                client: (empty(), sink()).into(),
+                shared_resources: SharedResourceTable::default(),
+                input_stream_chunk_size: DEFAULT_INPUT_STREAM_CHUNK_SIZE,
+                vals: None,
+                buffers: None,
+            },
+        },
+    )
+}
+
+/// Writer discarding all data written to it
+pub struct Discard;
+
+impl AsyncWrite for Discard {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl wrpc_transport::Index<Self> for Discard {
+    fn index(&self, _: &[usize]) -> anyhow::Result<Self> {
+        Ok(Self)
+    }
+}
@@ -0,0 +1,151 @@
+//! Benchmarks encoding of wide records and large `list<u8>` values using
+//! [`wrpc_runtime_wasmtime::encode_sync`].
+//!
+//! The amount of buffer reallocations performed by a single encoding is printed before
+//! the benchmarks are run.
+
+use core::fmt::Write as _;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use anyhow::Context as _;
+use criterion::{BenchmarkId, Criterion, Throughput};
+use wasmtime::component::{types, Component, Type, Val};
+use wasmtime::{Engine, Store};
+use wrpc_runtime_wasmtime::encode_sync;
+
+use common::{new_store, Ctx};
+
+mod common;
+
+/// Amount of `u64` and `string` fields each in the encoded record
+const FIELDS: usize = 64;
+
+/// Length of the encoded `list<u8>`
+const LIST_LEN: usize = 1 << 20;
+
+static REALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+/// [System] allocator counting reallocations
+struct CountingAlloc;
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        REALLOCS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+/// Returns the type of the first parameter of a function imported as `f` by `wat`
+fn param_type(engine: &Engine, wat: &str) -> anyhow::Result<Type> {
+    let component = Component::new(engine, wat)?;
+    let (_, ty) = component
+        .component_type()
+        .imports(engine)
+        .find(|(name, _)| *name == "f")
+        .context("component does not import function `f`")?;
+    let types::ComponentItem::ComponentFunc(ty) = ty else {
+        anyhow::bail!("`f` is not a function");
+    };
+    let (_, ty) = ty.params().next().context("function has no parameters")?;
+    Ok(ty)
+}
+
+/// Returns the type of a record with [FIELDS] `u64` and [FIELDS] `string` fields
+fn record_type(engine: &Engine) -> anyhow::Result<Type> {
+    let mut fields = String::new();
+    for i in 0..FIELDS {
+        write!(fields, r#" (field "u{i}" u64) (field "s{i}" string)"#)?;
+    }
+    param_type(
+        engine,
+        &format!(
+            r#"(component
+                (type $r' (record{fields}))
+                (import "r" (type $r (eq $r')))
+                (import "f" (func (param "r" $r)))
+            )"#
+        ),
+    )
+}
+
+fn record_value() -> Val {
+    Val::Record(
+        (0..FIELDS)
+            .flat_map(|i| {
+                [
+                    (format!("u{i}"), Val::U64(u64::MAX - i as u64)),
+                    (format!("s{i}"), Val::String(format!("value {i}"))),
+                ]
+            })
+            .collect(),
+    )
+}
+
+fn list_value() -> Val {
+    Val::List((0..LIST_LEN).map(|i| Val::U8(i as u8)).collect())
+}
+
+/// Encodes `v` of type `ty` once, printing the amount of reallocations performed
+fn count_reallocs(store: &mut Store<Ctx>, ty: &Type, v: &Val, name: &str) -> anyhow::Result<()> {
+    let reallocs = REALLOCS.load(Ordering::Relaxed);
+    let buf = encode_sync(store, ty, &[], v)?;
+    let reallocs = REALLOCS.load(Ordering::Relaxed) - reallocs;
+    println!(
+        "encoding {name} into {} bytes performed {reallocs} reallocations",
+        buf.len()
+    );
+    Ok(())
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut c = Criterion::default().configure_from_args();
+    let engine = Engine::default();
+    let record_ty = record_type(&engine)?;
+    let record = record_value();
+    let list_ty = param_type(
+        &engine,
+        r#"(component (import "f" (func (param "b" (list u8)))))"#,
+    )?;
+    let list = list_value();
+    let mut store = new_store(&engine);
+
+    count_reallocs(
+        &mut store,
+        &record_ty,
+        &record,
+        &format!("a record of {} fields", 2 * FIELDS),
+    )?;
+    count_reallocs(
+        &mut store,
+        &list_ty,
+        &list,
+        &format!("a `list<u8>` of {LIST_LEN} elements"),
+    )?;
+
+    c.bench_function("encode record", |b| {
+        b.iter(|| {
+            encode_sync(&mut store, &record_ty, &[], &record).expect("failed to encode record")
+        });
+    });
+    let mut group = c.benchmark_group("encode list<u8>");
+    group.throughput(Throughput::Bytes(LIST_LEN as u64));
+    group.bench_function(BenchmarkId::from_parameter(LIST_LEN), |b| {
+        b.iter(|| encode_sync(&mut store, &list_ty, &[], &list).expect("failed to encode list"));
+    });
+    group.finish();
+    c.final_summary();
+    Ok(())
+}
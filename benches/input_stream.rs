@@ -0,0 +1,61 @@
+//! Benchmarks transmission of a `wasi:io/input-stream` using varying
+//! [`WrpcCtx::input_stream_chunk_size`] values.
+
+use core::num::NonZeroUsize;
+
+use anyhow::Context as _;
+use bytes::{Bytes, BytesMut};
+use criterion::{BenchmarkId, Criterion, Throughput};
+use tokio_util::codec::Encoder as _;
+use wasmtime::component::{ResourceType, Type, Val};
+use wasmtime::{AsContextMut as _, Engine, Store};
+use wasmtime_wasi::p2::pipe::MemoryInputPipe;
+use wasmtime_wasi::p2::DynInputStream;
+use wrpc_runtime_wasmtime::ValEncoder;
+
+use common::{new_store, Ctx, Discard};
+
+mod common;
+
+/// Amount of bytes transmitted over the stream
+const STREAM_LEN: usize = 16 << 20;
+
+/// Encodes an input stream containing `payload` and drains it into [Discard]
+async fn transmit(store: &mut Store<Ctx>, ty: &Type, payload: &Bytes) -> anyhow::Result<()> {
+    let stream: DynInputStream = Box::new(MemoryInputPipe::new(payload.clone()));
+    let stream = store.data_mut().table.push(stream)?;
+    let stream = stream.try_into_resource_any(&mut *store)?;
+    let mut enc = ValEncoder::<_, Discard>::new(store.as_context_mut(), ty, &[]);
+    enc.encode(&Val::Resource(stream), &mut BytesMut::default())?;
+    let deferred = enc.deferred.context("input stream is not deferred")?;
+    deferred(Discard).await
+}
+
+fn main() -> anyhow::Result<()> {
+    let mut c = Criterion::default().configure_from_args();
+    let rt = tokio::runtime::Runtime::new().context("failed to build runtime")?;
+    let engine = Engine::default();
+    let ty = Type::Own(ResourceType::host::<DynInputStream>());
+    let payload = Bytes::from(vec![0x42; STREAM_LEN]);
+
+    let mut group = c.benchmark_group("input stream");
+    group.throughput(Throughput::Bytes(STREAM_LEN as u64));
+    for chunk_size in [1 << 10, 8 << 10, 64 << 10, 1 << 20] {
+        let chunk_size = NonZeroUsize::new(chunk_size).context("chunk size is zero")?;
+        let mut store = new_store(&engine);
+        store.data_mut().wrpc.input_stream_chunk_size = chunk_size;
+        group.bench_with_input(
+            BenchmarkId::from_parameter(chunk_size),
+            &payload,
+            |b, payload| {
+                b.iter(|| {
+                    rt.block_on(transmit(&mut store, &ty, payload))
+                        .expect("failed to transmit input stream");
+                });
+            },
+        );
+    }
+    group.finish();
+    c.final_summary();
+    Ok(())
+}
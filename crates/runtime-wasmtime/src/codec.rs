@@ -1,17 +1,22 @@
+use core::fmt;
 use core::future::Future;
 use core::iter::zip;
+use core::marker::PhantomData;
+use core::mem;
+use core::num::NonZeroUsize;
 use core::ops::{BitOrAssign, Shl};
 use core::pin::{pin, Pin};
 
 use std::collections::HashSet;
 
-use anyhow::{bail, Context as _};
-use bytes::{BufMut as _, BytesMut};
-use futures::stream::FuturesUnordered;
-use futures::TryStreamExt as _;
+use anyhow::{bail, ensure, Context as _};
+use bytes::{BufMut as _, Bytes, BytesMut};
+use futures::{future, stream, StreamExt as _, TryStreamExt as _};
 use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::sync::{mpsc, oneshot};
 use tokio_util::codec::{Encoder, FramedRead};
 use tokio_util::compat::FuturesAsyncReadCompatExt as _;
+use tokio_util::sync::PollSender;
 use tracing::{error, instrument, trace, warn};
 use uuid::Uuid;
 use wasm_tokio::cm::AsyncReadValue as _;
@@ -20,21 +25,148 @@ use wasm_tokio::{
     CoreVecEncoderBytes, Leb128Encoder, Utf8Codec,
 };
 use wasmtime::component::types::{Case, Field};
-use wasmtime::component::{ResourceType, Type, Val};
+use wasmtime::component::{
+    ComponentType, Destination, FutureAny, FutureConsumer, FutureReader, Lift, Lower, ResourceAny,
+    ResourceType, Source, StreamAny, StreamConsumer, StreamProducer, StreamReader, StreamResult,
+    Type, Val, VecBuffer,
+};
 use wasmtime::{AsContextMut, StoreContextMut};
-use wasmtime_wasi::p2::pipe::AsyncReadStream;
-use wasmtime_wasi::p2::{DynInputStream, StreamError};
+use wasmtime_wasi::p2::pipe::{AsyncReadStream, AsyncWriteStream, ClosedInputStream};
+use wasmtime_wasi::p2::{DynInputStream, DynOutputStream, StreamError};
 use wrpc_transport::ListDecoderU8;
 
-use crate::{RemoteResource, WrpcView};
+use crate::{LazyRemoteResource, RemoteResource, WrpcCtxView, WrpcView};
+
+/// Upper bound on the amount of elements pre-allocated for a decoded list.
+/// The length prefix is received from the peer and cannot be trusted, so any list
+/// longer than this will be grown as elements are actually received.
+const MAX_LIST_PREALLOC: usize = 1024;
+
+/// Size of the buffer of pipes backing decoded `wasi:io/output-stream` values
+const OUTPUT_STREAM_BUFFER_SIZE: usize = 8192;
+
+/// Amount of encoded `stream` chunks buffered before the writer of the stream is blocked
+const STREAM_CHUNK_BUFFER: usize = 16;
+
+/// Length of a resource handle encoded without a length prefix, i.e. of a UUID.
+/// See [`WrpcCtx::fixed_length_resource_handles`](crate::WrpcCtx::fixed_length_resource_handles).
+const FIXED_RESOURCE_HANDLE_LEN: u8 = 16;
+
+/// Encodes a host resource into an opaque handle
+type HostResourceEncoder<T> =
+    dyn Fn(StoreContextMut<'_, T>, ResourceAny) -> wasmtime::Result<Bytes> + Send + Sync;
+
+/// Decodes an opaque handle into a host resource
+type HostResourceDecoder<T> =
+    dyn Fn(StoreContextMut<'_, T>, Bytes) -> wasmtime::Result<ResourceAny> + Send + Sync;
+
+/// Codecs of host resources, which are transmitted as opaque handles.
+///
+/// Host resources with a registered codec are encoded by [`ValEncoder`] and decoded by
+/// [`read_value`] using the registered functions. See [`WrpcView::host_resource_codecs`].
+pub struct HostResourceCodecs<T> {
+    codecs: Vec<(
+        ResourceType,
+        Box<HostResourceEncoder<T>>,
+        Box<HostResourceDecoder<T>>,
+    )>,
+}
+
+impl<T> Default for HostResourceCodecs<T> {
+    fn default() -> Self {
+        Self {
+            codecs: Vec::default(),
+        }
+    }
+}
+
+impl<T: WrpcView + 'static> HostResourceCodecs<T> {
+    /// Registers a codec for host resource `R`, replacing the previously registered one, if any.
+    ///
+    /// `encode` converts a resource table entry into an opaque handle. Owned resources are
+    /// removed from the table once encoded. `decode` converts an opaque handle received from
+    /// the peer into an owned resource table entry.
+    pub fn register<R: Send + 'static>(
+        &mut self,
+        encode: impl Fn(&R) -> wasmtime::Result<Bytes> + Send + Sync + 'static,
+        decode: impl Fn(Bytes) -> wasmtime::Result<R> + Send + Sync + 'static,
+    ) -> &mut Self {
+        let ty = ResourceType::host::<R>();
+        self.codecs.retain(|(registered, ..)| *registered != ty);
+        self.codecs.push((
+            ty,
+            Box::new(move |mut store, resource| {
+                let resource = resource
+                    .try_into_resource::<R>(&mut store)
+                    .context("resource type mismatch")?;
+                let table = store.data_mut().wrpc().table;
+                if resource.owned() {
+                    let resource = table
+                        .delete(resource)
+                        .context("failed to delete host resource")?;
+                    encode(&resource)
+                } else {
+                    let resource = table
+                        .get(&resource)
+                        .context("failed to get host resource")?;
+                    encode(resource)
+                }
+            }),
+            Box::new(move |mut store, buf| {
+                let resource = decode(buf)?;
+                let resource = store
+                    .data_mut()
+                    .wrpc()
+                    .table
+                    .push(resource)
+                    .context("failed to push host resource")?;
+                resource.try_into_resource_any(store)
+            }),
+        ));
+        self
+    }
+}
+
+impl<T> HostResourceCodecs<T> {
+    fn encoder(&self, ty: &ResourceType) -> Option<&HostResourceEncoder<T>> {
+        self.codecs
+            .iter()
+            .find_map(|(registered, encode, _)| (registered == ty).then_some(&**encode))
+    }
+
+    fn decoder(&self, ty: &ResourceType) -> Option<&HostResourceDecoder<T>> {
+        self.codecs
+            .iter()
+            .find_map(|(registered, _, decode)| (registered == ty).then_some(&**decode))
+    }
+}
+
+/// Transmits the asynchronous portion of an encoded value on a writer
+type DeferredWrite<W> =
+    Box<dyn FnOnce(W) -> Pin<Box<dyn Future<Output = wasmtime::Result<()>> + Send>> + Send>;
+
+/// Owned `wasi:io/output-stream` values contained in an encoded value along with their
+/// path relative to it, see [`ValEncoder::output_streams`]
+pub type OutputStreams = Vec<(Vec<usize>, DynOutputStream)>;
 
+/// Encoder of [`Val`]s of type [`Self::ty`], which stores the asynchronous portion of the
+/// encoded value, if any, in [`Self::deferred`].
+///
+/// Data written to an owned `wasi:io/output-stream` flows in the opposite direction of the
+/// value itself, so such streams are moved out of the resource table into
+/// [`Self::output_streams`] and the caller is responsible for writing the data received from
+/// the peer to them. Borrowed output streams cannot be encoded.
+///
+/// NOTE: The contents of a `wasi:io/input-stream` are transmitted in full, even if the stream
+/// is only borrowed. A borrowed stream is replaced by a closed stream in the resource table, so
+/// the lender keeps a valid handle, but any data not read before the call is consumed and
+/// cannot be read by the lender anymore.
 pub struct ValEncoder<'a, T: 'static, W> {
     pub store: StoreContextMut<'a, T>,
     pub ty: &'a Type,
     pub resources: &'a [ResourceType],
-    pub deferred: Option<
-        Box<dyn FnOnce(W) -> Pin<Box<dyn Future<Output = wasmtime::Result<()>> + Send>> + Send>,
-    >,
+    pub deferred: Option<DeferredWrite<W>>,
+    pub output_streams: OutputStreams,
 }
 
 impl<T, W> ValEncoder<'_, T, W> {
@@ -49,6 +181,7 @@ impl<T, W> ValEncoder<'_, T, W> {
             ty,
             resources,
             deferred: None,
+            output_streams: OutputStreams::default(),
         }
     }
 
@@ -58,10 +191,449 @@ impl<T, W> ValEncoder<'_, T, W> {
             ty,
             resources: self.resources,
             deferred: None,
+            output_streams: OutputStreams::default(),
+        }
+    }
+}
+
+/// Writer type used by [`encode_sync`], which is never constructed, since values requiring
+/// deferred transmission are rejected
+enum SyncWriter {}
+
+impl AsyncWrite for SyncWriter {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut core::task::Context<'_>,
+        _: &[u8],
+    ) -> core::task::Poll<std::io::Result<usize>> {
+        match *self {}
+    }
+
+    fn poll_flush(
+        self: Pin<&mut Self>,
+        _: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        match *self {}
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        _: &mut core::task::Context<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        match *self {}
+    }
+}
+
+impl wrpc_transport::Index<Self> for SyncWriter {
+    fn index(&self, _: &[usize]) -> anyhow::Result<Self> {
+        match *self {}
+    }
+}
+
+/// Encodes `val` of type `ty` into a single buffer.
+///
+/// Unlike [`ValEncoder`], this does not require a writer for the deferred transmission of
+/// asynchronous values, but fails if `val` contains any, e.g. a `wasi:io/input-stream`.
+pub fn encode_sync<T>(
+    mut store: impl AsContextMut<Data = T>,
+    ty: &Type,
+    resources: &[ResourceType],
+    val: &Val,
+) -> anyhow::Result<Bytes>
+where
+    T: WrpcView + 'static,
+{
+    let mut buf = BytesMut::default();
+    let mut enc = ValEncoder::<_, SyncWriter>::new(store.as_context_mut(), ty, resources);
+    enc.encode(val, &mut buf)?;
+    ensure!(
+        enc.deferred.is_none() && enc.output_streams.is_empty(),
+        "value contains asynchronous values, which cannot be encoded synchronously"
+    );
+    Ok(buf.freeze())
+}
+
+/// Encodes and decodes values using a store and the guest resources exported by the component,
+/// which [`ValEncoder`] and [`read_value`] otherwise require on every call.
+pub struct Codec<'a, T: 'static> {
+    store: StoreContextMut<'a, T>,
+    resources: &'a [ResourceType],
+}
+
+impl<'a, T> Codec<'a, T>
+where
+    T: WrpcView + 'static,
+{
+    /// Constructs a new [`Codec`], `resources` are the guest resources exported by the component
+    #[must_use]
+    pub fn new(store: StoreContextMut<'a, T>, resources: &'a [ResourceType]) -> Self {
+        Self { store, resources }
+    }
+
+    /// Encodes `val` of type `ty` into `dst`, returning a function transmitting the
+    /// asynchronous values contained in `val` on a writer, if there are any.
+    /// See [`ValEncoder`].
+    pub fn encode<W>(
+        &mut self,
+        val: &Val,
+        ty: &Type,
+        dst: &mut BytesMut,
+    ) -> wasmtime::Result<Option<DeferredWrite<W>>>
+    where
+        W: AsyncWrite + wrpc_transport::Index<W> + Sync + Send + 'static,
+    {
+        let mut enc = ValEncoder::new(self.store.as_context_mut(), ty, self.resources);
+        enc.encode(val, dst)?;
+        ensure!(
+            enc.output_streams.is_empty(),
+            "value contains `wasi:io/output-stream` values, which require `ValEncoder`"
+        );
+        Ok(enc.deferred)
+    }
+
+    /// Encodes `val` of type `ty` into a single buffer. See [`encode_sync`].
+    pub fn encode_sync(&mut self, val: &Val, ty: &Type) -> anyhow::Result<Bytes> {
+        encode_sync(&mut self.store, ty, self.resources, val)
+    }
+
+    /// Returns the amount of bytes `val` of type `ty` would be encoded into.
+    /// See [`ValEncoder::encoded_len`].
+    pub fn encoded_len(&mut self, val: &Val, ty: &Type) -> wasmtime::Result<usize> {
+        ValEncoder::<_, SyncWriter>::new(self.store.as_context_mut(), ty, self.resources)
+            .encoded_len(val)
+    }
+
+    /// Decodes a value of type `ty` at `path` from `r`. See [`read_value`].
+    pub async fn decode<R>(
+        &mut self,
+        r: &mut Pin<&mut R>,
+        ty: &Type,
+        path: &[usize],
+    ) -> std::io::Result<Val>
+    where
+        R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+    {
+        let mut val = Val::Bool(false);
+        read_value(&mut self.store, r, self.resources, &mut val, ty, path).await?;
+        Ok(val)
+    }
+}
+
+/// Returns the amount of bytes used to LEB128-encode an unsigned integer `v`
+#[inline]
+fn leb128_len(v: u64) -> usize {
+    (u64::BITS - v.leading_zeros()).div_ceil(7).max(1) as usize
+}
+
+/// Returns the amount of bytes used to LEB128-encode a signed integer `v`
+#[inline]
+fn sleb128_len(v: i64) -> usize {
+    let redundant = if v < 0 {
+        v.leading_ones()
+    } else {
+        v.leading_zeros()
+    };
+    // one sign bit is required in addition to the significant bits
+    (i64::BITS - redundant + 1).div_ceil(7) as usize
+}
+
+/// Returns the error returned for a LEB128-encoded integer, which does not fit `bits` bits
+fn leb128_overflow(bits: u32) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!("LEB128-encoded integer exceeds {bits} bits"),
+    )
+}
+
+/// Reads an unsigned LEB128-encoded integer of width `bits` from `r`.
+///
+/// Sequences longer than the byte budget of the target width, i.e. `bits.div_ceil(7)` bytes,
+/// or encoding bits beyond `bits` are rejected rather than truncated.
+async fn read_uleb128<T: TryFrom<u64>>(
+    r: &mut (impl AsyncRead + Unpin),
+    bits: u32,
+) -> std::io::Result<T> {
+    let mut v = 0;
+    let mut shift = 0;
+    let v = loop {
+        let b = r.read_u8().await?;
+        let payload = u64::from(b & 0x7f);
+        if bits - shift <= 7 {
+            // last byte allowed by the byte budget, it must terminate the sequence
+            if b & 0x80 != 0 || payload >> (bits - shift) != 0 {
+                return Err(leb128_overflow(bits));
+            }
+            break v | payload << shift;
+        }
+        v |= payload << shift;
+        if b & 0x80 == 0 {
+            break v;
+        }
+        shift += 7;
+    };
+    T::try_from(v).map_err(|_| leb128_overflow(bits))
+}
+
+/// Reads a signed LEB128-encoded integer of width `bits` from `r`.
+///
+/// Like [`read_uleb128`], over-long sequences are rejected. Bits of the last byte beyond
+/// `bits` must equal the sign bit.
+async fn read_sleb128<T: TryFrom<i64>>(
+    r: &mut (impl AsyncRead + Unpin),
+    bits: u32,
+) -> std::io::Result<T> {
+    let mut v = 0;
+    let mut shift = 0;
+    let v = loop {
+        let b = r.read_u8().await?;
+        // sign-extend the 7-bit payload
+        let payload = i64::from(i8::from_ne_bytes([b << 1]) >> 1);
+        if bits - shift <= 7 {
+            // last byte allowed by the byte budget, it must terminate the sequence
+            let sign = payload >> (bits - shift - 1);
+            if b & 0x80 != 0 || (sign != 0 && sign != -1) {
+                return Err(leb128_overflow(bits));
+            }
+            break v | payload << shift;
+        }
+        if b & 0x80 == 0 {
+            break v | payload << shift;
+        }
+        v |= (payload & 0x7f) << shift;
+        shift += 7;
+    };
+    T::try_from(v).map_err(|_| leb128_overflow(bits))
+}
+
+impl<T, W> ValEncoder<'_, T, W>
+where
+    T: WrpcView,
+{
+    /// Returns the amount of bytes `v` of type [`Self::ty`] would be encoded into by
+    /// [`Encoder::encode`], without encoding it.
+    ///
+    /// Only the synchronously encoded portion of `v` is counted, values transmitted on deferred
+    /// writers, like the contents of a `wasi:io/input-stream`, are not included.
+    /// Unlike encoding, this never consumes resources, therefore owned remote resources and host
+    /// resources encoded using [`HostResourceCodecs`] cannot be measured.
+    pub fn encoded_len(&mut self, v: &Val) -> wasmtime::Result<usize> {
+        match (v, self.ty) {
+            (Val::Bool(..), Type::Bool) | (Val::S8(..), Type::S8) | (Val::U8(..), Type::U8) => {
+                Ok(1)
+            }
+            (Val::S16(v), Type::S16) => Ok(sleb128_len((*v).into())),
+            (Val::U16(v), Type::U16) => Ok(leb128_len((*v).into())),
+            (Val::S32(v), Type::S32) => Ok(sleb128_len((*v).into())),
+            (Val::U32(v), Type::U32) => Ok(leb128_len((*v).into())),
+            (Val::S64(v), Type::S64) => Ok(sleb128_len(*v)),
+            (Val::U64(v), Type::U64) => Ok(leb128_len(*v)),
+            (Val::Float32(..), Type::Float32) => Ok(4),
+            (Val::Float64(..), Type::Float64) => Ok(8),
+            (Val::Char(v), Type::Char) => Ok(v.len_utf8()),
+            (Val::String(v), Type::String) => {
+                let n = u32::try_from(v.len()).context("string length does not fit in u32")?;
+                Ok(leb128_len(n.into()) + v.len())
+            }
+            (Val::List(vs), Type::List(ty)) => {
+                let ty = ty.ty();
+                let n = u32::try_from(vs.len()).context("list length does not fit in u32")?;
+                let mut len = leb128_len(n.into());
+                if let Type::U8 = ty {
+                    ensure!(
+                        vs.iter().all(|v| matches!(v, Val::U8(..))),
+                        "list element type mismatch"
+                    );
+                    return Ok(len + vs.len());
+                }
+                for (i, v) in vs.iter().enumerate() {
+                    len += self
+                        .with_type(&ty)
+                        .encoded_len(v)
+                        .with_context(|| format!("failed to measure list element {i}"))?;
+                }
+                Ok(len)
+            }
+            (Val::Record(vs), Type::Record(ty)) => {
+                let mut len = 0;
+                for ((name, v), Field { ref ty, .. }) in zip(vs, ty.fields()) {
+                    len += self
+                        .with_type(ty)
+                        .encoded_len(v)
+                        .with_context(|| format!("failed to measure `{name}` field"))?;
+                }
+                Ok(len)
+            }
+            (Val::Tuple(vs), Type::Tuple(ty)) => {
+                let mut len = 0;
+                for (i, (v, ref ty)) in zip(vs, ty.types()).enumerate() {
+                    len += self
+                        .with_type(ty)
+                        .encoded_len(v)
+                        .with_context(|| format!("failed to measure tuple element {i}"))?;
+                }
+                Ok(len)
+            }
+            (Val::Variant(discriminant, v), Type::Variant(ty)) => {
+                let cases = ty.cases();
+                ensure!(cases.len() <= 0xffff_ffff, "case count does not fit in u32");
+                let (i, ty) = find_variant_discriminant(0u32.., cases, discriminant)?;
+                let mut len = leb128_len(i.into());
+                if let Some(v) = v {
+                    let ty = ty.context("type missing for variant")?;
+                    len += self.with_type(&ty).encoded_len(v).with_context(|| {
+                        format!("failed to measure `{discriminant}` variant value")
+                    })?;
+                }
+                Ok(len)
+            }
+            (Val::Enum(discriminant), Type::Enum(ty)) => {
+                let names = ty.names();
+                ensure!(names.len() <= 0xffff_ffff, "name count does not fit in u32");
+                let i = find_enum_discriminant(0u32.., names, discriminant)?;
+                Ok(leb128_len(i.into()))
+            }
+            (Val::Option(None), Type::Option(_)) => Ok(1),
+            (Val::Option(Some(v)), Type::Option(ty)) => {
+                let ty = ty.ty();
+                let len = self
+                    .with_type(&ty)
+                    .encoded_len(v)
+                    .context("failed to measure `option::some` value")?;
+                Ok(1 + len)
+            }
+            (Val::Result(v), Type::Result(ty)) => {
+                let (v, ty, case) = match v {
+                    Ok(v) => (v, ty.ok(), "ok"),
+                    Err(v) => (v, ty.err(), "err"),
+                };
+                match (v, ty) {
+                    (Some(v), Some(ty)) => {
+                        let len = self
+                            .with_type(&ty)
+                            .encoded_len(v)
+                            .with_context(|| format!("failed to measure `result::{case}` value"))?;
+                        Ok(1 + len)
+                    }
+                    (Some(_v), None) => bail!("`result::{case}` value of unknown type"),
+                    (None, Some(_ty)) => bail!("`result::{case}` value missing"),
+                    (None, None) => Ok(1),
+                }
+            }
+            (Val::Flags(..), Type::Flags(ty)) => Ok(flag_byte_count(ty.names().len()).max(1)),
+            (Val::Resource(resource), Type::Own(ty) | Type::Borrow(ty)) => {
+                if *ty == ResourceType::host::<DynInputStream>() {
+                    // stream contents are transmitted on a deferred writer
+                    Ok(0)
+                } else if *ty == ResourceType::host::<DynOutputStream>() {
+                    // stream contents are transmitted by the peer
+                    Ok(0)
+                } else if *ty == ResourceType::host::<LazyRemoteResource>() {
+                    // handles are transmitted on a deferred writer
+                    Ok(0)
+                } else if resource.ty() == ResourceType::host::<RemoteResource>() {
+                    // NOTE: Lifting an owned resource removes it from the store
+                    ensure!(
+                        !resource.owned(),
+                        "measuring owned remote resources not supported"
+                    );
+                    let resource = resource
+                        .try_into_resource::<RemoteResource>(&mut self.store)
+                        .context("resource type mismatch")?;
+                    let WrpcCtxView { ctx, table } = self.store.data_mut().wrpc();
+                    let fixed = ctx.fixed_length_resource_handles();
+                    let RemoteResource(buf) = table
+                        .get(&resource)
+                        .context("failed to get remote resource")?;
+                    if fixed {
+                        ensure!(
+                            buf.len() == usize::from(FIXED_RESOURCE_HANDLE_LEN),
+                            "resource handle of length {} cannot be encoded \
+                             without a length prefix",
+                            buf.len(),
+                        );
+                        return Ok(buf.len());
+                    }
+                    let n = u32::try_from(buf.len())
+                        .context("resource handle length does not fit in u32")?;
+                    Ok(leb128_len(n.into()) + buf.len())
+                } else if self.resources.contains(ty) {
+                    if self
+                        .store
+                        .data_mut()
+                        .wrpc()
+                        .ctx
+                        .fixed_length_resource_handles()
+                    {
+                        Ok(FIXED_RESOURCE_HANDLE_LEN.into())
+                    } else {
+                        // length-prefixed UUID
+                        Ok(usize::from(FIXED_RESOURCE_HANDLE_LEN) + 1)
+                    }
+                } else {
+                    bail!("measuring host resources not supported")
+                }
+            }
+            (Val::Future(..), Type::Future(..)) | (Val::Stream(..), Type::Stream(..)) => Ok(1),
+            (_, Type::ErrorContext) => bail!(
+                "encoding `error-context` values not supported, \
+                 since their debug message cannot be retrieved from the host"
+            ),
+            _ => bail!("value type mismatch"),
         }
     }
 }
 
+/// Returns an estimate of the amount of bytes required to encode a value of type `ty`,
+/// used to reserve buffer capacity up front.
+///
+/// Fixed-size values are estimated using their maximum encoded size, variable-size values
+/// using the size of the length prefix or discriminant only, since their encoders reserve
+/// the capacity required for the payload themselves.
+fn encoded_len_hint(ty: &Type) -> usize {
+    match ty {
+        Type::Bool | Type::S8 | Type::U8 => 1,
+        Type::S16 | Type::U16 => 3,
+        Type::S32 | Type::U32 => 5,
+        Type::S64 | Type::U64 => 10,
+        Type::Float32 | Type::Char => 4,
+        Type::Float64 => 8,
+        Type::Record(ty) => ty
+            .fields()
+            .map(|Field { ty, .. }| encoded_len_hint(&ty))
+            .sum(),
+        Type::Tuple(ty) => ty.types().map(|ty| encoded_len_hint(&ty)).sum(),
+        Type::Flags(ty) => ty.names().len().div_ceil(8),
+        Type::String
+        | Type::List(..)
+        | Type::Variant(..)
+        | Type::Enum(..)
+        | Type::Option(..)
+        | Type::Result(..)
+        | Type::Own(..)
+        | Type::Borrow(..) => 1,
+        Type::Future(..) | Type::Stream(..) => 1,
+        Type::ErrorContext => 0,
+    }
+}
+
+/// Encodes a shared or remote resource handle into `dst`, either as-is if `fixed` is set or
+/// prefixed by its length otherwise
+fn encode_resource_handle(buf: &[u8], fixed: bool, dst: &mut BytesMut) -> anyhow::Result<()> {
+    if fixed {
+        ensure!(
+            buf.len() == usize::from(FIXED_RESOURCE_HANDLE_LEN),
+            "resource handle of length {} cannot be encoded without a length prefix",
+            buf.len(),
+        );
+        dst.extend_from_slice(buf);
+        Ok(())
+    } else {
+        CoreVecEncoderBytes
+            .encode(buf, dst)
+            .context("failed to encode resource handle")
+    }
+}
+
 fn find_enum_discriminant<'a, T>(
     iter: impl IntoIterator<Item = T>,
     names: impl IntoIterator<Item = &'a str>,
@@ -97,7 +669,67 @@ fn flag_bits<'a, T: BitOrAssign + Shl<u8, Output = T> + From<u8>>(
     v
 }
 
-async fn write_deferred<W, I>(w: W, deferred: I) -> wasmtime::Result<()>
+/// Returns the amount of bytes used to encode flags with `n` names
+#[inline]
+fn flag_byte_count(n: usize) -> usize {
+    n.div_ceil(8)
+}
+
+/// Encodes flags as a little-endian bit vector with one bit per name
+fn flag_bytes<'a>(
+    names: impl ExactSizeIterator<Item = &'a str>,
+    flags: impl IntoIterator<Item = &'a str>,
+) -> Vec<u8> {
+    let mut buf = vec![0; flag_byte_count(names.len())];
+    let flags: HashSet<&str> = flags.into_iter().collect();
+    for (i, name) in names.enumerate() {
+        if flags.contains(name) {
+            if let Some(b) = buf.get_mut(i / 8) {
+                *b |= 1 << (i % 8);
+            }
+        }
+    }
+    buf
+}
+
+/// Decodes flags encoded by [`flag_bytes`]
+fn flag_names<'a>(
+    names: impl ExactSizeIterator<Item = &'a str>,
+    buf: &[u8],
+) -> std::io::Result<Vec<String>> {
+    let n = names.len();
+    if buf.len() != flag_byte_count(n) {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!(
+                "invalid flag byte count {}, expected {} for {n} flags",
+                buf.len(),
+                flag_byte_count(n)
+            ),
+        ));
+    }
+    let mut vs = Vec::with_capacity(
+        buf.iter()
+            .map(|b| b.count_ones())
+            .sum::<u32>()
+            .try_into()
+            .unwrap_or(usize::MAX),
+    );
+    for (i, name) in names.enumerate() {
+        if buf.get(i / 8).is_some_and(|b| b & (1 << (i % 8)) != 0) {
+            vs.push(name.to_string());
+        }
+    }
+    Ok(vs)
+}
+
+/// Writes `deferred` values to the indexes of `w` corresponding to their position, running
+/// at most `limit` writers concurrently. If `limit` is [None], all writers run concurrently.
+pub(crate) async fn write_deferred<W, I>(
+    w: &W,
+    deferred: I,
+    limit: Option<NonZeroUsize>,
+) -> wasmtime::Result<()>
 where
     W: wrpc_transport::Index<W> + Sync + Send + 'static,
     I: IntoIterator,
@@ -107,121 +739,655 @@ where
         >,
     >,
 {
-    let mut futs: FuturesUnordered<_> = zip(0.., deferred)
-        .filter_map(|(i, f)| f.map(|f| (w.index(&[i]), f)))
-        .map(|(w, f)| async move {
-            let w = w?;
-            f(w).await
-        })
-        .collect();
+    let mut futs = pin!(
+        stream::iter(zip(0.., deferred).filter_map(|(i, f)| f.map(|f| (i, f))))
+            .map(|(i, f)| async move {
+                let w = w.index(&[i])?;
+                f(w).await
+            })
+            .buffer_unordered(limit.map_or(usize::MAX, NonZeroUsize::get))
+    );
     while let Some(()) = futs.try_next().await? {}
     Ok(())
 }
 
-impl<T, W> Encoder<&Val> for ValEncoder<'_, T, W>
+/// Appends `nested` output streams of the element at index `i` to `output_streams`
+fn nest_output_streams(output_streams: &mut OutputStreams, nested: OutputStreams, i: usize) {
+    output_streams.extend(nested.into_iter().map(|(mut path, stream)| {
+        path.insert(0, i);
+        (path, stream)
+    }));
+}
+
+/// Writes the data received on `r` to `stream` until the sender terminates it with an empty
+/// chunk or `r` is closed, see [`ValEncoder::output_streams`].
+pub(crate) async fn read_output_stream<R>(mut stream: DynOutputStream, r: R) -> wasmtime::Result<()>
 where
-    T: WrpcView,
-    W: AsyncWrite + wrpc_transport::Index<W> + Sync + Send + 'static,
+    R: AsyncRead + Send,
 {
-    type Error = wasmtime::Error;
+    let mut chunks = pin!(FramedRead::new(r, ListDecoderU8::default()));
+    while let Some(chunk) = chunks
+        .try_next()
+        .await
+        .context("failed to read output stream chunk")?
+    {
+        if chunk.is_empty() {
+            break;
+        }
+        stream
+            .blocking_write_and_flush(Bytes::from(chunk))
+            .await
+            .context("failed to write output stream chunk")?;
+    }
+    Ok(())
+}
 
-    #[allow(clippy::too_many_lines)]
-    #[instrument(level = "trace", skip(self))]
-    fn encode(&mut self, v: &Val, dst: &mut BytesMut) -> Result<(), Self::Error> {
-        match (v, self.ty) {
-            (Val::Bool(v), Type::Bool) => {
-                dst.reserve(1);
-                dst.put_u8((*v).into());
-                Ok(())
+/// Default maximum size of chunks read from a `wasi:io/input-stream`,
+/// see [`WrpcCtx::input_stream_chunk_size`](crate::WrpcCtx::input_stream_chunk_size)
+pub const DEFAULT_INPUT_STREAM_CHUNK_SIZE: NonZeroUsize = NonZeroUsize::new(8192).unwrap();
+
+/// Drains `stream` into `w` as a sequence of length-prefixed chunks of at most `chunk_size`
+/// bytes terminated by an empty chunk.
+///
+/// The next chunk is only read from `stream` once the previous one has been written in full,
+/// so at most `chunk_size` bytes are buffered regardless of how fast `stream` produces data
+/// compared to `w` consuming it.
+pub(crate) async fn write_input_stream<W>(
+    mut stream: DynInputStream,
+    chunk_size: NonZeroUsize,
+    w: W,
+) -> wasmtime::Result<()>
+where
+    W: AsyncWrite + Send,
+{
+    let mut w = pin!(w);
+    loop {
+        stream.ready().await;
+        match stream.read(chunk_size.get()) {
+            Ok(buf) if buf.is_empty() => continue,
+            Ok(buf) => {
+                let mut chunk = BytesMut::with_capacity(buf.len().saturating_add(5));
+                CoreVecEncoderBytes
+                    .encode(buf, &mut chunk)
+                    .context("failed to encode input stream chunk")?;
+                w.write_all(&chunk).await?;
             }
-            (Val::S8(v), Type::S8) => {
-                dst.reserve(1);
-                dst.put_i8(*v);
-                Ok(())
+            Err(StreamError::Closed) => {
+                w.write_all(&[0x00]).await?;
+                return Ok(());
             }
-            (Val::U8(v), Type::U8) => {
-                dst.reserve(1);
-                dst.put_u8(*v);
-                Ok(())
+            Err(err) => return Err(err.into()),
+        }
+    }
+}
+
+/// Rust representation of a `future` or `stream` payload type.
+///
+/// wasmtime only provides statically-typed endpoints of `future` and `stream` values, so only
+/// values of primitive payload types, which have a corresponding Rust type, can be transmitted.
+trait Payload: ComponentType + Lift + Lower + Send + Sync + Unpin + 'static {
+    fn into_val(self) -> Val;
+
+    fn from_val(val: Val) -> Option<Self>;
+}
+
+macro_rules! impl_payload {
+    ($($case:ident($ty:ty)),+ $(,)?) => {
+        $(
+            impl Payload for $ty {
+                fn into_val(self) -> Val {
+                    Val::$case(self)
+                }
+
+                fn from_val(val: Val) -> Option<Self> {
+                    if let Val::$case(v) = val {
+                        Some(v)
+                    } else {
+                        None
+                    }
+                }
             }
-            (Val::S16(v), Type::S16) => Leb128Encoder
-                .encode(*v, dst)
-                .context("failed to encode s16"),
-            (Val::U16(v), Type::U16) => Leb128Encoder
-                .encode(*v, dst)
-                .context("failed to encode u16"),
-            (Val::S32(v), Type::S32) => Leb128Encoder
-                .encode(*v, dst)
-                .context("failed to encode s32"),
-            (Val::U32(v), Type::U32) => Leb128Encoder
-                .encode(*v, dst)
-                .context("failed to encode u32"),
-            (Val::S64(v), Type::S64) => Leb128Encoder
-                .encode(*v, dst)
-                .context("failed to encode s64"),
-            (Val::U64(v), Type::U64) => Leb128Encoder
-                .encode(*v, dst)
-                .context("failed to encode u64"),
-            (Val::Float32(v), Type::Float32) => {
-                dst.reserve(4);
-                dst.put_f32_le(*v);
-                Ok(())
+        )+
+    };
+}
+
+impl_payload!(
+    Bool(bool),
+    S8(i8),
+    U8(u8),
+    S16(i16),
+    U16(u16),
+    S32(i32),
+    U32(u32),
+    S64(i64),
+    U64(u64),
+    Float32(f32),
+    Float64(f64),
+    Char(char),
+    String(String),
+);
+
+/// Evaluates `$body` with `$p` aliased to the [`Payload`] corresponding to the payload type
+/// `$ty`, or to an error if `$ty` is not supported.
+macro_rules! with_payload {
+    ($ty:expr, $p:ident => $body:expr) => {
+        match $ty {
+            Type::Bool => {
+                type $p = bool;
+                $body
             }
-            (Val::Float64(v), Type::Float64) => {
-                dst.reserve(8);
-                dst.put_f64_le(*v);
-                Ok(())
+            Type::S8 => {
+                type $p = i8;
+                $body
             }
-            (Val::Char(v), Type::Char) => {
-                Utf8Codec.encode(*v, dst).context("failed to encode char")
+            Type::U8 => {
+                type $p = u8;
+                $body
             }
-            (Val::String(v), Type::String) => CoreNameEncoder
-                .encode(v.as_str(), dst)
-                .context("failed to encode string"),
-            (Val::List(vs), Type::List(ty)) => {
-                let ty = ty.ty();
-                let n = u32::try_from(vs.len()).context("list length does not fit in u32")?;
-                dst.reserve(5 + vs.len());
-                Leb128Encoder
-                    .encode(n, dst)
-                    .context("failed to encode list length")?;
-                let mut deferred = Vec::with_capacity(vs.len());
-                for v in vs {
-                    let mut enc = self.with_type(&ty);
-                    enc.encode(v, dst)
-                        .context("failed to encode list element")?;
+            Type::S16 => {
+                type $p = i16;
+                $body
+            }
+            Type::U16 => {
+                type $p = u16;
+                $body
+            }
+            Type::S32 => {
+                type $p = i32;
+                $body
+            }
+            Type::U32 => {
+                type $p = u32;
+                $body
+            }
+            Type::S64 => {
+                type $p = i64;
+                $body
+            }
+            Type::U64 => {
+                type $p = u64;
+                $body
+            }
+            Type::Float32 => {
+                type $p = f32;
+                $body
+            }
+            Type::Float64 => {
+                type $p = f64;
+                $body
+            }
+            Type::Char => {
+                type $p = char;
+                $body
+            }
+            Type::String => {
+                type $p = String;
+                $body
+            }
+            ty => Err(unsupported_payload(ty).into()),
+        }
+    };
+}
+
+fn unsupported_payload(ty: &Type) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        format!(
+            "`future` and `stream` values are only supported for primitive payload types, \
+             got {ty:?}"
+        ),
+    )
+}
+
+fn payload_mismatch() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "`future` or `stream` payload type mismatch",
+    )
+}
+
+/// Encodes a chunk of `stream` elements of type `ty` as a length-prefixed list
+fn encode_chunk<T, P>(
+    mut store: impl AsContextMut<Data = T>,
+    ty: &Type,
+    items: Vec<P>,
+) -> wasmtime::Result<Bytes>
+where
+    T: WrpcView + 'static,
+    P: Payload,
+{
+    let n = u32::try_from(items.len()).context("`stream` chunk length does not fit in u32")?;
+    let mut buf = BytesMut::default();
+    Leb128Encoder
+        .encode(n, &mut buf)
+        .context("failed to encode `stream` chunk length")?;
+    let mut enc = ValEncoder::<_, SyncWriter>::new(store.as_context_mut(), ty, &[]);
+    for v in items {
+        enc.encode(&v.into_val(), &mut buf)?;
+    }
+    Ok(buf.freeze())
+}
+
+/// Consumes the value of a `future` written by the guest and sends it encoded.
+struct FutureSender<P> {
+    ty: Type,
+    tx: Option<oneshot::Sender<wasmtime::Result<Bytes>>>,
+    _payload: PhantomData<fn() -> P>,
+}
+
+impl<T, P> FutureConsumer<T> for FutureSender<P>
+where
+    T: WrpcView + 'static,
+    P: Payload,
+{
+    type Item = P;
+
+    fn poll_consume(
+        self: Pin<&mut Self>,
+        _: &mut core::task::Context<'_>,
+        mut store: StoreContextMut<T>,
+        mut source: Source<'_, P>,
+        _: bool,
+    ) -> core::task::Poll<wasmtime::Result<()>> {
+        let this = self.get_mut();
+        let mut v = None;
+        source.read(&mut store, &mut v)?;
+        if let (Some(v), Some(tx)) = (v, this.tx.take()) {
+            let buf = encode_sync(&mut store, &this.ty, &[], &v.into_val());
+            if tx.send(buf).is_err() {
+                trace!("`future` value receiver closed, discard value");
+            }
+        }
+        core::task::Poll::Ready(Ok(()))
+    }
+}
+
+/// Consumes the elements of a `stream` written by the guest and sends them in encoded chunks.
+///
+/// Capacity for a chunk is reserved before any elements are taken from the writer, so the
+/// writer is blocked while [`STREAM_CHUNK_BUFFER`] chunks are pending transmission.
+struct StreamSender<P> {
+    ty: Type,
+    tx: PollSender<wasmtime::Result<Bytes>>,
+    _payload: PhantomData<fn() -> P>,
+}
+
+impl<T, P> StreamConsumer<T> for StreamSender<P>
+where
+    T: WrpcView + 'static,
+    P: Payload,
+{
+    type Item = P;
+
+    fn poll_consume(
+        self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        mut store: StoreContextMut<T>,
+        mut source: Source<'_, P>,
+        finish: bool,
+    ) -> core::task::Poll<wasmtime::Result<StreamResult>> {
+        use core::task::Poll;
+
+        let this = self.get_mut();
+        match this.tx.poll_reserve(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(..)) => return Poll::Ready(Ok(StreamResult::Dropped)),
+            Poll::Pending if finish => return Poll::Ready(Ok(StreamResult::Cancelled)),
+            Poll::Pending => return Poll::Pending,
+        }
+        let n = source.remaining(&mut store);
+        if n == 0 {
+            this.tx.abort_send();
+            return Poll::Ready(Ok(StreamResult::Completed));
+        }
+        let mut items = Vec::with_capacity(n);
+        source.read(&mut store, &mut items)?;
+        let chunk = encode_chunk(&mut store, &this.ty, items);
+        if this.tx.send_item(chunk).is_err() {
+            return Poll::Ready(Ok(StreamResult::Dropped));
+        }
+        Poll::Ready(Ok(StreamResult::Completed))
+    }
+}
+
+/// Produces the elements of a pending `stream` in chunks as they are received from the peer.
+struct StreamReceiver<P>(Pin<Box<dyn futures::Stream<Item = std::io::Result<Vec<P>>> + Send>>);
+
+impl<T, P> StreamProducer<T> for StreamReceiver<P>
+where
+    T: 'static,
+    P: Payload,
+{
+    type Item = P;
+    type Buffer = VecBuffer<P>;
+
+    fn poll_produce<'a>(
+        mut self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        _: StoreContextMut<'a, T>,
+        mut dst: Destination<'a, P, VecBuffer<P>>,
+        finish: bool,
+    ) -> core::task::Poll<wasmtime::Result<StreamResult>> {
+        use core::task::Poll;
+
+        match self.0.poll_next_unpin(cx) {
+            Poll::Ready(Some(Ok(chunk))) => {
+                dst.set_buffer(chunk.into());
+                Poll::Ready(Ok(StreamResult::Completed))
+            }
+            Poll::Ready(Some(Err(err))) => {
+                // returning an error would trap the guest, so the stream is closed instead
+                warn!(?err, "failed to receive `stream` chunk");
+                Poll::Ready(Ok(StreamResult::Dropped))
+            }
+            Poll::Ready(None) => Poll::Ready(Ok(StreamResult::Dropped)),
+            Poll::Pending if finish => Poll::Ready(Ok(StreamResult::Cancelled)),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+/// Pipes the value of `future` into the returned channel, encoded as a value of type `ty`.
+///
+/// The value is only transferred while the event loop of the store is running, see
+/// [`wasmtime::Store::run_concurrent`].
+fn pipe_future<P, T>(
+    mut store: StoreContextMut<'_, T>,
+    future: FutureAny,
+    ty: Type,
+) -> wasmtime::Result<oneshot::Receiver<wasmtime::Result<Bytes>>>
+where
+    P: Payload,
+    T: WrpcView + 'static,
+{
+    let future = future.try_into_future_reader::<P>()?;
+    let (tx, rx) = oneshot::channel();
+    future.pipe(
+        &mut store,
+        FutureSender {
+            ty,
+            tx: Some(tx),
+            _payload: PhantomData,
+        },
+    );
+    Ok(rx)
+}
+
+/// Pipes the elements of `stream` into the returned channel, encoded in chunks of elements of
+/// type `ty`. Like [`pipe_future`], this requires the event loop of the store to be running.
+fn pipe_stream<P, T>(
+    mut store: StoreContextMut<'_, T>,
+    stream: StreamAny,
+    ty: Type,
+) -> wasmtime::Result<mpsc::Receiver<wasmtime::Result<Bytes>>>
+where
+    P: Payload,
+    T: WrpcView + 'static,
+{
+    let stream = stream.try_into_stream_reader::<P>()?;
+    let (tx, rx) = mpsc::channel(STREAM_CHUNK_BUFFER);
+    stream.pipe(
+        &mut store,
+        StreamSender {
+            ty,
+            tx: PollSender::new(tx),
+            _payload: PhantomData,
+        },
+    );
+    Ok(rx)
+}
+
+/// Writes the encoded value of a `future` received on `rx` to `w`
+async fn write_future<W>(
+    rx: oneshot::Receiver<wasmtime::Result<Bytes>>,
+    w: W,
+) -> wasmtime::Result<()>
+where
+    W: AsyncWrite + Send,
+{
+    let buf = rx
+        .await
+        .context("`future` was dropped before a value was written")??;
+    let mut w = pin!(w);
+    w.write_all(&buf)
+        .await
+        .context("failed to write `future` value")?;
+    Ok(())
+}
+
+/// Writes the encoded `stream` chunks received on `rx` to `w` followed by an empty chunk,
+/// which marks the end of the stream
+async fn write_stream<W>(
+    mut rx: mpsc::Receiver<wasmtime::Result<Bytes>>,
+    w: W,
+) -> wasmtime::Result<()>
+where
+    W: AsyncWrite + Send,
+{
+    let mut w = pin!(w);
+    while let Some(chunk) = rx.recv().await {
+        w.write_all(&chunk?)
+            .await
+            .context("failed to write `stream` chunk")?;
+    }
+    w.write_all(&[0x00])
+        .await
+        .context("failed to write `stream` end")?;
+    Ok(())
+}
+
+/// Reads `n` elements of a `stream` of primitive type `ty`
+async fn read_chunk<P: Payload>(
+    r: &mut (impl AsyncRead + Unpin),
+    ty: &Type,
+    n: u32,
+) -> std::io::Result<Vec<P>> {
+    let n = usize::try_from(n).unwrap_or(usize::MAX);
+    let mut chunk = Vec::with_capacity(n.min(MAX_LIST_PREALLOC));
+    for _ in 0..n {
+        let v = read_primitive(r, ty).await?;
+        chunk.push(P::from_val(v).ok_or_else(payload_mismatch)?);
+    }
+    Ok(chunk)
+}
+
+/// Reads a `future` with payload type `ty`, the value of which is either encoded inline or,
+/// if pending, received on the index `path` of `r` once the guest reads it
+async fn read_future<P, T, R>(
+    mut store: StoreContextMut<'_, T>,
+    r: &mut Pin<&mut R>,
+    ty: &Type,
+    path: &[usize],
+) -> std::io::Result<Val>
+where
+    P: Payload,
+    T: 'static,
+    R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+{
+    let future = if r.read_option_status().await? {
+        let v = read_primitive(r, ty).await?;
+        let v = P::from_val(v).ok_or_else(payload_mismatch)?;
+        FutureReader::new(&mut store, future::ready(anyhow::Ok(v)))
+    } else {
+        let mut r = r.index(path).map_err(std::io::Error::other)?;
+        let ty = ty.clone();
+        FutureReader::new(&mut store, async move {
+            let v = read_primitive(&mut r, &ty)
+                .await
+                .context("failed to read `future` value")?;
+            anyhow::Ok(P::from_val(v).ok_or_else(payload_mismatch)?)
+        })
+    };
+    let future = FutureAny::try_from_future_reader(store, future).map_err(std::io::Error::other)?;
+    Ok(Val::Future(future))
+}
+
+/// Reads a `stream` with element type `ty`, which is either encoded inline in full or, if
+/// pending, received in chunks on the index `path` of `r` as the guest reads it
+async fn read_stream<P, T, R>(
+    mut store: StoreContextMut<'_, T>,
+    r: &mut Pin<&mut R>,
+    ty: &Type,
+    path: &[usize],
+) -> std::io::Result<Val>
+where
+    P: Payload,
+    T: 'static,
+    R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+{
+    let n = r.read_u32_leb128().await?;
+    let stream = if n > 0 {
+        let chunk = read_chunk::<P>(r, ty, n).await?;
+        StreamReader::new(&mut store, chunk)
+    } else {
+        let r = r.index(path).map_err(std::io::Error::other)?;
+        let chunks = stream::try_unfold((r, ty.clone()), |(mut r, ty)| async move {
+            let n = r.read_u32_leb128().await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            let chunk = read_chunk::<P>(&mut r, &ty, n).await?;
+            Ok(Some((chunk, (r, ty))))
+        });
+        StreamReader::new(&mut store, StreamReceiver(Box::pin(chunks)))
+    };
+    let stream = StreamAny::try_from_stream_reader(store, stream).map_err(std::io::Error::other)?;
+    Ok(Val::Stream(stream))
+}
+
+impl<T, W> Encoder<&Val> for ValEncoder<'_, T, W>
+where
+    T: WrpcView,
+    W: AsyncWrite + wrpc_transport::Index<W> + Sync + Send + 'static,
+{
+    type Error = wasmtime::Error;
+
+    #[allow(clippy::too_many_lines)]
+    #[instrument(level = "trace", skip(self))]
+    fn encode(&mut self, v: &Val, dst: &mut BytesMut) -> Result<(), Self::Error> {
+        match (v, self.ty) {
+            (Val::Bool(v), Type::Bool) => {
+                dst.reserve(1);
+                dst.put_u8((*v).into());
+                Ok(())
+            }
+            (Val::S8(v), Type::S8) => {
+                dst.reserve(1);
+                dst.put_i8(*v);
+                Ok(())
+            }
+            (Val::U8(v), Type::U8) => {
+                dst.reserve(1);
+                dst.put_u8(*v);
+                Ok(())
+            }
+            (Val::S16(v), Type::S16) => Leb128Encoder
+                .encode(*v, dst)
+                .context("failed to encode s16"),
+            (Val::U16(v), Type::U16) => Leb128Encoder
+                .encode(*v, dst)
+                .context("failed to encode u16"),
+            (Val::S32(v), Type::S32) => Leb128Encoder
+                .encode(*v, dst)
+                .context("failed to encode s32"),
+            (Val::U32(v), Type::U32) => Leb128Encoder
+                .encode(*v, dst)
+                .context("failed to encode u32"),
+            (Val::S64(v), Type::S64) => Leb128Encoder
+                .encode(*v, dst)
+                .context("failed to encode s64"),
+            (Val::U64(v), Type::U64) => Leb128Encoder
+                .encode(*v, dst)
+                .context("failed to encode u64"),
+            // Floats are encoded as their IEEE 754 bit patterns in little-endian byte order.
+            // NaNs are not canonicalized, their sign and payload bits are transmitted as-is.
+            (Val::Float32(v), Type::Float32) => {
+                dst.reserve(4);
+                dst.put_f32_le(*v);
+                Ok(())
+            }
+            (Val::Float64(v), Type::Float64) => {
+                dst.reserve(8);
+                dst.put_f64_le(*v);
+                Ok(())
+            }
+            (Val::Char(v), Type::Char) => {
+                Utf8Codec.encode(*v, dst).context("failed to encode char")
+            }
+            (Val::String(v), Type::String) => CoreNameEncoder
+                .encode(v.as_str(), dst)
+                .context("failed to encode string"),
+            (Val::List(vs), Type::List(ty)) => {
+                let ty = ty.ty();
+                let n = u32::try_from(vs.len()).context("list length does not fit in u32")?;
+                dst.reserve(5 + vs.len());
+                Leb128Encoder
+                    .encode(n, dst)
+                    .context("failed to encode list length")?;
+                if let Type::U8 = ty {
+                    for v in vs {
+                        let Val::U8(v) = v else {
+                            bail!("list element type mismatch")
+                        };
+                        dst.put_u8(*v);
+                    }
+                    return Ok(());
+                }
+                let mut deferred = Vec::with_capacity(vs.len());
+                let mut output_streams = OutputStreams::default();
+                for (i, v) in vs.iter().enumerate() {
+                    let mut enc = self.with_type(&ty);
+                    enc.encode(v, dst)
+                        .with_context(|| format!("failed to encode list element {i}"))?;
                     deferred.push(enc.deferred);
+                    nest_output_streams(&mut output_streams, enc.output_streams, i);
                 }
+                self.output_streams.extend(output_streams);
                 if deferred.iter().any(Option::is_some) {
-                    self.deferred = Some(Box::new(|w| Box::pin(write_deferred(w, deferred))));
+                    let limit = self.store.data_mut().wrpc().ctx.max_deferred_writers();
+                    self.deferred = Some(Box::new(move |w| {
+                        Box::pin(async move { write_deferred(&w, deferred, limit).await })
+                    }));
                 }
                 Ok(())
             }
             (Val::Record(vs), Type::Record(ty)) => {
-                dst.reserve(vs.len());
+                dst.reserve(encoded_len_hint(self.ty));
                 let mut deferred = Vec::with_capacity(vs.len());
-                for ((name, v), Field { ref ty, .. }) in zip(vs, ty.fields()) {
+                let mut output_streams = OutputStreams::default();
+                for (i, ((name, v), Field { ref ty, .. })) in zip(vs, ty.fields()).enumerate() {
                     let mut enc = self.with_type(ty);
                     enc.encode(v, dst)
                         .with_context(|| format!("failed to encode `{name}` field"))?;
                     deferred.push(enc.deferred);
+                    nest_output_streams(&mut output_streams, enc.output_streams, i);
                 }
+                self.output_streams.extend(output_streams);
                 if deferred.iter().any(Option::is_some) {
-                    self.deferred = Some(Box::new(|w| Box::pin(write_deferred(w, deferred))));
+                    let limit = self.store.data_mut().wrpc().ctx.max_deferred_writers();
+                    self.deferred = Some(Box::new(move |w| {
+                        Box::pin(async move { write_deferred(&w, deferred, limit).await })
+                    }));
                 }
                 Ok(())
             }
             (Val::Tuple(vs), Type::Tuple(ty)) => {
-                dst.reserve(vs.len());
+                dst.reserve(encoded_len_hint(self.ty));
                 let mut deferred = Vec::with_capacity(vs.len());
-                for (v, ref ty) in zip(vs, ty.types()) {
+                let mut output_streams = OutputStreams::default();
+                for (i, (v, ref ty)) in zip(vs, ty.types()).enumerate() {
                     let mut enc = self.with_type(ty);
                     enc.encode(v, dst)
-                        .context("failed to encode tuple element")?;
+                        .with_context(|| format!("failed to encode tuple element {i}"))?;
                     deferred.push(enc.deferred);
+                    nest_output_streams(&mut output_streams, enc.output_streams, i);
                 }
+                self.output_streams.extend(output_streams);
                 if deferred.iter().any(Option::is_some) {
-                    self.deferred = Some(Box::new(|w| Box::pin(write_deferred(w, deferred))));
+                    let limit = self.store.data_mut().wrpc().ctx.max_deferred_writers();
+                    self.deferred = Some(Box::new(move |w| {
+                        Box::pin(async move { write_deferred(&w, deferred, limit).await })
+                    }));
                 }
                 Ok(())
             }
@@ -261,11 +1427,14 @@ where
                 if let Some(v) = v {
                     let ty = ty.context("type missing for variant")?;
                     let mut enc = self.with_type(&ty);
-                    enc.encode(v, dst)
-                        .context("failed to encode variant value")?;
+                    enc.encode(v, dst).with_context(|| {
+                        format!("failed to encode `{discriminant}` variant value")
+                    })?;
+                    let output_streams = enc.output_streams;
                     if let Some(f) = enc.deferred {
                         self.deferred = Some(f);
                     }
+                    self.output_streams.extend(output_streams);
                 }
                 Ok(())
             }
@@ -308,9 +1477,11 @@ where
                 let mut enc = self.with_type(&ty);
                 enc.encode(v, dst)
                     .context("failed to encode `option::some` value")?;
+                let output_streams = enc.output_streams;
                 if let Some(f) = enc.deferred {
                     self.deferred = Some(f);
                 }
+                self.output_streams.extend(output_streams);
                 Ok(())
             }
             (Val::Result(v), Type::Result(ty)) => match v {
@@ -321,9 +1492,11 @@ where
                         let mut enc = self.with_type(&ty);
                         enc.encode(v, dst)
                             .context("failed to encode `result::ok` value")?;
+                        let output_streams = enc.output_streams;
                         if let Some(f) = enc.deferred {
                             self.deferred = Some(f);
                         }
+                        self.output_streams.extend(output_streams);
                         Ok(())
                     }
                     (Some(_v), None) => bail!("`result::ok` value of unknown type"),
@@ -341,9 +1514,11 @@ where
                         let mut enc = self.with_type(&ty);
                         enc.encode(v, dst)
                             .context("failed to encode `result::err` value")?;
+                        let output_streams = enc.output_streams;
                         if let Some(f) = enc.deferred {
                             self.deferred = Some(f);
                         }
+                        self.output_streams.extend(output_streams);
                         Ok(())
                     }
                     (Some(_v), None) => bail!("`result::err` value of unknown type"),
@@ -423,18 +1598,8 @@ where
                         dst.reserve(16);
                         dst.put_u128_le(flag_bits(names, vs));
                     }
-                    bits @ 129.. => {
-                        let mut cap = bits / 8;
-                        if bits % 8 != 0 {
-                            cap = cap.saturating_add(1);
-                        }
-                        let mut buf = vec![0; cap];
-                        let flags: HashSet<&str> = vs.into_iter().collect();
-                        for (i, name) in names.enumerate() {
-                            if flags.contains(name) {
-                                buf[i / 8] |= 1 << (i % 8);
-                            }
-                        }
+                    129.. => {
+                        let buf = flag_bytes(names, vs);
                         dst.extend_from_slice(&buf);
                     }
                 }
@@ -445,97 +1610,153 @@ where
                     let stream = resource
                         .try_into_resource::<DynInputStream>(&mut self.store)
                         .context("failed to downcast `wasi:io/input-stream`")?;
-                    if stream.owned() {
-                        let mut stream = self
-                            .store
-                            .data_mut()
-                            .wrpc()
-                            .table
+                    let table = self.store.data_mut().wrpc().table;
+                    let stream = if stream.owned() {
+                        table
                             .delete(stream)
-                            .context("failed to delete input stream")?;
-                        self.deferred = Some(Box::new(|w| {
-                            Box::pin(async move {
-                                let mut w = pin!(w);
-                                loop {
-                                    stream.ready().await;
-                                    match stream.read(8096) {
-                                        Ok(buf) => {
-                                            let mut chunk = BytesMut::with_capacity(
-                                                buf.len().saturating_add(5),
-                                            );
-                                            CoreVecEncoderBytes
-                                                .encode(buf, &mut chunk)
-                                                .context("failed to encode input stream chunk")?;
-                                            w.write_all(&chunk).await?;
-                                        }
-                                        Err(StreamError::Closed) => {
-                                            w.write_all(&[0x00]).await?;
-                                        }
-                                        Err(err) => return Err(err.into()),
-                                    }
-                                }
-                            })
-                        }));
+                            .context("failed to delete input stream")?
                     } else {
-                        self.store
-                            .data_mut()
-                            .wrpc()
-                            .table
+                        // NOTE: The stream is drained until it is closed, so the lender is left
+                        // with a closed stream, which keeps the borrowed handle valid after the
+                        // call. Tracking the amount of bytes actually consumed by the receiver
+                        // would require a callback from the receiver, which is not supported.
+                        let stream = table
                             .get_mut(&stream)
                             .context("failed to get input stream")?;
-                        // NOTE: In order to handle this we'd need to know how many bytes the
-                        // receiver has read. That means that some kind of callback would be required from
-                        // the receiver. This is not trivial and generally should be a very rare use case.
-                        bail!("encoding borrowed `wasi:io/input-stream` not supported yet");
+                        mem::replace(stream, Box::new(ClosedInputStream))
                     };
+                    let chunk_size = self.store.data_mut().wrpc().ctx.input_stream_chunk_size();
+                    self.deferred = Some(Box::new(move |w| {
+                        Box::pin(write_input_stream(stream, chunk_size, w))
+                    }));
+                    Ok(())
+                } else if *ty == ResourceType::host::<DynOutputStream>() {
+                    let stream = resource
+                        .try_into_resource::<DynOutputStream>(&mut self.store)
+                        .context("failed to downcast `wasi:io/output-stream`")?;
+                    ensure!(
+                        stream.owned(),
+                        "encoding borrowed `wasi:io/output-stream` not supported"
+                    );
+                    // NOTE: Data written to an output stream flows in the opposite direction of
+                    // the value itself, so nothing is transmitted here and the data received from
+                    // the peer is written to the stream by the caller
+                    let stream = self
+                        .store
+                        .data_mut()
+                        .wrpc()
+                        .table
+                        .delete(stream)
+                        .context("failed to delete output stream")?;
+                    self.output_streams.push((Vec::default(), stream));
+                    Ok(())
+                } else if *ty == ResourceType::host::<LazyRemoteResource>() {
+                    let resource = resource
+                        .try_into_resource::<LazyRemoteResource>(&mut self.store)
+                        .context("resource type mismatch")?;
+                    ensure!(
+                        resource.owned(),
+                        "encoding borrowed lazy remote resources not supported"
+                    );
+                    let LazyRemoteResource(r) = self
+                        .store
+                        .data_mut()
+                        .wrpc()
+                        .table
+                        .delete(resource)
+                        .context("failed to delete lazy remote resource")?;
+                    // the handle is forwarded in the format it was received in
+                    let stream: DynInputStream = Box::new(AsyncReadStream::new(r));
+                    let chunk_size = self.store.data_mut().wrpc().ctx.input_stream_chunk_size();
+                    self.deferred = Some(Box::new(move |w| {
+                        Box::pin(write_input_stream(stream, chunk_size, w))
+                    }));
                     Ok(())
                 } else if resource.ty() == ResourceType::host::<RemoteResource>() {
                     let resource = resource
                         .try_into_resource(&mut self.store)
                         .context("resource type mismatch")?;
-                    let table = self.store.data_mut().wrpc().table;
+                    let WrpcCtxView { ctx, table } = self.store.data_mut().wrpc();
+                    let fixed = ctx.fixed_length_resource_handles();
                     if resource.owned() {
+                        // Ownership is transferred to the peer, any subsequent attempt to
+                        // encode this handle, whether owned or borrowed, fails
                         let RemoteResource(buf) = table
                             .delete(resource)
                             .context("failed to delete remote resource")?;
-                        CoreVecEncoderBytes
-                            .encode(buf, dst)
-                            .context("failed to encode resource handle")
+                        encode_resource_handle(&buf, fixed, dst)
                     } else {
+                        // Borrowed handles are copied without modifying the table, so encoding
+                        // the same handle repeatedly always produces identical bytes
                         let RemoteResource(buf) = table
                             .get(&resource)
-                            .context("failed to get remote resource")?;
-                        CoreVecEncoderBytes
-                            .encode(buf, dst)
-                            .context("failed to encode resource handle")
+                            .context("failed to get borrowed remote resource")?;
+                        encode_resource_handle(buf, fixed, dst)
                     }
                 } else if self.resources.contains(ty) {
-                    let id = Uuid::now_v7();
-                    CoreVecEncoderBytes
-                        .encode(id.to_bytes_le().as_slice(), dst)
-                        .context("failed to encode resource handle")?;
-                    trace!(?id, "store shared resource");
-                    if self
+                    let id = self.store.data_mut().new_resource_id();
+                    let fixed = self
                         .store
                         .data_mut()
                         .wrpc()
                         .ctx
-                        .shared_resources()
-                        .0
-                        .insert(id, *resource)
-                        .is_some()
-                    {
+                        .fixed_length_resource_handles();
+                    encode_resource_handle(id.to_bytes_le().as_slice(), fixed, dst)?;
+                    trace!(?id, "store shared resource");
+                    let ctx = self.store.data_mut().wrpc().ctx;
+                    if ctx.shared_resources().insert(id, *resource).is_some() {
                         error!(?id, "duplicate resource ID generated");
                     }
+                    ctx.on_resource_stored(id);
                     Ok(())
+                } else if let Some(codecs) = self.store.data().host_resource_codecs() {
+                    let Some(encode) = codecs.encoder(ty) else {
+                        bail!("encoding host resources not supported yet")
+                    };
+                    let buf = encode(self.store.as_context_mut(), *resource)?;
+                    CoreVecEncoderBytes
+                        .encode(buf, dst)
+                        .context("failed to encode resource handle")
                 } else {
                     bail!("encoding host resources not supported yet")
                 }
             }
 
-            (_, Type::Future(..) | Type::Stream(..) | Type::ErrorContext) => {
-                bail!("async not supported")
+            (Val::Future(future), Type::Future(ty)) => {
+                let ty = ty
+                    .ty()
+                    .context("`future` values without a payload type not supported")?;
+                let rx = with_payload!(&ty, P => pipe_future::<P, _>(
+                    self.store.as_context_mut(),
+                    future.clone(),
+                    ty.clone(),
+                ))?;
+                // the value is transmitted on the index of the `future` once it is written
+                dst.reserve(1);
+                dst.put_u8(0x00);
+                self.deferred = Some(Box::new(|w| Box::pin(write_future(rx, w))));
+                Ok(())
+            }
+            (Val::Stream(stream), Type::Stream(ty)) => {
+                let ty = ty
+                    .ty()
+                    .context("`stream` values without an element type not supported")?;
+                let rx = with_payload!(&ty, P => pipe_stream::<P, _>(
+                    self.store.as_context_mut(),
+                    stream.clone(),
+                    ty.clone(),
+                ))?;
+                // the elements are transmitted in chunks on the index of the `stream` as they
+                // are written
+                dst.reserve(1);
+                dst.put_u8(0x00);
+                self.deferred = Some(Box::new(|w| Box::pin(write_stream(rx, w))));
+                Ok(())
             }
+            (_, Type::ErrorContext) => bail!(
+                "encoding `error-context` values not supported, \
+                 since their debug message cannot be retrieved from the host"
+            ),
             _ => bail!("value type mismatch"),
         }
     }
@@ -548,8 +1769,59 @@ async fn read_flags(n: usize, r: &mut (impl AsyncRead + Unpin)) -> std::io::Resu
     Ok(u128::from_le_bytes(buf))
 }
 
+/// Reads a variant or enum discriminant using the width rules used by [`ValEncoder`] for a type
+/// with `n` cases
+#[inline]
+async fn read_discriminant(n: usize, r: &mut (impl AsyncRead + Unpin)) -> std::io::Result<usize> {
+    match n {
+        ..=0x0000_00ff => r.read_u8_leb128().await.map(usize::from),
+        0x0000_0100..=0x0000_ffff => r.read_u16_leb128().await.map(usize::from),
+        _ => {
+            let discriminant = r.read_u32_leb128().await?;
+            discriminant
+                .try_into()
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))
+        }
+    }
+}
+
+/// Error returned by [`read_value`], annotated with the path of the value, which failed to decode
+#[derive(Debug)]
+pub struct ValuePathError {
+    pub path: Box<[usize]>,
+    pub error: std::io::Error,
+}
+
+impl fmt::Display for ValuePathError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to decode value at path {:?}: {}",
+            self.path, self.error
+        )
+    }
+}
+
+impl std::error::Error for ValuePathError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
 /// Read encoded value of type [`Type`] from an [`AsyncRead`] into a [`Val`]
-#[instrument(level = "trace", skip_all, fields(ty, path))]
+///
+/// Errors returned by this function wrap a [`ValuePathError`] containing the path of the
+/// (nested) value, which failed to decode.
+///
+/// # Cancellation
+///
+/// This function is not cancellation safe. If the returned future is dropped before
+/// completion, an unspecified amount of bytes has been consumed from `r` and `val` may hold a
+/// partially decoded value. The same applies if decoding fails. In either case `r` must not
+/// be used to decode further values, see [`ValueReader`].
+///
+/// Owned `wasi:io/output-stream` values cannot be decoded by this function, since the data
+/// written to them has to be transmitted back to the peer, see [`ValEncoder::output_streams`].
 pub async fn read_value<T, R>(
     store: &mut impl AsContextMut<Data = T>,
     r: &mut Pin<&mut R>,
@@ -562,84 +1834,234 @@ where
     T: WrpcView + 'static,
     R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
 {
-    match ty {
-        Type::Bool => {
-            let v = r.read_bool().await?;
-            *val = Val::Bool(v);
-            Ok(())
-        }
-        Type::S8 => {
-            let v = r.read_i8().await?;
-            *val = Val::S8(v);
-            Ok(())
-        }
-        Type::U8 => {
-            let v = r.read_u8().await?;
-            *val = Val::U8(v);
-            Ok(())
-        }
-        Type::S16 => {
-            let v = r.read_i16_leb128().await?;
-            *val = Val::S16(v);
-            Ok(())
-        }
-        Type::U16 => {
-            let v = r.read_u16_leb128().await?;
-            *val = Val::U16(v);
-            Ok(())
-        }
-        Type::S32 => {
-            let v = r.read_i32_leb128().await?;
-            *val = Val::S32(v);
-            Ok(())
-        }
-        Type::U32 => {
-            let v = r.read_u32_leb128().await?;
-            *val = Val::U32(v);
-            Ok(())
-        }
-        Type::S64 => {
-            let v = r.read_i64_leb128().await?;
-            *val = Val::S64(v);
-            Ok(())
-        }
-        Type::U64 => {
-            let v = r.read_u64_leb128().await?;
-            *val = Val::U64(v);
-            Ok(())
-        }
-        Type::Float32 => {
-            let v = r.read_f32_le().await?;
-            *val = Val::Float32(v);
-            Ok(())
-        }
-        Type::Float64 => {
-            let v = r.read_f64_le().await?;
-            *val = Val::Float64(v);
-            Ok(())
-        }
-        Type::Char => {
-            let v = r.read_char_utf8().await?;
-            *val = Val::Char(v);
-            Ok(())
-        }
-        Type::String => {
-            let mut s = String::default();
-            r.read_core_name(&mut s).await?;
-            *val = Val::String(s);
-            Ok(())
-        }
-        Type::List(ty) => {
-            let n = r.read_u32_leb128().await?;
-            let n = n.try_into().unwrap_or(usize::MAX);
-            let mut vs = Vec::with_capacity(n);
+    read_value_inner(store, r, resources, val, ty, path, None).await
+}
+
+/// Reads a value of type [`Type`] into a [`Val`] like [`read_value`], decoding owned
+/// `wasi:io/output-stream` values as pipes. The read ends of the pipes are appended to
+/// `output_streams` along with the absolute path of the corresponding value and the caller is
+/// responsible for transmitting the data read from them to the peer.
+pub(crate) async fn read_value_with_output_streams<T, R>(
+    store: &mut impl AsContextMut<Data = T>,
+    r: &mut Pin<&mut R>,
+    resources: &[ResourceType],
+    val: &mut Val,
+    ty: &Type,
+    path: &[usize],
+    output_streams: &mut Vec<(Vec<usize>, DynInputStream)>,
+) -> std::io::Result<()>
+where
+    T: WrpcView + 'static,
+    R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+{
+    read_value_inner(store, r, resources, val, ty, path, Some(output_streams)).await
+}
+
+#[instrument(level = "trace", skip_all, fields(ty, path))]
+async fn read_value_inner<T, R>(
+    store: &mut impl AsContextMut<Data = T>,
+    r: &mut Pin<&mut R>,
+    resources: &[ResourceType],
+    val: &mut Val,
+    ty: &Type,
+    path: &[usize],
+    output_streams: Option<&mut Vec<(Vec<usize>, DynInputStream)>>,
+) -> std::io::Result<()>
+where
+    T: WrpcView + 'static,
+    R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+{
+    decode_value(store, r, resources, val, ty, path, output_streams)
+        .await
+        .map_err(|error| {
+            if error
+                .get_ref()
+                .is_some_and(|error| error.is::<ValuePathError>())
+            {
+                error
+            } else {
+                std::io::Error::new(
+                    error.kind(),
+                    ValuePathError {
+                        path: path.into(),
+                        error,
+                    },
+                )
+            }
+        })
+}
+
+/// [`AsyncRead`] wrapper decoding a sequence of values using [`read_value`], which is poisoned
+/// if decoding a value does not complete.
+///
+/// [`read_value`] is not cancellation safe, so once a decode is cancelled or fails, the
+/// position of the underlying stream is unknown and any data read from it afterwards would be
+/// misinterpreted. Once poisoned, all subsequent reads and decodes fail instead.
+pub struct ValueReader<R> {
+    inner: R,
+    poisoned: bool,
+}
+
+impl<R> ValueReader<R> {
+    /// Wraps `inner`
+    #[must_use]
+    pub fn new(inner: R) -> Self {
+        Self {
+            inner,
+            poisoned: false,
+        }
+    }
+
+    /// Returns `true` if a previous decode was cancelled or failed
+    #[must_use]
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// Returns the underlying reader
+    #[must_use]
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads a value of type [`Type`] into a [`Val`] using [`read_value`].
+    ///
+    /// If the returned future is dropped before completion or fails, the reader is poisoned.
+    pub async fn read_value<T>(
+        &mut self,
+        store: &mut impl AsContextMut<Data = T>,
+        resources: &[ResourceType],
+        val: &mut Val,
+        ty: &Type,
+        path: &[usize],
+    ) -> std::io::Result<()>
+    where
+        T: WrpcView + 'static,
+        R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+    {
+        if self.poisoned {
+            return Err(poisoned_error());
+        }
+        // cleared only once the value is fully decoded, so that a decode future dropped at any
+        // `.await` point leaves the reader poisoned
+        self.poisoned = true;
+        read_value(
+            store,
+            &mut Pin::new(&mut self.inner),
+            resources,
+            val,
+            ty,
+            path,
+        )
+        .await?;
+        self.poisoned = false;
+        Ok(())
+    }
+}
+
+fn poisoned_error() -> std::io::Error {
+    std::io::Error::other("reader poisoned by a cancelled or failed value decode")
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for ValueReader<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut core::task::Context<'_>,
+        buf: &mut tokio::io::ReadBuf<'_>,
+    ) -> core::task::Poll<std::io::Result<()>> {
+        if self.poisoned {
+            return core::task::Poll::Ready(Err(poisoned_error()));
+        }
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
+
+/// Reads a value of primitive type `ty`, i.e. a boolean, number, character or string, which
+/// does not require a store to be decoded.
+async fn read_primitive(r: &mut (impl AsyncRead + Unpin), ty: &Type) -> std::io::Result<Val> {
+    match ty {
+        Type::Bool => r.read_bool().await.map(Val::Bool),
+        Type::S8 => r.read_i8().await.map(Val::S8),
+        Type::U8 => r.read_u8().await.map(Val::U8),
+        Type::S16 => read_sleb128(r, i16::BITS).await.map(Val::S16),
+        Type::U16 => read_uleb128(r, u16::BITS).await.map(Val::U16),
+        Type::S32 => read_sleb128(r, i32::BITS).await.map(Val::S32),
+        Type::U32 => read_uleb128(r, u32::BITS).await.map(Val::U32),
+        Type::S64 => read_sleb128(r, i64::BITS).await.map(Val::S64),
+        Type::U64 => read_uleb128(r, u64::BITS).await.map(Val::U64),
+        Type::Float32 => r.read_f32_le().await.map(Val::Float32),
+        Type::Float64 => r.read_f64_le().await.map(Val::Float64),
+        Type::Char => r.read_char_utf8().await.map(Val::Char),
+        Type::String => {
+            let mut s = String::default();
+            r.read_core_name(&mut s).await?;
+            Ok(Val::String(s))
+        }
+        _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{ty:?} is not a primitive type"),
+        )),
+    }
+}
+
+async fn decode_value<T, R>(
+    store: &mut impl AsContextMut<Data = T>,
+    r: &mut Pin<&mut R>,
+    resources: &[ResourceType],
+    val: &mut Val,
+    ty: &Type,
+    path: &[usize],
+    mut output_streams: Option<&mut Vec<(Vec<usize>, DynInputStream)>>,
+) -> std::io::Result<()>
+where
+    T: WrpcView + 'static,
+    R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+{
+    match ty {
+        Type::Bool
+        | Type::S8
+        | Type::U8
+        | Type::S16
+        | Type::U16
+        | Type::S32
+        | Type::U32
+        | Type::S64
+        | Type::U64
+        | Type::Float32
+        | Type::Float64
+        | Type::Char
+        | Type::String => {
+            *val = read_primitive(r, ty).await?;
+            Ok(())
+        }
+        Type::List(ty) => {
+            let len = r.read_u32_leb128().await?;
+            let n = len.try_into().unwrap_or(usize::MAX);
             let ty = ty.ty();
+            if let Type::U8 = ty {
+                let mut buf = Vec::with_capacity(n.min(MAX_LIST_PREALLOC));
+                if r.as_mut().take(len.into()).read_to_end(&mut buf).await? != n {
+                    return Err(std::io::ErrorKind::UnexpectedEof.into());
+                }
+                *val = Val::List(buf.into_iter().map(Val::U8).collect());
+                return Ok(());
+            }
+            let mut vs = Vec::with_capacity(n.min(MAX_LIST_PREALLOC));
             let mut path = path.to_vec();
             for i in 0..n {
                 let mut v = Val::Bool(false);
                 path.push(i);
                 trace!(i, "reading list element value");
-                Box::pin(read_value(store, r, resources, &mut v, &ty, &path)).await?;
+                Box::pin(read_value_inner(
+                    store,
+                    r,
+                    resources,
+                    &mut v,
+                    &ty,
+                    &path,
+                    output_streams.as_deref_mut(),
+                ))
+                .await?;
                 path.pop();
                 vs.push(v);
             }
@@ -654,7 +2076,16 @@ where
                 let mut v = Val::Bool(false);
                 path.push(i);
                 trace!(i, "reading struct field value");
-                Box::pin(read_value(store, r, resources, &mut v, &ty, &path)).await?;
+                Box::pin(read_value_inner(
+                    store,
+                    r,
+                    resources,
+                    &mut v,
+                    &ty,
+                    &path,
+                    output_streams.as_deref_mut(),
+                ))
+                .await?;
                 path.pop();
                 vs.push((name.to_string(), v));
             }
@@ -669,7 +2100,16 @@ where
                 let mut v = Val::Bool(false);
                 path.push(i);
                 trace!(i, "reading tuple element value");
-                Box::pin(read_value(store, r, resources, &mut v, &ty, &path)).await?;
+                Box::pin(read_value_inner(
+                    store,
+                    r,
+                    resources,
+                    &mut v,
+                    &ty,
+                    &path,
+                    output_streams.as_deref_mut(),
+                ))
+                .await?;
                 path.pop();
                 vs.push(v);
             }
@@ -677,11 +2117,9 @@ where
             Ok(())
         }
         Type::Variant(ty) => {
-            let discriminant = r.read_u32_leb128().await?;
-            let discriminant = discriminant
-                .try_into()
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
-            let Case { name, ty } = ty.cases().nth(discriminant).ok_or_else(|| {
+            let mut cases = ty.cases();
+            let discriminant = read_discriminant(cases.len(), r).await?;
+            let Case { name, ty } = cases.nth(discriminant).ok_or_else(|| {
                 std::io::Error::new(
                     std::io::ErrorKind::InvalidInput,
                     format!("unknown variant discriminant `{discriminant}`"),
@@ -691,7 +2129,16 @@ where
             if let Some(ty) = ty {
                 let mut v = Val::Bool(false);
                 trace!(variant = name, "reading nested variant value");
-                Box::pin(read_value(store, r, resources, &mut v, &ty, path)).await?;
+                Box::pin(read_value_inner(
+                    store,
+                    r,
+                    resources,
+                    &mut v,
+                    &ty,
+                    path,
+                    output_streams.as_deref_mut(),
+                ))
+                .await?;
                 *val = Val::Variant(name, Some(Box::new(v)));
             } else {
                 *val = Val::Variant(name, None);
@@ -699,11 +2146,9 @@ where
             Ok(())
         }
         Type::Enum(ty) => {
-            let discriminant = r.read_u32_leb128().await?;
-            let discriminant = discriminant
-                .try_into()
-                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
-            let name = ty.names().nth(discriminant).ok_or_else(|| {
+            let mut names = ty.names();
+            let discriminant = read_discriminant(names.len(), r).await?;
+            let name = names.nth(discriminant).ok_or_else(|| {
                 std::io::Error::new(
                     std::io::ErrorKind::InvalidInput,
                     format!("unknown enum discriminant `{discriminant}`"),
@@ -717,7 +2162,16 @@ where
             if ok {
                 let mut v = Val::Bool(false);
                 trace!("reading nested `option::some` value");
-                Box::pin(read_value(store, r, resources, &mut v, &ty.ty(), path)).await?;
+                Box::pin(read_value_inner(
+                    store,
+                    r,
+                    resources,
+                    &mut v,
+                    &ty.ty(),
+                    path,
+                    output_streams.as_deref_mut(),
+                ))
+                .await?;
                 *val = Val::Option(Some(Box::new(v)));
             } else {
                 *val = Val::Option(None);
@@ -730,7 +2184,16 @@ where
                 if let Some(ty) = ty.ok() {
                     let mut v = Val::Bool(false);
                     trace!("reading nested `result::ok` value");
-                    Box::pin(read_value(store, r, resources, &mut v, &ty, path)).await?;
+                    Box::pin(read_value_inner(
+                        store,
+                        r,
+                        resources,
+                        &mut v,
+                        &ty,
+                        path,
+                        output_streams.as_deref_mut(),
+                    ))
+                    .await?;
                     *val = Val::Result(Ok(Some(Box::new(v))));
                 } else {
                     *val = Val::Result(Ok(None));
@@ -738,7 +2201,16 @@ where
             } else if let Some(ty) = ty.err() {
                 let mut v = Val::Bool(false);
                 trace!("reading nested `result::err` value");
-                Box::pin(read_value(store, r, resources, &mut v, &ty, path)).await?;
+                Box::pin(read_value_inner(
+                    store,
+                    r,
+                    resources,
+                    &mut v,
+                    &ty,
+                    path,
+                    output_streams.as_deref_mut(),
+                ))
+                .await?;
                 *val = Val::Result(Err(Some(Box::new(v))));
             } else {
                 *val = Val::Result(Err(None));
@@ -764,26 +2236,10 @@ where
                 105..=112 => read_flags(14, r).await?,
                 113..=120 => read_flags(15, r).await?,
                 121..=128 => r.read_u128_le().await?,
-                bits @ 129.. => {
-                    let mut cap = bits / 8;
-                    if bits % 8 != 0 {
-                        cap = cap.saturating_add(1);
-                    }
-                    let mut buf = vec![0; cap];
+                n @ 129.. => {
+                    let mut buf = vec![0; flag_byte_count(n)];
                     r.read_exact(&mut buf).await?;
-                    let mut vs = Vec::with_capacity(
-                        buf.iter()
-                            .map(|b| b.count_ones())
-                            .sum::<u32>()
-                            .try_into()
-                            .unwrap_or(usize::MAX),
-                    );
-                    for (i, name) in names.enumerate() {
-                        if buf[i / 8] & (1 << (i % 8)) != 0 {
-                            vs.push(name.to_string());
-                        }
-                    }
-                    *val = Val::Flags(vs);
+                    *val = Val::Flags(flag_names(names, &buf)?);
                     return Ok(());
                 }
             };
@@ -796,85 +2252,1936 @@ where
             *val = Val::Flags(vs);
             Ok(())
         }
-        Type::Own(ty) | Type::Borrow(ty) => {
-            if *ty == ResourceType::host::<DynInputStream>() {
-                let mut store = store.as_context_mut();
-                let r = r.index(path).map_err(std::io::Error::other)?;
-                // TODO: Implement a custom reader, this approach ignores the stream end (`\0`),
-                // which will could potentially break/hang with some transports
-                let res = store
-                    .data_mut()
-                    .wrpc()
-                    .table
-                    .push(Box::new(AsyncReadStream::new(
-                        FramedRead::new(r, ListDecoderU8::default())
-                            .into_async_read()
-                            .compat(),
-                    )))
-                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))?;
-                let v = res
-                    .try_into_resource_any(store)
-                    .map_err(std::io::Error::other)?;
-                *val = Val::Resource(v);
-                Ok(())
-            } else if resources.contains(ty) {
-                let mut store = store.as_context_mut();
-                let mut id = uuid::Bytes::default();
-                debug_assert_eq!(id.len(), 16);
-                let n = r.read_u8_leb128().await?;
-                if usize::from(n) != id.len() {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidInput,
-                        format!(
-                            "invalid guest resource handle length {n}, expected {}",
-                            id.len()
-                        ),
-                    ));
-                }
-                let n = r.read_exact(&mut id).await?;
-                if n != id.len() {
-                    return Err(std::io::Error::new(
-                        std::io::ErrorKind::InvalidInput,
-                        format!(
-                            "invalid amount of guest resource handle bytes read {n}, expected {}",
-                            id.len()
-                        ),
-                    ));
+        Type::Own(ty) => {
+            decode_resource(store, r, resources, val, ty, true, path, output_streams).await
+        }
+        Type::Borrow(ty) => {
+            decode_resource(store, r, resources, val, ty, false, path, output_streams).await
+        }
+        Type::Future(ty) => {
+            let ty = ty.ty().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "`future` values without a payload type not supported",
+                )
+            })?;
+            *val = with_payload!(&ty, P => {
+                read_future::<P, _, _>(store.as_context_mut(), r, &ty, path).await
+            })?;
+            Ok(())
+        }
+        Type::Stream(ty) => {
+            let ty = ty.ty().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "`stream` values without an element type not supported",
+                )
+            })?;
+            *val = with_payload!(&ty, P => {
+                read_stream::<P, _, _>(store.as_context_mut(), r, &ty, path).await
+            })?;
+            Ok(())
+        }
+        // NOTE: `error-context` values cannot be constructed by the host, the debug message
+        // would have to be passed to the guest using the component-model async ABI
+        Type::ErrorContext => Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "decoding `error-context` values not supported, \
+             since they cannot be constructed by the host",
+        )),
+    }
+}
+
+/// Returns an [`AsyncRead`] yielding the data of the length-prefixed chunks received on `r`.
+/// The sender terminates the data with an empty chunk, reading stops there rather than waiting
+/// for the transport to close.
+fn read_chunks<R>(r: R) -> impl AsyncRead + Send + Unpin + 'static
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    FramedRead::new(r, ListDecoderU8::default())
+        .take_while(|chunk| future::ready(!matches!(chunk, Ok(chunk) if chunk.is_empty())))
+        .into_async_read()
+        .compat()
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn decode_resource<T, R>(
+    store: &mut impl AsContextMut<Data = T>,
+    r: &mut Pin<&mut R>,
+    resources: &[ResourceType],
+    val: &mut Val,
+    ty: &ResourceType,
+    owned: bool,
+    path: &[usize],
+    output_streams: Option<&mut Vec<(Vec<usize>, DynInputStream)>>,
+) -> std::io::Result<()>
+where
+    T: WrpcView + 'static,
+    R: AsyncRead + wrpc_transport::Index<R> + Send + Unpin + 'static,
+{
+    if *ty == ResourceType::host::<DynInputStream>() {
+        let mut store = store.as_context_mut();
+        let r = r.index(path).map_err(std::io::Error::other)?;
+        let res = store
+            .data_mut()
+            .wrpc()
+            .table
+            .push(Box::new(AsyncReadStream::new(read_chunks(r))) as DynInputStream)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))?;
+        let v = res
+            .try_into_resource_any(store)
+            .map_err(std::io::Error::other)?;
+        *val = Val::Resource(v);
+        Ok(())
+    } else if *ty == ResourceType::host::<LazyRemoteResource>() {
+        let mut store = store.as_context_mut();
+        let r = r.index(path).map_err(std::io::Error::other)?;
+        let res = store
+            .data_mut()
+            .wrpc()
+            .table
+            .push(LazyRemoteResource(Box::pin(read_chunks(r))))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))?;
+        let v = res
+            .try_into_resource_any(store)
+            .map_err(std::io::Error::other)?;
+        *val = Val::Resource(v);
+        Ok(())
+    } else if *ty == ResourceType::host::<DynOutputStream>() {
+        let Some(output_streams) = output_streams.filter(|_| owned) else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "decoding `wasi:io/output-stream` only supported for owned parameters",
+            ));
+        };
+        let mut store = store.as_context_mut();
+        let (rx, tx) = tokio::io::duplex(OUTPUT_STREAM_BUFFER_SIZE);
+        let stream: DynOutputStream =
+            Box::new(AsyncWriteStream::new(OUTPUT_STREAM_BUFFER_SIZE, tx));
+        let res = store
+            .data_mut()
+            .wrpc()
+            .table
+            .push(stream)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))?;
+        let v = res
+            .try_into_resource_any(store)
+            .map_err(std::io::Error::other)?;
+        output_streams.push((path.to_vec(), Box::new(AsyncReadStream::new(rx))));
+        *val = Val::Resource(v);
+        Ok(())
+    } else if resources.contains(ty) {
+        let mut store = store.as_context_mut();
+        let mut id = uuid::Bytes::default();
+        debug_assert_eq!(id.len(), usize::from(FIXED_RESOURCE_HANDLE_LEN));
+        let n = if store.data_mut().wrpc().ctx.fixed_length_resource_handles() {
+            FIXED_RESOURCE_HANDLE_LEN
+        } else {
+            r.read_u8_leb128().await?
+        };
+        if usize::from(n) != id.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid guest resource handle length {n}, expected {}",
+                    id.len()
+                ),
+            ));
+        }
+        let n = r.read_exact(&mut id).await?;
+        if n != id.len() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!(
+                    "invalid amount of guest resource handle bytes read {n}, expected {}",
+                    id.len()
+                ),
+            ));
+        }
+
+        let id = Uuid::from_bytes_le(id);
+        trace!(?id, "lookup shared resource");
+        let ctx = store.data_mut().wrpc().ctx;
+        let shared = ctx.shared_resources();
+        // ownership of an owned handle is transferred to the guest, so it must not be
+        // looked up again
+        let resource = if owned {
+            shared.remove(&id)
+        } else {
+            shared.get(&id).copied()
+        }
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+        ctx.on_resource_retrieved(id);
+        *val = Val::Resource(resource);
+        Ok(())
+    } else {
+        let mut store = store.as_context_mut();
+        let codecs = store.data().host_resource_codecs();
+        let decode = codecs.as_ref().and_then(|codecs| codecs.decoder(ty));
+        // host resource handles are opaque and always length-prefixed
+        let n = if decode.is_none() && store.data_mut().wrpc().ctx.fixed_length_resource_handles() {
+            FIXED_RESOURCE_HANDLE_LEN.into()
+        } else {
+            r.read_u32_leb128().await?
+        };
+        let k = usize::try_from(n)
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+        let mut buf = Vec::with_capacity(k.min(MAX_LIST_PREALLOC));
+        if r.as_mut().take(n.into()).read_to_end(&mut buf).await? != k {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::UnexpectedEof,
+                "remote resource handle truncated",
+            ));
+        }
+        if let Some(decode) = decode {
+            let resource = decode(store, buf.into()).map_err(std::io::Error::other)?;
+            *val = Val::Resource(resource);
+            return Ok(());
+        }
+        let table = store.data_mut().wrpc().table;
+        let resource = table
+            .push(RemoteResource(buf.into()))
+            .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))?;
+        let resource = resource
+            .try_into_resource_any(store)
+            .map_err(std::io::Error::other)?;
+        *val = Val::Resource(resource);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::task::{Context, Poll};
+    use core::time::Duration;
+
+    use std::io::Cursor;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    use tokio::io::{empty, sink, Empty, ReadBuf, Sink};
+    use wasmtime::component::{types, Component, Linker, Resource};
+    use wasmtime::{Engine, Store};
+    use wasmtime_wasi::p2::pipe::MemoryInputPipe;
+    use wasmtime_wasi::p2::{InputStream, Pollable, StreamResult};
+    use wrpc_transport::frame::{memory, Oneshot, Outgoing, Server};
+    use wrpc_transport::{Index as _, Invoke as _, Serve as _};
+
+    use super::*;
+    use crate::test_util;
+
+    type Client = Oneshot<Empty, Sink>;
+
+    type TestCtx = test_util::TestCtx<Client>;
+
+    struct TestReader(Cursor<Vec<u8>>);
+
+    impl AsyncRead for TestReader {
+        fn poll_read(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_read(cx, buf)
+        }
+    }
+
+    impl wrpc_transport::Index<Self> for TestReader {
+        fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+            panic!("index should not be called with path {path:?}")
+        }
+    }
+
+    struct TestWriter(Sink);
+
+    impl AsyncWrite for TestWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_shutdown(cx)
+        }
+    }
+
+    impl wrpc_transport::Index<Self> for TestWriter {
+        fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+            panic!("index should not be called with path {path:?}")
+        }
+    }
+
+    /// Number of concurrently active and maximum number of ever concurrently active writers
+    #[derive(Default)]
+    struct WriterCount {
+        active: AtomicUsize,
+        max: AtomicUsize,
+    }
+
+    /// Writer, which tracks the number of indexed writers alive in [WriterCount] and
+    /// yields once before accepting any writes
+    struct CountingWriter {
+        count: Arc<WriterCount>,
+        indexed: bool,
+        yielded: bool,
+    }
+
+    impl CountingWriter {
+        fn new(count: Arc<WriterCount>) -> Self {
+            Self {
+                count,
+                indexed: false,
+                yielded: false,
+            }
+        }
+    }
+
+    impl Drop for CountingWriter {
+        fn drop(&mut self) {
+            if self.indexed {
+                self.count.active.fetch_sub(1, Ordering::Relaxed);
+            }
+        }
+    }
+
+    impl AsyncWrite for CountingWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            if !self.yielded {
+                self.yielded = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            Poll::Ready(Ok(buf.len()))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    impl wrpc_transport::Index<Self> for CountingWriter {
+        fn index(&self, _: &[usize]) -> anyhow::Result<Self> {
+            let active = self.count.active.fetch_add(1, Ordering::Relaxed) + 1;
+            self.count.max.fetch_max(active, Ordering::Relaxed);
+            Ok(Self {
+                count: Arc::clone(&self.count),
+                indexed: true,
+                yielded: false,
+            })
+        }
+    }
+
+    fn new_store() -> (Engine, Store<TestCtx>) {
+        new_store_with(Engine::default())
+    }
+
+    fn new_store_with(engine: Engine) -> (Engine, Store<TestCtx>) {
+        let store = Store::new(&engine, test_util::new_ctx((empty(), sink()).into()));
+        (engine, store)
+    }
+
+    /// Returns a store supporting `future` and `stream` values
+    fn new_async_store() -> anyhow::Result<(Engine, Store<TestCtx>)> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        config.wasm_component_model_async(true);
+        Ok(new_store_with(Engine::new(&config)?))
+    }
+
+    /// Returns the parameter types of a function imported as `f` by `wat`
+    fn param_types(engine: &Engine, wat: &str) -> anyhow::Result<Vec<Type>> {
+        let component = Component::new(engine, wat)?;
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) = component
+            .component_type()
+            .imports(engine)
+            .find(|(name, _)| *name == "f")
+        else {
+            bail!("component does not import function `f`");
+        };
+        Ok(ty.params().map(|(_, ty)| ty).collect())
+    }
+
+    /// Returns the type of the first parameter of a function imported as `f` by `wat`
+    fn param_type(engine: &Engine, wat: &str) -> anyhow::Result<Type> {
+        param_types(engine, wat)?
+            .into_iter()
+            .next()
+            .context("function has no parameters")
+    }
+
+    fn encode(store: &mut Store<TestCtx>, ty: &Type, v: &Val) -> anyhow::Result<BytesMut> {
+        let mut buf = BytesMut::default();
+        let mut enc = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), ty, &[]);
+        enc.encode(v, &mut buf)?;
+        ensure!(
+            enc.deferred.is_none(),
+            "value encoding should not be deferred"
+        );
+        Ok(buf)
+    }
+
+    async fn decode(
+        store: &mut Store<TestCtx>,
+        ty: &Type,
+        buf: impl Into<Vec<u8>>,
+    ) -> std::io::Result<Val> {
+        let mut r = pin!(TestReader(Cursor::new(buf.into())));
+        let mut v = Val::Bool(false);
+        read_value(store, &mut r, &[], &mut v, ty, &[]).await?;
+        Ok(v)
+    }
+
+    /// Encodes `v` of type `ty`, decodes it back and asserts that the decoded value equals `v`
+    async fn assert_round_trip(
+        store: &mut Store<TestCtx>,
+        ty: &Type,
+        v: &Val,
+    ) -> anyhow::Result<()> {
+        let buf = encode(store, ty, v).with_context(|| format!("failed to encode {v:?}"))?;
+        let decoded = decode(store, ty, buf)
+            .await
+            .with_context(|| format!("failed to decode {v:?}"))?;
+        assert_eq!(decoded, *v);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn error_context_unsupported() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model_error_context(true);
+        let (engine, mut store) = new_store_with(Engine::new(&config)?);
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "f" (func (param "e" error-context)))
+            )"#,
+        )?;
+        assert_eq!(ty, Type::ErrorContext);
+
+        // `error-context` values cannot be constructed by the host, so any value is used to
+        // exercise the encoder
+        let err = encode(&mut store, &ty, &Val::Bool(false)).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "encoding `error-context` values not supported, since their debug message cannot be retrieved from the host"
+        );
+        let err = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), &ty, &[])
+            .encoded_len(&Val::Bool(false))
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .starts_with("encoding `error-context` values not supported"),
+            "unexpected error: {err:#}"
+        );
+
+        let err = decode(&mut store, &ty, b"\x04test".as_slice())
+            .await
+            .unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::Unsupported);
+        assert_eq!(
+            err.to_string(),
+            "decoding `error-context` values not supported, since they cannot be constructed by the host"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_primitives() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (import "f" (func
+                    (param "a" bool)
+                    (param "b" s8)
+                    (param "c" u8)
+                    (param "d" s16)
+                    (param "e" u16)
+                    (param "f" s32)
+                    (param "g" u32)
+                    (param "h" s64)
+                    (param "i" u64)
+                    (param "j" f32)
+                    (param "k" f64)
+                    (param "l" char)
+                    (param "m" string)
+                ))
+            )"#,
+        )?;
+        let cases: [&[Val]; 13] = [
+            &[Val::Bool(false), Val::Bool(true)],
+            &[Val::S8(i8::MIN), Val::S8(-1), Val::S8(0), Val::S8(i8::MAX)],
+            &[Val::U8(0), Val::U8(0x80), Val::U8(u8::MAX)],
+            &[
+                Val::S16(i16::MIN),
+                Val::S16(-0x40),
+                Val::S16(0x40),
+                Val::S16(i16::MAX),
+            ],
+            &[
+                Val::U16(0),
+                Val::U16(0x7f),
+                Val::U16(0x80),
+                Val::U16(u16::MAX),
+            ],
+            &[
+                Val::S32(i32::MIN),
+                Val::S32(-1),
+                Val::S32(0),
+                Val::S32(i32::MAX),
+            ],
+            &[
+                Val::U32(0),
+                Val::U32(0x3fff),
+                Val::U32(0x4000),
+                Val::U32(u32::MAX),
+            ],
+            &[
+                Val::S64(i64::MIN),
+                Val::S64(-1),
+                Val::S64(0),
+                Val::S64(i64::MAX),
+            ],
+            &[
+                Val::U64(0),
+                Val::U64(u64::from(u32::MAX) + 1),
+                Val::U64(u64::MAX),
+            ],
+            &[
+                Val::Float32(0.0),
+                Val::Float32(-1.5),
+                Val::Float32(f32::MAX),
+                Val::Float32(f32::INFINITY),
+            ],
+            &[
+                Val::Float64(0.0),
+                Val::Float64(-1.5),
+                Val::Float64(f64::MIN_POSITIVE),
+                Val::Float64(f64::NEG_INFINITY),
+            ],
+            &[
+                Val::Char('a'),
+                Val::Char('\u{7ff}'),
+                Val::Char('\u{10ffff}'),
+            ],
+            &[Val::String(String::new()), Val::String("test ✓".into())],
+        ];
+        for (ty, vs) in zip(&tys, cases) {
+            for v in vs {
+                assert_round_trip(&mut store, ty, v).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn leb128_bounds() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (import "f" (func
+                    (param "a" s16)
+                    (param "b" u16)
+                    (param "c" s32)
+                    (param "d" u32)
+                    (param "e" s64)
+                    (param "f" u64)
+                ))
+            )"#,
+        )?;
+        let [s16_ty, u16_ty, s32_ty, u32_ty, s64_ty, u64_ty] = tys.as_slice() else {
+            bail!("unexpected parameter types {tys:?}");
+        };
+        for (ty, v, buf) in [
+            (s16_ty, Val::S16(i16::MIN), &b"\x80\x80\x7e"[..]),
+            (s16_ty, Val::S16(i16::MAX), b"\xff\xff\x01"),
+            (u16_ty, Val::U16(u16::MAX), b"\xff\xff\x03"),
+            (s32_ty, Val::S32(i32::MIN), b"\x80\x80\x80\x80\x78"),
+            (u32_ty, Val::U32(u32::MAX), b"\xff\xff\xff\xff\x0f"),
+            (
+                s64_ty,
+                Val::S64(i64::MIN),
+                b"\x80\x80\x80\x80\x80\x80\x80\x80\x80\x7f",
+            ),
+            (
+                u64_ty,
+                Val::U64(u64::MAX),
+                b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x01",
+            ),
+        ] {
+            assert_eq!(encode(&mut store, ty, &v)?, buf);
+            assert_eq!(decode(&mut store, ty, buf).await?, v);
+        }
+
+        // non-minimal encodings within the byte budget of the target width are accepted
+        assert_eq!(
+            decode(&mut store, u32_ty, b"\x80\x80\x80\x80\x00").await?,
+            Val::U32(0)
+        );
+        assert_eq!(
+            decode(&mut store, s16_ty, b"\xff\xff\x7f").await?,
+            Val::S16(-1)
+        );
+
+        for (ty, buf) in [
+            // over-long sequences
+            (s16_ty, &b"\x80\x80\x80\x00"[..]),
+            (u32_ty, b"\x80\x80\x80\x80\x80\x00"),
+            (u64_ty, b"\x80\x80\x80\x80\x80\x80\x80\x80\x80\x80\x00"),
+            // bits beyond the target width
+            (s16_ty, b"\x80\x80\x02"),
+            (s16_ty, b"\x80\x80\x7d"),
+            (u16_ty, b"\x80\x80\x04"),
+            (s32_ty, b"\xff\xff\xff\xff\x08"),
+            (u32_ty, b"\xff\xff\xff\xff\x1f"),
+            (s64_ty, b"\x80\x80\x80\x80\x80\x80\x80\x80\x80\x7e"),
+            (u64_ty, b"\xff\xff\xff\xff\xff\xff\xff\xff\xff\x02"),
+        ] {
+            let err = decode(&mut store, ty, buf)
+                .await
+                .expect_err("decoding out-of-range LEB128 integer should fail");
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData, "{buf:02x?}");
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn float_bytes() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component (import "f" (func (param "a" f32) (param "b" f64))))"#,
+        )?;
+        let [f32_ty, f64_ty] = tys.as_slice() else {
+            bail!("unexpected parameter types {tys:?}");
+        };
+        for bits in [
+            0x0000_0000,
+            0x8000_0000,
+            0x3f80_0000, // 1.0
+            0x0000_0001, // smallest subnormal
+            0x807f_ffff, // largest negative subnormal
+            0x7f80_0000, // infinity
+            0xff80_0000, // negative infinity
+            0x7fc0_1234, // quiet NaN with payload
+            0xff80_0001, // negative signaling NaN
+        ] {
+            let v = Val::Float32(f32::from_bits(bits));
+            let buf = encode(&mut store, f32_ty, &v)?;
+            assert_eq!(buf, bits.to_le_bytes()[..], "{bits:#010x}");
+            let Val::Float32(v) = decode(&mut store, f32_ty, buf).await? else {
+                bail!("decoded value is not an f32");
+            };
+            assert_eq!(v.to_bits(), bits, "{bits:#010x}");
+        }
+        for bits in [
+            0x0000_0000_0000_0000,
+            0x8000_0000_0000_0000,
+            0x3ff0_0000_0000_0000, // 1.0
+            0x0000_0000_0000_0001, // smallest subnormal
+            0x800f_ffff_ffff_ffff, // largest negative subnormal
+            0x7ff0_0000_0000_0000, // infinity
+            0xfff0_0000_0000_0000, // negative infinity
+            0x7ff8_0000_dead_beef, // quiet NaN with payload
+            0xfff0_0000_0000_0001, // negative signaling NaN
+        ] {
+            let v = Val::Float64(f64::from_bits(bits));
+            let buf = encode(&mut store, f64_ty, &v)?;
+            assert_eq!(buf, bits.to_le_bytes()[..], "{bits:#018x}");
+            let Val::Float64(v) = decode(&mut store, f64_ty, buf).await? else {
+                bail!("decoded value is not an f64");
+            };
+            assert_eq!(v.to_bits(), bits, "{bits:#018x}");
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_compound() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (type $r' (record (field "a" u8) (field "b" string)))
+                (import "r" (type $r (eq $r')))
+                (type $v' (variant (case "a") (case "b" $r) (case "c" (list u32))))
+                (import "v" (type $v (eq $v')))
+                (type $e' (enum "x" "y" "z"))
+                (import "e" (type $e (eq $e')))
+                (import "f" (func
+                    (param "a" (list $r))
+                    (param "b" (tuple u32 (option string) (list u8)))
+                    (param "c" $v)
+                    (param "d" $e)
+                    (param "e" (option (option u64)))
+                    (param "f" (result u8 (error string)))
+                    (param "g" (result))
+                    (param "h" (list (list s16)))
+                ))
+            )"#,
+        )?;
+        let record = |a, b: &str| {
+            Val::Record(vec![
+                ("a".into(), Val::U8(a)),
+                ("b".into(), Val::String(b.into())),
+            ])
+        };
+        let cases: [&[Val]; 8] = [
+            &[
+                Val::List(vec![]),
+                Val::List(vec![record(0, ""), record(0xff, "foo")]),
+            ],
+            &[
+                Val::Tuple(vec![Val::U32(42), Val::Option(None), Val::List(vec![])]),
+                Val::Tuple(vec![
+                    Val::U32(u32::MAX),
+                    Val::Option(Some(Box::new(Val::String("bar".into())))),
+                    Val::List(vec![Val::U8(1), Val::U8(2), Val::U8(3)]),
+                ]),
+            ],
+            &[
+                Val::Variant("a".into(), None),
+                Val::Variant("b".into(), Some(Box::new(record(1, "baz")))),
+                Val::Variant(
+                    "c".into(),
+                    Some(Box::new(Val::List(vec![Val::U32(0), Val::U32(1)]))),
+                ),
+            ],
+            &[
+                Val::Enum("x".into()),
+                Val::Enum("y".into()),
+                Val::Enum("z".into()),
+            ],
+            &[
+                Val::Option(None),
+                Val::Option(Some(Box::new(Val::Option(None)))),
+                Val::Option(Some(Box::new(Val::Option(Some(Box::new(Val::U64(
+                    u64::MAX,
+                ))))))),
+            ],
+            &[
+                Val::Result(Ok(Some(Box::new(Val::U8(42))))),
+                Val::Result(Err(Some(Box::new(Val::String("error".into()))))),
+            ],
+            &[Val::Result(Ok(None)), Val::Result(Err(None))],
+            &[
+                Val::List(vec![]),
+                Val::List(vec![
+                    Val::List(vec![]),
+                    Val::List(vec![Val::S16(i16::MIN), Val::S16(i16::MAX)]),
+                ]),
+            ],
+        ];
+        for (ty, vs) in zip(&tys, cases) {
+            for v in vs {
+                assert_round_trip(&mut store, ty, v).await?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_large_list_u8() -> anyhow::Result<()> {
+        const LEN: u32 = 1 << 20;
+
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (type $r' (record (field "name" string) (field "data" (list u8))))
+                (import "r" (type $r (eq $r')))
+                (import "f" (func (param "a" (list u8)) (param "b" $r)))
+            )"#,
+        )?;
+        let payload = (0..LEN).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let list = Val::List(payload.iter().copied().map(Val::U8).collect());
+
+        let buf = encode(&mut store, &tys[0], &list)?;
+        let mut expected = BytesMut::default();
+        Leb128Encoder.encode(LEN, &mut expected)?;
+        expected.extend_from_slice(&payload);
+        assert_eq!(buf, expected);
+        assert_round_trip(&mut store, &tys[0], &list).await?;
+
+        let record = Val::Record(vec![
+            ("name".into(), Val::String("upload".into())),
+            ("data".into(), list),
+        ]);
+        assert_round_trip(&mut store, &tys[1], &record).await?;
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_flags() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        for n in [1, 7, 8, 9, 15, 16, 17, 23, 24, 25, 31, 32] {
+            let names = (0..n).map(|i| format!("f{i}")).collect::<Vec<_>>();
+            let ty = param_type(
+                &engine,
+                &format!(
+                    r#"(component
+                        (type $f' (flags {}))
+                        (import "flags" (type $f (eq $f')))
+                        (import "f" (func (param "x" $f)))
+                    )"#,
+                    names
+                        .iter()
+                        .map(|name| format!(r#""{name}""#))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+            )?;
+            for vs in [
+                vec![],
+                vec![names[0].clone()],
+                vec![names[n - 1].clone()],
+                names.iter().step_by(3).cloned().collect(),
+                names.clone(),
+            ] {
+                assert_round_trip(&mut store, &ty, &Val::Flags(vs))
+                    .await
+                    .with_context(|| format!("failed to round trip flags with {n} names"))?;
+            }
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn list_length_prefix_untrusted() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component (import "f" (func (param "x" (list u8)))))"#,
+        )?;
+        let err = decode(&mut store, &ty, b"\xff\xff\xff\xff\x0f")
+            .await
+            .expect_err("decoding truncated list should fail");
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+
+        let v = decode(&mut store, &ty, b"\x02\x42\x43").await?;
+        let Val::List(vs) = v else {
+            bail!("value is not a list");
+        };
+        assert!(matches!(vs.as_slice(), [Val::U8(0x42), Val::U8(0x43)]));
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn decode_error_path() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (type $v' (variant (case "x") (case "y" u8)))
+                (import "v" (type $v (eq $v')))
+                (type $r' (record (field "a" u32) (field "b" $v)))
+                (import "r" (type $r (eq $r')))
+                (import "f" (func (param "x" $r)))
+            )"#,
+        )?;
+        let err = decode(&mut store, &ty, b"\x01\x05")
+            .await
+            .expect_err("decoding unknown variant discriminant should fail");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        let err = err
+            .get_ref()
+            .and_then(|err| err.downcast_ref::<ValuePathError>())
+            .context("error is not a `ValuePathError`")?;
+        assert_eq!(*err.path, [1]);
+        assert!(err.to_string().contains("[1]"));
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn value_reader_cancellation() -> anyhow::Result<()> {
+        /// Reader returning the contained bytes and then never becoming ready again
+        struct StallingReader(Bytes);
+
+        impl AsyncRead for StallingReader {
+            fn poll_read(
+                mut self: Pin<&mut Self>,
+                _: &mut Context<'_>,
+                buf: &mut ReadBuf<'_>,
+            ) -> Poll<std::io::Result<()>> {
+                if self.0.is_empty() {
+                    return Poll::Pending;
                 }
+                let n = buf.remaining().min(self.0.len());
+                buf.put_slice(&self.0.split_to(n));
+                Poll::Ready(Ok(()))
+            }
+        }
 
-                let id = Uuid::from_bytes_le(id);
-                trace!(?id, "lookup shared resource");
-                let resource = store
-                    .data_mut()
-                    .wrpc()
-                    .ctx
-                    .shared_resources()
-                    .0
-                    .get(&id)
-                    .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
-                *val = Val::Resource(*resource);
-                Ok(())
-            } else {
-                let mut store = store.as_context_mut();
-                let n = r.read_u32_leb128().await?;
-                let n = usize::try_from(n)
-                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
-                let mut buf = Vec::with_capacity(n);
-                r.read_to_end(&mut buf).await?;
-                let table = store.data_mut().wrpc().table;
-                let resource = table
-                    .push(RemoteResource(buf.into()))
-                    .map_err(|err| std::io::Error::new(std::io::ErrorKind::OutOfMemory, err))?;
-                let resource = resource
-                    .try_into_resource_any(store)
-                    .map_err(std::io::Error::other)?;
-                *val = Val::Resource(resource);
+        impl wrpc_transport::Index<Self> for StallingReader {
+            fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+                panic!("index should not be called with path {path:?}")
+            }
+        }
+
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component (import "f" (func (param "x" (tuple u32 u32)))))"#,
+        )?;
+        let v = Val::Tuple(vec![Val::U32(1), Val::U32(2)]);
+
+        // values decoded to completion leave the reader usable
+        let mut r = ValueReader::new(TestReader(Cursor::new(b"\x01\x02\x01\x02".to_vec())));
+        for _ in 0..2 {
+            let mut decoded = Val::Bool(false);
+            r.read_value(&mut store, &[], &mut decoded, &ty, &[])
+                .await?;
+            assert_eq!(decoded, v);
+        }
+        assert!(!r.is_poisoned());
+
+        // the second tuple element never arrives
+        let mut r = ValueReader::new(StallingReader(Bytes::from_static(b"\x01")));
+        let mut decoded = Val::Bool(false);
+        tokio::time::timeout(
+            Duration::from_millis(10),
+            r.read_value(&mut store, &[], &mut decoded, &ty, &[]),
+        )
+        .await
+        .expect_err("decode should time out");
+        assert!(r.is_poisoned());
+        r.read_value(&mut store, &[], &mut decoded, &ty, &[])
+            .await
+            .expect_err("poisoned reader should not decode values");
+        r.read_u8()
+            .await
+            .expect_err("poisoned reader should not be read from");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn discriminant_width() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        for n in [0x100, 0x1_0001] {
+            let names = (0..n).map(|i| format!(r#""c{i}""#)).collect::<Vec<_>>();
+            let ty = param_type(
+                &engine,
+                &format!(
+                    r#"(component
+                        (type $e' (enum {}))
+                        (import "e" (type $e (eq $e')))
+                        (import "f" (func (param "x" $e)))
+                    )"#,
+                    names.join(" ")
+                ),
+            )?;
+            for i in [0, 0x7f, 0x80, n - 1] {
+                let v = Val::Enum(format!("c{i}"));
+                let buf = encode(&mut store, &ty, &v)?;
+                assert_eq!(decode(&mut store, &ty, buf).await?, v);
+            }
+            let mut buf = BytesMut::default();
+            Leb128Encoder.encode(u32::try_from(n)?, &mut buf)?;
+            decode(&mut store, &ty, buf)
+                .await
+                .expect_err("decoding out-of-range enum discriminant should fail");
+        }
+
+        let cases = (0..0x100)
+            .map(|i| format!(r#"(case "c{i}" u32)"#))
+            .collect::<Vec<_>>();
+        let ty = param_type(
+            &engine,
+            &format!(
+                r#"(component
+                    (type $v' (variant {}))
+                    (import "v" (type $v (eq $v')))
+                    (import "f" (func (param "x" $v)))
+                )"#,
+                cases.join(" ")
+            ),
+        )?;
+        let v = Val::Variant("c255".into(), Some(Box::new(Val::U32(0x42))));
+        let buf = encode(&mut store, &ty, &v)?;
+        assert_eq!(buf.as_ref(), b"\xff\x01\x42");
+        assert_eq!(decode(&mut store, &ty, buf).await?, v);
+        decode(&mut store, &ty, b"\x80\x02")
+            .await
+            .expect_err("decoding out-of-range variant discriminant should fail");
+        Ok(())
+    }
+
+    /// Host resource transmitted using a registered codec
+    struct TestHandle(String);
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_host_resource_codec() -> anyhow::Result<()> {
+        let (_, mut store) = new_store();
+        let ty = Type::Own(ResourceType::host::<TestHandle>());
+        let resource = Resource::<TestHandle>::new_own(0).try_into_resource_any(&mut store)?;
+        encode(&mut store, &ty, &Val::Resource(resource))
+            .expect_err("encoding host resource without a codec should fail");
+
+        let mut codecs = HostResourceCodecs::default();
+        codecs.register(
+            |TestHandle(name)| Ok(Bytes::copy_from_slice(name.as_bytes())),
+            |buf| Ok(TestHandle(String::from_utf8(buf.into())?)),
+        );
+        store.data_mut().host_resource_codecs = Some(Arc::new(codecs));
+
+        let handle = store.data_mut().table.push(TestHandle("test".into()))?;
+        let rep = handle.rep();
+        let resource = handle.try_into_resource_any(&mut store)?;
+        let buf = encode(&mut store, &ty, &Val::Resource(resource))?;
+        assert_eq!(buf, b"\x04test"[..]);
+        assert!(
+            store
+                .data()
+                .table
+                .get(&Resource::<TestHandle>::new_borrow(rep))
+                .is_err(),
+            "owned host resource should be removed from the table once encoded"
+        );
+
+        let Val::Resource(resource) = decode(&mut store, &ty, buf).await? else {
+            bail!("decoded value is not a resource");
+        };
+        let resource = resource.try_into_resource::<TestHandle>(&mut store)?;
+        let TestHandle(name) = store.data_mut().table.delete(resource)?;
+        assert_eq!(name, "test");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn codec() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (import "f" (func
+                    (param "a" (list (tuple string u32)))
+                    (param "b" (option bool))
+                ))
+            )"#,
+        )?;
+        let vals = [
+            Val::List(vec![
+                Val::Tuple(vec![Val::String("foo".into()), Val::U32(1)]),
+                Val::Tuple(vec![Val::String("bar".into()), Val::U32(0x80)]),
+            ]),
+            Val::Option(Some(Box::new(Val::Bool(true)))),
+        ];
+
+        let mut codec = Codec::new(store.as_context_mut(), &[]);
+        let mut buf = BytesMut::default();
+        for (v, ty) in zip(&vals, &tys) {
+            let n = buf.len();
+            let deferred = codec.encode::<TestWriter>(v, ty, &mut buf)?;
+            assert!(deferred.is_none(), "value encoding should not be deferred");
+            assert_eq!(codec.encoded_len(v, ty)?, buf.len() - n);
+        }
+        assert_eq!(buf, b"\x02\x03foo\x01\x03bar\x80\x01\x01\x01"[..],);
+        assert_eq!(codec.encode_sync(&vals[1], &tys[1])?, b"\x01\x01"[..]);
+
+        let mut r = pin!(TestReader(Cursor::new(buf.to_vec())));
+        for (i, (v, ty)) in zip(&vals, &tys).enumerate() {
+            assert_eq!(codec.decode(&mut r, ty, &[i]).await?, *v);
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn shared_resource_callbacks() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "r" (type $r (sub resource)))
+                (import "f" (func (param "x" (own $r))))
+            )"#,
+        )?;
+        let Type::Own(resource_ty) = &ty else {
+            bail!("parameter is not an owned resource");
+        };
+        let resources = [*resource_ty];
+
+        let resource = Resource::<u32>::new_own(42).try_into_resource_any(&mut store)?;
+        let mut buf = BytesMut::default();
+        let mut enc = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), &ty, &resources);
+        enc.encode(&Val::Resource(resource), &mut buf)?;
+        let [id] = store.data().wrpc.stored[..] else {
+            bail!("exactly one shared resource should have been stored");
+        };
+        assert!(store.data().wrpc.retrieved.is_empty());
+
+        let mut r = pin!(TestReader(Cursor::new(buf.to_vec())));
+        let mut v = Val::Bool(false);
+        read_value(&mut store, &mut r, &resources, &mut v, &ty, &[]).await?;
+        assert_eq!(v, Val::Resource(resource));
+        assert_eq!(store.data().wrpc.retrieved, [id]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn decode_shared_resource_borrow() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (import "r" (type $r (sub resource)))
+                (import "f" (func (param "x" (borrow $r)) (param "y" (own $r))))
+            )"#,
+        )?;
+        let [borrow_ty, own_ty] = &tys[..] else {
+            bail!("function should have exactly two parameters");
+        };
+        let Type::Own(resource_ty) = own_ty else {
+            bail!("parameter is not an owned resource");
+        };
+        let resources = [*resource_ty];
+
+        let resource = Resource::<u32>::new_own(42).try_into_resource_any(&mut store)?;
+        let mut buf = BytesMut::default();
+        let mut enc = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), own_ty, &resources);
+        enc.encode(&Val::Resource(resource), &mut buf)?;
+        let [id] = store.data().wrpc.stored[..] else {
+            bail!("exactly one shared resource should have been stored");
+        };
+
+        // the lender retains borrowed resources, so they can be borrowed repeatedly
+        for _ in 0..2 {
+            let mut r = pin!(TestReader(Cursor::new(buf.to_vec())));
+            let mut v = Val::Bool(false);
+            read_value(&mut store, &mut r, &resources, &mut v, borrow_ty, &[]).await?;
+            assert_eq!(v, Val::Resource(resource));
+            assert_eq!(store.data().wrpc.shared_resources.get(&id), Some(&resource));
+        }
+
+        // ownership is transferred by owned handles
+        let mut r = pin!(TestReader(Cursor::new(buf.to_vec())));
+        let mut v = Val::Bool(false);
+        read_value(&mut store, &mut r, &resources, &mut v, own_ty, &[]).await?;
+        assert_eq!(v, Val::Resource(resource));
+        assert!(store.data().wrpc.shared_resources.is_empty());
+
+        let mut r = pin!(TestReader(Cursor::new(buf.to_vec())));
+        let mut v = Val::Bool(false);
+        read_value(&mut store, &mut r, &resources, &mut v, borrow_ty, &[])
+            .await
+            .expect_err("borrowing a transferred resource should fail");
+        Ok(())
+    }
+
+    #[test]
+    fn shared_resource_id() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "r" (type $r (sub resource)))
+                (import "f" (func (param "x" (own $r))))
+            )"#,
+        )?;
+        let Type::Own(resource_ty) = &ty else {
+            bail!("parameter is not an owned resource");
+        };
+        let resources = [*resource_ty];
+
+        let id = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        store.data_mut().resource_id = Some(id);
+        for rep in [1, 2] {
+            let resource = Resource::<u32>::new_own(rep).try_into_resource_any(&mut store)?;
+            let mut buf = BytesMut::default();
+            let mut enc = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), &ty, &resources);
+            enc.encode(&Val::Resource(resource), &mut buf)?;
+            assert_eq!(
+                buf.as_ref(),
+                b"\x10\x67\x45\x23\x01\xab\x89\xef\xcd\x01\x23\x45\x67\x89\xab\xcd\xef"
+            );
+        }
+        // the duplicate ID replaces the previously stored resource
+        assert_eq!(store.data().wrpc.stored, [id, id]);
+        assert_eq!(store.data().wrpc.shared_resources.len(), 1);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn resource_handle_framing() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "r" (type $r (sub resource)))
+                (import "f" (func (param "x" (own $r))))
+            )"#,
+        )?;
+        let Type::Own(resource_ty) = &ty else {
+            bail!("parameter is not an owned resource");
+        };
+        let resources = [*resource_ty];
+
+        let id = Uuid::from_u128(0x0123_4567_89ab_cdef_0123_4567_89ab_cdef);
+        store.data_mut().resource_id = Some(id);
+        let id = id.to_bytes_le();
+        for (fixed, expected) in [
+            (false, [b"\x10".as_slice(), &id].concat()),
+            (true, id.to_vec()),
+        ] {
+            store.data_mut().wrpc.fixed_length_resource_handles = fixed;
+
+            // shared resources
+            let resource = Resource::<u32>::new_own(42).try_into_resource_any(&mut store)?;
+            let mut buf = BytesMut::default();
+            let mut enc = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), &ty, &resources);
+            assert_eq!(enc.encoded_len(&Val::Resource(resource))?, expected.len());
+            enc.encode(&Val::Resource(resource), &mut buf)?;
+            assert_eq!(buf, expected);
+            let mut r = pin!(TestReader(Cursor::new(buf.to_vec())));
+            let mut v = Val::Bool(false);
+            read_value(&mut store, &mut r, &resources, &mut v, &ty, &[]).await?;
+            assert_eq!(v, Val::Resource(resource));
+
+            // remote resources
+            let v = decode(&mut store, &ty, expected.clone()).await?;
+            let Val::Resource(resource) = v else {
+                bail!("decoded value is not a resource");
+            };
+            assert_eq!(resource.ty(), ResourceType::host::<RemoteResource>());
+            assert_eq!(encode(&mut store, &ty, &v)?, expected);
+        }
+
+        // only UUIDs can be encoded without a length prefix
+        let v = decode(&mut store, &ty, b"\x06handle").await?;
+        store.data_mut().wrpc.fixed_length_resource_handles = true;
+        encode(&mut store, &ty, &v).expect_err("variable-length handle should not be encoded");
+        Ok(())
+    }
+
+    #[test]
+    fn encode_sync_flat() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "f" (func (param "x" (tuple u32 string (list u8)))))
+            )"#,
+        )?;
+        let v = Val::Tuple(vec![
+            Val::U32(42),
+            Val::String("foo".into()),
+            Val::List(vec![Val::U8(1), Val::U8(2)]),
+        ]);
+        let buf = encode_sync(&mut store, &ty, &[], &v)?;
+        assert_eq!(buf, encode(&mut store, &ty, &v)?);
+        assert_eq!(buf, b"\x2a\x03foo\x02\x01\x02"[..]);
+        Ok(())
+    }
+
+    #[test]
+    fn encode_sync_async_value() -> anyhow::Result<()> {
+        let (_, mut store) = new_store();
+        let ty = Type::Own(ResourceType::host::<DynInputStream>());
+        let stream: DynInputStream = Box::new(MemoryInputPipe::new(Bytes::from_static(b"test")));
+        let stream = store.data_mut().table.push(stream)?;
+        let resource = stream.try_into_resource_any(&mut store)?;
+        let err = encode_sync(&mut store, &ty, &[], &Val::Resource(resource))
+            .expect_err("encoding an input stream synchronously should fail");
+        assert!(
+            err.to_string().contains("asynchronous"),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+
+    /// Asserts that [`ValEncoder::encoded_len`] of `v` equals the length of its encoding
+    /// Encodes the same borrowed remote resource handle passed twice as parameters of a single
+    /// call of imported `f` and finally transfers its ownership by passing it to imported `g`
+    #[test]
+    fn encode_repeated_borrow() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (import "r" (type $r (sub resource)))
+                (import "new" (func $new (result (own $r))))
+                (import "f" (func $f (param "a" (borrow $r)) (param "b" (borrow $r))))
+                (import "g" (func $g (param "a" (own $r))))
+                (core func $new-lower (canon lower (func $new)))
+                (core func $f-lower (canon lower (func $f)))
+                (core func $g-lower (canon lower (func $g)))
+                (core module $m
+                    (import "" "new" (func $new (result i32)))
+                    (import "" "f" (func $f (param i32 i32)))
+                    (import "" "g" (func $g (param i32)))
+                    (func (export "run")
+                        (local $h i32)
+                        call $new
+                        local.set $h
+                        local.get $h
+                        local.get $h
+                        call $f
+                        local.get $h
+                        call $g)
+                )
+                (core instance $i (instantiate $m
+                    (with "" (instance
+                        (export "new" (func $new-lower))
+                        (export "f" (func $f-lower))
+                        (export "g" (func $g-lower))
+                    ))
+                ))
+                (func (export "run") (canon lift (core func $i "run")))
+            )"#,
+        )?;
+
+        let encoded = Arc::new(std::sync::Mutex::new(Vec::default()));
+        let mut linker = Linker::<TestCtx>::new(&engine);
+        let mut root = linker.root();
+        root.resource("r", ResourceType::host::<RemoteResource>(), |_, _| Ok(()))?;
+        root.func_wrap("new", |mut store, ()| {
+            let resource = store
+                .data_mut()
+                .table
+                .push(RemoteResource(Bytes::from_static(b"handle")))?;
+            Ok((resource,))
+        })?;
+        for name in ["f", "g"] {
+            let encoded = Arc::clone(&encoded);
+            root.func_new(name, move |mut store, ty, params, _| {
+                let mut buf = BytesMut::default();
+                for (v, (_, ref ty)) in zip(params, ty.params()) {
+                    ValEncoder::<_, TestWriter>::new(store.as_context_mut(), ty, &[])
+                        .encode(v, &mut buf)?;
+                }
+                encoded.lock().unwrap().push((name, buf));
                 Ok(())
+            })?;
+        }
+        let instance = linker.instantiate(&mut store, &component)?;
+        let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+        run.call(&mut store, ())?;
+        run.post_return(&mut store)?;
+
+        let encoded = encoded.lock().unwrap();
+        assert_eq!(
+            *encoded,
+            [
+                ("f", BytesMut::from(b"\x06handle\x06handle".as_slice())),
+                ("g", BytesMut::from(b"\x06handle".as_slice())),
+            ],
+            "borrowed handle should be encoded identically each time"
+        );
+        assert!(
+            store.data().table.is_empty(),
+            "remote resource should have been removed once ownership was transferred"
+        );
+        Ok(())
+    }
+
+    fn assert_encoded_len(store: &mut Store<TestCtx>, ty: &Type, v: &Val) -> anyhow::Result<()> {
+        let len = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), ty, &[])
+            .encoded_len(v)
+            .with_context(|| format!("failed to measure {v:?}"))?;
+        let buf = encode(store, ty, v).with_context(|| format!("failed to encode {v:?}"))?;
+        assert_eq!(len, buf.len(), "encoded length mismatch for {v:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn encoded_len() -> anyhow::Result<()> {
+        let (engine, mut store) = new_store();
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (type $r' (record (field "a" u8) (field "b" string) (field "c" (list u8))))
+                (import "r" (type $r (eq $r')))
+                (type $v' (variant (case "a") (case "b" $r) (case "c" (list s64))))
+                (import "v" (type $v (eq $v')))
+                (type $e' (enum "x" "y"))
+                (import "e" (type $e (eq $e')))
+                (type $f' (flags "a" "b" "c" "d" "e" "f" "g" "h" "i"))
+                (import "fl" (type $f (eq $f')))
+                (import "f" (func
+                    (param "a" s16)
+                    (param "b" u32)
+                    (param "c" s64)
+                    (param "d" u64)
+                    (param "e" char)
+                    (param "f" string)
+                    (param "g" (list $r))
+                    (param "h" (tuple f32 f64 bool))
+                    (param "i" $v)
+                    (param "j" $e)
+                    (param "k" (option (option u16)))
+                    (param "l" (result s32 (error string)))
+                    (param "m" $f)
+                ))
+            )"#,
+        )?;
+        let record = |a, b: &str, c: usize| {
+            Val::Record(vec![
+                ("a".into(), Val::U8(a)),
+                ("b".into(), Val::String(b.into())),
+                ("c".into(), Val::List(vec![Val::U8(0xff); c])),
+            ])
+        };
+        let cases: [&[Val]; 13] = [
+            &[
+                Val::S16(i16::MIN),
+                Val::S16(-0x41),
+                Val::S16(-0x40),
+                Val::S16(0),
+                Val::S16(0x3f),
+                Val::S16(0x40),
+                Val::S16(i16::MAX),
+            ],
+            &[
+                Val::U32(0),
+                Val::U32(0x7f),
+                Val::U32(0x80),
+                Val::U32(0x3fff),
+                Val::U32(0x4000),
+                Val::U32(u32::MAX),
+            ],
+            &[Val::S64(i64::MIN), Val::S64(-1), Val::S64(i64::MAX)],
+            &[Val::U64(0), Val::U64(u64::MAX)],
+            &[
+                Val::Char('a'),
+                Val::Char('\u{7ff}'),
+                Val::Char('\u{ffff}'),
+                Val::Char('\u{10ffff}'),
+            ],
+            &[
+                Val::String(String::new()),
+                Val::String("test ✓".into()),
+                Val::String("a".repeat(0x80)),
+            ],
+            &[
+                Val::List(vec![]),
+                Val::List(vec![record(0, "", 0), record(0xff, "foo", 0x100)]),
+            ],
+            &[Val::Tuple(vec![
+                Val::Float32(-1.5),
+                Val::Float64(f64::MAX),
+                Val::Bool(true),
+            ])],
+            &[
+                Val::Variant("a".into(), None),
+                Val::Variant("b".into(), Some(Box::new(record(1, "bar", 3)))),
+                Val::Variant(
+                    "c".into(),
+                    Some(Box::new(Val::List(vec![Val::S64(-0x80), Val::S64(0)]))),
+                ),
+            ],
+            &[Val::Enum("x".into()), Val::Enum("y".into())],
+            &[
+                Val::Option(None),
+                Val::Option(Some(Box::new(Val::Option(None)))),
+                Val::Option(Some(Box::new(Val::Option(Some(Box::new(Val::U16(
+                    u16::MAX,
+                ))))))),
+            ],
+            &[
+                Val::Result(Ok(Some(Box::new(Val::S32(i32::MIN))))),
+                Val::Result(Err(Some(Box::new(Val::String("error".into()))))),
+            ],
+            &[Val::Flags(vec![]), Val::Flags(vec!["a".into(), "i".into()])],
+        ];
+        for (ty, vs) in zip(&tys, cases) {
+            for v in vs {
+                assert_encoded_len(&mut store, ty, v)?;
             }
         }
-        Type::Future(..) | Type::Stream(..) | Type::ErrorContext => Err(std::io::Error::new(
-            std::io::ErrorKind::Unsupported,
-            "async not supported",
-        )),
+        Ok(())
+    }
+
+    #[test]
+    fn encoded_len_input_stream() -> anyhow::Result<()> {
+        let (_, mut store) = new_store();
+        let ty = Type::Own(ResourceType::host::<DynInputStream>());
+        let stream: DynInputStream = Box::new(MemoryInputPipe::new(Bytes::from_static(b"test")));
+        let stream = store.data_mut().table.push(stream)?;
+        let resource = stream.try_into_resource_any(&mut store)?;
+        let len = ValEncoder::<_, TestWriter>::new(store.as_context_mut(), &ty, &[])
+            .encoded_len(&Val::Resource(resource))?;
+        assert_eq!(len, 0, "stream contents should not be counted");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn input_stream_end_marker() -> anyhow::Result<()> {
+        let (_, mut store) = new_store();
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let invocations = srv.serve("test", "f", [Box::from([Some(0)])]).await?;
+        let (outgoing, _incoming) = clt
+            .invoke((), "test", "f", Bytes::new(), [[Some(0)].as_slice(); 0])
+            .await?;
+        let mut stream_tx = outgoing.index(&[0])?;
+        // Write a single chunk followed by the end marker, but keep the transport open
+        stream_tx.write_all(b"\x03foo\x00").await?;
+
+        srv.accept(&lis).await?;
+        let mut invocations = pin!(invocations);
+        let ((), _tx, rx) = invocations
+            .try_next()
+            .await?
+            .context("unexpected end of stream")?;
+        let mut rx = pin!(rx);
+        let ty = Type::Own(ResourceType::host::<DynInputStream>());
+        let mut v = Val::Bool(false);
+        read_value(&mut store, &mut rx, &[], &mut v, &ty, &[0]).await?;
+        let Val::Resource(stream) = v else {
+            bail!("expected a resource, got {v:?}");
+        };
+        let stream = stream.try_into_resource::<DynInputStream>(&mut store)?;
+        let mut stream = store.data_mut().table.delete(stream)?;
+        let mut buf = Vec::default();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                stream.ready().await;
+                match stream.read(1024) {
+                    Ok(chunk) => buf.extend_from_slice(&chunk),
+                    Err(StreamError::Closed) => return anyhow::Ok(()),
+                    Err(err) => return Err(err.into()),
+                }
+            }
+        })
+        .await
+        .context("stream did not end at the end marker")??;
+        assert_eq!(buf, b"foo");
+        drop(stream_tx);
+        drop(outgoing);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn lazy_remote_resource() -> anyhow::Result<()> {
+        let (_, mut store) = new_store();
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let invocations = srv.serve("test", "f", [Box::from([Some(0)])]).await?;
+        let (outgoing, _incoming) = clt
+            .invoke((), "test", "f", Bytes::new(), [[Some(0)].as_slice(); 0])
+            .await?;
+        let mut handle_tx = outgoing.index(&[0])?;
+        // Only transmit the first chunk of the handle, the value must still be decoded
+        handle_tx.write_all(b"\x03foo").await?;
+
+        srv.accept(&lis).await?;
+        let mut invocations = pin!(invocations);
+        let ((), _tx, rx) = invocations
+            .try_next()
+            .await?
+            .context("unexpected end of stream")?;
+        let mut rx = pin!(rx);
+        let ty = Type::Own(ResourceType::host::<LazyRemoteResource>());
+        let mut v = Val::Bool(false);
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            read_value(&mut store, &mut rx, &[], &mut v, &ty, &[0]),
+        )
+        .await
+        .context("decoding waited for the whole handle")??;
+        let Val::Resource(handle) = v else {
+            bail!("expected a resource, got {v:?}");
+        };
+        let handle = handle.try_into_resource::<LazyRemoteResource>(&mut store)?;
+        let LazyRemoteResource(mut handle) = store.data_mut().table.delete(handle)?;
+
+        let mut buf = [0; 3];
+        tokio::time::timeout(Duration::from_secs(5), handle.read_exact(&mut buf))
+            .await
+            .context("first chunk of the handle was not received")??;
+        assert_eq!(&buf, b"foo");
+
+        handle_tx.write_all(b"\x03bar\x00").await?;
+        let mut buf = Vec::default();
+        tokio::time::timeout(Duration::from_secs(5), handle.read_to_end(&mut buf))
+            .await
+            .context("handle did not end at the end marker")??;
+        assert_eq!(buf, b"bar");
+        drop(handle_tx);
+        drop(outgoing);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn input_stream_closed() -> anyhow::Result<()> {
+        let stream: DynInputStream = Box::new(MemoryInputPipe::new(Bytes::from("foo")));
+        let mut buf = Vec::default();
+        tokio::time::timeout(
+            Duration::from_secs(5),
+            write_input_stream(stream, DEFAULT_INPUT_STREAM_CHUNK_SIZE, &mut buf),
+        )
+        .await
+        .context("writing a closed stream did not finish")??;
+        // a single end marker is written once the stream is closed
+        assert_eq!(buf, b"\x03foo\x00");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn deferred_writer_limit() -> anyhow::Result<()> {
+        type DeferredFuture = Pin<Box<dyn Future<Output = wasmtime::Result<()>> + Send>>;
+        type Deferred = Box<dyn FnOnce(CountingWriter) -> DeferredFuture + Send>;
+
+        const STREAMS: usize = 32;
+
+        async fn max_writers(limit: Option<NonZeroUsize>) -> anyhow::Result<usize> {
+            let count = Arc::<WriterCount>::default();
+            let deferred = (0..STREAMS).map(|i| -> Option<Deferred> {
+                let stream: DynInputStream =
+                    Box::new(MemoryInputPipe::new(Bytes::from(format!("stream {i}"))));
+                Some(Box::new(move |w: CountingWriter| -> DeferredFuture {
+                    Box::pin(write_input_stream(
+                        stream,
+                        DEFAULT_INPUT_STREAM_CHUNK_SIZE,
+                        w,
+                    ))
+                }))
+            });
+            write_deferred(&CountingWriter::new(Arc::clone(&count)), deferred, limit).await?;
+            assert_eq!(count.active.load(Ordering::Relaxed), 0);
+            Ok(count.max.load(Ordering::Relaxed))
+        }
+
+        assert_eq!(max_writers(None).await?, STREAMS);
+        assert_eq!(max_writers(NonZeroUsize::new(4)).await?, 4);
+        assert_eq!(max_writers(NonZeroUsize::new(1)).await?, 1);
+        Ok(())
+    }
+
+    /// Input stream producing `remaining` bytes as fast as they are requested and counting
+    /// the amount of bytes read
+    struct FastStream {
+        read: Arc<AtomicUsize>,
+        remaining: usize,
+    }
+
+    #[wasmtime_wasi::async_trait]
+    impl Pollable for FastStream {
+        async fn ready(&mut self) {}
+    }
+
+    impl InputStream for FastStream {
+        fn read(&mut self, size: usize) -> StreamResult<Bytes> {
+            if self.remaining == 0 {
+                return Err(StreamError::Closed);
+            }
+            let n = size.min(self.remaining);
+            self.remaining -= n;
+            self.read.fetch_add(n, Ordering::Relaxed);
+            Ok(Bytes::from(vec![0x42; n]))
+        }
+    }
+
+    /// Writer accepting at most 256 bytes per write after yielding once, which tracks the
+    /// maximum amount of bytes read from [FastStream], but not yet written
+    struct SlowWriter {
+        read: Arc<AtomicUsize>,
+        written: usize,
+        max_buffered: Arc<AtomicUsize>,
+        yielded: bool,
+    }
+
+    impl AsyncWrite for SlowWriter {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            if !self.yielded {
+                self.yielded = true;
+                cx.waker().wake_by_ref();
+                return Poll::Pending;
+            }
+            self.yielded = false;
+            let buffered = self
+                .read
+                .load(Ordering::Relaxed)
+                .saturating_sub(self.written);
+            self.max_buffered.fetch_max(buffered, Ordering::Relaxed);
+            let n = buf.len().min(256);
+            self.written += n;
+            Poll::Ready(Ok(n))
+        }
+
+        fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn input_stream_backpressure() -> anyhow::Result<()> {
+        const LEN: usize = 1 << 20;
+
+        let chunk_size = NonZeroUsize::new(1024).context("chunk size is zero")?;
+        let read = Arc::<AtomicUsize>::default();
+        let max_buffered = Arc::<AtomicUsize>::default();
+        let stream: DynInputStream = Box::new(FastStream {
+            read: Arc::clone(&read),
+            remaining: LEN,
+        });
+        write_input_stream(
+            stream,
+            chunk_size,
+            SlowWriter {
+                read: Arc::clone(&read),
+                written: 0,
+                max_buffered: Arc::clone(&max_buffered),
+                yielded: false,
+            },
+        )
+        .await?;
+        assert_eq!(read.load(Ordering::Relaxed), LEN);
+        let max_buffered = max_buffered.load(Ordering::Relaxed);
+        assert!(
+            (1..=chunk_size.get()).contains(&max_buffered),
+            "{max_buffered} bytes were buffered"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn large_flags() -> anyhow::Result<()> {
+        let names = (0..200).map(|i| format!("f{i}")).collect::<Vec<_>>();
+        let flags = names.iter().step_by(7).cloned().collect::<Vec<_>>();
+        let buf = flag_bytes(
+            names.iter().map(String::as_str),
+            flags.iter().map(String::as_str),
+        );
+        assert_eq!(buf.len(), 25);
+        assert_eq!(flag_names(names.iter().map(String::as_str), &buf)?, flags);
+
+        let err = flag_names(names.iter().map(String::as_str), &buf[..24])
+            .expect_err("decoding truncated flags should fail");
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_future() -> anyhow::Result<()> {
+        let (engine, mut store) = new_async_store()?;
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "f" (func (param "v" (future u32))))
+            )"#,
+        )?;
+        let future = FutureReader::new(&mut store, async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            anyhow::Ok(42u32)
+        });
+        let future = FutureAny::try_from_future_reader(&mut store, future)?;
+
+        let mut buf = BytesMut::default();
+        let mut enc = ValEncoder::<_, Outgoing>::new(store.as_context_mut(), &ty, &[]);
+        enc.encode(&Val::Future(future), &mut buf)?;
+        let deferred = enc.deferred.context("`future` value should be deferred")?;
+        assert_eq!(buf, b"\x00".as_slice(), "`future` value should be pending");
+
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let invocations = srv.serve("test", "f", [Box::from([Some(0)])]).await?;
+        let (outgoing, _incoming) = clt
+            .invoke((), "test", "f", buf.freeze(), [[Some(0)].as_slice(); 0])
+            .await?;
+        srv.accept(&lis).await?;
+        let mut invocations = pin!(invocations);
+        let ((), _tx, rx) = invocations
+            .try_next()
+            .await?
+            .context("unexpected end of stream")?;
+        let mut rx = pin!(rx);
+        let mut v = Val::Bool(false);
+        read_value(&mut store, &mut rx, &[], &mut v, &ty, &[0]).await?;
+        let Val::Future(future) = v else {
+            bail!("expected a future, got {v:?}");
+        };
+        let value = pipe_future::<u32, _>(store.as_context_mut(), future, Type::U32)?;
+
+        let w = outgoing.index(&[0])?;
+        let (tx, value) = tokio::time::timeout(
+            Duration::from_secs(5),
+            store.run_concurrent(async move |_| futures::join!(deferred(w), value)),
+        )
+        .await
+        .context("`future` value was not transmitted")??;
+        tx?;
+        let value = value??;
+        assert_eq!(
+            decode(&mut store, &Type::U32, value.to_vec()).await?,
+            Val::U32(42)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn future_unsupported_payload() -> anyhow::Result<()> {
+        let (engine, mut store) = new_async_store()?;
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (type $r' (record (field "a" u32)))
+                (import "r" (type $r (eq $r')))
+                (import "f" (func (param "v" (future $r))))
+            )"#,
+        )?;
+        let future = FutureReader::new(&mut store, async { anyhow::Ok(0u32) });
+        let future = FutureAny::try_from_future_reader(&mut store, future)?;
+        let err = encode(&mut store, &ty, &Val::Future(future)).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("only supported for primitive payload types"),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn round_trip_stream() -> anyhow::Result<()> {
+        let (engine, mut store) = new_async_store()?;
+        let tys = param_types(
+            &engine,
+            r#"(component
+                (import "f" (func (param "v" (stream string)) (param "l" (list string))))
+            )"#,
+        )?;
+        let [ty, list_ty] = tys.as_slice() else {
+            bail!("unexpected parameter types {tys:?}");
+        };
+        let items = ["a", "bc", "def"].map(String::from).to_vec();
+        let stream = StreamReader::new(&mut store, items.clone());
+        let stream = StreamAny::try_from_stream_reader(&mut store, stream)?;
+
+        let mut buf = BytesMut::default();
+        let mut enc = ValEncoder::<_, Outgoing>::new(store.as_context_mut(), ty, &[]);
+        enc.encode(&Val::Stream(stream), &mut buf)?;
+        let deferred = enc.deferred.context("`stream` value should be deferred")?;
+        assert_eq!(buf, b"\x00".as_slice(), "`stream` value should be pending");
+
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let invocations = srv.serve("test", "f", [Box::from([Some(0)])]).await?;
+        let (outgoing, _incoming) = clt
+            .invoke((), "test", "f", buf.freeze(), [[Some(0)].as_slice(); 0])
+            .await?;
+        srv.accept(&lis).await?;
+        let mut invocations = pin!(invocations);
+        let ((), _tx, rx) = invocations
+            .try_next()
+            .await?
+            .context("unexpected end of stream")?;
+        let mut rx = pin!(rx);
+        let mut v = Val::Bool(false);
+        read_value(&mut store, &mut rx, &[], &mut v, ty, &[0]).await?;
+        let Val::Stream(stream) = v else {
+            bail!("expected a stream, got {v:?}");
+        };
+        let mut chunks = pipe_stream::<String, _>(store.as_context_mut(), stream, Type::String)?;
+
+        let w = outgoing.index(&[0])?;
+        let (tx, chunks) = tokio::time::timeout(
+            Duration::from_secs(5),
+            store.run_concurrent(async move |_| {
+                futures::join!(deferred(w), async {
+                    let mut received = vec![];
+                    while let Some(chunk) = chunks.recv().await {
+                        received.push(chunk?);
+                    }
+                    anyhow::Ok(received)
+                })
+            }),
+        )
+        .await
+        .context("`stream` elements were not transmitted")??;
+        tx?;
+        let mut received = vec![];
+        for chunk in chunks? {
+            // chunks are encoded as length-prefixed lists of elements
+            let Val::List(vs) = decode(&mut store, list_ty, chunk.to_vec()).await? else {
+                bail!("expected a list");
+            };
+            received.extend(vs);
+        }
+        assert_eq!(
+            received,
+            items.into_iter().map(Val::String).collect::<Vec<_>>()
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn decode_stream_inline() -> anyhow::Result<()> {
+        let (engine, mut store) = new_async_store()?;
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (import "f" (func (param "v" (stream u8))))
+            )"#,
+        )?;
+        let v = decode(&mut store, &ty, b"\x03\x01\x02\x03").await?;
+        let Val::Stream(stream) = v else {
+            bail!("expected a stream, got {v:?}");
+        };
+        let mut chunks = pipe_stream::<u8, _>(store.as_context_mut(), stream, Type::U8)?;
+        let chunks = tokio::time::timeout(
+            Duration::from_secs(5),
+            store.run_concurrent(async move |_| {
+                let mut received = vec![];
+                while let Some(chunk) = chunks.recv().await {
+                    let chunk = chunk?;
+                    // each chunk is prefixed by the amount of elements it contains
+                    let (n, elements) = chunk.split_first().context("chunk is empty")?;
+                    ensure!(usize::from(*n) == elements.len(), "invalid chunk length");
+                    received.extend_from_slice(elements);
+                }
+                anyhow::Ok(received)
+            }),
+        )
+        .await
+        .context("`stream` elements were not received")???;
+        assert_eq!(chunks, [1, 2, 3]);
+        Ok(())
+    }
+
+    #[test]
+    fn stream_unsupported_payload() -> anyhow::Result<()> {
+        let (engine, mut store) = new_async_store()?;
+        let ty = param_type(
+            &engine,
+            r#"(component
+                (type $r' (record (field "a" u32)))
+                (import "r" (type $r (eq $r')))
+                (import "f" (func (param "v" (stream $r))))
+            )"#,
+        )?;
+        let stream = StreamReader::new(&mut store, vec![0u32]);
+        let stream = StreamAny::try_from_stream_reader(&mut store, stream)?;
+        let err = encode(&mut store, &ty, &Val::Stream(stream)).unwrap_err();
+        assert!(
+            format!("{err:#}").contains("only supported for primitive payload types"),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
     }
 }
@@ -5,23 +5,31 @@ use core::borrow::Borrow;
 use core::fmt;
 use core::future::Future;
 use core::iter::zip;
-use core::pin::pin;
+use core::num::NonZeroUsize;
+use core::pin::{pin, Pin};
 use core::time::Duration;
 
-use std::collections::{BTreeMap, HashMap};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::sync::Arc;
 
 use anyhow::{anyhow, bail, Context as _};
-use bytes::{Bytes, BytesMut};
-use futures::future::try_join_all;
-use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt as _};
+use bytes::{BufMut as _, Bytes, BytesMut};
+use futures::future;
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _};
+use tokio::time::Instant;
 use tokio_util::codec::Encoder;
-use tracing::{debug, instrument, trace, warn};
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{debug, instrument, trace, Span};
 use uuid::Uuid;
+use wasm_tokio::cm::AsyncReadValue as _;
+use wasm_tokio::{AsyncReadLeb128 as _, CoreVecEncoderBytes, Leb128Encoder};
 use wasmtime::component::{
     types, Func, Resource, ResourceAny, ResourceTable, ResourceType, Type, Val,
 };
 use wasmtime::{AsContextMut, Engine};
+use wasmtime_wasi::p2::DynInputStream;
+use wrpc_transport::trace::TraceContext;
 use wrpc_transport::Invoke;
 
 use crate::bindings::rpc::context::Context;
@@ -33,15 +41,19 @@ mod codec;
 mod polyfill;
 pub mod rpc;
 mod serve;
+#[cfg(test)]
+mod test_util;
 
 pub use codec::*;
 pub use polyfill::*;
 pub use serve::*;
 
-// this returns the RPC name for a wasmtime function name.
-// Unfortunately, the [`types::ComponentFunc`] does not include the kind information and we want to
-// avoid (re-)parsing the WIT here.
-fn rpc_func_name(name: &str) -> &str {
+/// Returns the RPC name for a wasmtime function name by stripping the `[constructor]`,
+/// `[static]` and `[method]` prefixes. This is the default name mapping of [`WrpcView::rpc_name`].
+///
+/// Unfortunately, the [`types::ComponentFunc`] does not include the kind information and we want to
+/// avoid (re-)parsing the WIT here.
+pub fn rpc_func_name(name: &str) -> &str {
     if let Some(name) = name.strip_prefix("[constructor]") {
         name
     } else if let Some(name) = name.strip_prefix("[static]") {
@@ -76,11 +88,276 @@ fn rpc_result_type<T: Borrow<Type>>(
     }
 }
 
+// this returns whether values of resource type `ty` are transmitted on their index, i.e. whether
+// `ty` is, or is mapped to, a `wasi:io/input-stream` or a lazily received remote resource
+fn is_async_resource(
+    host_resources: &HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>,
+    ty: &ResourceType,
+) -> bool {
+    let is_async = |ty: &ResourceType| {
+        *ty == ResourceType::host::<DynInputStream>()
+            || *ty == ResourceType::host::<LazyRemoteResource>()
+    };
+    is_async(ty)
+        || host_resources
+            .values()
+            .flat_map(HashMap::values)
+            .any(|(guest_ty, host_ty)| guest_ty == ty && is_async(host_ty))
+}
+
+// this returns the paths of asynchronous values nested within a type and whether the type itself
+// is asynchronous, analogous to [`wrpc_introspect::async_paths_ty`]
+fn async_paths_ty(
+    host_resources: &HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>,
+    ty: &Type,
+) -> (BTreeSet<VecDeque<Option<usize>>>, bool) {
+    fn nest(
+        paths: &mut BTreeSet<VecDeque<Option<usize>>>,
+        (nested, fut): (BTreeSet<VecDeque<Option<usize>>>, bool),
+        i: Option<usize>,
+    ) {
+        for mut path in nested {
+            path.push_front(i);
+            paths.insert(path);
+        }
+        if fut {
+            paths.insert(VecDeque::from([i]));
+        }
+    }
+
+    match ty {
+        Type::List(ty) => {
+            let mut paths = BTreeSet::default();
+            nest(&mut paths, async_paths_ty(host_resources, &ty.ty()), None);
+            (paths, false)
+        }
+        Type::Record(ty) => {
+            let mut paths = BTreeSet::default();
+            for (i, types::Field { ty, .. }) in ty.fields().enumerate() {
+                nest(&mut paths, async_paths_ty(host_resources, &ty), Some(i));
+            }
+            (paths, false)
+        }
+        Type::Tuple(ty) => {
+            let mut paths = BTreeSet::default();
+            for (i, ty) in ty.types().enumerate() {
+                nest(&mut paths, async_paths_ty(host_resources, &ty), Some(i));
+            }
+            (paths, false)
+        }
+        Type::Variant(ty) => ty.cases().filter_map(|types::Case { ty, .. }| ty).fold(
+            (BTreeSet::default(), false),
+            |(mut paths, is_fut), ty| {
+                let (nested, fut) = async_paths_ty(host_resources, &ty);
+                paths.extend(nested);
+                (paths, is_fut || fut)
+            },
+        ),
+        Type::Option(ty) => async_paths_ty(host_resources, &ty.ty()),
+        Type::Result(ty) => [ty.ok(), ty.err()].into_iter().flatten().fold(
+            (BTreeSet::default(), false),
+            |(mut paths, is_fut), ty| {
+                let (nested, fut) = async_paths_ty(host_resources, &ty);
+                paths.extend(nested);
+                (paths, is_fut || fut)
+            },
+        ),
+        Type::Own(ty) | Type::Borrow(ty) => {
+            (BTreeSet::default(), is_async_resource(host_resources, ty))
+        }
+        // payloads of `future` and `stream` values are always primitive, see `codec`
+        Type::Future(..) | Type::Stream(..) => (BTreeSet::default(), true),
+        _ => (BTreeSet::default(), false),
+    }
+}
+
+// this returns the paths of all asynchronous values nested within a sequence of parameter
+// or result types, which are passed to [`Invoke::invoke`] and [`wrpc_transport::Serve::serve`]
+fn async_paths<T: Borrow<Type>>(
+    host_resources: &HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>,
+    tys: impl IntoIterator<Item = T>,
+) -> Box<[Box<[Option<usize>]>]> {
+    tys.into_iter()
+        .enumerate()
+        .fold(BTreeSet::default(), |mut paths, (i, ty)| {
+            let (nested, fut) = async_paths_ty(host_resources, ty.borrow());
+            for mut path in nested {
+                path.push_front(Some(i));
+                paths.insert(path);
+            }
+            if fut {
+                paths.insert(VecDeque::from([Some(i)]));
+            }
+            paths
+        })
+        .into_iter()
+        .map(|path| Vec::from(path).into_boxed_slice())
+        .collect()
+}
+
+/// An opaque handle of a resource owned by a remote peer.
+///
+/// Encoding an owned handle transfers ownership to the peer and removes the handle from the
+/// [`ResourceTable`], after which it cannot be encoded again. Encoding a borrowed handle only
+/// copies the handle bytes, so the same borrowed handle may be encoded any number of times,
+/// for example as multiple parameters of a single invocation, and the peer observes identical
+/// handles each time.
 pub struct RemoteResource(pub Bytes);
 
+/// An opaque handle of a resource owned by a remote peer, which is received lazily.
+///
+/// Unlike the handle of a [`RemoteResource`], which is buffered in full while the value
+/// containing it is decoded, this handle is transmitted on the index of the value in chunks,
+/// like the contents of a `wasi:io/input-stream`, and can be drained from the reader as it is
+/// received. Resources mapped to this type in the host resources passed to
+/// [`link_item`] are decoded lazily.
+///
+/// Encoding a handle forwards the remaining bytes of the reader to the peer, so only owned
+/// handles can be encoded.
+pub struct LazyRemoteResource(pub Pin<Box<dyn AsyncRead + Send>>);
+
 /// A table of shared resources exported by the component
 #[derive(Debug, Default)]
-pub struct SharedResourceTable(HashMap<Uuid, ResourceAny>);
+pub struct SharedResourceTable(HashMap<Uuid, (ResourceAny, Instant)>);
+
+impl SharedResourceTable {
+    /// Inserts a resource under `id`, returning the resource previously stored under `id`, if any
+    pub fn insert(&mut self, id: Uuid, resource: ResourceAny) -> Option<ResourceAny> {
+        self.0
+            .insert(id, (resource, Instant::now()))
+            .map(|(resource, _)| resource)
+    }
+
+    /// Returns the resource stored under `id`, if any
+    #[must_use]
+    pub fn get(&self, id: &Uuid) -> Option<&ResourceAny> {
+        self.0.get(id).map(|(resource, _)| resource)
+    }
+
+    /// Removes the resource stored under `id`, if any
+    pub fn remove(&mut self, id: &Uuid) -> Option<ResourceAny> {
+        self.0.remove(id).map(|(resource, _)| resource)
+    }
+
+    /// Removes all resources, which were inserted more than `ttl` ago and returns them.
+    ///
+    /// The returned resources are not dropped, it is the responsibility of the caller to
+    /// drop them using the store, which owns them.
+    pub fn evict_older_than(&mut self, ttl: Duration) -> Vec<(Uuid, ResourceAny)> {
+        let now = Instant::now();
+        let mut evicted = Vec::new();
+        self.0.retain(|id, (resource, inserted)| {
+            if now.saturating_duration_since(*inserted) > ttl {
+                evicted.push((*id, *resource));
+                false
+            } else {
+                true
+            }
+        });
+        evicted
+    }
+
+    /// Returns an iterator over the IDs of resources in the table, in arbitrary order
+    pub fn ids(&self) -> impl Iterator<Item = Uuid> + '_ {
+        self.0.keys().copied()
+    }
+
+    /// Returns the number of resources in the table
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    /// Returns `true` if the table contains no resources
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// A pool of reusable [`BytesMut`] buffers used for encoding parameters and results
+#[derive(Clone, Debug, Default)]
+pub struct BufferPool(Arc<std::sync::Mutex<Vec<BytesMut>>>);
+
+impl BufferPool {
+    /// Returns an empty buffer from the pool or allocates a new one, if the pool is empty
+    #[must_use]
+    pub fn get(&self) -> BytesMut {
+        self.0
+            .lock()
+            .ok()
+            .and_then(|mut bufs| bufs.pop())
+            .unwrap_or_default()
+    }
+
+    /// Clears the buffer and returns it to the pool
+    pub fn put(&self, mut buf: BytesMut) {
+        buf.clear();
+        if let Ok(mut bufs) = self.0.lock() {
+            bufs.push(buf);
+        }
+    }
+
+    /// Returns the buffer backing `buf` to the pool, if `buf` is the only reference to it
+    pub fn put_bytes(&self, buf: Bytes) {
+        if let Ok(buf) = buf.try_into_mut() {
+            self.put(buf);
+        }
+    }
+
+    /// Returns the number of buffers currently available in the pool
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.lock().map(|bufs| bufs.len()).unwrap_or_default()
+    }
+
+    /// Returns `true` if there are no buffers currently available in the pool
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A pool of reusable [`Val`] vectors used for decoding parameters and storing results of
+/// served function calls
+#[derive(Clone, Debug, Default)]
+pub struct ValPool(Arc<std::sync::Mutex<Vec<Vec<Val>>>>);
+
+impl ValPool {
+    /// Returns a vector of `n` placeholder values, which reuses the allocation of a vector
+    /// from the pool, if the pool is not empty
+    #[must_use]
+    pub fn get(&self, n: usize) -> Vec<Val> {
+        let mut vals = self
+            .0
+            .lock()
+            .ok()
+            .and_then(|mut vals| vals.pop())
+            .unwrap_or_default();
+        vals.resize(n, Val::Bool(false));
+        vals
+    }
+
+    /// Clears the vector and returns it to the pool
+    pub fn put(&self, mut vals: Vec<Val>) {
+        vals.clear();
+        if let Ok(mut pool) = self.0.lock() {
+            pool.push(vals);
+        }
+    }
+
+    /// Returns the number of vectors currently available in the pool
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.0.lock().map(|vals| vals.len()).unwrap_or_default()
+    }
+
+    /// Returns `true` if there are no vectors currently available in the pool
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
 
 pub trait WrpcCtx<T: Invoke>: Send {
     /// Returns context to use for invocation
@@ -94,9 +371,124 @@ pub trait WrpcCtx<T: Invoke>: Send {
 
     /// Optional invocation timeout, component will trap if invocation is not finished within the
     /// returned [Duration]. If this method returns [None], then no timeout will be used.
+    ///
+    /// Timeouts and deadlines are measured using the [`tokio::time`] clock, so they can be
+    /// tested deterministically using [`tokio::time::pause`] and [`tokio::time::advance`].
     fn timeout(&self) -> Option<Duration> {
         None
     }
+
+    /// Optional invocation timeout for function `func` of instance `instance`, which allows
+    /// overriding [`Self::timeout`] for individual polyfilled imports.
+    ///
+    /// `instance` and `func` are the names of the import as declared by the component, for
+    /// example, `[method]handle.read`, before they are mapped by [`WrpcView::rpc_name`].
+    /// Defaults to [`Self::timeout`].
+    fn timeout_for(&self, instance: &str, func: &str) -> Option<Duration> {
+        let _ = (instance, func);
+        self.timeout()
+    }
+
+    /// Optional limit on the time spent executing the guest function of a served invocation.
+    /// Unlike [`Self::timeout`], it does not cover decoding the parameters or transmitting the
+    /// results. If exceeded, the call fails with [`CallError::ExecutionTimeout`].
+    ///
+    /// Guest code, which does not call into the host, can only be interrupted if the engine is
+    /// configured with [`wasmtime::Config::epoch_interruption`] and
+    /// [`wasmtime::Engine::increment_epoch`] is called periodically. When a limit is set, [call]
+    /// configures the epoch deadline of the store to yield to the async executor on every epoch
+    /// increment.
+    ///
+    /// This is mutually exclusive with any epoch deadline behavior configured by the embedder,
+    /// for example, [`wasmtime::Store::epoch_deadline_trap`] or
+    /// [`wasmtime::Store::epoch_deadline_callback`]: wasmtime does not allow the previous
+    /// behavior to be inspected, so it cannot be restored after the call and the store keeps
+    /// yielding on every epoch increment afterwards. Embedders relying on their own epoch
+    /// deadline must not return a limit here.
+    ///
+    /// Defaults to [None], in which case the epoch deadline of the store is left unchanged.
+    fn execution_timeout(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Whether invocations carry a deadline header preceding the parameters, see
+    /// [`encode_deadline`]. When enabled, polyfilled imports transmit the invocation timeout to
+    /// the peer and served exports abort calls, which exceed the deadline sent by the peer.
+    /// Both the invoking and the serving side must agree on this setting.
+    /// Defaults to `false`.
+    fn deadline_header(&self) -> bool {
+        false
+    }
+
+    /// Whether invocations carry an invocation ID header preceding the parameters, see
+    /// [`encode_invocation_id`]. When enabled, the ID generated for each invocation of a
+    /// polyfilled import is transmitted to the peer, which records it on the span of the
+    /// served call, allowing logs of both sides to be correlated.
+    /// Both the invoking and the serving side must agree on this setting.
+    /// Defaults to `false`.
+    fn invocation_id_header(&self) -> bool {
+        false
+    }
+
+    /// Maximum number of asynchronous values, e.g. streams, of a parameter or result list,
+    /// list, record or tuple, which are written concurrently. If this method returns [None],
+    /// all asynchronous values are written concurrently.
+    /// Defaults to [None].
+    fn max_deferred_writers(&self) -> Option<NonZeroUsize> {
+        None
+    }
+
+    /// Maximum size of chunks read from a `wasi:io/input-stream` and transmitted to the peer.
+    /// This also applies to data written by the guest to `wasi:io/output-stream` parameters
+    /// of served functions. Larger chunks reduce the per-chunk framing overhead of
+    /// high-throughput byte streams at the cost of larger buffers.
+    /// Defaults to [`DEFAULT_INPUT_STREAM_CHUNK_SIZE`].
+    fn input_stream_chunk_size(&self) -> NonZeroUsize {
+        DEFAULT_INPUT_STREAM_CHUNK_SIZE
+    }
+
+    /// Whether failing to shut down the outgoing stream of a polyfilled import invocation fails
+    /// the invocation. Depending on the transport, the peer may never observe the end of the
+    /// parameters in that case and wait for further data indefinitely.
+    /// If this method returns `false`, the failure is only logged as a warning.
+    /// Defaults to `false`.
+    fn fail_on_shutdown_error(&self) -> bool {
+        false
+    }
+
+    /// Whether shared and remote resource handles are encoded as fixed-length 16-byte UUIDs
+    /// rather than prefixed by their length. This saves a byte per handle, but both peers must
+    /// agree on the framing, since it is not self-describing.
+    /// Handles of host resources encoded using [`HostResourceCodecs`] are always
+    /// length-prefixed.
+    /// Defaults to `false`.
+    fn fixed_length_resource_handles(&self) -> bool {
+        false
+    }
+
+    /// Called when a guest resource is stored in the [SharedResourceTable] under `id`
+    fn on_resource_stored(&mut self, id: Uuid) {
+        let _ = id;
+    }
+
+    /// Called when a guest resource stored in the [SharedResourceTable] under `id` is looked up
+    fn on_resource_retrieved(&mut self, id: Uuid) {
+        let _ = id;
+    }
+
+    /// Optional [BufferPool] to allocate parameter and result encoding buffers from.
+    /// If this method returns [None], then a new buffer will be allocated for each invocation.
+    fn buffer_pool(&self) -> Option<&BufferPool> {
+        None
+    }
+
+    /// Optional [ValPool] to allocate parameter and result values of served function calls from.
+    /// Sharing a single pool among the stores constructed for each invocation of
+    /// [`ServeExt::serve_function`] avoids allocating new vectors on every call.
+    /// If this method returns [None], then new vectors will be allocated for each invocation.
+    fn val_pool(&self) -> Option<&ValPool> {
+        None
+    }
 }
 
 pub struct WrpcCtxView<'a, T: Invoke> {
@@ -108,14 +500,124 @@ pub trait WrpcView: Send {
     type Invoke: Invoke;
 
     fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke>;
+
+    /// Optional [HostResourceCodecs] used to transmit host resources as opaque handles.
+    /// If this method returns [None], then host resources other than [RemoteResource] cannot
+    /// be encoded and are decoded as [RemoteResource].
+    ///
+    /// NOTE: The codecs are specific to the view type `Self`, so this method is not forwarded by
+    /// the implementation of [`WrpcView`] for `&mut T`: a `&mut T` view always uses the default
+    /// and transmits no host resources as opaque handles, even if `T` overrides this method.
+    /// Stores used to serve or polyfill functions must therefore be constructed with `T` itself
+    /// as the data type for codecs registered by `T` to take effect.
+    fn host_resource_codecs(&self) -> Option<Arc<HostResourceCodecs<Self>>>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Maps function `name` of instance `instance` to the instance and function names used
+    /// by the transport, both for polyfilled imports and served exports.
+    /// Defaults to `instance` and [`rpc_func_name`] of `name`.
+    fn rpc_name<'a>(instance: &'a str, name: &'a str) -> (Cow<'a, str>, Cow<'a, str>)
+    where
+        Self: Sized,
+    {
+        (Cow::Borrowed(instance), Cow::Borrowed(rpc_func_name(name)))
+    }
+
+    /// Called with the transport-specific [`Serve::Context`](wrpc_transport::Serve::Context)
+    /// of an accepted invocation before a served export is called in a store constructed for it,
+    /// e.g. to make the identity of an authenticated peer available to host functions.
+    /// The context can be retrieved using [`Any::downcast_ref`].
+    ///
+    /// This is not called for stores shared by multiple invocations.
+    /// Defaults to ignoring the context.
+    fn set_serve_context(&mut self, cx: &dyn Any) {
+        let _ = cx;
+    }
+
+    /// Returns the [`TraceContext`] propagated by the peer along with the transport-specific
+    /// [`Serve::Context`](wrpc_transport::Serve::Context) of an accepted invocation, if any.
+    ///
+    /// If a trace context is returned, served exports are called within a span recording it
+    /// and with a child of it as [`TraceContext::current`], so that it is propagated to nested
+    /// invocations performed using
+    /// [`TracePropagatingInvoke`](wrpc_transport::trace::TracePropagatingInvoke).
+    /// Defaults to the trace context of invocations served using
+    /// [`TracePropagatingServe`](wrpc_transport::trace::TracePropagatingServe) wrapping a
+    /// transport with context `()`.
+    fn serve_trace_context(cx: &dyn Any) -> Option<TraceContext>
+    where
+        Self: Sized,
+    {
+        cx.downcast_ref::<(Option<TraceContext>, ())>()
+            .and_then(|(trace, ())| *trace)
+    }
+
+    /// Generates the ID of a shared resource stored in [`WrpcCtx::shared_resources`] and
+    /// transmitted to the peer as the resource handle.
+    /// IDs must be unique among the resources stored in the table.
+    /// Defaults to [`Uuid::now_v7`].
+    fn new_resource_id(&mut self) -> Uuid {
+        Uuid::now_v7()
+    }
+
+    /// Called by [call] with the decoded parameter values of `ty` before the function is called,
+    /// e.g. to redact or migrate values received from the peer.
+    /// Returning an error fails the invocation with [`CallError::Decode`].
+    /// Defaults to leaving the values unchanged.
+    fn transform_params(&mut self, params: &mut [Val], ty: &[Type]) -> anyhow::Result<()> {
+        let _ = (params, ty);
+        Ok(())
+    }
+
+    /// Called by [call] with the result values of `ty` returned by the function before they are
+    /// encoded and transmitted to the peer.
+    /// Returning an error fails the invocation with [`CallError::Encode`].
+    /// Defaults to leaving the values unchanged.
+    fn transform_results(&mut self, results: &mut [Val], ty: &[Type]) -> anyhow::Result<()> {
+        let _ = (results, ty);
+        Ok(())
+    }
 }
 
+/// Forwards all methods to `T`, except for [`WrpcView::host_resource_codecs`].
+///
+/// NOTE: [`HostResourceCodecs<T>`] operate on stores of `T` and cannot be used for stores of
+/// `&mut T`, so host resource codecs registered by `T` are NOT used for this view and host
+/// resources other than [RemoteResource] cannot be encoded through it.
 impl<T: WrpcView> WrpcView for &mut T {
     type Invoke = T::Invoke;
 
     fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke> {
         T::wrpc(self)
     }
+
+    fn set_serve_context(&mut self, cx: &dyn Any) {
+        T::set_serve_context(self, cx);
+    }
+
+    fn new_resource_id(&mut self) -> Uuid {
+        T::new_resource_id(self)
+    }
+
+    fn transform_params(&mut self, params: &mut [Val], ty: &[Type]) -> anyhow::Result<()> {
+        T::transform_params(self, params, ty)
+    }
+
+    fn transform_results(&mut self, results: &mut [Val], ty: &[Type]) -> anyhow::Result<()> {
+        T::transform_results(self, results, ty)
+    }
+
+    fn rpc_name<'a>(instance: &'a str, name: &'a str) -> (Cow<'a, str>, Cow<'a, str>) {
+        T::rpc_name(instance, name)
+    }
+
+    fn serve_trace_context(cx: &dyn Any) -> Option<TraceContext> {
+        T::serve_trace_context(cx)
+    }
 }
 
 pub trait WrpcViewExt: WrpcView {
@@ -319,11 +821,26 @@ pub trait WrpcViewExt: WrpcView {
         let cx = cx.downcast().map_err(|_| anyhow!("invalid context type"))?;
         Ok(*cx)
     }
+
+    /// Returns the IDs of shared resources currently stored in
+    /// [`WrpcCtx::shared_resources`], e.g. for diagnosing resource leaks
+    fn shared_resource_ids(&mut self) -> Vec<Uuid> {
+        self.wrpc().ctx.shared_resources().ids().collect()
+    }
+
+    /// Returns the number of shared resources currently stored in
+    /// [`WrpcCtx::shared_resources`]
+    fn shared_resource_count(&mut self) -> usize {
+        self.wrpc().ctx.shared_resources().len()
+    }
 }
 
 impl<T: WrpcView> WrpcViewExt for T {}
 
-/// Error type returned by [call]
+/// Error type returned by [call].
+///
+/// When returned by functions served using [`ServeExt`](crate::ServeExt), it can be retrieved
+/// from the returned [`anyhow::Error`] using [`anyhow::Error::downcast_ref`].
 pub enum CallError {
     Decode(anyhow::Error),
     Encode(anyhow::Error),
@@ -333,7 +850,13 @@ pub enum CallError {
     Write(anyhow::Error),
     Flush(anyhow::Error),
     Deferred(anyhow::Error),
+    /// The call completed and its results were transmitted, but the post-return cleanup of the
+    /// guest failed. The instance may be left in an unusable state and should not be reused.
     PostReturn(anyhow::Error),
+    Cancelled(anyhow::Error),
+    DeadlineExceeded(anyhow::Error),
+    /// The guest exceeded the execution time limit returned by [`WrpcCtx::execution_timeout`]
+    ExecutionTimeout(anyhow::Error),
     Guest(Error),
 }
 
@@ -350,7 +873,10 @@ impl fmt::Debug for CallError {
             | CallError::Write(error)
             | CallError::Flush(error)
             | CallError::Deferred(error)
-            | CallError::PostReturn(error) => error.fmt(f),
+            | CallError::PostReturn(error)
+            | CallError::Cancelled(error)
+            | CallError::DeadlineExceeded(error)
+            | CallError::ExecutionTimeout(error) => error.fmt(f),
             CallError::Guest(error) => error.fmt(f),
         }
     }
@@ -367,7 +893,10 @@ impl fmt::Display for CallError {
             | CallError::Write(error)
             | CallError::Flush(error)
             | CallError::Deferred(error)
-            | CallError::PostReturn(error) => error.fmt(f),
+            | CallError::PostReturn(error)
+            | CallError::Cancelled(error)
+            | CallError::DeadlineExceeded(error)
+            | CallError::ExecutionTimeout(error) => error.fmt(f),
             CallError::Guest(error) => error.fmt(f),
         }
     }
@@ -375,6 +904,139 @@ impl fmt::Display for CallError {
 
 #[allow(clippy::too_many_arguments)]
 pub async fn call<C, I, O>(
+    store: C,
+    rx: I,
+    tx: O,
+    guest_resources: &[ResourceType],
+    host_resources: &HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>,
+    params_ty: impl ExactSizeIterator<Item = &Type>,
+    results_ty: &[Type],
+    func: Func,
+) -> Result<(), CallError>
+where
+    I: AsyncRead + wrpc_transport::Index<I> + Send + Sync + Unpin + 'static,
+    O: AsyncWrite + wrpc_transport::Index<O> + Send + Sync + Unpin + 'static,
+    C: AsContextMut,
+    C::Data: WrpcView,
+{
+    call_with(
+        store,
+        rx,
+        tx,
+        guest_resources,
+        host_resources,
+        params_ty,
+        results_ty,
+        func,
+        false,
+    )
+    .await
+}
+
+/// Like [call], but interrupts the guest call with [`CallError::Cancelled`] if the incoming
+/// stream fails, for example, because the invoking peer reset the connection, while the
+/// function is executing.
+///
+/// Since the function call is dropped mid-execution on cancellation, the store should not be
+/// used for any further calls once [`CallError::Cancelled`] is returned.
+#[allow(clippy::too_many_arguments)]
+pub async fn call_cancellable<C, I, O>(
+    store: C,
+    rx: I,
+    tx: O,
+    guest_resources: &[ResourceType],
+    host_resources: &HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>,
+    params_ty: impl ExactSizeIterator<Item = &Type>,
+    results_ty: &[Type],
+    func: Func,
+) -> Result<(), CallError>
+where
+    I: AsyncRead + wrpc_transport::Index<I> + Send + Sync + Unpin + 'static,
+    O: AsyncWrite + wrpc_transport::Index<O> + Send + Sync + Unpin + 'static,
+    C: AsContextMut,
+    C::Data: WrpcView,
+{
+    call_with(
+        store,
+        rx,
+        tx,
+        guest_resources,
+        host_resources,
+        params_ty,
+        results_ty,
+        func,
+        true,
+    )
+    .await
+}
+
+/// Encodes the invocation deadline header as an `option<u64>` of milliseconds remaining until
+/// the deadline, where `none` indicates that no deadline is set.
+/// See [`WrpcCtx::deadline_header`].
+pub fn encode_deadline(timeout: Option<Duration>, dst: &mut BytesMut) -> anyhow::Result<()> {
+    let Some(timeout) = timeout else {
+        dst.put_u8(0);
+        return Ok(());
+    };
+    dst.put_u8(1);
+    let ms = timeout.as_millis().try_into().unwrap_or(u64::MAX);
+    Leb128Encoder
+        .encode(ms, dst)
+        .context("failed to encode deadline")
+}
+
+/// Reads the invocation deadline header encoded by [`encode_deadline`]
+pub async fn read_deadline(mut r: impl AsyncRead + Unpin) -> std::io::Result<Option<Duration>> {
+    if !r.read_option_status().await? {
+        return Ok(None);
+    }
+    let ms = r.read_u64_leb128().await?;
+    Ok(Some(Duration::from_millis(ms)))
+}
+
+/// Encodes the invocation ID header as the 16 bytes of `id` in little-endian byte order,
+/// encoded as a `list<u8>`. See [`WrpcCtx::invocation_id_header`].
+pub fn encode_invocation_id(id: Uuid, dst: &mut BytesMut) -> anyhow::Result<()> {
+    CoreVecEncoderBytes
+        .encode(id.to_bytes_le().as_slice(), dst)
+        .context("failed to encode invocation ID")
+}
+
+/// Reads the invocation ID header encoded by [`encode_invocation_id`]
+pub async fn read_invocation_id(mut r: impl AsyncRead + Unpin) -> std::io::Result<Uuid> {
+    let mut id = uuid::Bytes::default();
+    let n = r.read_u8_leb128().await?;
+    if usize::from(n) != id.len() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("invalid invocation ID length {n}, expected {}", id.len()),
+        ));
+    }
+    r.read_exact(&mut id).await?;
+    Ok(Uuid::from_bytes_le(id))
+}
+
+/// Reads from `rx` until it fails, discarding any data. Never completes if `rx` is closed
+/// gracefully.
+async fn stream_failed(mut rx: impl AsyncRead + Unpin) -> std::io::Error {
+    let mut buf = [0; 64];
+    loop {
+        match rx.read(&mut buf).await {
+            Ok(0) => return future::pending().await,
+            Ok(_) => {}
+            Err(err) => return err,
+        }
+    }
+}
+
+#[instrument(
+    level = "debug",
+    name = "call",
+    skip_all,
+    fields(invocation_id = tracing::field::Empty)
+)]
+#[allow(clippy::too_many_arguments)]
+async fn call_with<C, I, O>(
     mut store: C,
     rx: I,
     mut tx: O,
@@ -383,6 +1045,7 @@ pub async fn call<C, I, O>(
     params_ty: impl ExactSizeIterator<Item = &Type>,
     results_ty: &[Type],
     func: Func,
+    cancel: bool,
 ) -> Result<(), CallError>
 where
     I: AsyncRead + wrpc_transport::Index<I> + Send + Sync + Unpin + 'static,
@@ -390,21 +1053,180 @@ where
     C: AsContextMut,
     C::Data: WrpcView,
 {
-    let mut params = vec![Val::Bool(false); params_ty.len()];
     let mut rx = pin!(rx);
-    for (i, (v, ty)) in zip(&mut params, params_ty).enumerate() {
-        read_value(&mut store, &mut rx, guest_resources, v, ty, &[i])
+    let (deadline_header, invocation_id_header, execution_timeout, vals) = {
+        let mut store = store.as_context_mut();
+        let view = store.data_mut().wrpc();
+        (
+            view.ctx.deadline_header(),
+            view.ctx.invocation_id_header(),
+            view.ctx.execution_timeout(),
+            view.ctx.val_pool().cloned(),
+        )
+    };
+    let deadline = if deadline_header {
+        read_deadline(&mut rx)
+            .await
+            .context("failed to decode deadline header")
+            .map_err(CallError::Decode)?
+            .and_then(|timeout| Instant::now().checked_add(timeout))
+    } else {
+        None
+    };
+    let invocation_id = if invocation_id_header {
+        read_invocation_id(&mut rx)
             .await
-            .with_context(|| format!("failed to decode parameter value {i}"))
-            .map_err(CallError::Decode)?;
+            .context("failed to decode invocation ID header")
+            .map_err(CallError::Decode)?
+    } else {
+        Uuid::now_v7()
+    };
+    Span::current().record("invocation_id", tracing::field::display(invocation_id));
+    let func_ty = func.ty(&store);
+    if func_ty.params().len() != params_ty.len() || func_ty.results().len() != results_ty.len() {
+        return Err(CallError::TypeMismatch(anyhow!(
+            "function has {} parameters and {} results, but types of {} and {} were specified",
+            func_ty.params().len(),
+            func_ty.results().len(),
+            params_ty.len(),
+            results_ty.len(),
+        )));
+    }
+    let mut params = vals.as_ref().map_or_else(
+        || vec![Val::Bool(false); params_ty.len()],
+        |vals| vals.get(params_ty.len()),
+    );
+    if params.len() != params_ty.len() {
+        return Err(CallError::TypeMismatch(anyhow!(
+            "allocated {} parameter values, but {} parameter types were specified",
+            params.len(),
+            params_ty.len(),
+        )));
     }
-    let mut results = vec![Val::Bool(false); results_ty.len()];
-    func.call_async(&mut store, &params, &mut results)
+    let params_ty: Vec<Type> = params_ty.cloned().collect();
+    let mut output_streams = vec![];
+    let mut forwarders = vec![];
+    for (i, (v, ty)) in zip(&mut params, &params_ty).enumerate() {
+        read_value_with_output_streams(
+            &mut store,
+            &mut rx,
+            guest_resources,
+            v,
+            ty,
+            &[i],
+            &mut output_streams,
+        )
         .await
-        .context("failed to call function")
-        .map_err(CallError::Call)?;
+        .with_context(|| format!("failed to decode parameter value {i}"))
+        .map_err(CallError::Decode)?;
+    }
+    if !output_streams.is_empty() {
+        // data written to output stream parameters is transmitted on the outgoing stream
+        // following the results, see [`ValEncoder::output_streams`]
+        let results = match rpc_result_type(host_resources, results_ty) {
+            None => results_ty.len(),
+            Some(None) => 0,
+            Some(Some(..)) => 1,
+        };
+        let chunk_size = store
+            .as_context_mut()
+            .data_mut()
+            .wrpc()
+            .ctx
+            .input_stream_chunk_size();
+        for (mut path, stream) in output_streams {
+            path[0] += results;
+            let w = tx
+                .index(&path)
+                .context("failed to index outgoing stream")
+                .map_err(CallError::Write)?;
+            // NOTE: The guest may hold on to the stream past the end of the call, so the data is
+            // forwarded concurrently and the invocation only completes once the stream is closed
+            forwarders.push(AbortOnDropHandle::new(tokio::spawn(async move {
+                write_input_stream(stream, chunk_size, w)
+                    .await
+                    .with_context(|| format!("failed to transmit output stream data at {path:?}"))
+            })));
+        }
+    }
+    store
+        .as_context_mut()
+        .data_mut()
+        .transform_params(&mut params, &params_ty)
+        .context("failed to transform parameter values")
+        .map_err(CallError::Decode)?;
+    let mut results = vals.as_ref().map_or_else(
+        || vec![Val::Bool(false); results_ty.len()],
+        |vals| vals.get(results_ty.len()),
+    );
+    if results.len() != results_ty.len() {
+        return Err(CallError::TypeMismatch(anyhow!(
+            "allocated {} result values, but {} result types were specified",
+            results.len(),
+            results_ty.len(),
+        )));
+    }
+    if execution_timeout.is_some() {
+        // NOTE: This replaces any epoch deadline behavior configured by the embedder for good,
+        // see [`WrpcCtx::execution_timeout`]
+        let mut store = store.as_context_mut();
+        store.set_epoch_deadline(1);
+        store.epoch_deadline_async_yield_and_update(1);
+    }
+    let call = async {
+        let call = async {
+            let call = func.call_async(&mut store, &params, &mut results);
+            if let Some(timeout) = execution_timeout {
+                tokio::time::timeout(timeout, call).await.map_err(|_| {
+                    CallError::ExecutionTimeout(anyhow!(
+                        "guest execution time limit of {timeout:?} exceeded"
+                    ))
+                })
+            } else {
+                Ok(call.await)
+            }
+        };
+        let res = if let Some(deadline) = deadline {
+            tokio::time::timeout_at(deadline, call)
+                .await
+                .map_err(|_| CallError::DeadlineExceeded(anyhow!("invocation deadline exceeded")))?
+        } else {
+            call.await
+        };
+        res?.context("failed to call function")
+            .map_err(CallError::Call)
+    };
+    if cancel {
+        tokio::select! {
+            res = call => res?,
+            err = stream_failed(&mut rx) => {
+                debug!(?err, "incoming stream failed, cancel function call");
+                return Err(CallError::Cancelled(
+                    anyhow!(err).context("invocation was cancelled by the peer"),
+                ));
+            }
+        }
+    } else {
+        call.await?;
+    }
+    if let Some(vals) = &vals {
+        vals.put(params);
+    }
+    store
+        .as_context_mut()
+        .data_mut()
+        .transform_results(&mut results, results_ty)
+        .context("failed to transform result values")
+        .map_err(CallError::Encode)?;
 
-    let mut buf = BytesMut::default();
+    let pool = store
+        .as_context_mut()
+        .data_mut()
+        .wrpc()
+        .ctx
+        .buffer_pool()
+        .cloned();
+    let mut buf = pool.as_ref().map(BufferPool::get).unwrap_or_default();
     let mut deferred = vec![];
     match (
         &rpc_result_type(host_resources, results_ty),
@@ -416,6 +1238,11 @@ where
                 enc.encode(v, &mut buf)
                     .with_context(|| format!("failed to encode result value {i}"))
                     .map_err(CallError::Encode)?;
+                if !enc.output_streams.is_empty() {
+                    return Err(CallError::Encode(anyhow!(
+                        "`wasi:io/output-stream` result values are not supported"
+                    )));
+                }
                 deferred.push(enc.deferred);
             }
         }
@@ -427,6 +1254,11 @@ where
             enc.encode(v, &mut buf)
                 .context("failed to encode result value 0")
                 .map_err(CallError::Encode)?;
+            if !enc.output_streams.is_empty() {
+                return Err(CallError::Encode(anyhow!(
+                    "`wasi:io/output-stream` result values are not supported"
+                )));
+            }
             deferred.push(enc.deferred);
         }
         (Some(..), [Val::Result(Err(Some(err)))]) => {
@@ -448,12 +1280,18 @@ where
         }
         _ => return Err(CallError::TypeMismatch(anyhow!("RPC result type mismatch"))),
     }
+    if let Some(vals) = vals {
+        vals.put(results);
+    }
 
     debug!("transmitting results");
     tx.write_all(&buf)
         .await
         .context("failed to transmit results")
         .map_err(CallError::Write)?;
+    if let Some(pool) = pool {
+        pool.put(buf);
+    }
     tx.flush()
         .await
         .context("failed to flush outgoing stream")
@@ -461,20 +1299,29 @@ where
     if let Err(err) = tx.shutdown().await {
         trace!(?err, "failed to shutdown outgoing stream");
     }
-    try_join_all(
-        zip(0.., deferred)
-            .filter_map(|(i, f)| f.map(|f| (tx.index(&[i]), f)))
-            .map(|(w, f)| async move {
-                let w = w?;
-                f(w).await
-            }),
-    )
-    .await
-    .map_err(CallError::Deferred)?;
+    let limit = store
+        .as_context_mut()
+        .data_mut()
+        .wrpc()
+        .ctx
+        .max_deferred_writers();
+    // values of `future` and `stream` results are only transferred from the guest while the
+    // event loop of the store is running
+    store
+        .as_context_mut()
+        .run_concurrent(async |_| write_deferred(&tx, deferred, limit).await)
+        .await
+        .and_then(|res| res)
+        .map_err(CallError::Deferred)?;
     func.post_return_async(&mut store)
         .await
         .context("failed to perform post-return cleanup")
         .map_err(CallError::PostReturn)?;
+    for res in future::join_all(forwarders).await {
+        res.context("output stream forwarder panicked")
+            .and_then(|res| res)
+            .map_err(CallError::Deferred)?;
+    }
     Ok(())
 }
 
@@ -568,3 +1415,265 @@ pub fn collect_component_resource_imports(
         }
     }
 }
+
+/// Kind of an item imported by a component
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ImportKind {
+    /// Component function
+    Func,
+    /// Core function
+    CoreFunc,
+    /// Core module
+    Module,
+    /// Component
+    Component,
+    /// Component instance, which is only reported if nested within an imported instance
+    Instance,
+    /// Resource type
+    Resource,
+    /// Type
+    Type,
+}
+
+impl From<&types::ComponentItem> for ImportKind {
+    fn from(ty: &types::ComponentItem) -> Self {
+        match ty {
+            types::ComponentItem::ComponentFunc(..) => Self::Func,
+            types::ComponentItem::CoreFunc(..) => Self::CoreFunc,
+            types::ComponentItem::Module(..) => Self::Module,
+            types::ComponentItem::Component(..) => Self::Component,
+            types::ComponentItem::ComponentInstance(..) => Self::Instance,
+            types::ComponentItem::Resource(..) => Self::Resource,
+            types::ComponentItem::Type(..) => Self::Type,
+        }
+    }
+}
+
+/// Item imported by a component, see [`collect_component_imports`]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ComponentImport {
+    /// Name of the imported instance containing the item, empty for items imported by the
+    /// component directly
+    pub instance: Box<str>,
+    /// Name of the item
+    pub name: Box<str>,
+    /// Kind of the item
+    pub kind: ImportKind,
+}
+
+/// Iterates the component type and collects all imported items. Items of imported instances
+/// are collected individually, analogous to how they are polyfilled by [`link_item`].
+#[instrument(level = "debug", skip_all)]
+pub fn collect_component_imports(
+    engine: &Engine,
+    ty: &types::Component,
+    imports: &mut impl Extend<ComponentImport>,
+) {
+    for (name, ty) in ty.imports(engine) {
+        if let types::ComponentItem::ComponentInstance(ty) = ty {
+            let instance = name;
+            for (name, ty) in ty.exports(engine) {
+                debug!(instance, name, ?ty, "collect instance import");
+                imports.extend([ComponentImport {
+                    instance: instance.into(),
+                    name: name.into(),
+                    kind: ImportKind::from(&ty),
+                }]);
+            }
+        } else {
+            debug!(name, ?ty, "collect component import");
+            imports.extend([ComponentImport {
+                instance: "".into(),
+                name: name.into(),
+                kind: ImportKind::from(&ty),
+            }]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use wasmtime::component::Component;
+    use wasmtime::Store;
+
+    use super::*;
+
+    #[test]
+    fn buffer_pool_recycles() {
+        let pool = BufferPool::default();
+        assert!(pool.is_empty());
+
+        let mut buf = pool.get();
+        buf.extend_from_slice(b"test");
+        let ptr = buf.as_ptr();
+        pool.put(buf);
+        assert_eq!(pool.len(), 1);
+
+        let buf = pool.get();
+        assert!(buf.is_empty());
+        assert_eq!(buf.as_ptr(), ptr);
+        assert!(pool.is_empty());
+
+        let mut buf = buf;
+        buf.extend_from_slice(b"test");
+        let buf = buf.freeze();
+        let shared = buf.clone();
+        pool.put_bytes(buf);
+        assert!(pool.is_empty());
+        pool.put_bytes(shared);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn val_pool_recycles() {
+        let pool = ValPool::default();
+        let mut vals = pool.get(2);
+        assert_eq!(vals, [Val::Bool(false), Val::Bool(false)]);
+        vals[0] = Val::U32(42);
+        vals.reserve(8);
+        let ptr = vals.as_ptr();
+        pool.put(vals);
+        assert_eq!(pool.len(), 1);
+
+        let vals = pool.get(3);
+        assert_eq!(vals, [Val::Bool(false), Val::Bool(false), Val::Bool(false)]);
+        assert_eq!(vals.as_ptr(), ptr);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn async_paths_nested_input_stream() -> anyhow::Result<()> {
+        let engine = Engine::default();
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (import "wasi:io/streams@0.2.0" (instance $streams
+                    (export "input-stream" (type (sub resource)))
+                ))
+                (alias export $streams "input-stream" (type $input-stream))
+                (import "f" (func (param "a" u32) (param "b" (list (own $input-stream)))))
+            )"#,
+        )?;
+        let ty = component.component_type();
+        let mut resources = BTreeMap::default();
+        collect_component_resource_imports(&engine, &ty, &mut resources);
+        let host_resources: HashMap<_, HashMap<_, _>> = resources
+            .into_iter()
+            .map(|(instance, resources)| {
+                let resources = resources
+                    .into_iter()
+                    .map(|(name, ty)| (name, (ty, ResourceType::host::<DynInputStream>())))
+                    .collect();
+                (instance, resources)
+            })
+            .collect();
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            ty.imports(&engine).find(|(name, _)| *name == "f")
+        else {
+            bail!("component does not import `f`");
+        };
+        let paths = async_paths(&host_resources, ty.params().map(|(_, ty)| ty));
+        assert_eq!(paths.len(), 1);
+        assert_eq!(*paths[0], [Some(1), None]);
+        Ok(())
+    }
+
+    #[test]
+    fn component_imports() -> anyhow::Result<()> {
+        let engine = Engine::default();
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (import "wasi:io/streams@0.2.0" (instance $streams
+                    (export "input-stream" (type (sub resource)))
+                ))
+                (import "test:test/iface" (instance
+                    (export "f" (func (param "a" u32)))
+                    (export "g" (func (result string)))
+                ))
+                (import "h" (func (param "a" u32)))
+                (import "r" (type (sub resource)))
+            )"#,
+        )?;
+        let mut imports = Vec::default();
+        collect_component_imports(&engine, &component.component_type(), &mut imports);
+        imports.sort_by(|a, b| (&a.instance, &a.name).cmp(&(&b.instance, &b.name)));
+        let import = |instance: &str, name: &str, kind| ComponentImport {
+            instance: instance.into(),
+            name: name.into(),
+            kind,
+        };
+        assert_eq!(
+            imports,
+            [
+                import("", "h", ImportKind::Func),
+                import("", "r", ImportKind::Resource),
+                import("test:test/iface", "f", ImportKind::Func),
+                import("test:test/iface", "g", ImportKind::Func),
+                import(
+                    "wasi:io/streams@0.2.0",
+                    "input-stream",
+                    ImportKind::Resource
+                ),
+            ]
+        );
+        Ok(())
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn shared_resource_eviction() -> anyhow::Result<()> {
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let a = Resource::<u32>::new_own(1).try_into_resource_any(&mut store)?;
+        let b = Resource::<u32>::new_own(2).try_into_resource_any(&mut store)?;
+
+        let mut table = SharedResourceTable::default();
+        let a_id = Uuid::now_v7();
+        let b_id = Uuid::now_v7();
+        assert!(table.insert(a_id, a).is_none());
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(table.insert(b_id, b).is_none());
+        assert_eq!(table.len(), 2);
+
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert_eq!(table.evict_older_than(Duration::from_secs(7)), [(a_id, a)]);
+        assert_eq!(table.len(), 1);
+        assert!(table.get(&a_id).is_none());
+        assert_eq!(table.get(&b_id), Some(&b));
+
+        assert_eq!(table.remove(&b_id), Some(b));
+        assert!(table.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn shared_resource_ids() -> anyhow::Result<()> {
+        let engine = Engine::default();
+        let mut store = Store::new(&engine, ());
+        let a = Resource::<u32>::new_own(1).try_into_resource_any(&mut store)?;
+        let b = Resource::<u32>::new_own(2).try_into_resource_any(&mut store)?;
+
+        let mut table = SharedResourceTable::default();
+        assert!(table.is_empty());
+        assert_eq!(table.ids().next(), None);
+
+        let a_id = Uuid::now_v7();
+        let b_id = Uuid::now_v7();
+        table.insert(a_id, a);
+        assert_eq!(table.len(), 1);
+        table.insert(b_id, b);
+        assert_eq!(table.len(), 2);
+        let mut ids: Vec<_> = table.ids().collect();
+        ids.sort();
+        assert_eq!(ids, [a_id, b_id]);
+        // listing IDs does not consume the resources
+        assert_eq!(table.get(&a_id), Some(&a));
+
+        table.remove(&a_id);
+        assert_eq!(table.len(), 1);
+        assert_eq!(table.ids().collect::<Vec<_>>(), [b_id]);
+        table.remove(&b_id);
+        assert!(table.is_empty());
+        Ok(())
+    }
+}
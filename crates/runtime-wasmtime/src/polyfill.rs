@@ -1,25 +1,34 @@
+use core::fmt;
 use core::iter::zip;
 use core::pin::pin;
 
+use std::borrow::Cow;
 use std::collections::HashMap;
 use std::sync::Arc;
 
 use anyhow::{bail, ensure, Context as _};
-use bytes::BytesMut;
 use futures::future::try_join_all;
-use tokio::io::AsyncWriteExt as _;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
 use tokio::time::Instant;
 use tokio::try_join;
 use tokio_util::codec::Encoder;
-use tracing::{debug, instrument, trace, warn, Instrument as _, Span};
-use wasmtime::component::{types, LinkerInstance, ResourceType, Type, Val};
-use wasmtime::{AsContextMut, Engine, StoreContextMut};
+use tracing::{debug, debug_span, instrument, warn, Instrument as _, Span};
+use uuid::Uuid;
+use wasmtime::component::{types, LinkerInstance, Resource, ResourceType, Type, Val};
+use wasmtime::{AsContextMut, Engine, FuncType, StoreContextMut, ValType};
 use wrpc_transport::{Index as _, Invoke, InvokeExt as _};
 
 use crate::rpc::Error;
-use crate::{read_value, rpc_func_name, rpc_result_type, ValEncoder, WrpcView, WrpcViewExt as _};
+use crate::{
+    async_paths, encode_deadline, encode_invocation_id, read_output_stream, read_value,
+    rpc_result_type, write_deferred, BufferPool, LazyRemoteResource, RemoteResource, ValEncoder,
+    WrpcView, WrpcViewExt as _,
+};
 
 /// Polyfill [`types::ComponentItem`] in a [`LinkerInstance`] using [`wrpc_transport::Invoke`]
+///
+/// Dropping a resource, which is represented by a [`RemoteResource`] on the host, invokes
+/// `[resource-drop]<resource>` on the peer as described in the specification.
 #[instrument(level = "trace", skip_all)]
 pub fn link_item<V>(
     engine: &Engine,
@@ -49,6 +58,7 @@ where
                 name,
             )?;
         }
+        // core functions imported by core modules are polyfilled using `link_core_function`
         types::ComponentItem::CoreFunc(_) => {
             bail!("polyfilling core functions not supported yet")
         }
@@ -94,7 +104,45 @@ where
             ensure!(ty == *guest_ty, "{instance}/{name} resource type mismatch");
 
             debug!(?instance, ?name, "linking resource");
-            linker.resource(&name, *host_ty, |_, _| Ok(()))?;
+            if *host_ty == ResourceType::host::<RemoteResource>()
+                || *host_ty == ResourceType::host::<LazyRemoteResource>()
+            {
+                // the resource is owned by the peer, notify it of the drop
+                let drop_name: Arc<str> = format!("[resource-drop]{name}").into();
+                let host_ty = *host_ty;
+                linker.resource_async(&name, host_ty, move |mut store, rep| {
+                    let instance = Arc::clone(&instance);
+                    let name = Arc::clone(&drop_name);
+                    let resources = Arc::clone(&guest_resources);
+                    Box::new(async move {
+                        let res = if host_ty == ResourceType::host::<RemoteResource>() {
+                            Resource::<RemoteResource>::new_own(rep)
+                                .try_into_resource_any(&mut store)?
+                        } else {
+                            Resource::<LazyRemoteResource>::new_own(rep)
+                                .try_into_resource_any(&mut store)?
+                        };
+                        if let Err(err) = invoke(
+                            &mut store,
+                            &[Val::Resource(res)],
+                            &mut [],
+                            resources,
+                            [("self", Type::Own(host_ty))],
+                            None,
+                            &[],
+                            Arc::clone(&instance),
+                            Arc::clone(&name),
+                        )
+                        .await?
+                        {
+                            warn!(?err, %instance, %name, "failed to drop remote resource");
+                        }
+                        Ok(())
+                    })
+                })?;
+            } else {
+                linker.resource(&name, *host_ty, |_, _| Ok(()))?;
+            }
         }
     }
     Ok(())
@@ -131,76 +179,165 @@ where
     Ok(())
 }
 
+#[instrument(
+    level = "debug",
+    skip_all,
+    fields(%instance, %name, invocation_id = tracing::field::Empty)
+)]
 #[allow(clippy::too_many_arguments)]
 async fn invoke<T: WrpcView>(
     mut store: &mut StoreContextMut<'_, T>,
     params: &[Val],
     results: &mut [Val],
     guest_resources: Arc<[ResourceType]>,
-    params_ty: impl IntoIterator<Item = (&str, Type)>,
-    results_ty: impl IntoIterator<Item = Type>,
+    params_ty: impl IntoIterator<Item = (&str, Type), IntoIter: ExactSizeIterator>,
+    results_ty: impl IntoIterator<Item = Type, IntoIter: ExactSizeIterator>,
+    paths: &[Box<[Option<usize>]>],
     instance: Arc<str>,
     name: Arc<str>,
 ) -> wasmtime::Result<anyhow::Result<()>> {
-    let mut buf = BytesMut::default();
+    let params_ty = params_ty.into_iter();
+    let results_ty = results_ty.into_iter();
+    ensure!(
+        params.len() == params_ty.len(),
+        "`{instance}.{name}` takes {} parameters, but {} were given",
+        params_ty.len(),
+        params.len(),
+    );
+    ensure!(
+        results.len() == results_ty.len(),
+        "`{instance}.{name}` returns {} results, but space for {} was given",
+        results_ty.len(),
+        results.len(),
+    );
+    let (rpc_instance, rpc_name) = T::rpc_name(&instance, &name);
+    let view = store.data_mut().wrpc();
+    let timeout = view.ctx.timeout_for(&instance, &name);
+    let pool = view.ctx.buffer_pool().cloned();
+    let limit = view.ctx.max_deferred_writers();
+    let fail_on_shutdown_error = view.ctx.fail_on_shutdown_error();
+    let mut buf = pool.as_ref().map(BufferPool::get).unwrap_or_default();
+    if view.ctx.deadline_header() {
+        encode_deadline(timeout, &mut buf)?;
+    }
+    let invocation_id = Uuid::now_v7();
+    Span::current().record("invocation_id", tracing::field::display(invocation_id));
+    if view.ctx.invocation_id_header() {
+        encode_invocation_id(invocation_id, &mut buf)?;
+    }
     let mut deferred = vec![];
-    for (v, (name, ref ty)) in zip(params, params_ty) {
+    let mut output_streams = vec![];
+    for (i, (v, (name, ref ty))) in zip(params, params_ty).enumerate() {
         let mut enc = ValEncoder::new(store.as_context_mut(), ty, &guest_resources);
         enc.encode(v, &mut buf)
             .with_context(|| format!("failed to encode parameter `{name}`"))?;
         deferred.push(enc.deferred);
+        // data written to output stream parameters is received on the incoming stream
+        // following the results
+        let n = results.len() + i;
+        output_streams.extend(enc.output_streams.into_iter().map(|(mut path, stream)| {
+            path.insert(0, n);
+            (path, stream)
+        }));
     }
+    let paths = if output_streams.is_empty() {
+        Cow::Borrowed(paths)
+    } else {
+        Cow::Owned(
+            paths
+                .iter()
+                .cloned()
+                .chain(
+                    output_streams
+                        .iter()
+                        .map(|(path, _)| path.iter().copied().map(Some).collect()),
+                )
+                .collect::<Vec<_>>(),
+        )
+    };
     let view = store.data_mut().wrpc();
     let clt = view.ctx.client();
     let cx = view.ctx.context();
-    let timeout = view.ctx.timeout();
     let buf = buf.freeze();
-    // TODO: set paths
-    let paths = &[[]; 0];
-    let rpc_name = rpc_func_name(&name);
     let start = Instant::now();
     let invocation = if let Some(timeout) = timeout {
         clt.timeout(timeout)
-            .invoke(cx, &instance, rpc_name, buf, paths)
+            .invoke(cx, &rpc_instance, &rpc_name, buf.clone(), &*paths)
             .await
     } else {
-        clt.invoke(cx, &instance, rpc_name, buf, paths).await
+        clt.invoke(cx, &rpc_instance, &rpc_name, buf.clone(), &*paths)
+            .await
     }
     .with_context(|| format!("failed to invoke `{instance}.{name}` polyfill via wRPC"));
     let (outgoing, incoming) = match invocation {
         Ok((outgoing, incoming)) => (outgoing, incoming),
+        Err(err) => {
+            if let Some(pool) = pool {
+                pool.put_bytes(buf);
+            }
+            return Ok(Err(err));
+        }
+    };
+    let output_streams = match output_streams
+        .into_iter()
+        .map(|(path, stream)| {
+            let r = incoming
+                .index(&path)
+                .context("failed to index incoming stream")?;
+            anyhow::Ok(read_output_stream(stream, r))
+        })
+        .collect::<anyhow::Result<Vec<_>>>()
+    {
+        Ok(output_streams) => output_streams,
         Err(err) => return Ok(Err(err)),
     };
     let tx = async {
-        try_join_all(
-            zip(0.., deferred)
-                .filter_map(|(i, f)| f.map(|f| (outgoing.index(&[i]), f)))
-                .map(|(w, f)| async move {
-                    let w = w?;
-                    f(w).await
-                }),
-        )
-        .await
-        .context("failed to write asynchronous parameters")?;
+        write_deferred(&outgoing, deferred, limit)
+            .await
+            .context("failed to write asynchronous parameters")?;
         let mut outgoing = pin!(outgoing);
         outgoing
             .flush()
             .await
             .context("failed to flush outgoing stream")?;
         if let Err(err) = outgoing.shutdown().await {
-            trace!(?err, "failed to shutdown outgoing stream");
+            if fail_on_shutdown_error {
+                return Err(err).context("failed to shutdown outgoing stream");
+            }
+            warn!(?err, "failed to shutdown outgoing stream");
         }
         anyhow::Ok(())
-    };
+    }
+    .instrument(debug_span!("transmit", %invocation_id));
+    // NOTE: `incoming` is moved into the future, so on timeout it is dropped together with the
+    // cancelled decode, rather than being left at an arbitrary offset
     let rx = async {
         let mut incoming = pin!(incoming);
+        if results.is_empty() {
+            // no values are transmitted, so await the end of the result stream, which signals
+            // completion of the call, rather than closing the stream while the peer may
+            // still be transmitting
+            let n = incoming
+                .read(&mut [0])
+                .await
+                .context("failed to await end of result stream")?;
+            ensure!(n == 0, "received data for a function without results");
+        }
         for (i, (v, ref ty)) in zip(results, results_ty).enumerate() {
             read_value(&mut store, &mut incoming, &guest_resources, v, ty, &[i])
                 .await
                 .with_context(|| format!("failed to decode return value {i}"))?;
         }
         Ok(())
-    };
+    }
+    .instrument(debug_span!("receive", %invocation_id));
+    let fwd = async {
+        try_join_all(output_streams)
+            .await
+            .context("failed to receive output stream data")?;
+        anyhow::Ok(())
+    }
+    .instrument(debug_span!("forward", %invocation_id));
     let res = if let Some(timeout) = timeout {
         let timeout = timeout.saturating_sub(Instant::now().saturating_duration_since(start));
         try_join!(
@@ -214,16 +351,101 @@ async fn invoke<T: WrpcView>(
                     .await
                     .context("data receipt timed out")?
             },
+            async {
+                tokio::time::timeout(timeout, fwd)
+                    .await
+                    .context("output stream data receipt timed out")?
+            },
         )
     } else {
-        try_join!(tx, rx)
+        try_join!(tx, rx, fwd)
     };
+    // NOTE: the transport may hold on to the parameter buffer until the outgoing stream is
+    // shut down, so it can only be reclaimed once the invocation is complete
+    if let Some(pool) = pool {
+        pool.put_bytes(buf);
+    }
     match res {
-        Ok(((), ())) => Ok(Ok(())),
+        Ok(((), (), ())) => Ok(Ok(())),
         Err(err) => Ok(Err(err)),
     }
 }
 
+/// Invokes function `name` of `instance` of type `ty` with `params` using the
+/// [`wrpc_transport::Invoke`] implementation of the [`WrpcCtx`](crate::WrpcCtx) of `store`,
+/// the same way polyfilled imports are invoked, and returns the decoded results.
+///
+/// `ty` should be the type of an instantiated function, e.g. as returned by
+/// [`wasmtime::component::Func::ty`], so that `wasi:io/input-stream` values can be identified.
+/// `guest_resources` are the guest resources exported by the component.
+pub async fn invoke_values<T: WrpcView>(
+    mut store: impl AsContextMut<Data = T>,
+    guest_resources: impl Into<Arc<[ResourceType]>>,
+    ty: &types::ComponentFunc,
+    instance: &str,
+    name: &str,
+    params: &[Val],
+) -> anyhow::Result<Vec<Val>> {
+    let mut results = vec![Val::Bool(false); ty.results().len()];
+    let paths = async_paths(&HashMap::default(), ty.results());
+    invoke(
+        &mut store.as_context_mut(),
+        params,
+        &mut results,
+        guest_resources.into(),
+        ty.params(),
+        ty.results(),
+        &paths,
+        instance.into(),
+        name.into(),
+    )
+    .await??;
+    Ok(results)
+}
+
+/// Error returned by [invoke_result] if the invoked function returned `result::err`.
+///
+/// It can be retrieved from the returned [`anyhow::Error`] using [`anyhow::Error::downcast`].
+#[derive(Debug)]
+pub struct ResultError(pub Option<Val>);
+
+impl core::error::Error for ResultError {}
+
+impl fmt::Display for ResultError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(err) = &self.0 {
+            write!(f, "function returned an error: {err:?}")
+        } else {
+            write!(f, "function returned an error")
+        }
+    }
+}
+
+/// Invokes function `name` of `instance` returning a single `result` like [invoke_values] does
+/// and maps the returned `result` into a Rust [`Result`].
+///
+/// The `result::ok` payload is returned on success, while a `result::err` is returned as
+/// [`ResultError`] carrying the decoded payload.
+pub async fn invoke_result<T: WrpcView>(
+    store: impl AsContextMut<Data = T>,
+    guest_resources: impl Into<Arc<[ResourceType]>>,
+    ty: &types::ComponentFunc,
+    instance: &str,
+    name: &str,
+    params: &[Val],
+) -> anyhow::Result<Option<Val>> {
+    let mut results = ty.results();
+    let (Some(Type::Result(..)), None) = (results.next(), results.next()) else {
+        bail!("function `{instance}#{name}` does not return a single `result`")
+    };
+    let results = invoke_values(store, guest_resources, ty, instance, name, params).await?;
+    match <[Val; 1]>::try_from(results) {
+        Ok([Val::Result(Ok(v))]) => Ok(v.map(|v| *v)),
+        Ok([Val::Result(Err(err))]) => Err(ResultError(err.map(|err| *err)).into()),
+        _ => bail!("function `{instance}#{name}` returned an invalid value"),
+    }
+}
+
 /// Polyfill [`types::ComponentFunc`] in a [`LinkerInstance`] using [`wrpc_transport::Invoke`]
 #[instrument(level = "trace", skip_all)]
 pub fn link_function<V>(
@@ -243,32 +465,37 @@ where
     let guest_resources = guest_resources.into();
     let host_resources = host_resources.into();
     match rpc_result_type(&host_resources, ty.results()) {
-        None => linker.func_new_async(&Arc::clone(&name), move |mut store, ty, params, results| {
-            let instance = Arc::clone(&instance);
-            let name = Arc::clone(&name);
-            let resources = Arc::clone(&guest_resources);
-            Box::new(
-                async move {
-                    match invoke(
-                        &mut store,
-                        params,
-                        results,
-                        resources,
-                        ty.params(),
-                        ty.results(),
-                        instance,
-                        name,
-                    )
-                    .await
-                    {
-                        Ok(Ok(())) => Ok(()),
-                        Ok(Err(err)) => Err(err),
-                        Err(err) => Err(err),
+        None => {
+            let paths: Arc<[_]> = async_paths(&host_resources, ty.results()).into();
+            linker.func_new_async(&Arc::clone(&name), move |mut store, ty, params, results| {
+                let instance = Arc::clone(&instance);
+                let name = Arc::clone(&name);
+                let resources = Arc::clone(&guest_resources);
+                let paths = Arc::clone(&paths);
+                Box::new(
+                    async move {
+                        match invoke(
+                            &mut store,
+                            params,
+                            results,
+                            resources,
+                            ty.params(),
+                            ty.results(),
+                            &paths,
+                            instance,
+                            name,
+                        )
+                        .await
+                        {
+                            Ok(Ok(())) => Ok(()),
+                            Ok(Err(err)) => Err(err),
+                            Err(err) => Err(err),
+                        }
                     }
-                }
-                .instrument(span.clone()),
-            )
-        }),
+                    .instrument(span.clone()),
+                )
+            })
+        }
         // `result<_, rpc-eror>`
         Some(None) => {
             linker.func_new_async(&Arc::clone(&name), move |mut store, ty, params, results| {
@@ -287,6 +514,7 @@ where
                             resources,
                             ty.params(),
                             None,
+                            &[],
                             instance,
                             name,
                         )
@@ -311,11 +539,13 @@ where
         }
         // `result<T, rpc-eror>`
         Some(Some(result_ty)) => {
+            let paths: Arc<[_]> = async_paths(&host_resources, [&result_ty]).into();
             linker.func_new_async(&Arc::clone(&name), move |mut store, ty, params, results| {
                 let instance = Arc::clone(&instance);
                 let name = Arc::clone(&name);
                 let resources = Arc::clone(&guest_resources);
                 let result_ty = result_ty.clone();
+                let paths = Arc::clone(&paths);
                 Box::new(
                     async move {
                         let [result] = results else {
@@ -329,6 +559,7 @@ where
                             resources,
                             ty.params(),
                             [result_ty],
+                            &paths,
                             instance,
                             name,
                         )
@@ -354,3 +585,108 @@ where
         }
     }
 }
+
+/// Returns the component value type used to encode values of core type `ty`
+fn core_value_type(ty: &ValType) -> wasmtime::Result<Type> {
+    match ty {
+        ValType::I32 => Ok(Type::S32),
+        ValType::I64 => Ok(Type::S64),
+        ValType::F32 => Ok(Type::Float32),
+        ValType::F64 => Ok(Type::Float64),
+        ValType::V128 | ValType::Ref(..) => bail!("core value type `{ty}` not supported"),
+    }
+}
+
+fn from_core_value(v: &wasmtime::Val) -> wasmtime::Result<Val> {
+    match *v {
+        wasmtime::Val::I32(v) => Ok(Val::S32(v)),
+        wasmtime::Val::I64(v) => Ok(Val::S64(v)),
+        wasmtime::Val::F32(v) => Ok(Val::Float32(f32::from_bits(v))),
+        wasmtime::Val::F64(v) => Ok(Val::Float64(f64::from_bits(v))),
+        _ => bail!("core value type not supported"),
+    }
+}
+
+fn into_core_value(v: Val) -> wasmtime::Result<wasmtime::Val> {
+    match v {
+        Val::S32(v) => Ok(wasmtime::Val::I32(v)),
+        Val::S64(v) => Ok(wasmtime::Val::I64(v)),
+        Val::Float32(v) => Ok(wasmtime::Val::F32(v.to_bits())),
+        Val::Float64(v) => Ok(wasmtime::Val::F64(v.to_bits())),
+        _ => bail!("result type mismatch"),
+    }
+}
+
+/// Polyfill core function `name` of `module` of type `ty` in a core [`wasmtime::Linker`] using
+/// [`wrpc_transport::Invoke`], e.g. to satisfy the imports of a core module.
+///
+/// Only signatures consisting of numeric types are supported, with any amount of parameters
+/// and results. `i32` and `i64` values are encoded like `s32` and `s64` values and `f32` and
+/// `f64` values like `f32` and `f64` values of the component model. Signatures containing
+/// `v128` or reference types are rejected.
+#[instrument(level = "trace", skip_all)]
+pub fn link_core_function<V>(
+    linker: &mut wasmtime::Linker<V>,
+    ty: FuncType,
+    module: impl Into<Arc<str>>,
+    name: impl Into<Arc<str>>,
+) -> wasmtime::Result<()>
+where
+    V: WrpcView + 'static,
+{
+    let span = Span::current();
+    let module = module.into();
+    let name = name.into();
+    // core function parameters are unnamed, so they are identified by their index
+    let params_ty = ty
+        .params()
+        .enumerate()
+        .map(|(i, ty)| Ok((i.to_string(), core_value_type(&ty)?)))
+        .collect::<wasmtime::Result<Arc<[_]>>>()
+        .with_context(|| format!("unsupported parameter type of `{module}.{name}`"))?;
+    let results_ty = ty
+        .results()
+        .map(|ty| core_value_type(&ty))
+        .collect::<wasmtime::Result<Arc<[_]>>>()
+        .with_context(|| format!("unsupported result type of `{module}.{name}`"))?;
+    linker.func_new_async(
+        &Arc::clone(&module),
+        &Arc::clone(&name),
+        ty,
+        move |mut caller, params, results| {
+            let module = Arc::clone(&module);
+            let name = Arc::clone(&name);
+            let params_ty = Arc::clone(&params_ty);
+            let results_ty = Arc::clone(&results_ty);
+            Box::new(
+                async move {
+                    let params = params
+                        .iter()
+                        .map(from_core_value)
+                        .collect::<wasmtime::Result<Vec<_>>>()?;
+                    let mut vals = vec![Val::Bool(false); results.len()];
+                    invoke(
+                        &mut caller.as_context_mut(),
+                        &params,
+                        &mut vals,
+                        Arc::from([]),
+                        params_ty
+                            .iter()
+                            .map(|(name, ty)| (name.as_str(), ty.clone())),
+                        results_ty.iter().cloned(),
+                        &[],
+                        module,
+                        name,
+                    )
+                    .await??;
+                    for (result, v) in zip(results, vals) {
+                        *result = into_core_value(v)?;
+                    }
+                    Ok(())
+                }
+                .instrument(span.clone()),
+            )
+        },
+    )?;
+    Ok(())
+}
@@ -1,22 +1,385 @@
 use core::future::Future;
-use core::pin::Pin;
+use core::iter::zip;
+use core::num::NonZeroUsize;
+use core::pin::{pin, Pin};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use std::{collections::HashMap, sync::Arc};
 
-use anyhow::Context as _;
-use futures::{Stream, TryStreamExt as _};
-use tokio::sync::Mutex;
-use tracing::{debug, instrument, Instrument as _, Span};
+use anyhow::{bail, Context as _};
+use futures::future::Either;
+use futures::stream::BoxStream;
+use futures::{Stream, StreamExt as _, TryStreamExt as _};
+use tokio::io::AsyncWriteExt as _;
+use tokio::sync::{Mutex, Semaphore};
+use tokio::task::futures::TaskLocalFuture;
+use tracing::instrument::Instrumented;
+use tracing::{debug, debug_span, error, instrument, trace, warn, Instrument as _, Span};
 use wasmtime::component::types;
-use wasmtime::component::{Instance, InstancePre, ResourceType};
+use wasmtime::component::{
+    Component, ComponentExportIndex, Func, Instance, InstancePre, ResourceType, Type, Val,
+};
 use wasmtime::AsContextMut;
 use wasmtime_wasi::WasiView;
+use wrpc_transport::trace::TraceContext;
 
-use crate::{call, rpc_func_name, WrpcView};
+use crate::{async_paths, call, call_cancellable, read_value, CallError, WrpcView};
+
+/// Looks up the export index of instance `name` using `lookup`.
+///
+/// Names of nested instances are the names of all enclosing instances and the nested instance
+/// itself joined by `/`. Since interface names may contain `/` themselves, all possible splits
+/// are tried.
+fn instance_export_index(
+    name: &str,
+    lookup: &mut impl FnMut(Option<&ComponentExportIndex>, &str) -> Option<ComponentExportIndex>,
+) -> Option<ComponentExportIndex> {
+    if let Some(idx) = lookup(None, name) {
+        return Some(idx);
+    }
+    for (i, _) in name.rmatch_indices('/') {
+        let (parent, child) = (&name[..i], &name[i + 1..]);
+        if let Some(parent) = instance_export_index(parent, lookup) {
+            if let Some(idx) = lookup(Some(&parent), child) {
+                return Some(idx);
+            }
+        }
+    }
+    None
+}
+
+/// Looks up function export `name` of instance `instance_name` in `component` and checks that
+/// its signature matches `ty`, so that wiring mistakes are reported before serving instead of
+/// failing to encode or decode values of each invocation.
+fn func_export_index(
+    component: &Component,
+    instance_name: &str,
+    name: &str,
+    ty: &types::ComponentFunc,
+) -> anyhow::Result<ComponentExportIndex> {
+    let idx = if instance_name.is_empty() {
+        None
+    } else {
+        let idx = instance_export_index(instance_name, &mut |idx, name| {
+            component.get_export_index(idx, name)
+        })
+        .with_context(|| format!("export `{instance_name}` not found"))?;
+        Some(idx)
+    };
+    let (item, idx) = component
+        .get_export(idx.as_ref(), name)
+        .with_context(|| format!("export `{name}` not found"))?;
+    let types::ComponentItem::ComponentFunc(actual) = item else {
+        bail!("export `{name}` is not a function");
+    };
+    check_func_type(name, ty, &actual)?;
+    Ok(idx)
+}
+
+/// Checks that the signature `actual` of function export `name` matches `ty`
+fn check_func_type(
+    name: &str,
+    ty: &types::ComponentFunc,
+    actual: &types::ComponentFunc,
+) -> anyhow::Result<()> {
+    if ty.params().len() != actual.params().len() || ty.results().len() != actual.results().len() {
+        bail!(
+            "function export `{name}` has {} parameters and {} results, but {} and {} were specified",
+            actual.params().len(),
+            actual.results().len(),
+            ty.params().len(),
+            ty.results().len(),
+        );
+    }
+    for (i, ((_, expected), (_, actual))) in zip(ty.params(), actual.params()).enumerate() {
+        if expected != actual {
+            bail!(
+                "parameter {i} of function export `{name}` has type `{actual:?}`, but `{expected:?}` was specified"
+            );
+        }
+    }
+    for (i, (expected, actual)) in zip(ty.results(), actual.results()).enumerate() {
+        if expected != actual {
+            bail!(
+                "result {i} of function export `{name}` has type `{actual:?}`, but `{expected:?}` was specified"
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Item exported by a component
+#[derive(Clone, Debug)]
+pub enum Export {
+    /// Function, which is served by [`ServeExt::serve_component`]
+    Function {
+        /// Name of the exporting instance, empty for root exports
+        instance: String,
+        /// Name of the function
+        name: String,
+        /// Type of the function
+        ty: types::ComponentFunc,
+    },
+    /// Resource, drops of which are served by [`ServeExt::serve_component`] if the resource
+    /// is exported by the guest
+    Resource {
+        /// Name of the exporting instance, empty for root exports
+        instance: String,
+        /// Name of the resource
+        name: String,
+        /// Type of the resource
+        ty: ResourceType,
+    },
+    /// Item of `kind`, which cannot be served
+    Unsupported {
+        /// Name of the exporting instance, empty for root exports
+        instance: String,
+        /// Name of the item
+        name: String,
+        /// Kind of the item, for example, `module`
+        kind: &'static str,
+    },
+}
+
+/// Returns all items exported by `component`.
+///
+/// The instance name of items exported by the component directly is empty, items of
+/// nested instances are returned with the names of all enclosing instances joined by `/`.
+pub fn component_exports(component: &Component) -> Vec<Export> {
+    fn push(
+        exports: &mut Vec<Export>,
+        instances: &mut Vec<(String, types::ComponentInstance)>,
+        instance: &str,
+        name: &str,
+        ty: types::ComponentItem,
+    ) {
+        let instance = instance.to_string();
+        let name = name.to_string();
+        let export = match ty {
+            types::ComponentItem::ComponentFunc(ty) => Export::Function { instance, name, ty },
+            types::ComponentItem::Resource(ty) => Export::Resource { instance, name, ty },
+            types::ComponentItem::CoreFunc(_) => Export::Unsupported {
+                instance,
+                name,
+                kind: "core function",
+            },
+            types::ComponentItem::Module(_) => Export::Unsupported {
+                instance,
+                name,
+                kind: "module",
+            },
+            types::ComponentItem::Component(_) => Export::Unsupported {
+                instance,
+                name,
+                kind: "component",
+            },
+            types::ComponentItem::ComponentInstance(ty) => {
+                let instance = if instance.is_empty() {
+                    name
+                } else {
+                    format!("{instance}/{name}")
+                };
+                instances.push((instance, ty));
+                return;
+            }
+            types::ComponentItem::Type(_) => return,
+        };
+        exports.push(export);
+    }
+
+    let engine = component.engine();
+    let mut exports = Vec::new();
+    let mut instances = Vec::new();
+    for (name, ty) in component.component_type().exports(engine) {
+        push(&mut exports, &mut instances, "", name, ty);
+    }
+    while let Some((instance, ty)) = instances.pop() {
+        for (name, ty) in ty.exports(engine) {
+            push(&mut exports, &mut instances, &instance, name, ty);
+        }
+    }
+    exports
+}
+
+/// Annotates each invocation in `invocations` with the `instance` and `name` of the function
+#[allow(clippy::type_complexity)]
+fn with_names<C: 'static>(
+    instance: String,
+    name: String,
+    invocations: impl Stream<
+            Item = anyhow::Result<(
+                C,
+                Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+            )>,
+        > + Send
+        + 'static,
+) -> BoxStream<
+    'static,
+    anyhow::Result<(
+        Arc<str>,
+        Arc<str>,
+        C,
+        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+    )>,
+> {
+    let instance = Arc::<str>::from(instance);
+    let name = Arc::<str>::from(name);
+    invocations
+        .map_ok(move |(cx, fut)| (Arc::clone(&instance), Arc::clone(&name), cx, fut))
+        .boxed()
+}
+
+/// Idle instances of [`ServeExt::serve_function_pooled`] and permits bounding the number of
+/// instances in use
+struct InstancePool<T: 'static> {
+    idle: std::sync::Mutex<Vec<(wasmtime::Store<T>, Func)>>,
+    permits: Arc<Semaphore>,
+}
+
+/// Component instance in a store shared by all invocations served by
+/// [`ServeExt::serve_function_restartable`] and [`ServeExt::serve_resource_drop_restartable`].
+///
+/// Once a call leaves the instance in an unusable state, for example, because the guest
+/// trapped, the component is re-instantiated into a fresh store and serving continues.
+/// Since invocations are serialized by the instance lock, there are no calls in flight when
+/// the instance is rebuilt. All guest state, including guest-exported resources, is lost
+/// on restart.
+///
+/// The instance is also rebuilt before the next call if a call did not complete, because the
+/// future serving it panicked, e.g. in a host function, or was dropped, since the store may
+/// be left in the middle of a guest call in that case.
+pub struct RestartableInstance<T: 'static> {
+    store: Box<dyn Fn() -> wasmtime::Store<T> + Send + Sync>,
+    instance_pre: InstancePre<T>,
+    state: Mutex<(wasmtime::Store<T>, Instance)>,
+    restarts: AtomicUsize,
+    /// Whether a call was started, but did not complete
+    interrupted: AtomicBool,
+}
+
+impl<T: Send + 'static> RestartableInstance<T> {
+    /// Instantiates `instance_pre` in a store constructed by `store`, which is called again
+    /// to construct a fresh store on each restart
+    pub async fn new(
+        store: impl Fn() -> wasmtime::Store<T> + Send + Sync + 'static,
+        instance_pre: InstancePre<T>,
+    ) -> anyhow::Result<Self> {
+        let mut state = store();
+        let instance = instance_pre
+            .instantiate_async(&mut state)
+            .await
+            .context("failed to instantiate component")?;
+        Ok(Self {
+            store: Box::new(store),
+            instance_pre,
+            state: Mutex::new((state, instance)),
+            restarts: AtomicUsize::default(),
+            interrupted: AtomicBool::default(),
+        })
+    }
+
+    /// Returns the instantiated [`Component`]
+    pub fn component(&self) -> &Component {
+        self.instance_pre.component()
+    }
+
+    /// Returns the number of times the instance was rebuilt
+    pub fn restarts(&self) -> usize {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Replaces the instance in `state` by a fresh instance in a new store
+    async fn restart(&self, state: &mut (wasmtime::Store<T>, Instance)) -> anyhow::Result<()> {
+        let mut store = (self.store)();
+        let instance = self
+            .instance_pre
+            .instantiate_async(&mut store)
+            .await
+            .context("failed to re-instantiate component")?;
+        *state = (store, instance);
+        self.restarts.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Restarts the instance in `state` if the last call did not complete
+    async fn restart_if_interrupted(
+        &self,
+        state: &mut (wasmtime::Store<T>, Instance),
+    ) -> anyhow::Result<()> {
+        if self.interrupted.load(Ordering::Relaxed) {
+            warn!("previous call did not complete, restarting instance");
+            self.restart(state).await?;
+            self.interrupted.store(false, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Marks the start of a call, which must be followed by [`Self::end_call`] once the call
+    /// completes
+    fn begin_call(&self) {
+        self.interrupted.store(true, Ordering::Relaxed);
+    }
+
+    /// Marks the completion of the call started by [`Self::begin_call`]
+    fn end_call(&self) {
+        self.interrupted.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Returns `true` if the instance, which returned `err`, must not be used for any further calls
+fn is_unusable(err: &CallError) -> bool {
+    matches!(
+        err,
+        CallError::Call(..)
+            | CallError::PostReturn(..)
+            | CallError::Cancelled(..)
+            | CallError::DeadlineExceeded(..)
+            | CallError::ExecutionTimeout(..)
+    )
+}
+
+/// Logs `err` if it is a [`CallError::PostReturn`], which, unlike other call failures, means that
+/// the guest state may be corrupted
+fn log_post_return(err: CallError) -> CallError {
+    if let CallError::PostReturn(ref err) = err {
+        error!(
+            ?err,
+            "post-return cleanup failed after results were transmitted, instance may be unusable"
+        );
+    }
+    err
+}
+
+/// Instruments `fut` handling an invocation using `span`. If the peer propagated `trace`, see
+/// [`WrpcView::serve_trace_context`], `fut` is instead instrumented using a child span of `span`
+/// recording it and executed with a child of `trace` as [`TraceContext::current`].
+fn instrument_invocation<F: Future>(
+    fut: F,
+    span: &Span,
+    trace: Option<TraceContext>,
+) -> Either<Instrumented<F>, TaskLocalFuture<TraceContext, Instrumented<F>>> {
+    let Some(parent) = trace else {
+        return Either::Left(fut.instrument(span.clone()));
+    };
+    let trace = parent.child();
+    let span = debug_span!(
+        parent: span,
+        "invocation",
+        trace_id = %format_args!("{:032x}", trace.trace_id),
+        span_id = %format_args!("{:016x}", trace.span_id),
+        parent_span_id = %format_args!("{:016x}", parent.span_id),
+        sampled = trace.is_sampled(),
+    );
+    Either::Right(trace.scope(fut.instrument(span)))
+}
 
 pub trait ServeExt: wrpc_transport::Serve {
     /// Serve [`types::ComponentFunc`] from an [`InstancePre`] instantiating it on each call.
     /// This serving method does not support guest-exported resources.
+    /// Fails if `ty` does not match the signature of the function export.
+    ///
+    /// The context of each invocation is passed to [`WrpcView::set_serve_context`] of the
+    /// store constructed for it.
     #[instrument(level = "trace", skip(self, store, instance_pre, host_resources))]
     fn serve_function<T>(
         &self,
@@ -46,26 +409,17 @@ pub trait ServeExt: wrpc_transport::Serve {
         let host_resources = host_resources.into();
         async move {
             debug!(instance = instance_name, name, "serving function export");
-            let component_ty = instance_pre.component();
-            let idx = if instance_name.is_empty() {
-                None
-            } else {
-                let idx = component_ty
-                    .get_export_index(None, instance_name)
-                    .with_context(|| format!("export `{instance_name}` not found"))?;
-                Some(idx)
-            };
-            let idx = component_ty
-                .get_export_index(idx.as_ref(), name)
-                .with_context(|| format!("export `{name}` not found"))?;
+            let idx = func_export_index(instance_pre.component(), instance_name, name, &ty)?;
 
-            // TODO: set paths
-            let invocations = self.serve(instance_name, rpc_func_name(name), []).await?;
+            let paths = async_paths(&host_resources, ty.params().map(|(_, ty)| ty));
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, name);
+            let invocations = self.serve(&rpc_instance, &rpc_name, paths).await?;
             let name = Arc::<str>::from(name);
             let params_ty: Arc<[_]> = ty.params().map(|(_, ty)| ty).collect();
             let results_ty: Arc<[_]> = ty.results().collect();
             let host_resources = Arc::clone(&host_resources);
             Ok(invocations.map_ok(move |(cx, tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
                 let instance_pre = instance_pre.clone();
                 let name = Arc::clone(&name);
                 let params_ty = Arc::clone(&params_ty);
@@ -73,9 +427,10 @@ pub trait ServeExt: wrpc_transport::Serve {
                 let host_resources = Arc::clone(&host_resources);
 
                 let mut store = store();
+                store.data_mut().set_serve_context(&cx);
                 (
                     cx,
-                    Box::pin(
+                    Box::pin(instrument_invocation(
                         async move {
                             let instance = instance_pre
                                 .instantiate_async(&mut store)
@@ -84,7 +439,7 @@ pub trait ServeExt: wrpc_transport::Serve {
                             let func = instance
                                 .get_func(&mut store, idx)
                                 .with_context(|| format!("function export `{name}` not found"))?;
-                            call(
+                            call_cancellable(
                                 &mut store,
                                 rx,
                                 tx,
@@ -94,11 +449,134 @@ pub trait ServeExt: wrpc_transport::Serve {
                                 &results_ty,
                                 func,
                             )
-                            .await?;
+                            .await
+                            .map_err(log_post_return)?;
                             Ok(())
-                        }
-                        .instrument(span.clone()),
-                    ) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                )
+            }))
+        }
+    }
+
+    /// Like [`Self::serve_function`], but keeps up to `pool_size` instances in a pool and
+    /// checks one out for each invocation instead of instantiating the component on each call.
+    /// At most `pool_size` invocations are served concurrently, further invocations wait for
+    /// a pooled instance to become available.
+    ///
+    /// Instances are instantiated in stores constructed by `store` lazily, when no idle instance
+    /// is available. Guest state is not reset between invocations served by the same instance,
+    /// but instances, which failed to serve an invocation, are discarded.
+    /// This serving method does not support guest-exported resources.
+    ///
+    /// Since stores are reused by multiple invocations, [`WrpcView::set_serve_context`] is not
+    /// called.
+    #[instrument(level = "trace", skip(self, store, instance_pre, host_resources))]
+    #[allow(clippy::too_many_arguments)]
+    fn serve_function_pooled<T>(
+        &self,
+        store: impl Fn() -> wasmtime::Store<T> + Send + Sync + 'static,
+        instance_pre: InstancePre<T>,
+        pool_size: NonZeroUsize,
+        host_resources: impl Into<
+            Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
+        >,
+        ty: types::ComponentFunc,
+        instance_name: &str,
+        name: &str,
+    ) -> impl Future<
+        Output = anyhow::Result<
+            impl Stream<
+                    Item = anyhow::Result<(
+                        Self::Context,
+                        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+                    )>,
+                > + Send
+                + 'static,
+        >,
+    > + Send
+    where
+        T: WasiView + WrpcView + 'static,
+    {
+        let span = Span::current();
+        let host_resources = host_resources.into();
+        async move {
+            debug!(
+                instance = instance_name,
+                name,
+                pool_size = pool_size.get(),
+                "serving pooled function export"
+            );
+            let idx = func_export_index(instance_pre.component(), instance_name, name, &ty)?;
+
+            let paths = async_paths(&host_resources, ty.params().map(|(_, ty)| ty));
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, name);
+            let invocations = self.serve(&rpc_instance, &rpc_name, paths).await?;
+            let name = Arc::<str>::from(name);
+            let params_ty: Arc<[_]> = ty.params().map(|(_, ty)| ty).collect();
+            let results_ty: Arc<[_]> = ty.results().collect();
+            let host_resources = Arc::clone(&host_resources);
+            let store = Arc::new(store);
+            let pool = Arc::new(InstancePool {
+                idle: std::sync::Mutex::default(),
+                permits: Arc::new(Semaphore::new(pool_size.get())),
+            });
+            Ok(invocations.map_ok(move |(cx, tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
+                let store = Arc::clone(&store);
+                let instance_pre = instance_pre.clone();
+                let pool = Arc::clone(&pool);
+                let name = Arc::clone(&name);
+                let params_ty = Arc::clone(&params_ty);
+                let results_ty = Arc::clone(&results_ty);
+                let host_resources = Arc::clone(&host_resources);
+                (
+                    cx,
+                    Box::pin(instrument_invocation(
+                        async move {
+                            let _permit = Arc::clone(&pool.permits)
+                                .acquire_owned()
+                                .await
+                                .context("instance pool closed")?;
+                            let idle = pool.idle.lock().ok().and_then(|mut idle| idle.pop());
+                            let (mut store, func) = if let Some(idle) = idle {
+                                trace!("reusing pooled instance");
+                                idle
+                            } else {
+                                trace!("instantiating pooled instance");
+                                let mut store = store();
+                                let instance = instance_pre
+                                    .instantiate_async(&mut store)
+                                    .await
+                                    .context("failed to instantiate component")?;
+                                let func =
+                                    instance.get_func(&mut store, idx).with_context(|| {
+                                        format!("function export `{name}` not found")
+                                    })?;
+                                (store, func)
+                            };
+                            call_cancellable(
+                                &mut store,
+                                rx,
+                                tx,
+                                &[],
+                                &host_resources,
+                                params_ty.iter(),
+                                &results_ty,
+                                func,
+                            )
+                            .await
+                            .map_err(log_post_return)?;
+                            if let Ok(mut idle) = pool.idle.lock() {
+                                idle.push((store, func));
+                            }
+                            Ok(())
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
                 )
             }))
         }
@@ -106,6 +584,11 @@ pub trait ServeExt: wrpc_transport::Serve {
 
     /// Like [`Self::serve_function`], but with a shared `store` instance.
     /// This is required to allow for serving functions, which operate on guest-exported resources.
+    ///
+    /// Fails if `ty` does not match the signature of the function export.
+    ///
+    /// Since the `store` is shared by all invocations, [`WrpcView::set_serve_context`] is not
+    /// called.
     #[instrument(
         level = "trace",
         skip(self, store, instance, guest_resources, host_resources)
@@ -145,25 +628,31 @@ pub trait ServeExt: wrpc_transport::Serve {
                 let idx = if instance_name.is_empty() {
                     None
                 } else {
-                    let idx = instance
-                        .get_export_index(store.as_context_mut(), None, instance_name)
-                        .with_context(|| format!("export `{instance_name}` not found"))?;
+                    let idx = instance_export_index(instance_name, &mut |idx, name| {
+                        instance.get_export_index(store.as_context_mut(), idx, name)
+                    })
+                    .with_context(|| format!("export `{instance_name}` not found"))?;
                     Some(idx)
                 };
                 let idx = instance
                     .get_export_index(store.as_context_mut(), idx.as_ref(), name)
                     .with_context(|| format!("export `{name}` not found"))?;
-                instance.get_func(store.as_context_mut(), idx)
-            }
-            .with_context(|| format!("function export `{name}` not found"))?;
+                let func = instance
+                    .get_func(store.as_context_mut(), idx)
+                    .with_context(|| format!("function export `{name}` not found"))?;
+                check_func_type(name, &ty, &func.ty(&*store))?;
+                func
+            };
             debug!(instance = instance_name, name, "serving function export");
-            // TODO: set paths
-            let invocations = self.serve(instance_name, rpc_func_name(name), []).await?;
+            let paths = async_paths(&host_resources, ty.params().map(|(_, ty)| ty));
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, name);
+            let invocations = self.serve(&rpc_instance, &rpc_name, paths).await?;
             let params_ty: Arc<[_]> = ty.params().map(|(_, ty)| ty).collect();
             let results_ty: Arc<[_]> = ty.results().collect();
             let guest_resources = Arc::clone(&guest_resources);
             let host_resources = Arc::clone(&host_resources);
             Ok(invocations.map_ok(move |(cx, tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
                 let params_ty = Arc::clone(&params_ty);
                 let results_ty = Arc::clone(&results_ty);
                 let guest_resources = Arc::clone(&guest_resources);
@@ -171,7 +660,7 @@ pub trait ServeExt: wrpc_transport::Serve {
                 let store = Arc::clone(&store);
                 (
                     cx,
-                    Box::pin(
+                    Box::pin(instrument_invocation(
                         async move {
                             let mut store = store.lock().await;
                             call(
@@ -184,15 +673,3283 @@ pub trait ServeExt: wrpc_transport::Serve {
                                 &results_ty,
                                 func,
                             )
-                            .await?;
+                            .await
+                            .map_err(log_post_return)?;
                             Ok(())
-                        }
-                        .instrument(span.clone()),
-                    ) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
                 )
             }))
         }
     }
-}
 
-impl<T: wrpc_transport::Serve> ServeExt for T {}
+    /// Serve function `func` of an instance, which was already instantiated in the shared
+    /// `store`, as `name` of `instance_name`.
+    ///
+    /// Unlike [`Self::serve_function_shared`], `func` is resolved by the caller, for example,
+    /// using [`Instance::get_func`] with a precomputed [`ComponentExportIndex`], so no exports
+    /// are looked up by this method. This serving method does not support guest-exported
+    /// resources. Fails if `ty` does not match the signature of `func`.
+    ///
+    /// Since the `store` is shared by all invocations, [`WrpcView::set_serve_context`] is not
+    /// called.
+    #[instrument(level = "trace", skip(self, store, func, host_resources))]
+    fn serve_instance_function<T>(
+        &self,
+        store: Arc<Mutex<wasmtime::Store<T>>>,
+        func: Func,
+        host_resources: impl Into<
+            Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
+        >,
+        ty: types::ComponentFunc,
+        instance_name: &str,
+        name: &str,
+    ) -> impl Future<
+        Output = anyhow::Result<
+            impl Stream<
+                    Item = anyhow::Result<(
+                        Self::Context,
+                        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+                    )>,
+                > + Send
+                + 'static,
+        >,
+    > + Send
+    where
+        T: WasiView + WrpcView + 'static,
+    {
+        let span = Span::current();
+        let host_resources = host_resources.into();
+        async move {
+            check_func_type(name, &ty, &func.ty(&*store.lock().await))?;
+            debug!(instance = instance_name, name, "serving instance function");
+            let paths = async_paths(&host_resources, ty.params().map(|(_, ty)| ty));
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, name);
+            let invocations = self.serve(&rpc_instance, &rpc_name, paths).await?;
+            let params_ty: Arc<[_]> = ty.params().map(|(_, ty)| ty).collect();
+            let results_ty: Arc<[_]> = ty.results().collect();
+            let host_resources = Arc::clone(&host_resources);
+            Ok(invocations.map_ok(move |(cx, tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
+                let params_ty = Arc::clone(&params_ty);
+                let results_ty = Arc::clone(&results_ty);
+                let host_resources = Arc::clone(&host_resources);
+                let store = Arc::clone(&store);
+                (
+                    cx,
+                    Box::pin(instrument_invocation(
+                        async move {
+                            let mut store = store.lock().await;
+                            call(
+                                &mut *store,
+                                rx,
+                                tx,
+                                &[],
+                                &host_resources,
+                                params_ty.iter(),
+                                &results_ty,
+                                func,
+                            )
+                            .await
+                            .map_err(log_post_return)?;
+                            Ok(())
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                )
+            }))
+        }
+    }
+
+    /// Serve drops of the guest-exported resource `name` of type `ty`, which are received as
+    /// invocations of `[resource-drop]<name>` with the owned resource handle as the only
+    /// parameter. Like [`Self::serve_function_shared`], all invocations operate on a
+    /// shared `store` instance, in which the resource handles were created.
+    #[instrument(level = "trace", skip(self, store, ty, guest_resources))]
+    fn serve_resource_drop_shared<T>(
+        &self,
+        store: Arc<Mutex<wasmtime::Store<T>>>,
+        ty: ResourceType,
+        guest_resources: impl Into<Arc<[ResourceType]>>,
+        instance_name: &str,
+        name: &str,
+    ) -> impl Future<
+        Output = anyhow::Result<
+            impl Stream<
+                    Item = anyhow::Result<(
+                        Self::Context,
+                        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+                    )>,
+                > + Send
+                + 'static,
+        >,
+    > + Send
+    where
+        T: WasiView + WrpcView + 'static,
+    {
+        let span = Span::current();
+        let guest_resources = guest_resources.into();
+        async move {
+            debug!(instance = instance_name, name, "serving resource drop");
+            let name = format!("[resource-drop]{name}");
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, &name);
+            let invocations = self
+                .serve(
+                    &rpc_instance,
+                    &rpc_name,
+                    Vec::<Box<[Option<usize>]>>::default(),
+                )
+                .await?;
+            Ok(invocations.map_ok(move |(cx, mut tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
+                let guest_resources = Arc::clone(&guest_resources);
+                let store = Arc::clone(&store);
+                (
+                    cx,
+                    Box::pin(instrument_invocation(
+                        async move {
+                            let mut store = store.lock().await;
+                            let mut rx = pin!(rx);
+                            let mut v = Val::Bool(false);
+                            read_value(
+                                &mut *store,
+                                &mut rx,
+                                &guest_resources,
+                                &mut v,
+                                &Type::Own(ty),
+                                &[0],
+                            )
+                            .await
+                            .context("failed to decode resource handle")?;
+                            let Val::Resource(resource) = v else {
+                                bail!("decoded value is not a resource")
+                            };
+                            resource
+                                .resource_drop_async(&mut *store)
+                                .await
+                                .context("failed to drop resource")?;
+                            if let Err(err) = tx.shutdown().await {
+                                trace!(?err, "failed to shutdown outgoing stream");
+                            }
+                            Ok(())
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                )
+            }))
+        }
+    }
+
+    /// Like [`Self::serve_function_shared`], but with a [`RestartableInstance`], which is
+    /// rebuilt in a fresh store once a call leaves it in an unusable state, for example,
+    /// because the guest trapped. The invocation, which caused the restart, still fails.
+    ///
+    /// Since the store is shared by all invocations, [`WrpcView::set_serve_context`] is not
+    /// called.
+    #[instrument(level = "trace", skip(self, instance, guest_resources, host_resources))]
+    fn serve_function_restartable<T>(
+        &self,
+        instance: Arc<RestartableInstance<T>>,
+        guest_resources: impl Into<Arc<[ResourceType]>>,
+        host_resources: impl Into<
+            Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
+        >,
+        ty: types::ComponentFunc,
+        instance_name: &str,
+        name: &str,
+    ) -> impl Future<
+        Output = anyhow::Result<
+            impl Stream<
+                    Item = anyhow::Result<(
+                        Self::Context,
+                        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+                    )>,
+                > + Send
+                + 'static,
+        >,
+    > + Send
+    where
+        T: WasiView + WrpcView + 'static,
+    {
+        let span = Span::current();
+        let guest_resources = guest_resources.into();
+        let host_resources = host_resources.into();
+        async move {
+            let idx = func_export_index(instance.component(), instance_name, name, &ty)?;
+            debug!(
+                instance = instance_name,
+                name, "serving restartable function export"
+            );
+            let paths = async_paths(&host_resources, ty.params().map(|(_, ty)| ty));
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, name);
+            let invocations = self.serve(&rpc_instance, &rpc_name, paths).await?;
+            let name = Arc::<str>::from(name);
+            let params_ty: Arc<[_]> = ty.params().map(|(_, ty)| ty).collect();
+            let results_ty: Arc<[_]> = ty.results().collect();
+            let guest_resources = Arc::clone(&guest_resources);
+            let host_resources = Arc::clone(&host_resources);
+            Ok(invocations.map_ok(move |(cx, tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
+                let instance = Arc::clone(&instance);
+                let name = Arc::clone(&name);
+                let params_ty = Arc::clone(&params_ty);
+                let results_ty = Arc::clone(&results_ty);
+                let guest_resources = Arc::clone(&guest_resources);
+                let host_resources = Arc::clone(&host_resources);
+                (
+                    cx,
+                    Box::pin(instrument_invocation(
+                        async move {
+                            let mut state = instance.state.lock().await;
+                            instance.restart_if_interrupted(&mut state).await?;
+                            let (store, inst) = &mut *state;
+                            let func = inst
+                                .get_func(&mut *store, idx)
+                                .with_context(|| format!("function export `{name}` not found"))?;
+                            instance.begin_call();
+                            let res = call(
+                                &mut *store,
+                                rx,
+                                tx,
+                                &guest_resources,
+                                &host_resources,
+                                params_ty.iter(),
+                                &results_ty,
+                                func,
+                            )
+                            .await;
+                            instance.end_call();
+                            let Err(err) = res else {
+                                return Ok(());
+                            };
+                            let err = log_post_return(err);
+                            if is_unusable(&err) {
+                                warn!(?err, "instance is unusable, restarting");
+                                if let Err(err) = instance.restart(&mut state).await {
+                                    error!(?err, "failed to restart instance");
+                                }
+                            }
+                            Err(err.into())
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                )
+            }))
+        }
+    }
+
+    /// Like [`Self::serve_resource_drop_shared`], but with a [`RestartableInstance`], which is
+    /// rebuilt in a fresh store if the guest destructor fails.
+    ///
+    /// Resource handles created before a restart are no longer valid, drops of such handles fail.
+    #[instrument(level = "trace", skip(self, instance, ty, guest_resources))]
+    fn serve_resource_drop_restartable<T>(
+        &self,
+        instance: Arc<RestartableInstance<T>>,
+        ty: ResourceType,
+        guest_resources: impl Into<Arc<[ResourceType]>>,
+        instance_name: &str,
+        name: &str,
+    ) -> impl Future<
+        Output = anyhow::Result<
+            impl Stream<
+                    Item = anyhow::Result<(
+                        Self::Context,
+                        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+                    )>,
+                > + Send
+                + 'static,
+        >,
+    > + Send
+    where
+        T: WasiView + WrpcView + 'static,
+    {
+        let span = Span::current();
+        let guest_resources = guest_resources.into();
+        async move {
+            debug!(
+                instance = instance_name,
+                name, "serving restartable resource drop"
+            );
+            let name = format!("[resource-drop]{name}");
+            let (rpc_instance, rpc_name) = T::rpc_name(instance_name, &name);
+            let invocations = self
+                .serve(
+                    &rpc_instance,
+                    &rpc_name,
+                    Vec::<Box<[Option<usize>]>>::default(),
+                )
+                .await?;
+            Ok(invocations.map_ok(move |(cx, mut tx, rx)| {
+                let trace = T::serve_trace_context(&cx);
+                let guest_resources = Arc::clone(&guest_resources);
+                let instance = Arc::clone(&instance);
+                (
+                    cx,
+                    Box::pin(instrument_invocation(
+                        async move {
+                            let mut state = instance.state.lock().await;
+                            instance.restart_if_interrupted(&mut state).await?;
+                            let (store, _) = &mut *state;
+                            let mut rx = pin!(rx);
+                            let mut v = Val::Bool(false);
+                            read_value(
+                                &mut *store,
+                                &mut rx,
+                                &guest_resources,
+                                &mut v,
+                                &Type::Own(ty),
+                                &[0],
+                            )
+                            .await
+                            .context("failed to decode resource handle")?;
+                            let Val::Resource(resource) = v else {
+                                bail!("decoded value is not a resource")
+                            };
+                            instance.begin_call();
+                            let res = resource.resource_drop_async(&mut *store).await;
+                            instance.end_call();
+                            if let Err(err) = res {
+                                warn!(?err, "failed to drop resource, restarting instance");
+                                if let Err(err) = instance.restart(&mut state).await {
+                                    error!(?err, "failed to restart instance");
+                                }
+                                return Err(err.context("failed to drop resource"));
+                            }
+                            if let Err(err) = tx.shutdown().await {
+                                trace!(?err, "failed to shutdown outgoing stream");
+                            }
+                            Ok(())
+                        },
+                        &span,
+                        trace,
+                    )) as Pin<Box<dyn Future<Output = _> + Send + 'static>>,
+                )
+            }))
+        }
+    }
+
+    /// Serve all [`types::ComponentFunc`] exports of the component instantiated by `instance_pre`,
+    /// including functions exported by nested instances, which are served under the names of all
+    /// enclosing instances joined by `/`.
+    ///
+    /// If `guest_resources` is empty, each invocation is served by a fresh instance in a store
+    /// constructed by `store` as in [`Self::serve_function`]. Otherwise, a single
+    /// [`RestartableInstance`] is shared by all invocations as in
+    /// [`Self::serve_function_restartable`] and drops of exported resources are served as in
+    /// [`Self::serve_resource_drop_restartable`].
+    ///
+    /// Invocations of all functions are returned in a single stream along with the instance
+    /// and function name of the invoked function.
+    #[instrument(
+        level = "trace",
+        skip(self, store, instance_pre, guest_resources, host_resources)
+    )]
+    fn serve_component<T>(
+        &self,
+        store: impl Fn() -> wasmtime::Store<T> + Send + Sync + 'static,
+        instance_pre: InstancePre<T>,
+        guest_resources: impl Into<Arc<[ResourceType]>>,
+        host_resources: impl Into<
+            Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
+        >,
+    ) -> impl Future<
+        Output = anyhow::Result<
+            impl Stream<
+                    Item = anyhow::Result<(
+                        Arc<str>,
+                        Arc<str>,
+                        Self::Context,
+                        Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>,
+                    )>,
+                > + Send
+                + 'static,
+        >,
+    > + Send
+    where
+        T: WasiView + WrpcView + 'static,
+    {
+        let guest_resources = guest_resources.into();
+        let host_resources = host_resources.into();
+        async move {
+            let mut funcs = Vec::new();
+            let mut resources = Vec::new();
+            for export in component_exports(instance_pre.component()) {
+                match export {
+                    Export::Function { instance, name, ty } => funcs.push((instance, name, ty)),
+                    Export::Resource { instance, name, ty } => {
+                        resources.push((instance, name, ty));
+                    }
+                    Export::Unsupported {
+                        instance,
+                        name,
+                        kind,
+                    } => {
+                        warn!(instance, name, kind, "serving export not supported yet");
+                    }
+                }
+            }
+            let mut streams = Vec::with_capacity(funcs.len());
+            if guest_resources.is_empty() {
+                let store = Arc::new(store);
+                for (instance_name, name, ty) in funcs {
+                    let store = Arc::clone(&store);
+                    let invocations = self
+                        .serve_function(
+                            move || store(),
+                            instance_pre.clone(),
+                            Arc::clone(&host_resources),
+                            ty,
+                            &instance_name,
+                            &name,
+                        )
+                        .await?;
+                    streams.push(with_names(instance_name, name, invocations));
+                }
+            } else {
+                let instance = Arc::new(RestartableInstance::new(store, instance_pre).await?);
+                for (instance_name, name, ty) in funcs {
+                    let invocations = self
+                        .serve_function_restartable(
+                            Arc::clone(&instance),
+                            Arc::clone(&guest_resources),
+                            Arc::clone(&host_resources),
+                            ty,
+                            &instance_name,
+                            &name,
+                        )
+                        .await?;
+                    streams.push(with_names(instance_name, name, invocations));
+                }
+                for (instance_name, name, ty) in resources {
+                    let invocations = self
+                        .serve_resource_drop_restartable(
+                            Arc::clone(&instance),
+                            ty,
+                            Arc::clone(&guest_resources),
+                            &instance_name,
+                            &name,
+                        )
+                        .await?;
+                    streams.push(with_names(
+                        instance_name,
+                        format!("[resource-drop]{name}"),
+                        invocations,
+                    ));
+                }
+            }
+            Ok(futures::stream::select_all(streams))
+        }
+    }
+}
+
+impl<T: wrpc_transport::Serve> ServeExt for T {}
+
+#[cfg(test)]
+mod tests {
+    use core::task::{ready, Context, Poll};
+    use core::time::Duration;
+
+    use std::borrow::Cow;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use bytes::Bytes;
+    use tokio::io::{duplex, split, AsyncRead, AsyncWrite, DuplexStream, ReadBuf, ReadHalf};
+    use tokio::join;
+    use tokio::sync::{mpsc, oneshot};
+    use tracing::field::{Field, Visit};
+    use tracing::{span, Subscriber};
+    use tracing_subscriber::layer::{self, Layer, SubscriberExt as _};
+    use tracing_subscriber::registry::LookupSpan;
+    use wasmtime::component::{Component, Linker, ResourceTable};
+    use wasmtime::{Engine, Store};
+    use wasmtime_wasi::WasiCtxView;
+    use wrpc_transport::frame::AcceptExt as _;
+    use wrpc_transport::memory::{self, Client};
+    use wrpc_transport::{InvokeExt as _, ResourceBorrow, ResourceOwn, ServeExt as _};
+
+    use super::*;
+    use crate::test_util::{self, new_ctx};
+    use crate::{
+        collect_component_resource_exports, link_core_function, link_item, rpc_func_name,
+        RemoteResource, SharedResourceTable, WrpcCtx, WrpcCtxView,
+    };
+
+    type TestCtx = test_util::TestCtx<Client>;
+
+    /// [TestCtx] using a custom RPC name mapping
+    struct RenamedCtx(TestCtx);
+
+    impl WrpcView for RenamedCtx {
+        type Invoke = Client;
+
+        fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke> {
+            self.0.wrpc()
+        }
+
+        fn rpc_name<'a>(instance: &'a str, name: &'a str) -> (Cow<'a, str>, Cow<'a, str>) {
+            (
+                Cow::Owned(format!("renamed:{instance}")),
+                Cow::Owned(rpc_func_name(name).to_uppercase()),
+            )
+        }
+    }
+
+    impl WasiView for RenamedCtx {
+        fn ctx(&mut self) -> WasiCtxView<'_> {
+            self.0.ctx()
+        }
+    }
+
+    /// [TestCtx] multiplying the first `u32` parameter by 10 and incrementing the first `u32`
+    /// result
+    struct TransformCtx(TestCtx);
+
+    impl WrpcView for TransformCtx {
+        type Invoke = Client;
+
+        fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke> {
+            self.0.wrpc()
+        }
+
+        fn transform_params(&mut self, params: &mut [Val], ty: &[Type]) -> anyhow::Result<()> {
+            if let ([Val::U32(v), ..], [Type::U32, ..]) = (params, ty) {
+                *v *= 10;
+            }
+            Ok(())
+        }
+
+        fn transform_results(&mut self, results: &mut [Val], ty: &[Type]) -> anyhow::Result<()> {
+            if let ([Val::U32(v), ..], [Type::U32, ..]) = (results, ty) {
+                *v += 1;
+            }
+            Ok(())
+        }
+    }
+
+    impl WasiView for TransformCtx {
+        fn ctx(&mut self) -> WasiCtxView<'_> {
+            self.0.ctx()
+        }
+    }
+
+    /// Component exporting `test:test/iface.add`
+    const ADD_SERVER: &str = r#"(component
+  (core module $m
+    (func (export "add") (param i32 i32) (result i32)
+      local.get 0
+      local.get 1
+      i32.add)
+  )
+  (core instance $i (instantiate $m))
+  (func $add (param "a" u32) (param "b" u32) (result u32)
+    (canon lift (core func $i "add")))
+  (instance $iface (export "add" (func $add)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    /// Component importing `test:test/iface.add` and exporting `run`, which calls it
+    const ADD_CLIENT: &str = r#"(component
+  (import "test:test/iface" (instance $iface
+    (export "add" (func (param "a" u32) (param "b" u32) (result u32)))
+  ))
+  (alias export $iface "add" (func $add))
+  (core func $add-lower (canon lower (func $add)))
+  (core module $m
+    (import "" "add" (func $add (param i32 i32) (result i32)))
+    (func (export "run") (result i32)
+      i32.const 40
+      i32.const 2
+      call $add)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "add" (func $add-lower))))
+  ))
+  (func (export "run") (result u32) (canon lift (core func $i "run")))
+)"#;
+
+    fn add_func_type(engine: &Engine, component: &Component) -> types::ComponentFunc {
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = component
+            .component_type()
+            .exports(engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(engine).find(|(name, _)| *name == "add")
+        else {
+            panic!("`test:test/iface` does not export `add`");
+        };
+        ty
+    }
+
+    fn new_store(engine: &Engine, client: Client) -> Store<TestCtx> {
+        Store::new(engine, new_ctx(client))
+    }
+
+    /// Component modelled after the `resources` example server, exporting
+    /// `wrpc-examples:resources/resources` with resource `foo`. The destructor of `foo`
+    /// calls the imported `test:test/host.dropped` with the representation of the resource.
+    const RESOURCES_SERVER: &str = r#"(component
+  (import "test:test/host" (instance $host
+    (export "dropped" (func (param "rep" u32)))
+  ))
+  (alias export $host "dropped" (func $dropped))
+  (core func $dropped-lower (canon lower (func $dropped)))
+  (core module $dtor
+    (import "" "dropped" (func $dropped (param i32)))
+    (func (export "dtor") (param i32)
+      local.get 0
+      call $dropped)
+  )
+  (core instance $dtor (instantiate $dtor
+    (with "" (instance (export "dropped" (func $dropped-lower))))
+  ))
+  (type $foo (resource (rep i32) (dtor (func $dtor "dtor"))))
+  (core func $foo-new (canon resource.new $foo))
+  (core module $m
+    (import "" "foo-new" (func $foo-new (param i32) (result i32)))
+    (memory (export "memory") 1)
+    (data (i32.const 0) "\10\00\00\00\03\00\00\00")
+    (data (i32.const 16) "bar")
+    (func (export "[constructor]foo") (result i32)
+      i32.const 42
+      call $foo-new)
+    (func (export "[method]foo.bar") (param i32) (result i32)
+      i32.const 0)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "foo-new" (func $foo-new))))
+  ))
+  (func $new (result (own $foo))
+    (canon lift (core func $i "[constructor]foo")))
+  (func $bar (param "self" (borrow $foo)) (result string)
+    (canon lift (core func $i "[method]foo.bar") (memory $i "memory")))
+  (component $shim
+    (import "import-type-foo" (type $foo (sub resource)))
+    (import "import-constructor-foo" (func $new (result (own $foo))))
+    (import "import-method-foo-bar" (func $bar (param "self" (borrow $foo)) (result string)))
+    (export $foo-export "foo" (type $foo))
+    (export "[constructor]foo" (func $new)
+      (func (result (own $foo-export))))
+    (export "[method]foo.bar" (func $bar)
+      (func (param "self" (borrow $foo-export)) (result string)))
+  )
+  (instance $iface (instantiate $shim
+    (with "import-type-foo" (type $foo))
+    (with "import-constructor-foo" (func $new))
+    (with "import-method-foo-bar" (func $bar))
+  ))
+  (export "wrpc-examples:resources/resources" (instance $iface))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_resource_lifecycle() -> anyhow::Result<()> {
+        const INSTANCE: &str = "wrpc-examples:resources/resources";
+
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(&engine, RESOURCES_SERVER)?;
+        let mut guest_resources = Vec::new();
+        collect_component_resource_exports(
+            &engine,
+            &component.component_type(),
+            &mut guest_resources,
+        );
+        assert_eq!(guest_resources.len(), 1);
+
+        let (dropped_tx, mut dropped_rx) = mpsc::unbounded_channel();
+        let mut linker = Linker::new(&engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap("dropped", move |_, (rep,): (u32,)| {
+                _ = dropped_tx.send(rep);
+                Ok(())
+            })?;
+        let pre = linker.instantiate_pre(&component)?;
+
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let invocations = srv
+            .serve_component(
+                move || new_store(&engine, memory::pair(1).0),
+                pre,
+                guest_resources,
+                HashMap::default(),
+            )
+            .await?;
+        let served = tokio::spawn(async move {
+            let mut invocations = pin!(invocations);
+            let mut served = Vec::default();
+            while served.len() < 4 {
+                let (_, name, (), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")
+                    .expect("failed to accept invocation");
+                served.push((name, fut.await.is_ok()));
+            }
+            served
+        });
+
+        let (clt, lis) = memory::pair(1024);
+        let (foo, accepted) = join!(
+            clt.invoke_values_blocking::<_, _, (ResourceOwn<()>,)>(
+                (),
+                INSTANCE,
+                "foo",
+                (),
+                &[[]; 0]
+            ),
+            srv.accept(&lis),
+        );
+        accepted?;
+        let (foo,) = foo?;
+
+        let (res, accepted) = join!(
+            clt.invoke_values_blocking::<_, _, (String,)>(
+                (),
+                INSTANCE,
+                "foo.bar",
+                (ResourceBorrow::from(foo.clone()),),
+                &[[]; 0],
+            ),
+            srv.accept(&lis),
+        );
+        accepted?;
+        assert_eq!(res?, ("bar".to_string(),));
+
+        let (res, accepted) = join!(
+            clt.invoke_values_blocking::<_, _, ()>(
+                (),
+                INSTANCE,
+                "[resource-drop]foo",
+                (foo.clone(),),
+                &[[]; 0],
+            ),
+            srv.accept(&lis),
+        );
+        accepted?;
+        res?;
+        assert_eq!(dropped_rx.recv().await, Some(42));
+
+        // the handle is no longer valid after the drop
+        let (res, accepted) = join!(
+            clt.invoke_values_blocking::<_, _, (String,)>(
+                (),
+                INSTANCE,
+                "foo.bar",
+                (ResourceBorrow::from(foo),),
+                &[[]; 0],
+            ),
+            srv.accept(&lis),
+        );
+        accepted?;
+        res.expect_err("method call on a dropped resource should fail");
+
+        let served = served.await?;
+        assert_eq!(
+            served
+                .iter()
+                .map(|(name, ok)| (&**name, *ok))
+                .collect::<Vec<_>>(),
+            [
+                ("[constructor]foo", true),
+                ("[method]foo.bar", true),
+                ("[resource-drop]foo", true),
+                ("[method]foo.bar", false),
+            ]
+        );
+        Ok(())
+    }
+
+    /// Component importing resource `foo` of `wrpc-examples:resources/resources` and exporting
+    /// `run`, which constructs a `foo` and drops it
+    const RESOURCES_CLIENT: &str = r#"(component
+  (import "wrpc-examples:resources/resources" (instance $iface
+    (export "foo" (type $foo (sub resource)))
+    (export "[constructor]foo" (func (result (own $foo))))
+  ))
+  (alias export $iface "foo" (type $foo))
+  (alias export $iface "[constructor]foo" (func $new))
+  (core func $new-lower (canon lower (func $new)))
+  (core func $drop (canon resource.drop $foo))
+  (core module $m
+    (import "" "new" (func $new (result i32)))
+    (import "" "drop" (func $drop (param i32)))
+    (func (export "run")
+      call $new
+      call $drop)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance
+      (export "new" (func $new-lower))
+      (export "drop" (func $drop))
+    ))
+  ))
+  (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn polyfill_resource_drop() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, RESOURCES_SERVER)?;
+        let mut guest_resources = Vec::new();
+        collect_component_resource_exports(&engine, &server.component_type(), &mut guest_resources);
+        let (dropped_tx, mut dropped_rx) = mpsc::unbounded_channel();
+        let mut linker = Linker::new(&engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap("dropped", move |_, (rep,): (u32,)| {
+                _ = dropped_tx.send(rep);
+                Ok(())
+            })?;
+        let pre = linker.instantiate_pre(&server)?;
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let invocations = srv
+            .serve_component(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                pre,
+                guest_resources,
+                HashMap::default(),
+            )
+            .await?;
+        let served = tokio::spawn(async move {
+            let mut invocations = pin!(invocations);
+            let mut served = Vec::default();
+            while served.len() < 2 {
+                let (_, name, (), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")
+                    .expect("failed to accept invocation");
+                served.push((name, fut.await.is_ok()));
+            }
+            served
+        });
+
+        let client = Component::new(&engine, RESOURCES_CLIENT)?;
+        let mut host_resources = HashMap::<Box<str>, HashMap<Box<str>, _>>::default();
+        let mut linker = Linker::new(&engine);
+        for (name, ty) in client.component_type().imports(&engine) {
+            let types::ComponentItem::ComponentInstance(instance_ty) = &ty else {
+                bail!("unexpected import `{name}`");
+            };
+            for (resource, ty) in instance_ty.exports(&engine) {
+                if let types::ComponentItem::Resource(ty) = ty {
+                    host_resources.entry(name.into()).or_default().insert(
+                        resource.into(),
+                        (ty, ResourceType::host::<RemoteResource>()),
+                    );
+                }
+            }
+            link_item(
+                &engine,
+                &mut linker.root(),
+                Vec::<ResourceType>::default(),
+                host_resources.clone(),
+                ty,
+                "",
+                name,
+            )?;
+        }
+
+        let (clt, lis) = memory::pair(1024);
+        let mut store = new_store(&engine, clt);
+        let instance = linker.instantiate_async(&mut store, &client).await?;
+        let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+        let (res, accepted) = join!(
+            async {
+                run.call_async(&mut store, ()).await?;
+                run.post_return_async(&mut store).await
+            },
+            async {
+                srv.accept(&lis).await?;
+                srv.accept(&lis).await
+            },
+        );
+        accepted?;
+        res?;
+        assert_eq!(dropped_rx.recv().await, Some(42));
+
+        let served = served.await?;
+        assert_eq!(
+            served
+                .iter()
+                .map(|(name, ok)| (&**name, *ok))
+                .collect::<Vec<_>>(),
+            [("[constructor]foo", true), ("[resource-drop]foo", true)]
+        );
+        assert!(
+            store.data().table.is_empty(),
+            "remote resource was not deleted"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_component() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (core module $m
+                    (func (export "foo") (result i32) i32.const 42)
+                    (func (export "bar") (result i32) i32.const 7)
+                )
+                (core instance $i (instantiate $m))
+                (func $foo (result u32) (canon lift (core func $i "foo")))
+                (func $bar (result u32) (canon lift (core func $i "bar")))
+                (export "foo" (func $foo))
+                (instance $iface (export "bar" (func $bar)))
+                (export "test:test/iface" (instance $iface))
+            )"#,
+        )?;
+        let pre = Linker::new(&engine).instantiate_pre(&component)?;
+
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let invocations = srv
+            .serve_component(
+                move || new_store(&engine, memory::pair(1).0),
+                pre,
+                [],
+                HashMap::default(),
+            )
+            .await?;
+        let served = tokio::spawn(async move {
+            let mut invocations = pin!(invocations);
+            let mut served = Vec::default();
+            while served.len() < 2 {
+                let (instance, name, (), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")
+                    .expect("failed to accept invocation");
+                fut.await.expect("failed to serve invocation");
+                served.push((instance, name));
+            }
+            served
+        });
+
+        let (clt, lis) = memory::pair(1024);
+        for (instance, name, expected) in [("", "foo", 42), ("test:test/iface", "bar", 7)] {
+            let (res, accepted) = join!(
+                clt.invoke_values_blocking::<_, _, (u32,)>((), instance, name, (), &[[]; 0]),
+                srv.accept(&lis),
+            );
+            accepted?;
+            assert_eq!(res?, (expected,));
+        }
+        let served = served.await?;
+        assert_eq!(
+            served
+                .iter()
+                .map(|(instance, name)| (&**instance, &**name))
+                .collect::<Vec<_>>(),
+            [("", "foo"), ("test:test/iface", "bar")]
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn link_core_function_add() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let module = wasmtime::Module::new(
+            &engine,
+            r#"(module
+                (import "test:test/iface" "add" (func $add (param i32 i32) (result i32)))
+                (func (export "run") (param i32 i32) (result i32)
+                    local.get 0
+                    local.get 1
+                    call $add)
+            )"#,
+        )?;
+        let mut linker = wasmtime::Linker::new(&engine);
+        for import in module.imports() {
+            let wasmtime::ExternType::Func(ty) = import.ty() else {
+                bail!("unexpected import `{}`", import.name());
+            };
+            link_core_function(&mut linker, ty, import.module(), import.name())?;
+        }
+
+        let srv = wrpc_transport::frame::Server::default();
+        let adds = srv
+            .serve_values::<(i32, i32), (i32,)>(
+                "test:test/iface",
+                "add",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let (clt, lis) = memory::pair(1024);
+        let mut store = new_store(&engine, clt);
+        let instance = linker.instantiate_async(&mut store, &module).await?;
+        let run = instance.get_typed_func::<(i32, i32), i32>(&mut store, "run")?;
+        let mut adds = pin!(adds);
+        let (res, served) = join!(run.call_async(&mut store, (-2, 44)), async {
+            srv.accept(&lis).await?;
+            let ((), (a, b), _, tx) = adds.try_next().await?.expect("unexpected end of stream");
+            tx((a + b,)).await
+        });
+        served?;
+        assert_eq!(res?, 42);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn link_serve_round_trip() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let client = Component::new(&engine, ADD_CLIENT)?;
+        let ty = add_func_type(&engine, &server);
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+
+        let mut linker = Linker::new(&engine);
+        for (name, ty) in client.component_type().imports(&engine) {
+            link_item(
+                &engine,
+                &mut linker.root(),
+                Vec::<ResourceType>::default(),
+                HashMap::default(),
+                ty,
+                "",
+                name,
+            )?;
+        }
+        let mut store = new_store(&engine, clt);
+        let instance = linker.instantiate_async(&mut store, &client).await?;
+        let run = instance.get_typed_func::<(), (u32,)>(&mut store, "run")?;
+
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(run.call_async(&mut store, ()), async {
+            srv.accept(&lis).await?;
+            let ((), fut) = invocations
+                .next()
+                .await
+                .expect("unexpected end of stream")?;
+            fut.await
+        });
+        served?;
+        assert_eq!(res?, (42,));
+        Ok(())
+    }
+
+    /// Component importing `test:test/iface.f` taking a borrowed `wasi:io/input-stream` and
+    /// exporting `run`, which passes the stream borrowed from the host to it
+    const BORROW_STREAM_CLIENT: &str = r#"(component
+  (import "wasi:io/streams@0.2.0" (instance $streams
+    (export "input-stream" (type (sub resource)))
+  ))
+  (alias export $streams "input-stream" (type $input-stream))
+  (import "test:test/iface" (instance $iface
+    (export "f" (func (param "s" (borrow $input-stream))))
+  ))
+  (alias export $iface "f" (func $f))
+  (core func $f-lower (canon lower (func $f)))
+  (core module $m
+    (import "" "f" (func $f (param i32)))
+    (func (export "run") (param i32)
+      local.get 0
+      call $f)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "f" (func $f-lower))))
+  ))
+  (func (export "run") (param "s" (borrow $input-stream))
+    (canon lift (core func $i "run")))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn polyfill_borrowed_input_stream() -> anyhow::Result<()> {
+        use wasmtime::component::Resource;
+        use wasmtime_wasi::p2::pipe::MemoryInputPipe;
+        use wasmtime_wasi::p2::{DynInputStream, StreamError};
+
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let client = Component::new(&engine, BORROW_STREAM_CLIENT)?;
+        let mut linker = Linker::new(&engine);
+        linker.instance("wasi:io/streams@0.2.0")?.resource(
+            "input-stream",
+            ResourceType::host::<DynInputStream>(),
+            |_, _| Ok(()),
+        )?;
+        for (name, ty) in client.component_type().imports(&engine) {
+            if name == "test:test/iface" {
+                link_item(
+                    &engine,
+                    &mut linker.root(),
+                    Vec::<ResourceType>::default(),
+                    HashMap::default(),
+                    ty,
+                    "",
+                    name,
+                )?;
+            }
+        }
+
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations =
+            wrpc_transport::Serve::serve(&srv, "test:test/iface", "f", [Box::from([Some(0)])])
+                .await?;
+
+        let mut store = new_store(&engine, clt);
+        let instance = linker.instantiate_async(&mut store, &client).await?;
+        let run = instance
+            .get_func(&mut store, "run")
+            .context("function export `run` not found")?;
+        let stream: DynInputStream = Box::new(MemoryInputPipe::new(Bytes::from("foo")));
+        let stream = store.data_mut().table.push(stream)?;
+        let borrowed = Resource::<DynInputStream>::new_borrow(stream.rep())
+            .try_into_resource_any(&mut store)?;
+
+        let mut invocations = pin!(invocations);
+        let (res, received) = join!(
+            async {
+                run.call_async(&mut store, &[Val::Resource(borrowed)], &mut [])
+                    .await?;
+                run.post_return_async(&mut store).await
+            },
+            async {
+                srv.accept(&lis).await?;
+                let ((), tx, rx) = invocations
+                    .next()
+                    .await
+                    .context("unexpected end of stream")??;
+                let mut store = new_store(&engine, memory::pair(1).0);
+                let mut rx = pin!(rx);
+                let mut v = Val::Bool(false);
+                read_value(
+                    &mut store,
+                    &mut rx,
+                    &[],
+                    &mut v,
+                    &Type::Own(ResourceType::host::<DynInputStream>()),
+                    &[0],
+                )
+                .await?;
+                let Val::Resource(stream) = v else {
+                    bail!("expected a resource, got {v:?}");
+                };
+                let stream = stream.try_into_resource::<DynInputStream>(&mut store)?;
+                let mut stream = store.data_mut().table.delete(stream)?;
+                let mut buf = Vec::default();
+                loop {
+                    stream.ready().await;
+                    match stream.read(1024) {
+                        Ok(chunk) => buf.extend_from_slice(&chunk),
+                        Err(StreamError::Closed) => break,
+                        Err(err) => return Err(err.into()),
+                    }
+                }
+                // the function has no results, close the result stream to complete the call
+                drop(tx);
+                anyhow::Ok(buf)
+            }
+        );
+        assert_eq!(received?, b"foo");
+        res?;
+
+        // the borrowed stream was drained and the lender is left with a closed stream, but the
+        // borrowed handle remains valid
+        let stream = store.data_mut().table.get_mut(&stream)?;
+        assert!(matches!(stream.read(1024), Err(StreamError::Closed)));
+        Ok(())
+    }
+
+    /// Component exporting `test:test/iface.f`, which passes the output stream it is called
+    /// with to the imported `test:test/host.write`
+    const OUTPUT_STREAM_SERVER: &str = r#"(component
+  (import "wasi:io/streams@0.2.0" (instance $streams
+    (export "output-stream" (type (sub resource)))
+  ))
+  (alias export $streams "output-stream" (type $output-stream))
+  (import "test:test/host" (instance $host
+    (export "write" (func (param "s" (own $output-stream))))
+  ))
+  (alias export $host "write" (func $write))
+  (core func $write-lower (canon lower (func $write)))
+  (core module $m
+    (import "" "write" (func $write (param i32)))
+    (func (export "f") (param i32)
+      local.get 0
+      call $write)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "write" (func $write-lower))))
+  ))
+  (func $f (param "s" (own $output-stream)) (canon lift (core func $i "f")))
+  (instance $iface (export "f" (func $f)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn output_stream_param() -> anyhow::Result<()> {
+        use tokio::io::AsyncReadExt as _;
+        use wasmtime::component::Resource;
+        use wasmtime_wasi::p2::pipe::AsyncWriteStream;
+        use wasmtime_wasi::p2::DynOutputStream;
+
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, OUTPUT_STREAM_SERVER)?;
+        let mut linker = Linker::new(&engine);
+        linker.instance("wasi:io/streams@0.2.0")?.resource(
+            "output-stream",
+            ResourceType::host::<DynOutputStream>(),
+            |_, _| Ok(()),
+        )?;
+        linker.instance("test:test/host")?.func_wrap_async(
+            "write",
+            |mut store, (stream,): (Resource<DynOutputStream>,)| {
+                Box::new(async move {
+                    let mut stream = store.data_mut().table.delete(stream)?;
+                    stream.blocking_write_and_flush(Bytes::from("foo")).await?;
+                    anyhow::Ok(())
+                })
+            },
+        )?;
+        let mut store = new_store(&engine, memory::pair(1).0);
+        let instance = linker.instantiate_async(&mut store, &server).await?;
+        let iface = server
+            .get_export_index(None, "test:test/iface")
+            .expect("`test:test/iface` not found");
+        let idx = server
+            .get_export_index(Some(&iface), "f")
+            .expect("`f` not found");
+        let func = instance
+            .get_func(&mut store, idx)
+            .expect("function export `f` not found");
+        // the type of the instantiated function identifies `wasi:io/output-stream`
+        let ty = func.ty(&store);
+
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_instance_function(
+                Arc::new(Mutex::new(store)),
+                func,
+                HashMap::default(),
+                ty.clone(),
+                "test:test/iface",
+                "f",
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        let (mut rx, tx) = duplex(1024);
+        let stream: DynOutputStream = Box::new(AsyncWriteStream::new(1024, tx));
+        let stream = store
+            .data_mut()
+            .table
+            .push(stream)?
+            .try_into_resource_any(&mut store)?;
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            crate::invoke_values(
+                &mut store,
+                Vec::<ResourceType>::default(),
+                &ty,
+                "test:test/iface",
+                "f",
+                &[Val::Resource(stream)],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                fut.await
+            }
+        );
+        served?;
+        assert!(res?.is_empty());
+        // the stream was moved out of the table of the client and closed once the data
+        // written by the guest was received
+        assert!(store.data().table.is_empty());
+        let mut buf = vec![];
+        rx.read_to_end(&mut buf).await?;
+        assert_eq!(buf, b"foo");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn invoke_values() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                ty.clone(),
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            crate::invoke_values(
+                &mut store,
+                Vec::<ResourceType>::default(),
+                &ty,
+                "test:test/iface",
+                "add",
+                &[Val::U32(40), Val::U32(2)],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                fut.await
+            }
+        );
+        served?;
+        assert_eq!(res?, [Val::U32(42)]);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_trace_context() -> anyhow::Result<()> {
+        use wrpc_transport::trace::{TracePropagatingInvoke, TracePropagatingServe};
+
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (import "test:test/host" (instance $host
+                    (export "record" (func))
+                ))
+                (alias export $host "record" (func $record))
+                (core func $record-lower (canon lower (func $record)))
+                (core module $m
+                    (import "" "record" (func $record))
+                    (func (export "f") (result i32)
+                        call $record
+                        i32.const 42)
+                )
+                (core instance $i (instantiate $m
+                    (with "" (instance (export "record" (func $record-lower))))
+                ))
+                (func $f (result u32) (canon lift (core func $i "f")))
+                (instance $iface (export "f" (func $f)))
+                (export "test:test/iface" (instance $iface))
+            )"#,
+        )?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = component
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            bail!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "f")
+        else {
+            bail!("`test:test/iface` does not export `f`");
+        };
+
+        let (recorded_tx, mut recorded_rx) = mpsc::unbounded_channel();
+        let mut linker = Linker::new(&engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap("record", move |_, ()| {
+                _ = recorded_tx.send(TraceContext::current());
+                Ok(())
+            })?;
+
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = TracePropagatingServe::new(&srv)
+            .serve_function(
+                move || new_store(&engine, memory::pair(1).0),
+                linker.instantiate_pre(&component)?,
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "f",
+            )
+            .await?;
+
+        let trace = TraceContext {
+            trace_id: 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736,
+            span_id: 0x00f0_67aa_0ba9_02b7,
+            flags: TraceContext::FLAG_SAMPLED,
+        };
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            TracePropagatingInvoke::new(clt).invoke_values_blocking::<_, _, (u32,)>(
+                (Some(trace), ()),
+                "test:test/iface",
+                "f",
+                (),
+                &[[]; 0],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((received, ()), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                fut.await?;
+                anyhow::Ok(received)
+            }
+        );
+        assert_eq!(served?, Some(trace));
+        assert_eq!(res?, (42,));
+
+        // the export is called in a child span of the invoker's span within the same trace
+        let recorded = recorded_rx
+            .recv()
+            .await
+            .context("host function not called")?
+            .context("trace context not set")?;
+        assert_eq!(recorded.trace_id, trace.trace_id);
+        assert_eq!(recorded.flags, trace.flags);
+        assert_ne!(recorded.span_id, trace.span_id);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn transform_values() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || Store::new(&engine, TransformCtx(new_ctx(memory::pair(1).0)))
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                ty.clone(),
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            crate::invoke_values(
+                &mut store,
+                Vec::<ResourceType>::default(),
+                &ty,
+                "test:test/iface",
+                "add",
+                &[Val::U32(40), Val::U32(2)],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                fut.await
+            }
+        );
+        served?;
+        // the guest computes `40 * 10 + 2`, which is then incremented
+        assert_eq!(res?, [Val::U32(403)]);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_instance_function() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let iface = server
+            .get_export_index(None, "test:test/iface")
+            .expect("`test:test/iface` not found");
+        let idx = server
+            .get_export_index(Some(&iface), "add")
+            .expect("`add` not found");
+
+        let mut store = new_store(&engine, memory::pair(1).0);
+        let instance = Linker::new(&engine)
+            .instantiate_async(&mut store, &server)
+            .await?;
+        let func = instance
+            .get_func(&mut store, idx)
+            .expect("function export `add` not found");
+
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_instance_function(
+                Arc::new(Mutex::new(store)),
+                func,
+                HashMap::default(),
+                ty.clone(),
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        let mut invocations = pin!(invocations);
+        for (a, b) in [(40, 2), (1, 2)] {
+            let (res, served) = join!(
+                crate::invoke_values(
+                    &mut store,
+                    Vec::<ResourceType>::default(),
+                    &ty,
+                    "test:test/iface",
+                    "add",
+                    &[Val::U32(a), Val::U32(b)],
+                ),
+                async {
+                    srv.accept(&lis).await?;
+                    let ((), fut) = invocations
+                        .next()
+                        .await
+                        .expect("unexpected end of stream")?;
+                    fut.await
+                }
+            );
+            served?;
+            assert_eq!(res?, [Val::U32(a + b)]);
+        }
+        Ok(())
+    }
+
+    /// Component exporting `test:test/iface` with a `set` function taking a parameter and
+    /// a `reset` function taking none, neither of which returns results, and a `get` function
+    /// returning the value stored by them
+    const UNIT_SERVER: &str = r#"(component
+  (core module $m
+    (global $v (mut i32) (i32.const 0))
+    (func (export "reset")
+      i32.const 0
+      global.set $v)
+    (func (export "set") (param i32)
+      local.get 0
+      global.set $v)
+    (func (export "get") (result i32)
+      global.get $v)
+  )
+  (core instance $i (instantiate $m))
+  (func $reset (canon lift (core func $i "reset")))
+  (func $set (param "v" u32) (canon lift (core func $i "set")))
+  (func $get (result u32) (canon lift (core func $i "get")))
+  (instance $iface
+    (export "reset" (func $reset))
+    (export "set" (func $set))
+    (export "get" (func $get))
+  )
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn unit_functions() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, UNIT_SERVER)?;
+        let iface = server
+            .get_export_index(None, "test:test/iface")
+            .expect("`test:test/iface` not found");
+        let mut store = new_store(&engine, memory::pair(1).0);
+        let instance = Linker::new(&engine)
+            .instantiate_async(&mut store, &server)
+            .await?;
+        let mut funcs = Vec::default();
+        for name in ["set", "reset", "get"] {
+            let idx = server
+                .get_export_index(Some(&iface), name)
+                .with_context(|| format!("`{name}` not found"))?;
+            let func = instance
+                .get_func(&mut store, idx)
+                .with_context(|| format!("function export `{name}` not found"))?;
+            funcs.push((name, func, func.ty(&store)));
+        }
+        let store = Arc::new(Mutex::new(store));
+
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let mut served = Vec::default();
+        for ((name, func, ty), n) in zip(&funcs, [1, 1, 2]) {
+            let invocations = wrpc_transport::frame::Server::default()
+                .serve_instance_function(
+                    Arc::clone(&store),
+                    *func,
+                    HashMap::default(),
+                    ty.clone(),
+                    "test:test/iface",
+                    name,
+                )
+                .await?;
+            served.push(tokio::spawn(async move {
+                let mut invocations = pin!(invocations);
+                for _ in 0..n {
+                    let ((), fut) = invocations
+                        .next()
+                        .await
+                        .expect("unexpected end of stream")?;
+                    fut.await?;
+                }
+                anyhow::Ok(())
+            }));
+        }
+
+        let (clt, lis) = memory::pair(1024);
+        let accepted = tokio::spawn({
+            let srv = Arc::clone(&srv);
+            async move {
+                for _ in 0..4 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            }
+        });
+        let [(_, _, set_ty), (_, _, reset_ty), (_, _, get_ty)] = &funcs[..] else {
+            unreachable!()
+        };
+        let mut store = new_store(&engine, clt);
+        for (ty, name, params, expected) in [
+            (set_ty, "set", vec![Val::U32(42)], vec![]),
+            (get_ty, "get", vec![], vec![Val::U32(42)]),
+            (reset_ty, "reset", vec![], vec![]),
+            (get_ty, "get", vec![], vec![Val::U32(0)]),
+        ] {
+            let results = crate::invoke_values(
+                &mut store,
+                Vec::<ResourceType>::default(),
+                ty,
+                "test:test/iface",
+                name,
+                &params,
+            )
+            .await
+            .with_context(|| format!("failed to invoke `{name}`"))?;
+            assert_eq!(results, expected, "unexpected results of `{name}`");
+        }
+        accepted.await??;
+        for served in served {
+            served.await??;
+        }
+        Ok(())
+    }
+
+    /// [Client], the outgoing streams of which fail to shut down
+    struct ShutdownFailingClient(Client);
+
+    struct ShutdownFailingOutgoing(<Client as wrpc_transport::Invoke>::Outgoing);
+
+    impl wrpc_transport::Index<Self> for ShutdownFailingOutgoing {
+        fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+            self.0.index(path).map(Self)
+        }
+    }
+
+    impl AsyncWrite for ShutdownFailingOutgoing {
+        fn poll_write(
+            mut self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &[u8],
+        ) -> Poll<std::io::Result<usize>> {
+            Pin::new(&mut self.0).poll_write(cx, buf)
+        }
+
+        fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Pin::new(&mut self.0).poll_flush(cx)
+        }
+
+        fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+            Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()))
+        }
+    }
+
+    impl wrpc_transport::Invoke for ShutdownFailingClient {
+        type Context = ();
+        type Outgoing = ShutdownFailingOutgoing;
+        type Incoming = <Client as wrpc_transport::Invoke>::Incoming;
+
+        async fn invoke<P: AsRef<[Option<usize>]> + Send + Sync>(
+            &self,
+            cx: Self::Context,
+            instance: &str,
+            func: &str,
+            params: Bytes,
+            paths: impl AsRef<[P]> + Send,
+        ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)> {
+            let (tx, rx) = self.0.invoke(cx, instance, func, params, paths).await?;
+            Ok((ShutdownFailingOutgoing(tx), rx))
+        }
+    }
+
+    struct ShutdownFailingWrpcCtx {
+        client: ShutdownFailingClient,
+        shared_resources: SharedResourceTable,
+        fail_on_shutdown_error: bool,
+    }
+
+    impl WrpcCtx<ShutdownFailingClient> for ShutdownFailingWrpcCtx {
+        fn context(&self) {}
+
+        fn client(&self) -> &ShutdownFailingClient {
+            &self.client
+        }
+
+        fn shared_resources(&mut self) -> &mut SharedResourceTable {
+            &mut self.shared_resources
+        }
+
+        fn fail_on_shutdown_error(&self) -> bool {
+            self.fail_on_shutdown_error
+        }
+    }
+
+    struct ShutdownFailingCtx {
+        table: ResourceTable,
+        wrpc: ShutdownFailingWrpcCtx,
+    }
+
+    impl WrpcView for ShutdownFailingCtx {
+        type Invoke = ShutdownFailingClient;
+
+        fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke> {
+            WrpcCtxView {
+                ctx: &mut self.wrpc,
+                table: &mut self.table,
+            }
+        }
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn shutdown_error() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                ty.clone(),
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+        let mut invocations = pin!(invocations);
+        for fail_on_shutdown_error in [false, true] {
+            let (clt, lis) = memory::pair(1024);
+            let mut store = Store::new(
+                &engine,
+                ShutdownFailingCtx {
+                    table: ResourceTable::default(),
+                    wrpc: ShutdownFailingWrpcCtx {
+                        client: ShutdownFailingClient(clt),
+                        shared_resources: SharedResourceTable::default(),
+                        fail_on_shutdown_error,
+                    },
+                },
+            );
+            let (res, served) = join!(
+                crate::invoke_values(
+                    &mut store,
+                    Vec::<ResourceType>::default(),
+                    &ty,
+                    "test:test/iface",
+                    "add",
+                    &[Val::U32(40), Val::U32(2)],
+                ),
+                async {
+                    srv.accept(&lis).await?;
+                    let ((), fut) = invocations
+                        .next()
+                        .await
+                        .expect("unexpected end of stream")?;
+                    fut.await
+                }
+            );
+            if fail_on_shutdown_error {
+                let err = res.expect_err("invocation should fail on shutdown error");
+                assert!(
+                    format!("{err:#}").contains("failed to shutdown outgoing stream"),
+                    "{err:#}"
+                );
+            } else {
+                // the failure is only logged
+                served?;
+                assert_eq!(res?, [Val::U32(42)]);
+            }
+        }
+        Ok(())
+    }
+
+    /// Component exporting `test:test/iface.div`, which returns an error on division by zero
+    const DIV_SERVER: &str = r#"(component
+  (core module $m
+    (memory (export "memory") 1)
+    (data (i32.const 64) "division by zero")
+    (func (export "div") (param i32 i32) (result i32)
+      (if (i32.eqz (local.get 1))
+        (then
+          (i32.store8 (i32.const 0) (i32.const 1))
+          (i32.store (i32.const 4) (i32.const 64))
+          (i32.store (i32.const 8) (i32.const 16)))
+        (else
+          (i32.store8 (i32.const 0) (i32.const 0))
+          (i32.store (i32.const 4) (i32.div_u (local.get 0) (local.get 1)))))
+      (i32.const 0))
+  )
+  (core instance $i (instantiate $m))
+  (func $div (param "a" u32) (param "b" u32) (result (result u32 (error string)))
+    (canon lift (core func $i "div") (memory $i "memory")))
+  (instance $iface (export "div" (func $div)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn invoke_result() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, DIV_SERVER)?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = server
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "div")
+        else {
+            panic!("`test:test/iface` does not export `div`");
+        };
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                ty.clone(),
+                "test:test/iface",
+                "div",
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        let mut invocations = pin!(invocations);
+        for (params, expected) in [
+            ([Val::U32(42), Val::U32(2)], Ok(Val::U32(21))),
+            (
+                [Val::U32(42), Val::U32(0)],
+                Err(Val::String("division by zero".into())),
+            ),
+        ] {
+            let (res, served) = join!(
+                crate::invoke_result(
+                    &mut store,
+                    Vec::<ResourceType>::default(),
+                    &ty,
+                    "test:test/iface",
+                    "div",
+                    &params,
+                ),
+                async {
+                    srv.accept(&lis).await?;
+                    let ((), fut) = invocations
+                        .next()
+                        .await
+                        .expect("unexpected end of stream")?;
+                    fut.await
+                }
+            );
+            served?;
+            match (res, expected) {
+                (Ok(v), Ok(expected)) => assert_eq!(v, Some(expected)),
+                (Err(err), Err(expected)) => {
+                    let crate::ResultError(err) = err.downcast()?;
+                    assert_eq!(err, Some(expected));
+                }
+                (res, expected) => panic!("expected {expected:?}, got {res:?}"),
+            }
+        }
+        Ok(())
+    }
+
+    /// [Layer] collecting `invocation_id` fields recorded on spans along with the span names
+    #[derive(Clone, Default)]
+    struct InvocationIds(Arc<std::sync::Mutex<Vec<(&'static str, String)>>>);
+
+    struct InvocationIdVisitor<'a>(&'static str, &'a InvocationIds);
+
+    impl Visit for InvocationIdVisitor<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn core::fmt::Debug) {
+            if field.name() == "invocation_id" {
+                let InvocationIdVisitor(name, InvocationIds(ids)) = self;
+                ids.lock().unwrap().push((*name, format!("{value:?}")));
+            }
+        }
+    }
+
+    impl<S> Layer<S> for InvocationIds
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(
+            &self,
+            attrs: &span::Attributes<'_>,
+            _: &span::Id,
+            _: layer::Context<'_, S>,
+        ) {
+            attrs.record(&mut InvocationIdVisitor(attrs.metadata().name(), self));
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, cx: layer::Context<'_, S>) {
+            if let Some(metadata) = cx.metadata(id) {
+                values.record(&mut InvocationIdVisitor(metadata.name(), self));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn invocation_id_spans() -> anyhow::Result<()> {
+        let ids = InvocationIds::default();
+        let _subscriber =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(ids.clone()));
+
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let client = Component::new(&engine, ADD_CLIENT)?;
+        let ty = add_func_type(&engine, &server);
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || {
+                        let mut store = new_store(&engine, memory::pair(1).0);
+                        store.data_mut().wrpc.invocation_id_header = true;
+                        store
+                    }
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+
+        let mut linker = Linker::new(&engine);
+        for (name, ty) in client.component_type().imports(&engine) {
+            link_item(
+                &engine,
+                &mut linker.root(),
+                Vec::<ResourceType>::default(),
+                HashMap::default(),
+                ty,
+                "",
+                name,
+            )?;
+        }
+        let mut store = new_store(&engine, clt);
+        store.data_mut().wrpc.invocation_id_header = true;
+        let instance = linker.instantiate_async(&mut store, &client).await?;
+        let run = instance.get_typed_func::<(), (u32,)>(&mut store, "run")?;
+
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(run.call_async(&mut store, ()), async {
+            srv.accept(&lis).await?;
+            let ((), fut) = invocations
+                .next()
+                .await
+                .expect("unexpected end of stream")?;
+            fut.await
+        });
+        served?;
+        assert_eq!(res?, (42,));
+
+        let ids = ids.0.lock().unwrap().clone();
+        let ids_of = |span| {
+            ids.iter()
+                .filter(|(name, _)| *name == span)
+                .map(|(_, id)| id.as_str())
+                .collect::<Vec<_>>()
+        };
+        let invoke = ids_of("invoke");
+        assert_eq!(invoke.len(), 1, "unexpected invocation IDs: {ids:?}");
+        assert_eq!(ids_of("transmit"), invoke);
+        assert_eq!(ids_of("receive"), invoke);
+        // the ID is propagated to the serving side
+        assert_eq!(ids_of("call"), invoke);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn custom_rpc_name() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let client = Component::new(&engine, ADD_CLIENT)?;
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+
+        // exports are served under the mapped names
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || Store::new(&engine, RenamedCtx(new_ctx(memory::pair(1).0)))
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                add_func_type(&engine, &server),
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            clt.invoke_values_blocking::<_, _, (u32,)>(
+                (),
+                "renamed:test:test/iface",
+                "ADD",
+                (1u32, 2u32),
+                &[[]; 0],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                fut.await
+            }
+        );
+        served?;
+        assert_eq!(res?, (3,));
+
+        // imports are invoked using the mapped names
+        let adds = srv
+            .serve_values::<(u32, u32), (u32,)>(
+                "renamed:test:test/iface",
+                "ADD",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let mut linker = Linker::new(&engine);
+        for (name, ty) in client.component_type().imports(&engine) {
+            link_item(
+                &engine,
+                &mut linker.root(),
+                Vec::<ResourceType>::default(),
+                HashMap::default(),
+                ty,
+                "",
+                name,
+            )?;
+        }
+        let mut store = Store::new(&engine, RenamedCtx(new_ctx(clt)));
+        let instance = linker.instantiate_async(&mut store, &client).await?;
+        let run = instance.get_typed_func::<(), (u32,)>(&mut store, "run")?;
+        let mut adds = pin!(adds);
+        let (res, served) = join!(run.call_async(&mut store, ()), async {
+            srv.accept(&lis).await?;
+            let ((), (a, b), _, tx) = adds.try_next().await?.expect("unexpected end of stream");
+            tx((a + b,)).await
+        });
+        served?;
+        assert_eq!(res?, (42,));
+        Ok(())
+    }
+
+    /// Component exporting `test:test/iface.run`, which calls the imported `test:test/host.block`
+    const BLOCK_SERVER: &str = r#"(component
+  (import "test:test/host" (instance $host
+    (export "block" (func))
+  ))
+  (alias export $host "block" (func $block))
+  (core func $block-lower (canon lower (func $block)))
+  (core module $m
+    (import "" "block" (func $block))
+    (func (export "run")
+      call $block)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "block" (func $block-lower))))
+  ))
+  (func $run (canon lift (core func $i "run")))
+  (instance $iface (export "run" (func $run)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    /// Component exporting `test:test/iface.run`, which loops forever
+    const LOOP_SERVER: &str = r#"(component
+  (core module $m
+    (func (export "run")
+      (loop $l
+        br $l))
+  )
+  (core instance $i (instantiate $m))
+  (func $run (canon lift (core func $i "run")))
+  (instance $iface (export "run" (func $run)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    /// Reads from `rx` until `reset` is dropped, after which reads fail as if the peer reset
+    /// the connection. End of `rx` is never observed.
+    struct ResetReader {
+        rx: ReadHalf<DuplexStream>,
+        reset: Option<oneshot::Receiver<()>>,
+    }
+
+    impl AsyncRead for ResetReader {
+        fn poll_read(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+            buf: &mut ReadBuf<'_>,
+        ) -> Poll<std::io::Result<()>> {
+            let this = self.get_mut();
+            let Some(reset) = &mut this.reset else {
+                return Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into()));
+            };
+            if Pin::new(reset).poll(cx).is_ready() {
+                this.reset = None;
+                return Poll::Ready(Err(std::io::ErrorKind::ConnectionReset.into()));
+            }
+            let n = buf.filled().len();
+            ready!(Pin::new(&mut this.rx).poll_read(cx, buf))?;
+            if buf.filled().len() == n {
+                Poll::Pending
+            } else {
+                Poll::Ready(Ok(()))
+            }
+        }
+    }
+
+    /// Component importing `test:test/iface.run` and exporting `run`, which calls it
+    const BLOCK_CLIENT: &str = r#"(component
+  (import "test:test/iface" (instance $iface
+    (export "run" (func))
+  ))
+  (alias export $iface "run" (func $run))
+  (core func $run-lower (canon lower (func $run)))
+  (core module $m
+    (import "" "run" (func $run))
+    (func (export "run")
+      call $run)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "run" (func $run-lower))))
+  ))
+  (func (export "run") (canon lift (core func $i "run")))
+)"#;
+
+    fn run_func_type(engine: &Engine, component: &Component) -> types::ComponentFunc {
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = component
+            .component_type()
+            .exports(engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(engine).find(|(name, _)| *name == "run")
+        else {
+            panic!("`test:test/iface` does not export `run`");
+        };
+        ty
+    }
+
+    /// Returns a [Linker] defining `test:test/host.block`, which never returns. Instead, it sends
+    /// a receiver, which fails once the host future is dropped
+    fn block_linker(
+        engine: &Engine,
+    ) -> anyhow::Result<(
+        Linker<TestCtx>,
+        mpsc::UnboundedReceiver<oneshot::Receiver<()>>,
+    )> {
+        let (started_tx, started_rx) = mpsc::unbounded_channel();
+        let mut linker = Linker::new(engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap_async("block", move |_, ()| {
+                let (tx, rx) = oneshot::channel::<()>();
+                _ = started_tx.send(rx);
+                Box::new(async move {
+                    let _tx = tx;
+                    core::future::pending::<wasmtime::Result<()>>().await
+                })
+            })?;
+        Ok((linker, started_rx))
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn post_return_error() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (core module $m
+                    (func (export "get") (result i32) i32.const 42)
+                    (func (export "cabi_post_get") (param i32) unreachable)
+                )
+                (core instance $i (instantiate $m))
+                (func $get (result u32)
+                    (canon lift (core func $i "get") (post-return (func $i "cabi_post_get"))))
+                (instance $iface (export "get" (func $get)))
+                (export "test:test/iface" (instance $iface))
+            )"#,
+        )?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = component
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            bail!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "get")
+        else {
+            bail!("`test:test/iface` does not export `get`");
+        };
+
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                Linker::new(&engine).instantiate_pre(&component)?,
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "get",
+            )
+            .await?;
+
+        let (clt, lis) = memory::pair(1024);
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            clt.invoke_values_blocking::<_, _, (u32,)>((), "test:test/iface", "get", (), &[[]; 0]),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                anyhow::Ok(fut.await)
+            },
+        );
+        assert_eq!(
+            res?,
+            (42,),
+            "results should be transmitted before post-return"
+        );
+        let err = served?.expect_err("post-return cleanup should have failed");
+        assert!(
+            matches!(
+                err.downcast_ref::<CallError>(),
+                Some(CallError::PostReturn(..))
+            ),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_context() -> anyhow::Result<()> {
+        let engine = Engine::new(wasmtime::Config::new().async_support(true))?;
+        let component = Component::new(
+            &engine,
+            r#"(component
+                (import "test:test/host" (instance $host
+                    (export "peer-id" (func (result u32)))
+                ))
+                (alias export $host "peer-id" (func $peer-id))
+                (core func $peer-id-lower (canon lower (func $peer-id)))
+                (core module $m
+                    (import "" "peer-id" (func $peer-id (result i32)))
+                    (func (export "run") (result i32)
+                        call $peer-id)
+                )
+                (core instance $i (instantiate $m
+                    (with "" (instance (export "peer-id" (func $peer-id-lower))))
+                ))
+                (func $run (result u32) (canon lift (core func $i "run")))
+                (instance $iface (export "run" (func $run)))
+                (export "test:test/iface" (instance $iface))
+            )"#,
+        )?;
+        let ty = run_func_type(&engine, &component);
+
+        let mut linker = Linker::<TestCtx>::new(&engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap("peer-id", |store, ()| {
+                Ok((store.data().serve_context.unwrap_or_default(),))
+            })?;
+
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                linker.instantiate_pre(&component)?,
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "run",
+            )
+            .await?;
+
+        let (clt, lis) = memory::pair(1024);
+        let lis = lis.map_context(|()| 42u32);
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            clt.invoke_values_blocking::<_, _, (u32,)>((), "test:test/iface", "run", (), &[[]; 0]),
+            async {
+                srv.accept(&lis).await?;
+                let (cx, fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                assert_eq!(cx, 42);
+                anyhow::Ok(fut.await)
+            },
+        );
+        assert_eq!(
+            res?,
+            (42,),
+            "host function should observe the serve context"
+        );
+        served??;
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn cancel_on_reset() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, BLOCK_SERVER)?;
+        let ty = run_func_type(&engine, &server);
+        let (linker, mut started_rx) = block_linker(&engine)?;
+
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                linker.instantiate_pre(&server)?,
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "run",
+            )
+            .await?;
+
+        let (clt, conn) = duplex(1024);
+        let (clt_rx, clt_tx) = split(clt);
+        let (conn_rx, conn_tx) = split(conn);
+        let (reset_tx, reset_rx) = oneshot::channel();
+        let conn = wrpc_transport::frame::Oneshot::from((
+            ResetReader {
+                rx: conn_rx,
+                reset: Some(reset_rx),
+            },
+            conn_tx,
+        ));
+        let invocation = wrpc_transport::frame::invoke(
+            clt_tx,
+            clt_rx,
+            "test:test/iface",
+            "run",
+            Bytes::new(),
+            &[[]; 0],
+        )
+        .await?;
+        srv.accept(&conn).await?;
+        let mut invocations = pin!(invocations);
+        let ((), fut) = invocations
+            .next()
+            .await
+            .expect("unexpected end of stream")?;
+        let served = tokio::spawn(fut);
+
+        let blocked = started_rx.recv().await.expect("`block` was not called");
+        drop((invocation, reset_tx));
+        let err = served.await?.expect_err("call should have been cancelled");
+        assert!(
+            format!("{err:#}").contains("cancelled"),
+            "unexpected error: {err:#}"
+        );
+        assert!(
+            blocked.await.is_err(),
+            "host future should have been dropped"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_function_pooled() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, BLOCK_SERVER)?;
+        let ty = run_func_type(&engine, &server);
+
+        // `block` returns once a permit is added to `gate`
+        let gate = Arc::new(Semaphore::new(0));
+        let (started_tx, mut started_rx) = mpsc::unbounded_channel();
+        let mut linker = Linker::new(&engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap_async("block", {
+                let gate = Arc::clone(&gate);
+                move |_, ()| {
+                    let gate = Arc::clone(&gate);
+                    _ = started_tx.send(());
+                    Box::new(async move {
+                        gate.acquire().await?.forget();
+                        Ok(())
+                    })
+                }
+            })?;
+
+        let instantiated = Arc::new(AtomicUsize::default());
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let invocations = srv
+            .serve_function_pooled(
+                {
+                    let instantiated = Arc::clone(&instantiated);
+                    move || {
+                        instantiated.fetch_add(1, Ordering::Relaxed);
+                        new_store(&engine, memory::pair(1).0)
+                    }
+                },
+                linker.instantiate_pre(&server)?,
+                NonZeroUsize::new(2).unwrap(),
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "run",
+            )
+            .await?;
+        let served = tokio::spawn(async move {
+            let mut invocations = pin!(invocations);
+            let mut served = Vec::default();
+            for _ in 0..4 {
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                served.push(tokio::spawn(fut));
+            }
+            for fut in served {
+                fut.await??;
+            }
+            anyhow::Ok(())
+        });
+
+        let (clt, lis) = memory::pair(1024);
+        let accepted = tokio::spawn({
+            let srv = Arc::clone(&srv);
+            async move {
+                for _ in 0..4 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            }
+        });
+        let clt = &clt;
+        let invoke = move || {
+            clt.invoke_values_blocking::<_, _, ()>((), "test:test/iface", "run", (), &[[]; 0])
+        };
+        let (a, b, c, ()) = join!(invoke(), invoke(), invoke(), async {
+            started_rx.recv().await.expect("`block` was not called");
+            started_rx.recv().await.expect("`block` was not called");
+            // the third invocation waits for a pooled instance to become available
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(started_rx.try_recv().is_err());
+            assert_eq!(instantiated.load(Ordering::Relaxed), 2);
+            gate.add_permits(3);
+        });
+        a?;
+        b?;
+        c?;
+
+        gate.add_permits(1);
+        invoke().await?;
+        assert_eq!(instantiated.load(Ordering::Relaxed), 2);
+        accepted.await??;
+        served.await??;
+        Ok(())
+    }
+
+    /// Component exporting `test:test/iface.next`, which returns the number of times it was
+    /// called by the instance and traps if `trap` is set
+    const COUNTER_SERVER: &str = r#"(component
+  (core module $m
+    (global $n (mut i32) (i32.const 0))
+    (func (export "next") (param i32) (result i32)
+      local.get 0
+      if
+        unreachable
+      end
+      global.get $n
+      i32.const 1
+      i32.add
+      global.set $n
+      global.get $n)
+  )
+  (core instance $i (instantiate $m))
+  (func $next (param "trap" bool) (result u32)
+    (canon lift (core func $i "next")))
+  (instance $iface (export "next" (func $next)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_function_restartable() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, COUNTER_SERVER)?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = server
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "next")
+        else {
+            panic!("`test:test/iface` does not export `next`");
+        };
+
+        let instance = Arc::new(
+            RestartableInstance::new(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+            )
+            .await?,
+        );
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let invocations = srv
+            .serve_function_restartable(
+                Arc::clone(&instance),
+                Vec::<ResourceType>::new(),
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "next",
+            )
+            .await?;
+        let served = tokio::spawn(async move {
+            let mut invocations = pin!(invocations);
+            let mut served = Vec::default();
+            for _ in 0..4 {
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                served.push(fut.await.is_ok());
+            }
+            anyhow::Ok(served)
+        });
+
+        let (clt, lis) = memory::pair(1024);
+        let accepted = tokio::spawn({
+            let srv = Arc::clone(&srv);
+            async move {
+                for _ in 0..4 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            }
+        });
+        let clt = &clt;
+        let next = move |trap: bool| {
+            clt.invoke_values_blocking::<_, _, (u32,)>(
+                (),
+                "test:test/iface",
+                "next",
+                (trap,),
+                &[[]; 0],
+            )
+        };
+        assert_eq!(next(false).await?, (1,));
+        assert_eq!(next(false).await?, (2,));
+        next(true)
+            .await
+            .expect_err("trapping invocation should fail");
+        // guest state is reset by the restart
+        assert_eq!(next(false).await?, (1,));
+        assert_eq!(instance.restarts(), 1);
+        accepted.await??;
+        assert_eq!(served.await??, [true, true, false, true]);
+        Ok(())
+    }
+
+    /// Component exporting `test:test/iface.next`, which returns the number of times it was
+    /// called by the instance after calling the imported `test:test/host.check` with `panic`
+    const PANIC_SERVER: &str = r#"(component
+  (import "test:test/host" (instance $host
+    (export "check" (func (param "panic" bool)))
+  ))
+  (alias export $host "check" (func $check))
+  (core func $check-lower (canon lower (func $check)))
+  (core module $m
+    (import "" "check" (func $check (param i32)))
+    (global $n (mut i32) (i32.const 0))
+    (func (export "next") (param i32) (result i32)
+      global.get $n
+      i32.const 1
+      i32.add
+      global.set $n
+      local.get 0
+      call $check
+      global.get $n)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "check" (func $check-lower))))
+  ))
+  (func $next (param "panic" bool) (result u32)
+    (canon lift (core func $i "next")))
+  (instance $iface (export "next" (func $next)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_function_restartable_panic() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, PANIC_SERVER)?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = server
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "next")
+        else {
+            panic!("`test:test/iface` does not export `next`");
+        };
+
+        let mut linker = Linker::new(&engine);
+        linker
+            .instance("test:test/host")?
+            .func_wrap("check", |_, (panic,): (bool,)| {
+                assert!(!panic, "deliberate host function panic");
+                Ok(())
+            })?;
+        let instance = Arc::new(
+            RestartableInstance::new(
+                {
+                    let engine = engine.clone();
+                    move || new_store(&engine, memory::pair(1).0)
+                },
+                linker.instantiate_pre(&server)?,
+            )
+            .await?,
+        );
+        let srv = Arc::new(wrpc_transport::frame::Server::default());
+        let invocations = srv
+            .serve_function_restartable(
+                Arc::clone(&instance),
+                Vec::<ResourceType>::new(),
+                HashMap::default(),
+                ty,
+                "test:test/iface",
+                "next",
+            )
+            .await?;
+        let served = tokio::spawn(async move {
+            let mut invocations = pin!(invocations);
+            let mut served = Vec::default();
+            for _ in 0..3 {
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                // report whether the invocation succeeded or panicked
+                served.push(
+                    tokio::spawn(fut)
+                        .await
+                        .map(|res| res.is_ok())
+                        .map_err(|err| err.is_panic()),
+                );
+            }
+            anyhow::Ok(served)
+        });
+
+        let (clt, lis) = memory::pair(1024);
+        let accepted = tokio::spawn({
+            let srv = Arc::clone(&srv);
+            async move {
+                for _ in 0..3 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            }
+        });
+        let clt = &clt;
+        let next = move |panic: bool| {
+            clt.invoke_values_blocking::<_, _, (u32,)>(
+                (),
+                "test:test/iface",
+                "next",
+                (panic,),
+                &[[]; 0],
+            )
+        };
+        assert_eq!(next(false).await?, (1,));
+        next(true)
+            .await
+            .expect_err("panicking invocation should fail");
+        // the instance is rebuilt before the next call, since the previous one did not complete
+        assert_eq!(next(false).await?, (1,));
+        assert_eq!(instance.restarts(), 1);
+        accepted.await??;
+        assert_eq!(served.await??, [Ok(true), Err(true), Ok(true)]);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn arity_mismatch() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let counter = Component::new(&engine, COUNTER_SERVER)?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = counter
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(next_ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "next")
+        else {
+            panic!("`test:test/iface` does not export `next`");
+        };
+
+        // `add` served using the type of `next`, which takes a single parameter.
+        // The function is resolved by the caller, so the mismatch is only detected on call
+        let iface = server
+            .get_export_index(None, "test:test/iface")
+            .expect("`test:test/iface` not found");
+        let idx = server
+            .get_export_index(Some(&iface), "add")
+            .expect("`add` not found");
+        let mut store = new_store(&engine, memory::pair(1).0);
+        let instance = Linker::new(&engine)
+            .instantiate_async(&mut store, &server)
+            .await?;
+        let func = instance
+            .get_func(&mut store, idx)
+            .expect("function export `add` not found");
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_instance_function(
+                Arc::new(Mutex::new(store)),
+                func,
+                HashMap::default(),
+                next_ty,
+                "test:test/iface",
+                "add",
+            )
+            .await?;
+        let (clt, lis) = memory::pair(1024);
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            clt.invoke_values_blocking::<_, _, (u32,)>(
+                (),
+                "test:test/iface",
+                "add",
+                (true,),
+                &[[]; 0]
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                anyhow::Ok(fut.await)
+            },
+        );
+        res.expect_err("invocation should fail");
+        let err = served?.expect_err("call should fail");
+        assert!(
+            matches!(
+                err.downcast_ref::<CallError>(),
+                Some(CallError::TypeMismatch(..))
+            ),
+            "unexpected error: {err:#}"
+        );
+
+        // polyfilled invocation with too few parameters
+        let mut store = new_store(&engine, memory::pair(1).0);
+        let err = crate::invoke_values(
+            &mut store,
+            Vec::<ResourceType>::default(),
+            &ty,
+            "test:test/iface",
+            "add",
+            &[Val::U32(40)],
+        )
+        .await
+        .expect_err("invocation should fail");
+        assert!(
+            err.to_string()
+                .contains("takes 2 parameters, but 1 were given"),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn func_type_mismatch() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let add_ty = add_func_type(&engine, &server);
+        let div = Component::new(&engine, DIV_SERVER)?;
+        let Some((_, types::ComponentItem::ComponentInstance(iface))) = div
+            .component_type()
+            .exports(&engine)
+            .find(|(name, _)| *name == "test:test/iface")
+        else {
+            panic!("component does not export `test:test/iface`");
+        };
+        let Some((_, types::ComponentItem::ComponentFunc(div_ty))) =
+            iface.exports(&engine).find(|(name, _)| *name == "div")
+        else {
+            panic!("`test:test/iface` does not export `div`");
+        };
+
+        let srv = wrpc_transport::frame::Server::default();
+        for (ty, name, expected) in [
+            // `add` served using the type of `div`, which returns a `result`
+            (
+                div_ty.clone(),
+                "add",
+                Some("result 0 of function export `add` has type `U32`"),
+            ),
+            (add_ty.clone(), "sub", Some("export `sub` not found")),
+            (add_ty.clone(), "add", None),
+        ] {
+            let res = srv
+                .serve_function(
+                    {
+                        let engine = engine.clone();
+                        move || new_store(&engine, memory::pair(1).0)
+                    },
+                    Linker::new(&engine).instantiate_pre(&server)?,
+                    HashMap::default(),
+                    ty,
+                    "test:test/iface",
+                    name,
+                )
+                .await;
+            match (res, expected) {
+                (Ok(..), None) => {}
+                (Ok(..), Some(expected)) => {
+                    panic!("serving `{name}` should fail with `{expected}`")
+                }
+                (Err(err), None) => panic!("failed to serve `{name}`: {err:#}"),
+                (Err(err), Some(expected)) => assert!(
+                    err.to_string().starts_with(expected),
+                    "unexpected error: {err:#}"
+                ),
+            }
+        }
+
+        let mut store = new_store(&engine, memory::pair(1).0);
+        let instance = Linker::new(&engine)
+            .instantiate_async(&mut store, &server)
+            .await?;
+        let iface = server
+            .get_export_index(None, "test:test/iface")
+            .expect("`test:test/iface` not found");
+        let idx = server
+            .get_export_index(Some(&iface), "add")
+            .expect("`add` not found");
+        let func = instance
+            .get_func(&mut store, idx)
+            .expect("function export `add` not found");
+        let store = Arc::new(Mutex::new(store));
+        for (ty, expected) in [
+            (
+                div_ty,
+                Some("result 0 of function export `add` has type `U32`"),
+            ),
+            (add_ty, None),
+        ] {
+            let shared = wrpc_transport::frame::Server::default()
+                .serve_function_shared(
+                    Arc::clone(&store),
+                    instance,
+                    [],
+                    HashMap::default(),
+                    ty.clone(),
+                    "test:test/iface",
+                    "add",
+                )
+                .await
+                .map(|_| ());
+            let func = wrpc_transport::frame::Server::default()
+                .serve_instance_function(
+                    Arc::clone(&store),
+                    func,
+                    HashMap::default(),
+                    ty,
+                    "test:test/iface",
+                    "add",
+                )
+                .await
+                .map(|_| ());
+            for res in [shared, func] {
+                match (res, expected) {
+                    (Ok(()), None) => {}
+                    (Ok(()), Some(expected)) => {
+                        panic!("serving `add` should fail with `{expected}`")
+                    }
+                    (Err(err), None) => panic!("failed to serve `add`: {err:#}"),
+                    (Err(err), Some(expected)) => assert!(
+                        err.to_string().starts_with(expected),
+                        "unexpected error: {err:#}"
+                    ),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn deadline_header() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, BLOCK_SERVER)?;
+        let client = Component::new(&engine, BLOCK_CLIENT)?;
+        let (clt, lis) = memory::pair(1024);
+
+        let (linker, mut started_rx) = block_linker(&engine)?;
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || {
+                        let mut store = new_store(&engine, memory::pair(1).0);
+                        store.data_mut().wrpc.deadline_header = true;
+                        store
+                    }
+                },
+                linker.instantiate_pre(&server)?,
+                HashMap::default(),
+                run_func_type(&engine, &server),
+                "test:test/iface",
+                "run",
+            )
+            .await?;
+
+        let mut linker = Linker::new(&engine);
+        for (name, ty) in client.component_type().imports(&engine) {
+            link_item(
+                &engine,
+                &mut linker.root(),
+                Vec::<ResourceType>::default(),
+                HashMap::default(),
+                ty,
+                "",
+                name,
+            )?;
+        }
+        let mut store = new_store(&engine, clt);
+        store.data_mut().wrpc.timeout = Some(Duration::from_millis(100));
+        store.data_mut().wrpc.deadline_header = true;
+        let instance = linker.instantiate_async(&mut store, &client).await?;
+        let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(run.call_async(&mut store, ()), async {
+            srv.accept(&lis).await?;
+            let ((), fut) = invocations
+                .next()
+                .await
+                .expect("unexpected end of stream")?;
+            anyhow::Ok(fut.await)
+        });
+        assert!(res.is_err(), "client call should have timed out");
+        let err = served?.expect_err("served call should have exceeded the deadline");
+        assert!(
+            format!("{err:#}").contains("deadline exceeded"),
+            "unexpected error: {err:#}"
+        );
+        let blocked = started_rx.recv().await.expect("`block` was not called");
+        assert!(
+            blocked.await.is_err(),
+            "host future should have been dropped"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn invoke_timeout() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let adds = srv
+            .serve_values::<(u32, u32), (u32,)>(
+                "test:test/iface",
+                "add",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        store.data_mut().wrpc.timeout = Some(Duration::from_secs(60));
+        let started = std::time::Instant::now();
+        let mut adds = pin!(adds);
+        let (res, served) = join!(
+            crate::invoke_values(
+                &mut store,
+                Vec::<ResourceType>::default(),
+                &ty,
+                "test:test/iface",
+                "add",
+                &[Val::U32(40), Val::U32(2)],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), (a, b), _, tx) = adds.try_next().await?.expect("unexpected end of stream");
+                assert_eq!((a, b), (40, 2));
+                tokio::time::advance(Duration::from_secs(61)).await;
+                // keep the invocation open, so that the results are never received
+                anyhow::Ok(tx)
+            }
+        );
+        served?;
+        let err = res.expect_err("invocation should have timed out");
+        assert!(
+            format!("{err:#}").contains("timed out"),
+            "unexpected error: {err:#}"
+        );
+        assert!(
+            started.elapsed() < Duration::from_secs(60),
+            "timeout should not have waited for real time to pass"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(start_paused = true))]
+    async fn invoke_timeout_for() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        let engine = Engine::new(&config)?;
+        let server = Component::new(&engine, ADD_SERVER)?;
+        let ty = add_func_type(&engine, &server);
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let adds = srv
+            .serve_values::<(u32, u32), (u32,)>(
+                "test:test/iface",
+                "res.add",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+
+        let mut store = new_store(&engine, clt);
+        store.data_mut().wrpc.timeout = Some(Duration::from_secs(60));
+        // the timeout is looked up using the name declared by the component
+        store.data_mut().wrpc.function_timeout =
+            Some(("test:test/iface", "[static]res.add", Duration::from_secs(1)));
+        let mut adds = pin!(adds);
+        let (res, served) = join!(
+            crate::invoke_values(
+                &mut store,
+                Vec::<ResourceType>::default(),
+                &ty,
+                "test:test/iface",
+                "[static]res.add",
+                &[Val::U32(40), Val::U32(2)],
+            ),
+            async {
+                srv.accept(&lis).await?;
+                let ((), (a, b), _, tx) = adds.try_next().await?.expect("unexpected end of stream");
+                assert_eq!((a, b), (40, 2));
+                tokio::time::advance(Duration::from_secs(2)).await;
+                // keep the invocation open, so that the results are never received
+                anyhow::Ok(tx)
+            }
+        );
+        served?;
+        let err = res.expect_err("invocation should have timed out");
+        assert!(
+            format!("{err:#}").contains("timed out"),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn execution_timeout() -> anyhow::Result<()> {
+        let mut config = wasmtime::Config::new();
+        config.async_support(true);
+        config.epoch_interruption(true);
+        let engine = Engine::new(&config)?;
+        let ticker = tokio::spawn({
+            let engine = engine.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                    engine.increment_epoch();
+                }
+            }
+        });
+        let server = Component::new(&engine, LOOP_SERVER)?;
+        let (clt, lis) = memory::pair(1024);
+        let srv = wrpc_transport::frame::Server::default();
+        let invocations = srv
+            .serve_function(
+                {
+                    let engine = engine.clone();
+                    move || {
+                        let mut store = new_store(&engine, memory::pair(1).0);
+                        store.data_mut().wrpc.execution_timeout = Some(Duration::from_millis(100));
+                        store
+                    }
+                },
+                Linker::new(&engine).instantiate_pre(&server)?,
+                HashMap::default(),
+                run_func_type(&engine, &server),
+                "test:test/iface",
+                "run",
+            )
+            .await?;
+
+        let mut invocations = pin!(invocations);
+        let (res, served) = join!(
+            clt.invoke_values_blocking::<_, _, ()>((), "test:test/iface", "run", (), &[[]; 0]),
+            async {
+                srv.accept(&lis).await?;
+                let ((), fut) = invocations
+                    .next()
+                    .await
+                    .expect("unexpected end of stream")?;
+                anyhow::Ok(fut.await)
+            }
+        );
+        ticker.abort();
+        assert!(res.is_err(), "client call should have failed");
+        let err = served?.expect_err("served call should have been interrupted");
+        assert!(
+            matches!(
+                err.downcast_ref::<CallError>(),
+                Some(CallError::ExecutionTimeout(..))
+            ),
+            "unexpected error: {err:#}"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+//! Store data shared by the unit tests of this crate
+
+use core::any::Any;
+use core::time::Duration;
+
+use std::sync::Arc;
+
+use uuid::Uuid;
+use wasmtime::component::ResourceTable;
+use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+use wrpc_transport::Invoke;
+
+use crate::{HostResourceCodecs, SharedResourceTable, WrpcCtx, WrpcCtxView, WrpcView};
+
+pub struct TestWrpcCtx<C> {
+    pub client: C,
+    pub shared_resources: SharedResourceTable,
+    pub timeout: Option<Duration>,
+    /// Timeout returned by [`WrpcCtx::timeout_for`] for the instance and function name
+    pub function_timeout: Option<(&'static str, &'static str, Duration)>,
+    pub execution_timeout: Option<Duration>,
+    pub deadline_header: bool,
+    pub invocation_id_header: bool,
+    pub fixed_length_resource_handles: bool,
+    /// IDs passed to [`WrpcCtx::on_resource_stored`]
+    pub stored: Vec<Uuid>,
+    /// IDs passed to [`WrpcCtx::on_resource_retrieved`]
+    pub retrieved: Vec<Uuid>,
+}
+
+impl<C> WrpcCtx<C> for TestWrpcCtx<C>
+where
+    C: Invoke<Context = ()>,
+{
+    fn context(&self) {}
+
+    fn client(&self) -> &C {
+        &self.client
+    }
+
+    fn shared_resources(&mut self) -> &mut SharedResourceTable {
+        &mut self.shared_resources
+    }
+
+    fn timeout(&self) -> Option<Duration> {
+        self.timeout
+    }
+
+    fn timeout_for(&self, instance: &str, func: &str) -> Option<Duration> {
+        match self.function_timeout {
+            Some((i, f, timeout)) if (i, f) == (instance, func) => Some(timeout),
+            _ => self.timeout,
+        }
+    }
+
+    fn execution_timeout(&self) -> Option<Duration> {
+        self.execution_timeout
+    }
+
+    fn deadline_header(&self) -> bool {
+        self.deadline_header
+    }
+
+    fn invocation_id_header(&self) -> bool {
+        self.invocation_id_header
+    }
+
+    fn fixed_length_resource_handles(&self) -> bool {
+        self.fixed_length_resource_handles
+    }
+
+    fn on_resource_stored(&mut self, id: Uuid) {
+        self.stored.push(id);
+    }
+
+    fn on_resource_retrieved(&mut self, id: Uuid) {
+        self.retrieved.push(id);
+    }
+}
+
+pub struct TestCtx<C> {
+    pub table: ResourceTable,
+    pub wasi: WasiCtx,
+    pub wrpc: TestWrpcCtx<C>,
+    /// Context passed to [`WrpcView::set_serve_context`], if it is a `u32`
+    pub serve_context: Option<u32>,
+    pub host_resource_codecs: Option<Arc<HostResourceCodecs<Self>>>,
+    /// ID returned by [`WrpcView::new_resource_id`], a new one is generated if [None]
+    pub resource_id: Option<Uuid>,
+}
+
+impl<C> WrpcView for TestCtx<C>
+where
+    C: Invoke<Context = ()>,
+{
+    type Invoke = C;
+
+    fn wrpc(&mut self) -> WrpcCtxView<'_, Self::Invoke> {
+        WrpcCtxView {
+            ctx: &mut self.wrpc,
+            table: &mut self.table,
+        }
+    }
+
+    fn host_resource_codecs(&self) -> Option<Arc<HostResourceCodecs<Self>>> {
+        self.host_resource_codecs.clone()
+    }
+
+    fn set_serve_context(&mut self, cx: &dyn Any) {
+        self.serve_context = cx.downcast_ref::<u32>().copied();
+    }
+
+    fn new_resource_id(&mut self) -> Uuid {
+        self.resource_id.unwrap_or_else(Uuid::now_v7)
+    }
+}
+
+impl<C: Send> WasiView for TestCtx<C> {
+    fn ctx(&mut self) -> WasiCtxView<'_> {
+        WasiCtxView {
+            ctx: &mut self.wasi,
+            table: &mut self.table,
+        }
+    }
+}
+
+/// Returns [TestCtx] using `client` and the default configuration of [WrpcCtx]
+pub fn new_ctx<C>(client: C) -> TestCtx<C> {
+    TestCtx {
+        table: ResourceTable::default(),
+        wasi: WasiCtxBuilder::new().build(),
+        wrpc: TestWrpcCtx {
+            client,
+            shared_resources: SharedResourceTable::default(),
+            timeout: None,
+            function_timeout: None,
+            execution_timeout: None,
+            deadline_header: false,
+            invocation_id_header: false,
+            fixed_length_resource_handles: false,
+            stored: Vec::default(),
+            retrieved: Vec::default(),
+        },
+        serve_context: None,
+        host_resource_codecs: None,
+        resource_id: None,
+    }
+}
@@ -7,6 +7,7 @@ use core::iter::zip;
 use core::ops::{Deref, DerefMut};
 use core::pin::{pin, Pin};
 use core::task::{ready, Context, Poll};
+use core::time::Duration;
 use core::{mem, str};
 
 use std::collections::HashMap;
@@ -22,6 +23,7 @@ use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
 use tokio::select;
 use tokio::sync::{mpsc, oneshot};
 use tokio::task::JoinSet;
+use tokio::time::{sleep, Sleep};
 use tokio_stream::wrappers::ReceiverStream;
 use tracing::{debug, error, instrument, trace, warn};
 use wrpc_transport::Index as _;
@@ -117,6 +119,20 @@ pub fn invocation_subject(prefix: &str, instance: &str, func: &str) -> String {
     s
 }
 
+/// Returns `prefix` namespaced by `namespace`, e.g. a tenant ID.
+///
+/// Clients constructed with namespaced prefixes only reach servers using the same namespace,
+/// which allows multiple tenants to share a NATS.io cluster without subject collisions.
+#[must_use]
+#[inline]
+pub fn namespace_prefix(namespace: &str, prefix: &str) -> String {
+    match (namespace.is_empty(), prefix.is_empty()) {
+        (true, _) => prefix.to_string(),
+        (false, true) => namespace.to_string(),
+        (false, false) => format!("{namespace}.{prefix}"),
+    }
+}
+
 fn corrupted_memory_error() -> std::io::Error {
     std::io::Error::other("corrupted memory state")
 }
@@ -180,6 +196,7 @@ pub struct Client {
     queue_group: Option<Arc<str>>,
     commands: mpsc::Sender<Command>,
     tasks: Arc<JoinSet<()>>,
+    request_timeout: Option<Duration>,
 }
 
 impl Client {
@@ -263,8 +280,19 @@ impl Client {
             queue_group,
             commands: cmd_tx,
             tasks: Arc::new(tasks),
+            request_timeout: None,
         })
     }
+
+    /// Sets the maximum time to wait for the peer to acknowledge an invocation handshake.
+    ///
+    /// This only bounds the NATS.io subject round trip and does not limit the time
+    /// the invocation takes to complete once the handshake succeeds.
+    #[must_use]
+    pub fn with_request_timeout(mut self, timeout: Duration) -> Self {
+        self.request_timeout = Some(timeout);
+        self
+    }
 }
 
 pub struct ByteSubscription(Subscriber);
@@ -657,6 +685,7 @@ pub enum RootParamWriter {
         indexed: std::sync::Mutex<Vec<(Vec<usize>, oneshot::Sender<SubjectWriter>)>>,
         buffer: Bytes,
         tasks: Arc<JoinSet<()>>,
+        deadline: Option<Pin<Box<Sleep>>>,
     },
     Draining {
         tx: SubjectWriter,
@@ -671,6 +700,7 @@ impl RootParamWriter {
         sub: Subscriber,
         buffer: Bytes,
         tasks: Arc<JoinSet<()>>,
+        timeout: Option<Duration>,
     ) -> Self {
         Self::Handshaking {
             nats,
@@ -678,6 +708,7 @@ impl RootParamWriter {
             indexed: std::sync::Mutex::default(),
             buffer,
             tasks,
+            deadline: timeout.map(|timeout| Box::pin(sleep(timeout))),
         }
     }
 }
@@ -687,7 +718,7 @@ impl RootParamWriter {
     fn poll_active(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
         match &mut *self {
             Self::Corrupted => Poll::Ready(Err(corrupted_memory_error())),
-            Self::Handshaking { sub, .. } => {
+            Self::Handshaking { sub, deadline, .. } => {
                 trace!("polling for handshake response");
                 match sub.poll_next_unpin(cx) {
                     Poll::Ready(Some(Message {
@@ -753,7 +784,19 @@ impl RootParamWriter {
                         *self = Self::Corrupted;
                         Poll::Ready(Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe)))
                     }
-                    Poll::Pending => Poll::Pending,
+                    Poll::Pending => {
+                        match deadline.as_mut().map(|deadline| deadline.as_mut().poll(cx)) {
+                            Some(Poll::Ready(())) => {
+                                trace!("handshake timed out");
+                                *self = Self::Corrupted;
+                                Poll::Ready(Err(std::io::Error::new(
+                                    std::io::ErrorKind::TimedOut,
+                                    "timed out waiting for handshake response",
+                                )))
+                            }
+                            Some(Poll::Pending) | None => Poll::Pending,
+                        }
+                    }
                 }
             }
             Self::Draining { tx, buffer } => {
@@ -1131,6 +1174,7 @@ impl wrpc_transport::Invoke for Client {
                 },
                 params,
                 Arc::clone(&self.tasks),
+                self.request_timeout,
             )),
             Reader {
                 buffer: Bytes::default(),
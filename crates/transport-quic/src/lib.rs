@@ -1,9 +1,26 @@
 //! wRPC QUIC transport
 
-use anyhow::Context as _;
-use bytes::Bytes;
-use quinn::{Connection, RecvStream, SendStream, VarInt};
+use core::net::SocketAddr;
+use core::num::NonZeroUsize;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+use core::time::Duration;
+
+use std::sync::Arc;
+
+use anyhow::{bail, Context as _};
+use bytes::{BufMut as _, Bytes, BytesMut};
+use quinn::{
+    ClientConfig, Connection, Endpoint, IdleTimeout, RecvStream, SendStream, TransportConfig,
+    VarInt,
+};
+use tokio::io::{AsyncRead, AsyncReadExt as _, AsyncWrite, AsyncWriteExt as _, ReadBuf};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio_util::codec::Encoder as _;
 use tracing::{debug, error, trace, warn};
+use wasm_tokio::{
+    AsyncReadCore as _, AsyncReadLeb128 as _, CoreNameEncoder, CoreVecEncoderBytes, Leb128Encoder,
+};
 use wrpc_transport::frame::{Accept, Incoming, InvokeBuilder, Outgoing};
 use wrpc_transport::Invoke;
 
@@ -12,7 +29,215 @@ pub type Server = wrpc_transport::Server<(), RecvStream, SendStream, ConnHandler
 
 /// QUIC wRPC client
 #[derive(Clone, Debug)]
-pub struct Client(Connection);
+pub struct Client {
+    conn: Connection,
+    permits: Option<Arc<Semaphore>>,
+}
+
+/// Default maximum duration of inactivity after which a connection established using
+/// [`ConnectBuilder`] is closed
+pub const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Builder of [Client] connections with configurable liveness settings, see [`Client::connect`]
+#[derive(Clone, Debug)]
+pub struct ConnectBuilder {
+    config: ClientConfig,
+    max_idle_timeout: Option<Duration>,
+    keep_alive_interval: Option<Duration>,
+}
+
+impl ConnectBuilder {
+    /// Sets the maximum duration of inactivity after which the connection is closed,
+    /// defaults to [`DEFAULT_IDLE_TIMEOUT`]. [None] disables the idle timeout.
+    ///
+    /// The effective idle timeout is the minimum of the timeouts configured by both peers.
+    #[must_use]
+    pub fn max_idle_timeout(self, timeout: Option<Duration>) -> Self {
+        Self {
+            max_idle_timeout: timeout,
+            ..self
+        }
+    }
+
+    /// Sets the interval, at which keep-alive packets are sent on an otherwise idle connection,
+    /// which prevents both the idle timeout and the expiry of NAT mappings on long-lived
+    /// connections. [None], the default, disables keep-alive packets.
+    ///
+    /// The interval should be shorter than the idle timeout of both peers.
+    #[must_use]
+    pub fn keep_alive_interval(self, interval: Option<Duration>) -> Self {
+        Self {
+            keep_alive_interval: interval,
+            ..self
+        }
+    }
+
+    /// Connects to the server at `addr` with name `server_name` using `endpoint`.
+    ///
+    /// The transport configuration of the [`ClientConfig`] passed to [`Client::connect`], if any,
+    /// is replaced.
+    pub async fn connect(
+        self,
+        endpoint: &Endpoint,
+        addr: SocketAddr,
+        server_name: &str,
+    ) -> anyhow::Result<Client> {
+        let Self {
+            mut config,
+            max_idle_timeout,
+            keep_alive_interval,
+        } = self;
+        let max_idle_timeout = max_idle_timeout
+            .map(IdleTimeout::try_from)
+            .transpose()
+            .context("invalid idle timeout")?;
+        let mut transport = TransportConfig::default();
+        transport.max_idle_timeout(max_idle_timeout);
+        transport.keep_alive_interval(keep_alive_interval);
+        config.transport_config(Arc::new(transport));
+        let conn = endpoint
+            .connect_with(config, addr, server_name)
+            .context("failed to connect to server")?
+            .await
+            .context("failed to establish connection")?;
+        Ok(conn.into())
+    }
+}
+
+impl Client {
+    /// Returns a [`ConnectBuilder`] establishing connections using `config`, e.g. to configure
+    /// the idle timeout and keep-alive of a connection.
+    ///
+    /// Connections converted into a [Client] from a [Connection] directly use the transport
+    /// configuration of the [Endpoint] they were established on.
+    #[must_use]
+    pub fn connect(config: ClientConfig) -> ConnectBuilder {
+        ConnectBuilder {
+            config,
+            max_idle_timeout: Some(DEFAULT_IDLE_TIMEOUT),
+            keep_alive_interval: None,
+        }
+    }
+
+    /// Limits the number of concurrently outstanding invocations to `n`.
+    ///
+    /// Each invocation holds a permit, which is acquired before its stream is opened and
+    /// released once both the parameter and result streams of the invocation are complete.
+    /// By default, the number of outstanding invocations is only limited by the peer's
+    /// stream limit.
+    #[must_use]
+    pub fn max_concurrent_invocations(self, n: NonZeroUsize) -> Self {
+        Self {
+            permits: Some(Arc::new(Semaphore::new(n.get()))),
+            ..self
+        }
+    }
+
+    /// Closes the underlying connection immediately with an application error `code` and
+    /// human-readable `reason`, which are transmitted to the peer.
+    ///
+    /// This can be used to signal intentional shutdown to the peer, e.g. once authorization
+    /// is revoked. All pending invocations are aborted and subsequent invocations fail.
+    pub fn close(&self, code: VarInt, reason: &[u8]) {
+        self.conn.close(code, reason);
+    }
+
+    /// Like [`Accept::accept`], but fails with [`std::io::ErrorKind::TimedOut`] if the peer does
+    /// not open an invocation stream within `timeout`.
+    ///
+    /// This allows the accept loop of a server to periodically check whether it should shut down.
+    pub async fn accept_timeout(
+        &self,
+        timeout: Duration,
+    ) -> std::io::Result<((), SendStream, RecvStream)> {
+        match tokio::time::timeout(timeout, self.accept()).await {
+            Ok(res) => res,
+            Err(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "no invocation stream accepted within timeout",
+            )),
+        }
+    }
+
+    /// Accepts the next unidirectional stream opened by the peer.
+    ///
+    /// Unlike invocations accepted using [Accept], which arrive on bidirectional streams,
+    /// unidirectional streams carry fire-and-forget events, for which no reply is expected.
+    /// The returned stream contains the raw event data as written by the peer.
+    pub async fn accept_event(&self) -> std::io::Result<RecvStream> {
+        let rx = self.conn.accept_uni().await?;
+        Ok(rx)
+    }
+}
+
+/// Handshake data negotiated on a QUIC connection using rustls
+#[cfg(feature = "rustls")]
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct HandshakeData {
+    /// Server name indication (SNI) sent by the client. This is only available on the server
+    /// side of the connection and only if the client connected using a DNS name
+    pub server_name: Option<String>,
+    /// Negotiated application-layer protocol (ALPN)
+    pub protocol: Option<Vec<u8>>,
+}
+
+#[cfg(feature = "rustls")]
+impl Client {
+    /// Returns the handshake data negotiated on the connection, which can be used to dispatch
+    /// connections accepted on a single endpoint to distinct handler sets.
+    ///
+    /// Returns [None] if the handshake is not complete yet or the connection does not use rustls
+    #[must_use]
+    pub fn handshake_data(&self) -> Option<HandshakeData> {
+        let quinn::crypto::rustls::HandshakeData {
+            protocol,
+            server_name,
+            ..
+        } = *self
+            .conn
+            .handshake_data()?
+            .downcast::<quinn::crypto::rustls::HandshakeData>()
+            .ok()?;
+        Some(HandshakeData {
+            server_name,
+            protocol,
+        })
+    }
+}
+
+/// Stream of an invocation, which holds the invocation permit of a [Client]
+pub struct InvocationStream<T> {
+    stream: T,
+    permit: Option<Arc<OwnedSemaphorePermit>>,
+}
+
+impl AsyncRead for InvocationStream<RecvStream> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        AsyncRead::poll_read(Pin::new(&mut self.stream), cx, buf)
+    }
+}
+
+impl AsyncWrite for InvocationStream<SendStream> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        AsyncWrite::poll_write(Pin::new(&mut self.stream), cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        AsyncWrite::poll_flush(Pin::new(&mut self.stream), cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        AsyncWrite::poll_shutdown(Pin::new(&mut self.stream), cx)
+    }
+}
 
 /// Graceful stream shutdown handler
 pub struct ConnHandler;
@@ -55,9 +280,34 @@ impl wrpc_transport::frame::ConnHandler<RecvStream, SendStream> for ConnHandler
     }
 }
 
+impl wrpc_transport::frame::ConnHandler<InvocationStream<RecvStream>, InvocationStream<SendStream>>
+    for ConnHandler
+{
+    async fn on_ingress(rx: InvocationStream<RecvStream>, res: std::io::Result<()>) {
+        let InvocationStream { stream, permit } = rx;
+        <Self as wrpc_transport::frame::ConnHandler<RecvStream, SendStream>>::on_ingress(
+            stream, res,
+        )
+        .await;
+        drop(permit);
+    }
+
+    async fn on_egress(tx: InvocationStream<SendStream>, res: std::io::Result<()>) {
+        let InvocationStream { stream, permit } = tx;
+        <Self as wrpc_transport::frame::ConnHandler<RecvStream, SendStream>>::on_egress(
+            stream, res,
+        )
+        .await;
+        drop(permit);
+    }
+}
+
 impl From<Connection> for Client {
     fn from(conn: Connection) -> Self {
-        Self(conn)
+        Self {
+            conn,
+            permits: None,
+        }
     }
 }
 
@@ -77,11 +327,25 @@ impl Invoke for &Client {
     where
         P: AsRef<[Option<usize>]> + Send + Sync,
     {
+        let permit = if let Some(permits) = &self.permits {
+            let permit = Arc::clone(permits)
+                .acquire_owned()
+                .await
+                .context("failed to acquire invocation permit")?;
+            Some(Arc::new(permit))
+        } else {
+            None
+        };
         let (tx, rx) = self
-            .0
+            .conn
             .open_bi()
             .await
             .context("failed to open parameter stream")?;
+        let tx = InvocationStream {
+            stream: tx,
+            permit: permit.clone(),
+        };
+        let rx = InvocationStream { stream: rx, permit };
         InvokeBuilder::<ConnHandler>::default()
             .invoke(tx, rx, instance, func, params, paths)
             .await
@@ -114,7 +378,7 @@ impl Accept for &Client {
     type Incoming = RecvStream;
 
     async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
-        let (tx, rx) = self.0.accept_bi().await?;
+        let (tx, rx) = self.conn.accept_bi().await?;
         Ok(((), tx, rx))
     }
 }
@@ -128,3 +392,242 @@ impl Accept for Client {
         (&self).accept().await
     }
 }
+
+/// Leading byte of a batch stream, which distinguishes batches from invocations using the wRPC
+/// framing, which start with the framing protocol version
+pub const BATCH_HEADER: u8 = 0xff;
+
+/// Invocation batch accepted using [`Client::accept_batch`].
+///
+/// A batch is transmitted over a single bidirectional stream:
+/// - the invoker sends [`BATCH_HEADER`], followed by the instance name as a `string` and the
+///   number of calls as a `u32`. Each call consists of the function name as a `string` and the
+///   encoded parameters as a `list<u8>`.
+/// - the server responds with a `result<list<u8>, string>` for each call, containing either
+///   the encoded results of the call or an error message, in the order of the calls.
+///
+/// Unlike invocations using the wRPC framing, calls within a batch cannot transmit asynchronous
+/// values, since no nested streams are available.
+pub struct Batch {
+    instance: String,
+    remaining: u32,
+    tx: SendStream,
+    rx: RecvStream,
+}
+
+impl Batch {
+    /// Returns the name of the instance, on which all calls of the batch are invoked
+    #[must_use]
+    pub fn instance(&self) -> &str {
+        &self.instance
+    }
+
+    /// Returns the number of calls, which were not read yet
+    #[must_use]
+    pub fn remaining(&self) -> u32 {
+        self.remaining
+    }
+
+    /// Reads the function name and encoded parameters of the next call of the batch.
+    /// Returns [None] once all calls were read.
+    ///
+    /// Calls must be responded to using [`Self::respond`] in the order they were read in.
+    pub async fn next_call(&mut self) -> std::io::Result<Option<(String, Bytes)>> {
+        let Some(remaining) = self.remaining.checked_sub(1) else {
+            return Ok(None);
+        };
+        let mut func = String::default();
+        self.rx.read_core_name(&mut func).await?;
+        let params = read_list(&mut self.rx).await?;
+        self.remaining = remaining;
+        Ok(Some((func, params)))
+    }
+
+    /// Responds to the earliest call, which was not responded to yet, with either the encoded
+    /// results or an error message
+    pub async fn respond(&mut self, res: Result<&[u8], &str>) -> std::io::Result<()> {
+        let mut buf = BytesMut::default();
+        match res {
+            Ok(results) => {
+                buf.put_u8(0);
+                CoreVecEncoderBytes.encode(results, &mut buf)?;
+            }
+            Err(err) => {
+                buf.put_u8(1);
+                CoreNameEncoder.encode(err, &mut buf)?;
+            }
+        }
+        self.tx.write_all(&buf).await?;
+        Ok(())
+    }
+
+    /// Finishes the response stream, which must be called after all calls were responded to
+    pub async fn finish(self) -> std::io::Result<()> {
+        let Self { mut tx, mut rx, .. } = self;
+        tx.shutdown().await?;
+        if let Err(err) = rx.stop(DONE) {
+            debug!(?err, "failed to close incoming batch stream");
+        }
+        <ConnHandler as wrpc_transport::frame::ConnHandler<RecvStream, SendStream>>::on_egress(
+            tx,
+            Ok(()),
+        )
+        .await;
+        Ok(())
+    }
+}
+
+/// Maximum number of bytes preallocated for a `list<u8>` based on the peer-provided length
+const MAX_LIST_PREALLOC: usize = 1 << 16;
+
+/// Reads a `list<u8>` from `rx`
+///
+/// The buffer grows as bytes are actually received, so a peer cannot force a large allocation
+/// by merely claiming a large length.
+async fn read_list(mut rx: impl AsyncRead + Unpin) -> std::io::Result<Bytes> {
+    let len = rx.read_u32_leb128().await?;
+    let n: usize = len
+        .try_into()
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))?;
+    let mut buf = Vec::with_capacity(n.min(MAX_LIST_PREALLOC));
+    let k = rx.take(len.into()).read_to_end(&mut buf).await?;
+    if k != n {
+        return Err(std::io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(buf.into())
+}
+
+impl Client {
+    /// Invokes all `calls`, each consisting of the function name and encoded parameters, on
+    /// instance `instance` pipelined over a single stream, which amortizes the cost of stream
+    /// setup for many small calls. See [`Batch`] for the framing used.
+    ///
+    /// Returns the encoded results or error message reported by the peer for each call in the
+    /// order of `calls`. The batch must be served using [`Client::accept_batch`].
+    pub async fn invoke_batch(
+        &self,
+        instance: &str,
+        calls: Vec<(String, Bytes)>,
+    ) -> anyhow::Result<Vec<Result<Bytes, String>>> {
+        let n: u32 = calls.len().try_into().context("too many calls in batch")?;
+        let mut buf = BytesMut::default();
+        buf.put_u8(BATCH_HEADER);
+        CoreNameEncoder
+            .encode(instance, &mut buf)
+            .context("failed to encode instance name")?;
+        Leb128Encoder
+            .encode(n, &mut buf)
+            .context("failed to encode call count")?;
+        for (func, params) in &calls {
+            CoreNameEncoder
+                .encode(func.as_str(), &mut buf)
+                .context("failed to encode function name")?;
+            CoreVecEncoderBytes
+                .encode(params.as_ref(), &mut buf)
+                .context("failed to encode parameters")?;
+        }
+
+        let _permit = if let Some(permits) = &self.permits {
+            let permit = Arc::clone(permits)
+                .acquire_owned()
+                .await
+                .context("failed to acquire invocation permit")?;
+            Some(permit)
+        } else {
+            None
+        };
+        let (mut tx, mut rx) = self
+            .conn
+            .open_bi()
+            .await
+            .context("failed to open batch stream")?;
+        // responses are read while calls are still being written, since the peer may not read
+        // further calls until earlier responses are consumed
+        let ((), results) = tokio::try_join!(
+            async {
+                tx.write_all(&buf).await.context("failed to write calls")?;
+                tx.shutdown()
+                    .await
+                    .context("failed to shutdown batch stream")?;
+                anyhow::Ok(())
+            },
+            async {
+                let mut results = Vec::with_capacity(calls.len());
+                for i in 0..calls.len() {
+                    let res = match rx
+                        .read_u8()
+                        .await
+                        .with_context(|| format!("failed to read response status {i}"))?
+                    {
+                        0 => Ok(read_list(&mut rx)
+                            .await
+                            .with_context(|| format!("failed to read results {i}"))?),
+                        1 => {
+                            let mut err = String::default();
+                            rx.read_core_name(&mut err)
+                                .await
+                                .with_context(|| format!("failed to read error {i}"))?;
+                            Err(err)
+                        }
+                        status => bail!("invalid response status {status} of call {i}"),
+                    };
+                    results.push(res);
+                }
+                Ok(results)
+            }
+        )?;
+        if let Err(err) = rx.stop(DONE) {
+            debug!(?err, "failed to close incoming batch stream");
+        }
+        Ok(results)
+    }
+
+    /// Accepts the next bidirectional stream opened by the peer as an invocation [`Batch`]
+    /// issued using [`Client::invoke_batch`].
+    ///
+    /// Fails with [`std::io::ErrorKind::InvalidData`] if the stream does not start with
+    /// [`BATCH_HEADER`], so batches should be issued over connections dedicated to them.
+    pub async fn accept_batch(&self) -> std::io::Result<Batch> {
+        let (tx, mut rx) = self.conn.accept_bi().await?;
+        match rx.read_u8().await? {
+            BATCH_HEADER => {}
+            v => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("stream starts with `{v:#04x}`, which is not a batch header"),
+                ))
+            }
+        }
+        let mut instance = String::default();
+        rx.read_core_name(&mut instance).await?;
+        let remaining = rx.read_u32_leb128().await?;
+        Ok(Batch {
+            instance,
+            remaining,
+            tx,
+            rx,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn read_list_bounded() -> anyhow::Result<()> {
+        let mut buf = BytesMut::default();
+        CoreVecEncoderBytes.encode(b"test".as_slice(), &mut buf)?;
+        let list = read_list(buf.as_ref()).await?;
+        assert_eq!(list.as_ref(), b"test");
+
+        // a peer claiming `u32::MAX` bytes, but sending only a few, must not cause a 4 GiB
+        // allocation
+        let mut buf = BytesMut::default();
+        Leb128Encoder.encode(u32::MAX, &mut buf)?;
+        buf.put_slice(b"test");
+        let err = read_list(buf.as_ref()).await.unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::UnexpectedEof);
+        Ok(())
+    }
+}
@@ -1,13 +1,19 @@
+use core::num::NonZeroUsize;
 use core::pin::pin;
+use core::time::Duration;
 
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
-use anyhow::Context as _;
+use anyhow::{bail, Context as _};
+use bytes::Bytes;
+use futures::future::try_join_all;
 use futures::StreamExt as _;
+use quinn::{ApplicationClose, ConnectionError, VarInt};
 use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
 use tokio::try_join;
 use tracing::info;
-use wrpc_transport::{Index as _, Invoke as _, Serve as _};
+use wrpc_transport::{Index as _, Invoke as _, InvokeExt as _, Serve as _, ServeExt as _};
 use wrpc_transport_quic::Client;
 
 #[test_log::test(tokio::test(flavor = "multi_thread"))]
@@ -165,3 +171,414 @@ async fn loopback() -> anyhow::Result<()> {
     })
     .await
 }
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn unit_functions() -> anyhow::Result<()> {
+    wrpc_test::with_quic(|clt, srv| async {
+        let clt = Client::from(clt);
+        let srv_conn = Client::from(srv);
+        let srv = wrpc_transport_quic::Server::new();
+        let resets = srv
+            .serve_values::<(), ()>("foo", "reset", Vec::<Box<[Option<usize>]>>::default())
+            .await
+            .context("failed to serve `foo.reset`")?;
+        let sets = srv
+            .serve_values::<(u32,), ()>("foo", "set", Vec::<Box<[Option<usize>]>>::default())
+            .await
+            .context("failed to serve `foo.set`")?;
+        let mut resets = pin!(resets);
+        let mut sets = pin!(sets);
+        for v in [42, 0] {
+            try_join!(
+                async {
+                    clt.invoke_values_blocking::<_, _, ()>((), "foo", "reset", (), &[[]; 0])
+                        .await
+                        .context("failed to invoke `foo.reset`")?;
+                    clt.invoke_values_blocking::<_, _, ()>((), "foo", "set", (v,), &[[]; 0])
+                        .await
+                        .context("failed to invoke `foo.set`")
+                },
+                async {
+                    srv.accept(&srv_conn)
+                        .await
+                        .context("failed to accept invocation")?;
+                    let ((), (), _, tx) = resets
+                        .next()
+                        .await
+                        .context("invocation stream unexpectedly finished")?
+                        .context("failed to get invocation")?;
+                    tx(())
+                        .await
+                        .context("failed to write `foo.reset` results")?;
+
+                    srv.accept(&srv_conn)
+                        .await
+                        .context("failed to accept invocation")?;
+                    let ((), (got,), _, tx) = sets
+                        .next()
+                        .await
+                        .context("invocation stream unexpectedly finished")?
+                        .context("failed to get invocation")?;
+                    assert_eq!(got, v);
+                    tx(()).await.context("failed to write `foo.set` results")
+                }
+            )?;
+        }
+        Ok(())
+    })
+    .await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn max_concurrent_invocations() -> anyhow::Result<()> {
+    const INVOCATIONS: usize = 8;
+    const LIMIT: usize = 2;
+
+    wrpc_test::with_quic(|clt, srv| async {
+        let clt = Client::from(clt)
+            .max_concurrent_invocations(NonZeroUsize::new(LIMIT).expect("limit is not zero"));
+        let srv_conn = Client::from(srv);
+        let srv = wrpc_transport_quic::Server::new();
+        let invocations = srv
+            .serve("foo", "bar", Vec::<Box<[Option<usize>]>>::default())
+            .await
+            .context("failed to serve `foo.bar`")?;
+        let mut invocations = pin!(invocations);
+        let active = Arc::new(AtomicUsize::new(0));
+        let max_active = Arc::new(AtomicUsize::new(0));
+        try_join!(
+            try_join_all((0..INVOCATIONS).map(|i| {
+                let clt = &clt;
+                async move {
+                    let (mut outgoing, mut incoming) = clt
+                        .invoke(
+                            (),
+                            "foo",
+                            "bar",
+                            Bytes::new(),
+                            Vec::<Box<[Option<usize>]>>::default(),
+                        )
+                        .await
+                        .with_context(|| format!("failed to invoke `foo.bar` {i}"))?;
+                    outgoing
+                        .shutdown()
+                        .await
+                        .context("failed to shutdown stream")?;
+                    drop(outgoing);
+                    let mut buf = vec![];
+                    incoming
+                        .read_to_end(&mut buf)
+                        .await
+                        .context("failed to read result")?;
+                    assert_eq!(buf, b"done");
+                    anyhow::Ok(())
+                }
+            })),
+            async {
+                let mut handlers = Vec::with_capacity(INVOCATIONS);
+                for _ in 0..INVOCATIONS {
+                    srv.accept(&srv_conn)
+                        .await
+                        .context("failed to accept invocation")?;
+                    let ((), mut outgoing, mut incoming) = invocations
+                        .next()
+                        .await
+                        .context("invocation stream unexpectedly finished")?
+                        .context("failed to get invocation")?;
+                    let n = active.fetch_add(1, Ordering::Relaxed) + 1;
+                    max_active.fetch_max(n, Ordering::Relaxed);
+                    let active = Arc::clone(&active);
+                    handlers.push(tokio::spawn(async move {
+                        let mut buf = vec![];
+                        incoming
+                            .read_to_end(&mut buf)
+                            .await
+                            .context("failed to read parameters")?;
+                        // the client can only start a new invocation once this one is complete
+                        active.fetch_sub(1, Ordering::Relaxed);
+                        outgoing
+                            .write_all(b"done")
+                            .await
+                            .context("failed to write result")?;
+                        outgoing
+                            .shutdown()
+                            .await
+                            .context("failed to shutdown stream")?;
+                        anyhow::Ok(())
+                    }));
+                }
+                for handler in try_join_all(handlers).await? {
+                    handler?;
+                }
+                anyhow::Ok(())
+            }
+        )?;
+        let max_active = max_active.load(Ordering::Relaxed);
+        assert!(
+            (1..=LIMIT).contains(&max_active),
+            "{max_active} invocations were in flight concurrently"
+        );
+        Ok(())
+    })
+    .await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn close() -> anyhow::Result<()> {
+    wrpc_test::with_quic(|clt, srv| async move {
+        let clt = Client::from(clt);
+        clt.close(VarInt::from_u32(42), b"authorization revoked");
+        let (error_code, reason) = match srv.closed().await {
+            ConnectionError::ApplicationClosed(ApplicationClose { error_code, reason }) => {
+                (error_code, reason)
+            }
+            err => bail!("unexpected connection error: {err:?}"),
+        };
+        assert_eq!(error_code, VarInt::from_u32(42));
+        assert_eq!(reason, b"authorization revoked"[..]);
+        clt.invoke((), "foo", "bar", Bytes::new(), [[Some(0)].as_slice(); 0])
+            .await
+            .expect_err("invocation on a closed connection should fail");
+        Ok(())
+    })
+    .await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn event() -> anyhow::Result<()> {
+    wrpc_test::with_quic(|clt, srv| async move {
+        let srv = Client::from(srv);
+        try_join!(
+            async {
+                let mut tx = clt
+                    .open_uni()
+                    .await
+                    .context("failed to open event stream")?;
+                // `tuple<string, u8>` containing `("hello", 42)`
+                tx.write_all(b"\x05hello\x2a")
+                    .await
+                    .context("failed to write event")?;
+                tx.finish().context("failed to finish event stream")?;
+                tx.stopped()
+                    .await
+                    .context("failed to await event stream close")?;
+                anyhow::Ok(())
+            },
+            async {
+                let mut rx = srv.accept_event().await.context("failed to accept event")?;
+                let n = rx.read_u8().await.context("failed to read string length")?;
+                let mut name = vec![0; n.into()];
+                rx.read_exact(&mut name)
+                    .await
+                    .context("failed to read string")?;
+                assert_eq!(name, b"hello");
+                assert_eq!(rx.read_u8().await.context("failed to read u8")?, 42);
+                let rest = rx
+                    .read_to_end(1)
+                    .await
+                    .context("failed to read event end")?;
+                assert!(rest.is_empty(), "unexpected trailing event data");
+                anyhow::Ok(())
+            }
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn batch() -> anyhow::Result<()> {
+    wrpc_test::with_quic(|clt, srv| async move {
+        let clt = Client::from(clt);
+        let srv = Client::from(srv);
+        let (results, ()) = try_join!(
+            async {
+                clt.invoke_batch(
+                    "foo",
+                    vec![
+                        ("echo".into(), "a".into()),
+                        ("fail".into(), Bytes::new()),
+                        ("reverse".into(), "abc".into()),
+                        ("echo".into(), Bytes::new()),
+                    ],
+                )
+                .await
+                .context("failed to invoke batch")
+            },
+            async {
+                let mut batch = srv.accept_batch().await.context("failed to accept batch")?;
+                assert_eq!(batch.instance(), "foo");
+                assert_eq!(batch.remaining(), 4);
+                while let Some((func, params)) =
+                    batch.next_call().await.context("failed to read call")?
+                {
+                    let res = match func.as_str() {
+                        "echo" => batch.respond(Ok(&params)).await,
+                        "reverse" => {
+                            let mut buf = params.to_vec();
+                            buf.reverse();
+                            batch.respond(Ok(&buf)).await
+                        }
+                        _ => {
+                            batch
+                                .respond(Err(&format!("unknown function `{func}`")))
+                                .await
+                        }
+                    };
+                    res.context("failed to respond")?;
+                }
+                batch.finish().await.context("failed to finish batch")?;
+                anyhow::Ok(())
+            }
+        )?;
+        assert_eq!(
+            results,
+            [
+                Ok(Bytes::from("a")),
+                Err("unknown function `fail`".into()),
+                Ok(Bytes::from("cba")),
+                Ok(Bytes::new()),
+            ]
+        );
+        Ok(())
+    })
+    .await
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn accept_timeout() -> anyhow::Result<()> {
+    wrpc_test::with_quic(|_clt, srv| async move {
+        let srv = Client::from(srv);
+        let err = srv
+            .accept_timeout(Duration::from_millis(100))
+            .await
+            .expect_err("accept should have timed out");
+        assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(feature = "rustls")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn handshake_data() -> anyhow::Result<()> {
+    use core::net::Ipv6Addr;
+
+    use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+    use quinn::{ClientConfig, Endpoint, ServerConfig};
+    use wrpc_transport_quic::HandshakeData;
+
+    let (mut srv_cnf, mut clt_cnf) = wrpc_test::cert_pair()?;
+    srv_cnf.alpn_protocols = vec![b"wrpc".to_vec()];
+    clt_cnf.alpn_protocols = vec![b"wrpc".to_vec()];
+    let srv_cnf = QuicServerConfig::try_from(srv_cnf)
+        .context("failed to convert rustls server config to QUIC server config")?;
+    let clt_cnf = QuicClientConfig::try_from(clt_cnf)
+        .context("failed to convert rustls client config to QUIC client config")?;
+
+    let srv_ep = Endpoint::server(
+        ServerConfig::with_crypto(Arc::new(srv_cnf)),
+        (Ipv6Addr::LOCALHOST, 0).into(),
+    )
+    .context("failed to create server endpoint")?;
+    let mut clt_ep = Endpoint::client((Ipv6Addr::LOCALHOST, 0).into())
+        .context("failed to create client endpoint")?;
+    clt_ep.set_default_client_config(ClientConfig::new(Arc::new(clt_cnf)));
+    let addr = srv_ep
+        .local_addr()
+        .context("failed to query server address")?;
+
+    let (clt, srv) = try_join!(
+        async {
+            let conn = clt_ep
+                .connect(addr, "localhost")
+                .context("failed to connect to server")?;
+            conn.await.context("failed to establish client connection")
+        },
+        async {
+            let conn = srv_ep
+                .accept()
+                .await
+                .context("failed to accept connection")?;
+            conn.await.context("failed to establish server connection")
+        }
+    )?;
+    let clt = Client::from(clt);
+    let srv = Client::from(srv);
+    assert_eq!(
+        srv.handshake_data(),
+        Some(HandshakeData {
+            server_name: Some("localhost".into()),
+            protocol: Some(b"wrpc".to_vec()),
+        })
+    );
+    assert_eq!(
+        clt.handshake_data(),
+        Some(HandshakeData {
+            server_name: None,
+            protocol: Some(b"wrpc".to_vec()),
+        })
+    );
+    Ok(())
+}
+
+#[cfg(feature = "rustls")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn keep_alive() -> anyhow::Result<()> {
+    use core::net::Ipv6Addr;
+
+    use quinn::crypto::rustls::{QuicClientConfig, QuicServerConfig};
+    use quinn::{ClientConfig, Endpoint, ServerConfig};
+
+    const IDLE_TIMEOUT: Duration = Duration::from_millis(300);
+
+    let (srv_cnf, clt_cnf) = wrpc_test::cert_pair()?;
+    let srv_cnf = QuicServerConfig::try_from(srv_cnf)
+        .context("failed to convert rustls server config to QUIC server config")?;
+    let clt_cnf = ClientConfig::new(Arc::new(
+        QuicClientConfig::try_from(clt_cnf)
+            .context("failed to convert rustls client config to QUIC client config")?,
+    ));
+
+    let srv_ep = Endpoint::server(
+        ServerConfig::with_crypto(Arc::new(srv_cnf)),
+        (Ipv6Addr::LOCALHOST, 0).into(),
+    )
+    .context("failed to create server endpoint")?;
+    let clt_ep = Endpoint::client((Ipv6Addr::LOCALHOST, 0).into())
+        .context("failed to create client endpoint")?;
+    let addr = srv_ep
+        .local_addr()
+        .context("failed to query server address")?;
+
+    for keep_alive in [Some(IDLE_TIMEOUT / 4), None] {
+        let (clt, srv) = try_join!(
+            Client::connect(clt_cnf.clone())
+                .max_idle_timeout(Some(IDLE_TIMEOUT))
+                .keep_alive_interval(keep_alive)
+                .connect(&clt_ep, addr, "localhost"),
+            async {
+                let conn = srv_ep
+                    .accept()
+                    .await
+                    .context("failed to accept connection")?;
+                conn.await.context("failed to establish server connection")
+            }
+        )?;
+        tokio::time::sleep(IDLE_TIMEOUT * 3).await;
+        if keep_alive.is_some() {
+            assert_eq!(
+                srv.close_reason(),
+                None,
+                "connection should have been kept alive"
+            );
+            clt.close(VarInt::from_u32(0), b"done");
+        } else {
+            assert!(
+                matches!(srv.close_reason(), Some(ConnectionError::TimedOut)),
+                "idle connection should have timed out"
+            );
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,516 @@
+//! wRPC WebSocket transport
+//!
+//! Each wRPC bidirectional stream is multiplexed over a single WebSocket connection.
+//! Every binary WebSocket message carries a 5-byte header consisting of a frame kind byte
+//! followed by a big-endian `u32` stream ID. Streams opened by the client side of the
+//! WebSocket connection use even IDs, streams opened by the server side use odd IDs.
+
+use core::fmt;
+use core::future::{poll_fn, Future as _};
+use core::pin::Pin;
+use core::sync::atomic::{AtomicU32, Ordering};
+use core::task::{ready, Context, Poll};
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Context as _};
+use bytes::{BufMut as _, Bytes, BytesMut};
+use futures::{SinkExt as _, StreamExt as _};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::WebSocketStream;
+use tokio_util::sync::PollSender;
+use tracing::{debug, error, trace, warn};
+use wrpc_transport::frame::{Accept, Incoming, InvokeBuilder, Outgoing};
+use wrpc_transport::Invoke;
+
+/// WebSocket server with graceful stream shutdown handling
+pub type Server = wrpc_transport::Server<(), RecvStream, SendStream, ConnHandler>;
+
+/// Frame carrying stream data
+const KIND_DATA: u8 = 0;
+
+/// Frame signaling that the sender has finished writing to the stream
+const KIND_FIN: u8 = 1;
+
+/// Length of the frame header: kind byte and stream ID
+const HEADER_LEN: usize = 5;
+
+/// Maximum payload size of a single data frame
+const MAX_PAYLOAD: usize = 1 << 16;
+
+/// Maximum amount of frames queued for transmission on a connection, writes to streams of the
+/// connection are blocked once it is reached
+const EGRESS_BUFFER: usize = 64;
+
+type Streams = Arc<Mutex<HashMap<u32, mpsc::UnboundedSender<std::io::Result<Bytes>>>>>;
+
+/// Item queued for transmission on the WebSocket connection
+enum Egress {
+    Frame(Message),
+    /// Notifies the sender once all items queued before it have been sent
+    Flush(oneshot::Sender<()>),
+}
+
+fn frame(kind: u8, id: u32, payload: &[u8]) -> Egress {
+    let mut buf = BytesMut::with_capacity(HEADER_LEN.saturating_add(payload.len()));
+    buf.put_u8(kind);
+    buf.put_u32(id);
+    buf.put_slice(payload);
+    Egress::Frame(Message::Binary(buf.freeze()))
+}
+
+/// Multiplexed WebSocket connection
+#[derive(Clone)]
+pub struct Connection {
+    tx: mpsc::Sender<Egress>,
+    streams: Streams,
+    next_id: Arc<AtomicU32>,
+    incoming: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<(SendStream, RecvStream)>>>,
+}
+
+impl fmt::Debug for Connection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Connection")
+            .field("next_id", &self.next_id)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Connection {
+    /// Constructs a new [Connection] multiplexing streams over `ws`.
+    ///
+    /// `role` must match the role of the local side of the WebSocket connection.
+    /// This function spawns the tasks driving the connection on the current [tokio] runtime,
+    /// the connection is closed once all handles to it and all streams are dropped.
+    pub fn new<S>(ws: WebSocketStream<S>, role: Role) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (local_id, peer_id) = match role {
+            Role::Client => (0, 1),
+            Role::Server => (1, 0),
+        };
+        let (mut ws_tx, ws_rx) = ws.split();
+        let (tx, mut rx) = mpsc::channel(EGRESS_BUFFER);
+        let (incoming_tx, incoming_rx) = mpsc::unbounded_channel();
+        let streams = Streams::default();
+        tokio::spawn(async move {
+            while let Some(egress) = rx.recv().await {
+                match egress {
+                    Egress::Frame(msg) => {
+                        if let Err(err) = ws_tx.send(msg).await {
+                            error!(?err, "failed to send WebSocket message");
+                            return;
+                        }
+                    }
+                    Egress::Flush(tx) => _ = tx.send(()),
+                }
+            }
+            if let Err(err) = ws_tx.close().await {
+                debug!(?err, "failed to close WebSocket connection");
+            }
+        });
+        tokio::spawn(handle_ingress(
+            ws_rx,
+            tx.downgrade(),
+            Arc::clone(&streams),
+            incoming_tx,
+            peer_id,
+        ));
+        Self {
+            tx,
+            streams,
+            next_id: Arc::new(AtomicU32::new(local_id)),
+            incoming: Arc::new(tokio::sync::Mutex::new(incoming_rx)),
+        }
+    }
+
+    /// Opens a new bidirectional stream
+    pub fn open_bi(&self) -> anyhow::Result<(SendStream, RecvStream)> {
+        let id = self.next_id.fetch_add(2, Ordering::Relaxed);
+        if id >= u32::MAX - 1 {
+            bail!("stream IDs exhausted")
+        }
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.streams
+            .lock()
+            .map_err(|_| anyhow::anyhow!("stream table lock poisoned"))?
+            .insert(id, tx);
+        Ok((
+            SendStream::new(id, self.tx.clone()),
+            RecvStream::new(id, rx, Arc::clone(&self.streams)),
+        ))
+    }
+
+    /// Accepts the next bidirectional stream opened by the peer
+    pub async fn accept_bi(&self) -> std::io::Result<(SendStream, RecvStream)> {
+        let mut incoming = self.incoming.lock().await;
+        incoming
+            .recv()
+            .await
+            .ok_or_else(|| std::io::ErrorKind::UnexpectedEof.into())
+    }
+}
+
+async fn handle_ingress<S>(
+    mut ws: futures::stream::SplitStream<WebSocketStream<S>>,
+    tx: mpsc::WeakSender<Egress>,
+    streams: Streams,
+    incoming: mpsc::UnboundedSender<(SendStream, RecvStream)>,
+    mut peer_id: u32,
+) where
+    S: AsyncRead + AsyncWrite + Unpin,
+{
+    while let Some(msg) = ws.next().await {
+        let data = match msg {
+            Ok(Message::Binary(data)) => data,
+            Ok(Message::Close(..)) => break,
+            Ok(Message::Ping(..) | Message::Pong(..) | Message::Frame(..)) => continue,
+            Ok(Message::Text(..)) => {
+                warn!("ignoring unexpected text message");
+                continue;
+            }
+            Err(err) => {
+                error!(?err, "failed to receive WebSocket message");
+                break;
+            }
+        };
+        if data.len() < HEADER_LEN {
+            warn!(len = data.len(), "ignoring truncated frame");
+            continue;
+        }
+        let kind = data[0];
+        let id = u32::from_be_bytes([data[1], data[2], data[3], data[4]]);
+        let payload = data.slice(HEADER_LEN..);
+        let Ok(mut streams_lock) = streams.lock() else {
+            error!("stream table lock poisoned");
+            break;
+        };
+        let mut accepted = None;
+        if !streams_lock.contains_key(&id) {
+            if id % 2 != peer_id % 2 || id < peer_id {
+                trace!(id, "dropping frame for closed stream");
+                continue;
+            }
+            let Some(tx) = tx.upgrade() else {
+                debug!("connection dropped, stop handling ingress");
+                break;
+            };
+            peer_id = id.saturating_add(2);
+            let (data_tx, data_rx) = mpsc::unbounded_channel();
+            streams_lock.insert(id, data_tx);
+            accepted = Some((
+                SendStream::new(id, tx),
+                RecvStream::new(id, data_rx, Arc::clone(&streams)),
+            ));
+        }
+        match kind {
+            KIND_DATA => {
+                if let Some(stream) = streams_lock.get(&id) {
+                    if !payload.is_empty() && stream.send(Ok(payload)).is_err() {
+                        trace!(id, "stream receiver dropped");
+                        streams_lock.remove(&id);
+                    }
+                }
+            }
+            KIND_FIN => {
+                trace!(id, "stream finished");
+                streams_lock.remove(&id);
+            }
+            kind => {
+                warn!(id, kind, "ignoring frame of unknown kind");
+            }
+        }
+        // the lock must be released before the stream is handed off, since dropping
+        // a [RecvStream] acquires it
+        drop(streams_lock);
+        if let Some(stream) = accepted {
+            if incoming.send(stream).is_err() {
+                trace!(id, "connection dropped, drop incoming stream");
+            }
+        }
+    }
+    if let Ok(mut streams) = streams.lock() {
+        for (_, tx) in streams.drain() {
+            _ = tx.send(Err(std::io::ErrorKind::ConnectionReset.into()));
+        }
+    }
+}
+
+/// Outgoing half of a multiplexed WebSocket stream
+///
+/// Frames are queued for transmission on the connection, which is shared by all streams.
+/// Writes are blocked while [`EGRESS_BUFFER`] frames are queued and flushing waits for all frames
+/// queued by the stream to be sent.
+pub struct SendStream {
+    id: u32,
+    tx: PollSender<Egress>,
+    flush: Option<oneshot::Receiver<()>>,
+    finished: bool,
+}
+
+impl SendStream {
+    fn new(id: u32, tx: mpsc::Sender<Egress>) -> Self {
+        Self {
+            id,
+            tx: PollSender::new(tx),
+            flush: None,
+            finished: false,
+        }
+    }
+
+    /// Signals the peer that no more data will be written to this stream.
+    ///
+    /// If the transmission queue of the connection is full, the signal is queued in the
+    /// background. Use [`AsyncWrite::poll_shutdown`] to wait for the signal to be queued.
+    pub fn finish(&mut self) -> std::io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.finished = true;
+        let Some(tx) = self.tx.get_ref() else {
+            return Err(std::io::ErrorKind::BrokenPipe.into());
+        };
+        match tx.try_send(frame(KIND_FIN, self.id, &[])) {
+            Ok(()) => Ok(()),
+            Err(mpsc::error::TrySendError::Full(fin)) => {
+                let Ok(rt) = tokio::runtime::Handle::try_current() else {
+                    debug!(
+                        id = self.id,
+                        "no runtime available, drop stream finish frame"
+                    );
+                    return Err(std::io::ErrorKind::WouldBlock.into());
+                };
+                let tx = tx.clone();
+                rt.spawn(async move { _ = tx.send(fin).await });
+                Ok(())
+            }
+            Err(mpsc::error::TrySendError::Closed(..)) => {
+                Err(std::io::ErrorKind::BrokenPipe.into())
+            }
+        }
+    }
+
+    fn poll_send(&mut self, cx: &mut Context<'_>, egress: Egress) -> Poll<std::io::Result<()>> {
+        ready!(self.tx.poll_reserve(cx)).map_err(|_| std::io::ErrorKind::BrokenPipe)?;
+        self.tx
+            .send_item(egress)
+            .map_err(|_| std::io::ErrorKind::BrokenPipe.into())
+            .into()
+    }
+}
+
+impl Drop for SendStream {
+    fn drop(&mut self) {
+        _ = self.finish();
+    }
+}
+
+impl AsyncWrite for SendStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        if self.finished {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        // capacity is reserved before the frame is constructed, so that no data is buffered
+        // while the queue is full
+        ready!(self.tx.poll_reserve(cx)).map_err(|_| std::io::ErrorKind::BrokenPipe)?;
+        let n = buf.len().min(MAX_PAYLOAD);
+        let id = self.id;
+        if self.tx.send_item(frame(KIND_DATA, id, &buf[..n])).is_err() {
+            return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
+        }
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        loop {
+            if let Some(rx) = &mut self.flush {
+                let res = ready!(Pin::new(rx).poll(cx));
+                self.flush = None;
+                return Poll::Ready(res.map_err(|_| std::io::ErrorKind::BrokenPipe.into()));
+            }
+            let (tx, rx) = oneshot::channel();
+            ready!(self.poll_send(cx, Egress::Flush(tx)))?;
+            self.flush = Some(rx);
+        }
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        if !self.finished {
+            let id = self.id;
+            ready!(self.poll_send(cx, frame(KIND_FIN, id, &[])))?;
+            self.finished = true;
+        }
+        self.poll_flush(cx)
+    }
+}
+
+/// Incoming half of a multiplexed WebSocket stream
+pub struct RecvStream {
+    id: u32,
+    rx: mpsc::UnboundedReceiver<std::io::Result<Bytes>>,
+    buf: Bytes,
+    streams: Streams,
+}
+
+impl RecvStream {
+    fn new(id: u32, rx: mpsc::UnboundedReceiver<std::io::Result<Bytes>>, streams: Streams) -> Self {
+        Self {
+            id,
+            rx,
+            buf: Bytes::default(),
+            streams,
+        }
+    }
+
+    /// Stops receiving data on this stream, any data subsequently sent by the peer is discarded
+    pub fn stop(&mut self) {
+        if let Ok(mut streams) = self.streams.lock() {
+            streams.remove(&self.id);
+        }
+        self.rx.close();
+    }
+}
+
+impl Drop for RecvStream {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+impl AsyncRead for RecvStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        loop {
+            if !self.buf.is_empty() {
+                let n = buf.remaining().min(self.buf.len());
+                let data = self.buf.split_to(n);
+                buf.put_slice(&data);
+                return Poll::Ready(Ok(()));
+            }
+            match ready!(self.rx.poll_recv(cx)) {
+                Some(Ok(data)) => self.buf = data,
+                Some(Err(err)) => return Poll::Ready(Err(err)),
+                None => return Poll::Ready(Ok(())),
+            }
+        }
+    }
+}
+
+/// WebSocket wRPC client
+#[derive(Clone, Debug)]
+pub struct Client(Connection);
+
+impl From<Connection> for Client {
+    fn from(conn: Connection) -> Self {
+        Self(conn)
+    }
+}
+
+/// Graceful stream shutdown handler
+pub struct ConnHandler;
+
+impl wrpc_transport::frame::ConnHandler<RecvStream, SendStream> for ConnHandler {
+    async fn on_ingress(mut rx: RecvStream, res: std::io::Result<()>) {
+        if let Err(err) = res {
+            error!(?err, "ingress failed");
+        } else {
+            debug!("ingress successfully complete");
+        }
+        rx.stop();
+    }
+
+    async fn on_egress(mut tx: SendStream, res: std::io::Result<()>) {
+        if let Err(err) = res {
+            error!(?err, "egress failed");
+        } else {
+            debug!("egress successfully complete");
+        }
+        if let Err(err) = poll_fn(|cx| Pin::new(&mut tx).poll_shutdown(cx)).await {
+            debug!(?err, "failed to close outgoing stream");
+        } else {
+            trace!("stream successfully closed")
+        }
+    }
+}
+
+impl Invoke for &Client {
+    type Context = ();
+    type Outgoing = Outgoing;
+    type Incoming = Incoming;
+
+    async fn invoke<P>(
+        &self,
+        (): Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let (tx, rx) = self
+            .0
+            .open_bi()
+            .context("failed to open parameter stream")?;
+        InvokeBuilder::<ConnHandler>::default()
+            .invoke(tx, rx, instance, func, params, paths)
+            .await
+    }
+}
+
+impl Invoke for Client {
+    type Context = ();
+    type Outgoing = Outgoing;
+    type Incoming = Incoming;
+
+    async fn invoke<P>(
+        &self,
+        (): Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        (&self).invoke((), instance, func, params, paths).await
+    }
+}
+
+impl Accept for &Client {
+    type Context = ();
+    type Outgoing = SendStream;
+    type Incoming = RecvStream;
+
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        let (tx, rx) = self.0.accept_bi().await?;
+        Ok(((), tx, rx))
+    }
+}
+
+impl Accept for Client {
+    type Context = ();
+    type Outgoing = SendStream;
+    type Incoming = RecvStream;
+
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        (&self).accept().await
+    }
+}
@@ -0,0 +1,218 @@
+use core::pin::pin;
+
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use futures::StreamExt as _;
+use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::try_join;
+use tokio_tungstenite::tungstenite::protocol::Role;
+use tracing::info;
+use wrpc_transport::{Index as _, Invoke as _, Serve as _};
+use wrpc_transport_ws::{Client, Connection};
+
+async fn connect() -> anyhow::Result<(Connection, Connection)> {
+    let lis = TcpListener::bind("127.0.0.1:0")
+        .await
+        .context("failed to bind TCP listener")?;
+    let addr = lis.local_addr().context("failed to get listener address")?;
+    try_join!(
+        async {
+            let (stream, _) = lis.accept().await.context("failed to accept connection")?;
+            let ws = tokio_tungstenite::accept_async(stream)
+                .await
+                .context("failed to perform WebSocket handshake")?;
+            anyhow::Ok(Connection::new(ws, Role::Server))
+        },
+        async {
+            let stream = TcpStream::connect(addr)
+                .await
+                .context("failed to connect to listener")?;
+            let (ws, _) = tokio_tungstenite::client_async(format!("ws://{addr}"), stream)
+                .await
+                .context("failed to perform WebSocket handshake")?;
+            anyhow::Ok(Connection::new(ws, Role::Client))
+        },
+    )
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn loopback() -> anyhow::Result<()> {
+    let (srv, clt) = connect().await?;
+    let clt = Client::from(clt);
+    let srv_conn = Client::from(srv);
+    let srv = Arc::new(wrpc_transport_ws::Server::new());
+    let invocations = srv
+        .serve("foo", "bar", [Box::from([Some(42), Some(0)])])
+        .await
+        .context("failed to serve `foo.bar`")?;
+    let mut invocations = pin!(invocations);
+    try_join!(
+        async {
+            let (mut outgoing, mut incoming) = clt
+                .invoke((), "foo", "bar", "test".into(), &[&[Some(0), Some(42)]])
+                .await
+                .context("failed to invoke `foo.bar`")?;
+            let mut nested_tx = outgoing.index(&[42, 0]).context("failed to index `42.0`")?;
+            let mut nested_rx = incoming.index(&[0, 42]).context("failed to index `0.42`")?;
+            try_join!(
+                async {
+                    info!("reading `foo`");
+                    let mut buf = vec![];
+                    let n = incoming
+                        .read_to_end(&mut buf)
+                        .await
+                        .context("failed to read `foo`")?;
+                    assert_eq!(n, 3);
+                    assert_eq!(buf, b"foo");
+                    info!("read `foo`");
+                    anyhow::Ok(())
+                },
+                async {
+                    info!("writing `bar`");
+                    outgoing
+                        .write_all(b"bar")
+                        .await
+                        .context("failed to write `bar`")?;
+                    outgoing
+                        .shutdown()
+                        .await
+                        .context("failed to shutdown stream")?;
+                    drop(outgoing);
+                    info!("wrote `bar`");
+                    anyhow::Ok(())
+                },
+                async {
+                    info!("writing `client->server`");
+                    nested_tx
+                        .write_all(b"client->server")
+                        .await
+                        .context("failed to write `client->server`")?;
+                    nested_tx
+                        .shutdown()
+                        .await
+                        .context("failed to shutdown stream")?;
+                    drop(nested_tx);
+                    info!("wrote `client->server`");
+                    anyhow::Ok(())
+                },
+                async {
+                    info!("reading `server->client`");
+                    let mut buf = vec![];
+                    nested_rx
+                        .read_to_end(&mut buf)
+                        .await
+                        .context("failed to read `server->client`")?;
+                    assert_eq!(buf, b"server->client");
+                    info!("read `server->client`");
+                    anyhow::Ok(())
+                },
+            )?;
+            anyhow::Ok(())
+        },
+        async {
+            srv.accept(srv_conn)
+                .await
+                .context("failed to accept invocation")?;
+            let ((), mut outgoing, mut incoming) = invocations
+                .next()
+                .await
+                .context("invocation stream unexpectedly finished")?
+                .context("failed to get invocation")?;
+            let mut nested_tx = outgoing.index(&[0, 42]).context("failed to index `0.42`")?;
+            let mut nested_rx = incoming.index(&[42, 0]).context("failed to index `42.0`")?;
+            try_join!(
+                async {
+                    info!("reading `test`");
+                    let mut buf = vec![0; 4];
+                    incoming
+                        .read_exact(&mut buf)
+                        .await
+                        .context("failed to read `test`")?;
+                    assert_eq!(buf, b"test");
+                    info!("read `test`");
+
+                    info!("reading `bar`");
+                    let mut buf = vec![];
+                    let n = incoming
+                        .read_to_end(&mut buf)
+                        .await
+                        .context("failed to read `bar`")?;
+                    assert_eq!(n, 3);
+                    assert_eq!(buf, b"bar");
+                    info!("read `bar`");
+                    anyhow::Ok(())
+                },
+                async {
+                    info!("writing `foo`");
+                    outgoing
+                        .write_all(b"foo")
+                        .await
+                        .context("failed to write `foo`")?;
+                    outgoing
+                        .shutdown()
+                        .await
+                        .context("failed to shutdown stream")?;
+                    drop(outgoing);
+                    info!("wrote `foo`");
+                    anyhow::Ok(())
+                },
+                async {
+                    info!("writing `server->client`");
+                    nested_tx
+                        .write_all(b"server->client")
+                        .await
+                        .context("failed to write `server->client`")?;
+                    nested_tx
+                        .shutdown()
+                        .await
+                        .context("failed to shutdown stream")?;
+                    drop(nested_tx);
+                    info!("wrote `server->client`");
+                    anyhow::Ok(())
+                },
+                async {
+                    info!("reading `client->server`");
+                    let mut buf = vec![];
+                    let n = nested_rx
+                        .read_to_end(&mut buf)
+                        .await
+                        .context("failed to read `client->server`")?;
+                    assert_eq!(n, 14);
+                    assert_eq!(buf, b"client->server");
+                    info!("read `client->server`");
+                    anyhow::Ok(())
+                },
+            )?;
+            Ok(())
+        }
+    )?;
+    Ok(())
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+async fn large_write() -> anyhow::Result<()> {
+    let (srv, clt) = connect().await?;
+    // exceeds the egress queue capacity of the connection
+    let data: Vec<u8> = (0..1 << 23).map(|i: u32| i as u8).collect();
+    try_join!(
+        async {
+            let (mut tx, _rx) = clt.open_bi().context("failed to open stream")?;
+            tx.write_all(&data).await.context("failed to write data")?;
+            tx.flush().await.context("failed to flush stream")?;
+            tx.shutdown().await.context("failed to shutdown stream")?;
+            anyhow::Ok(())
+        },
+        async {
+            let (_tx, mut rx) = srv.accept_bi().await.context("failed to accept stream")?;
+            let mut buf = vec![];
+            rx.read_to_end(&mut buf)
+                .await
+                .context("failed to read data")?;
+            assert_eq!(buf, data);
+            anyhow::Ok(())
+        },
+    )?;
+    Ok(())
+}
@@ -0,0 +1,465 @@
+//! [Invoke] and [Serve] wrappers compressing all bytes transmitted over invocation streams
+//! using [zstd](https://facebook.github.io/zstd/), e.g. to reduce bandwidth used on constrained
+//! links.
+//!
+//! Compression changes the wire format, so both peers must be configured to use it:
+//! invocations performed using [`CompressedInvoke`] can only be served using [`CompressedServe`]
+//! and vice versa.
+//!
+//! Each stream, including the nested streams used for asynchronous values, is compressed
+//! independently as a sequence of zstd frames. Data is flushed, i.e. made decodable by the peer,
+//! whenever the stream is flushed.
+
+use core::pin::Pin;
+use core::task::{ready, Context, Poll};
+
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt as _};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::instrument;
+use zstd::stream::raw::{Decoder, Encoder, InBuffer, Operation as _, OutBuffer};
+
+use crate::{Index, Invoke, Serve};
+
+/// Compression level used by default, see [`zstd::DEFAULT_COMPRESSION_LEVEL`]
+pub const DEFAULT_LEVEL: i32 = zstd::DEFAULT_COMPRESSION_LEVEL;
+
+/// Minimum capacity reserved for compressed or decompressed bytes before each zstd operation
+const CHUNK_SIZE: usize = 8192;
+
+/// Compresses `src` using `encoder` appending the compressed bytes to `dst`
+fn compress(encoder: &mut Encoder<'static>, src: &[u8], dst: &mut Vec<u8>) -> std::io::Result<()> {
+    let mut input = InBuffer::around(src);
+    while input.pos() < src.len() {
+        dst.reserve(CHUNK_SIZE);
+        let pos = dst.len();
+        encoder.run(&mut input, &mut OutBuffer::around_pos(dst, pos))?;
+    }
+    Ok(())
+}
+
+/// Flushes `encoder` appending the compressed bytes to `dst`.
+/// If `end` is `true`, the current frame is ended.
+fn flush(encoder: &mut Encoder<'static>, dst: &mut Vec<u8>, end: bool) -> std::io::Result<()> {
+    loop {
+        dst.reserve(CHUNK_SIZE);
+        let pos = dst.len();
+        let mut output = OutBuffer::around_pos(dst, pos);
+        let remaining = if end {
+            encoder.finish(&mut output, false)?
+        } else {
+            encoder.flush(&mut output)?
+        };
+        if remaining == 0 {
+            return Ok(());
+        }
+    }
+}
+
+/// [Invoke] wrapper, which compresses parameters and decompresses results of all invocations
+/// performed using the inner [Invoke].
+///
+/// Invocations must be served using [`CompressedServe`].
+#[derive(Clone, Copy, Debug)]
+pub struct CompressedInvoke<T> {
+    /// Inner [Invoke]
+    pub inner: T,
+    /// zstd compression level
+    pub level: i32,
+}
+
+impl<T> CompressedInvoke<T> {
+    /// Wraps `inner` using [`DEFAULT_LEVEL`]
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            level: DEFAULT_LEVEL,
+        }
+    }
+}
+
+impl<T: Invoke> Invoke for CompressedInvoke<T> {
+    type Context = T::Context;
+    type Outgoing = CompressedOutgoing<T::Outgoing>;
+    type Incoming = CompressedIncoming<T::Incoming>;
+
+    #[instrument(level = "trace", skip(self, cx, params, paths))]
+    async fn invoke<P>(
+        &self,
+        cx: Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        // parameters are transmitted along with the invocation, so the compressed bytes are
+        // flushed to make them decodable by the peer without waiting for further writes
+        let (encoder, params) = if params.is_empty() {
+            (None, params)
+        } else {
+            let mut encoder =
+                Encoder::new(self.level).context("failed to construct zstd encoder")?;
+            let mut buf = Vec::default();
+            compress(&mut encoder, &params, &mut buf).context("failed to compress parameters")?;
+            flush(&mut encoder, &mut buf, false).context("failed to compress parameters")?;
+            (Some(encoder), Bytes::from(buf))
+        };
+        let (tx, rx) = self.inner.invoke(cx, instance, func, params, paths).await?;
+        let mut tx = CompressedOutgoing::new(tx, self.level);
+        tx.encoder = encoder;
+        Ok((tx, CompressedIncoming::new(rx)))
+    }
+}
+
+/// [Serve] wrapper, which decompresses parameters and compresses results of all invocations
+/// served using the inner [Serve].
+///
+/// Invocations must be performed using [`CompressedInvoke`].
+#[derive(Clone, Copy, Debug)]
+pub struct CompressedServe<T> {
+    /// Inner [Serve]
+    pub inner: T,
+    /// zstd compression level
+    pub level: i32,
+}
+
+impl<T> CompressedServe<T> {
+    /// Wraps `inner` using [`DEFAULT_LEVEL`]
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            level: DEFAULT_LEVEL,
+        }
+    }
+}
+
+impl<T: Serve> Serve for CompressedServe<T> {
+    type Context = T::Context;
+    type Outgoing = CompressedOutgoing<T::Outgoing>;
+    type Incoming = CompressedIncoming<T::Incoming>;
+
+    #[instrument(level = "trace", skip(self, paths))]
+    async fn serve(
+        &self,
+        instance: &str,
+        func: &str,
+        paths: impl Into<Arc<[Box<[Option<usize>]>]>> + Send,
+    ) -> anyhow::Result<
+        impl Stream<Item = anyhow::Result<(Self::Context, Self::Outgoing, Self::Incoming)>>
+            + Send
+            + 'static,
+    > {
+        let invocations = self.inner.serve(instance, func, paths).await?;
+        let level = self.level;
+        Ok(invocations.map_ok(move |(cx, tx, rx)| {
+            (
+                cx,
+                CompressedOutgoing::new(tx, level),
+                CompressedIncoming::new(rx),
+            )
+        }))
+    }
+}
+
+/// Outgoing stream of [`CompressedInvoke`] and [`CompressedServe`]
+pub struct CompressedOutgoing<T> {
+    inner: T,
+    level: i32,
+    /// Encoder of the current frame, constructed on first write
+    encoder: Option<Encoder<'static>>,
+    /// Compressed bytes, which were not written to `inner` yet, starting at `pos`
+    buf: Vec<u8>,
+    pos: usize,
+    /// Whether bytes were compressed since the last flush
+    dirty: bool,
+}
+
+impl<T> CompressedOutgoing<T> {
+    fn new(inner: T, level: i32) -> Self {
+        Self {
+            inner,
+            level,
+            encoder: None,
+            buf: Vec::default(),
+            pos: 0,
+            dirty: false,
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> CompressedOutgoing<T> {
+    /// Writes all buffered compressed bytes to the inner stream
+    fn poll_write_buf(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        while self.pos < self.buf.len() {
+            let n = ready!(Pin::new(&mut self.inner).poll_write(cx, &self.buf[self.pos..]))?;
+            if n == 0 {
+                return Poll::Ready(Err(std::io::ErrorKind::WriteZero.into()));
+            }
+            self.pos += n;
+        }
+        self.buf.clear();
+        self.pos = 0;
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<T: Index<T>> Index<Self> for CompressedOutgoing<T> {
+    fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+        let inner = self.inner.index(path)?;
+        Ok(Self::new(inner, self.level))
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for CompressedOutgoing<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.get_mut();
+        ready!(this.poll_write_buf(cx))?;
+        if buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+        let encoder = match this.encoder.take() {
+            Some(encoder) => encoder,
+            None => Encoder::new(this.level)?,
+        };
+        let encoder = this.encoder.insert(encoder);
+        compress(encoder, buf, &mut this.buf)?;
+        this.dirty = true;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if this.dirty {
+            if let Some(encoder) = &mut this.encoder {
+                flush(encoder, &mut this.buf, false)?;
+            }
+            this.dirty = false;
+        }
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        // streams, which were never written to, are shut down without emitting a frame
+        if let Some(mut encoder) = this.encoder.take() {
+            flush(&mut encoder, &mut this.buf, true)?;
+            this.dirty = false;
+        }
+        ready!(this.poll_write_buf(cx))?;
+        Pin::new(&mut this.inner).poll_shutdown(cx)
+    }
+}
+
+/// Incoming stream of [`CompressedInvoke`] and [`CompressedServe`]
+pub struct CompressedIncoming<T> {
+    inner: T,
+    /// Decoder constructed on first read
+    decoder: Option<Decoder<'static>>,
+    /// Compressed bytes read from `inner`, which were not decompressed yet, starting at `pos`
+    buf: Vec<u8>,
+    pos: usize,
+    /// Whether a frame was started, but not completed yet
+    in_frame: bool,
+    /// Whether `inner` reached end of stream
+    eof: bool,
+}
+
+impl<T> CompressedIncoming<T> {
+    fn new(inner: T) -> Self {
+        Self {
+            inner,
+            decoder: None,
+            buf: Vec::default(),
+            pos: 0,
+            in_frame: false,
+            eof: false,
+        }
+    }
+}
+
+impl<T: Index<T>> Index<Self> for CompressedIncoming<T> {
+    fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+        let inner = self.inner.index(path)?;
+        Ok(Self::new(inner))
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for CompressedIncoming<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        if buf.remaining() == 0 {
+            return Poll::Ready(Ok(()));
+        }
+        loop {
+            // the decoder may hold decompressed bytes even if all input was consumed
+            if this.pos < this.buf.len() || this.in_frame {
+                let decoder = match this.decoder.take() {
+                    Some(decoder) => decoder,
+                    None => Decoder::new()?,
+                };
+                let decoder = this.decoder.insert(decoder);
+                let (hint, read, written) = {
+                    let mut input = InBuffer::around(&this.buf[this.pos..]);
+                    let mut output = OutBuffer::around(buf.initialize_unfilled());
+                    let hint = decoder.run(&mut input, &mut output)?;
+                    (hint, input.pos(), output.pos())
+                };
+                this.pos += read;
+                if this.pos == this.buf.len() {
+                    this.buf.clear();
+                    this.pos = 0;
+                }
+                this.in_frame = hint != 0;
+                if written > 0 {
+                    buf.advance(written);
+                    return Poll::Ready(Ok(()));
+                }
+                if read > 0 {
+                    continue;
+                }
+            }
+            if this.eof {
+                if this.in_frame {
+                    return Poll::Ready(Err(std::io::Error::new(
+                        std::io::ErrorKind::UnexpectedEof,
+                        "compressed stream ended mid-frame",
+                    )));
+                }
+                return Poll::Ready(Ok(()));
+            }
+            let mut chunk = [0; CHUNK_SIZE];
+            let mut chunk = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk))?;
+            if chunk.filled().is_empty() {
+                this.eof = true;
+            } else {
+                this.buf.extend_from_slice(chunk.filled());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::{stream, StreamExt as _};
+
+    use super::*;
+    use crate::record::RecordingInvoke;
+    use crate::{memory, InvokeExt as _, ServeExt as _, Server};
+
+    type Items = Pin<Box<dyn Stream<Item = Vec<u32>> + Send>>;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn round_trip() -> anyhow::Result<()> {
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let compressed = CompressedServe::new(&srv);
+        let echoes = compressed
+            .serve_values::<(String,), (String,)>(
+                "test:test/iface",
+                "echo",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let counts = compressed
+            .serve_values::<(), (Items,)>(
+                "test:test/iface",
+                "count",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let clt = CompressedInvoke::new(RecordingInvoke::new(clt));
+        let payload = "wrpc".repeat(1 << 14);
+        let ((), (), (echoed, items)) = tokio::try_join!(
+            async {
+                for _ in 0..2 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            },
+            async {
+                let mut echoes = pin!(echoes);
+                let ((), (s,), _, tx) = echoes.try_next().await?.expect("unexpected end of stream");
+                tx((s,)).await?;
+                let mut counts = pin!(counts);
+                let ((), (), _, tx) = counts.try_next().await?.expect("unexpected end of stream");
+                let items: Items = Box::pin(stream::iter([vec![1, 2], vec![3]]));
+                tx((items,)).await?;
+                anyhow::Ok(())
+            },
+            async {
+                let (echoed,) = clt
+                    .invoke_values_blocking::<_, _, (String,)>(
+                        (),
+                        "test:test/iface",
+                        "echo",
+                        (payload.clone(),),
+                        &[[]; 0],
+                    )
+                    .await?;
+                let ((items,), io) = clt
+                    .invoke_values::<_, _, (Items,)>(
+                        (),
+                        "test:test/iface",
+                        "count",
+                        (),
+                        &[[Some(0)]],
+                    )
+                    .await?;
+                let (items, io) = tokio::join!(items.collect::<Vec<_>>(), async {
+                    if let Some(io) = io {
+                        io.await
+                    } else {
+                        Ok(())
+                    }
+                });
+                io?;
+                anyhow::Ok((echoed, items.concat()))
+            },
+        )?;
+        assert_eq!(echoed, payload);
+        assert_eq!(items, [1, 2, 3]);
+
+        let session = clt.inner.session()?;
+        let [echo, count] = &session.invocations[..] else {
+            panic!("exactly two invocations should have been recorded");
+        };
+        let sent = echo.params.len() + echo.outgoing.values().map(Bytes::len).sum::<usize>();
+        let received = echo.incoming.values().map(Bytes::len).sum::<usize>();
+        assert!(
+            sent < payload.len() / 10,
+            "{sent} bytes sent for a payload of {} bytes",
+            payload.len()
+        );
+        assert!(
+            received < payload.len() / 10,
+            "{received} bytes received for a payload of {} bytes",
+            payload.len()
+        );
+        assert!(
+            count
+                .incoming
+                .get(&vec![0])
+                .is_some_and(|buf| !buf.is_empty()),
+            "deferred stream bytes should have been transmitted"
+        );
+        Ok(())
+    }
+}
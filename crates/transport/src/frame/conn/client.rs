@@ -12,11 +12,23 @@ use crate::frame::{Conn, ConnHandler, PROTOCOL};
 
 /// Defines invocation behavior
 #[derive(Clone)]
-pub struct InvokeBuilder<H = ()>(PhantomData<H>)
+pub struct InvokeBuilder<H = ()>
 where
-    H: ?Sized;
+    H: ?Sized,
+{
+    max_incoming_bytes: Option<usize>,
+    conn_handler: PhantomData<H>,
+}
 
 impl<H> InvokeBuilder<H> {
+    /// Sets the maximum amount of bytes received from the peer per invocation, after which
+    /// the incoming stream fails
+    #[must_use]
+    pub fn max_incoming_bytes(mut self, n: usize) -> Self {
+        self.max_incoming_bytes = Some(n);
+        self
+    }
+
     /// Invoke function `func` on instance `instance`
     #[instrument(level = "trace", skip_all)]
     pub async fn invoke<P, I, O>(
@@ -50,14 +62,18 @@ impl<H> InvokeBuilder<H> {
             .await
             .context("failed to initialize connection")?;
 
-        let Conn { tx, rx } = Conn::new::<H, _, _, _>(rx, tx, paths.as_ref());
+        let Conn { tx, rx } =
+            Conn::new::<H, _, _, _>(rx, tx, paths.as_ref(), self.max_incoming_bytes);
         Ok((tx, rx))
     }
 }
 
 impl<H> Default for InvokeBuilder<H> {
     fn default() -> Self {
-        Self(PhantomData)
+        Self {
+            max_incoming_bytes: None,
+            conn_handler: PhantomData,
+        }
     }
 }
 
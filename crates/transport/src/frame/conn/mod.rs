@@ -426,12 +426,16 @@ impl AsyncWrite for Outgoing {
     }
 }
 
+/// Reads frames from `rx` and dispatches them to subscribers, failing once more than
+/// `max_bytes` bytes of frame data have been received, if set
 #[instrument(level = "trace", skip_all, ret(level = "trace"))]
 async fn ingress(
     mut rx: impl AsyncRead + Unpin,
     index: &std::sync::Mutex<IndexTrie>,
     param_tx: mpsc::Sender<std::io::Result<Bytes>>,
+    max_bytes: Option<usize>,
 ) -> std::io::Result<()> {
+    let mut received = 0usize;
     loop {
         trace!("reading path length");
         let b = match rx.read_u8().await {
@@ -476,6 +480,15 @@ async fn ingress(
             .try_into()
             .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
         trace!(n, "read data length");
+        received = received.saturating_add(n);
+        if let Some(max) = max_bytes {
+            if received > max {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("incoming data exceeds the limit of {max} bytes per invocation"),
+                ));
+            }
+        }
         let mut buf = BytesMut::with_capacity(n);
         buf.put_bytes(0, n);
         trace!("reading data");
@@ -544,8 +557,14 @@ pub(crate) struct Conn {
 }
 
 impl Conn {
-    /// Creates a new [Conn] given an [AsyncRead], [ConnHandler] and a set of async paths
-    fn new<H, Rx, Tx, P>(mut rx: Rx, mut tx: Tx, paths: impl IntoIterator<Item = P>) -> Self
+    /// Creates a new [Conn] given an [AsyncRead], [ConnHandler] and a set of async paths.
+    /// If `max_incoming_bytes` is set, ingress fails once more data is received
+    fn new<H, Rx, Tx, P>(
+        mut rx: Rx,
+        mut tx: Tx,
+        paths: impl IntoIterator<Item = P>,
+        max_incoming_bytes: Option<usize>,
+    ) -> Self
     where
         Rx: AsyncRead + Unpin + Send + 'static,
         Tx: AsyncWrite + Unpin + Send + 'static,
@@ -559,7 +578,13 @@ impl Conn {
         rx_io.spawn({
             let index = Arc::clone(&index);
             async move {
-                let res = ingress(&mut rx, &index, rx_tx).await;
+                let res = ingress(&mut rx, &index, rx_tx.clone(), max_incoming_bytes).await;
+                if let Err(err) = &res {
+                    // surface the failure, e.g. a reset by the peer, to the reader of the root
+                    // stream, which would otherwise observe a regular EOF
+                    _ = rx_tx.try_send(Err(std::io::Error::new(err.kind(), err.to_string())));
+                }
+                drop(rx_tx);
                 H::on_ingress(rx, res).await;
                 let Ok(mut index) = index.lock() else {
                     error!("failed to lock index trie");
@@ -1,5 +1,7 @@
 use core::fmt::{Debug, Display};
+use core::future::Future;
 use core::marker::PhantomData;
+use core::pin::Pin;
 
 use std::collections::{hash_map, HashMap};
 use std::sync::Arc;
@@ -19,6 +21,7 @@ use crate::Serve;
 /// wRPC server for framed transports
 pub struct Server<C, I, O, H = ()> {
     handlers: Mutex<HashMap<String, HashMap<String, mpsc::Sender<(C, I, O)>>>>,
+    max_incoming_bytes: Option<usize>,
     conn_handler: PhantomData<H>,
 }
 
@@ -27,9 +30,18 @@ impl<C, I, O, H> Server<C, I, O, H> {
     pub fn new() -> Self {
         Self {
             handlers: Mutex::default(),
+            max_incoming_bytes: None,
             conn_handler: PhantomData,
         }
     }
+
+    /// Sets the maximum amount of bytes received from the peer per invocation, including
+    /// parameters, after which the incoming stream of the invocation fails
+    #[must_use]
+    pub fn max_incoming_bytes(mut self, n: usize) -> Self {
+        self.max_incoming_bytes = Some(n);
+        self
+    }
 }
 
 impl<C, I, O> Default for Server<C, I, O> {
@@ -83,6 +95,27 @@ impl<C, I, O> Display for AcceptError<C, I, O> {
 
 impl<C, I, O> std::error::Error for AcceptError<C, I, O> {}
 
+/// Reads the header of an invocation from `rx` and returns the invoked instance and function
+async fn read_header<C, I, O>(rx: &mut I) -> Result<(String, String), AcceptError<C, I, O>>
+where
+    I: AsyncRead + Unpin,
+{
+    let mut instance = String::default();
+    let mut name = String::default();
+    match rx.read_u8().await.map_err(AcceptError::IO)? {
+        0x00 => {
+            rx.read_core_name(&mut instance)
+                .await
+                .map_err(AcceptError::IO)?;
+            rx.read_core_name(&mut name)
+                .await
+                .map_err(AcceptError::IO)?;
+        }
+        v => return Err(AcceptError::UnsupportedVersion(v)),
+    }
+    Ok((instance, name))
+}
+
 impl<C, I, O, H> Server<C, I, O, H>
 where
     I: AsyncRead + Unpin,
@@ -99,19 +132,7 @@ where
         listener: impl Accept<Context = C, Incoming = I, Outgoing = O>,
     ) -> Result<(), AcceptError<C, I, O>> {
         let (cx, tx, mut rx) = listener.accept().await.map_err(AcceptError::IO)?;
-        let mut instance = String::default();
-        let mut name = String::default();
-        match rx.read_u8().await.map_err(AcceptError::IO)? {
-            0x00 => {
-                rx.read_core_name(&mut instance)
-                    .await
-                    .map_err(AcceptError::IO)?;
-                rx.read_core_name(&mut name)
-                    .await
-                    .map_err(AcceptError::IO)?;
-            }
-            v => return Err(AcceptError::UnsupportedVersion(v)),
-        }
+        let (instance, name) = read_header(&mut rx).await?;
         let h = self.handlers.lock().await;
         let h = h
             .get(&instance)
@@ -150,9 +171,10 @@ where
         }
     }
     let paths = paths.into();
+    let max_incoming_bytes = srv.max_incoming_bytes;
     Ok(ReceiverStream::new(rx).map(move |(cx, rx, tx)| {
         trace!("received invocation");
-        let Conn { tx, rx } = Conn::new::<H, _, _, _>(rx, tx, paths.iter());
+        let Conn { tx, rx } = Conn::new::<H, _, _, _>(rx, tx, paths.iter(), max_incoming_bytes);
         Ok((cx, tx, rx))
     }))
 }
@@ -202,3 +224,211 @@ where
         serve(self, instance, func, paths).await
     }
 }
+
+/// Future handling a single invocation routed by [`ServeRouter`]
+pub type RouteFuture = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'static>>;
+
+/// Handler of invocations routed by [`ServeRouter`]
+pub type RouteHandler<C> = Arc<dyn Fn(C, Outgoing, Incoming) -> RouteFuture + Send + Sync>;
+
+/// Async paths of a function routed by [`ServeRouter`] and its handler
+type Route<C> = (Arc<[Box<[Option<usize>]>]>, RouteHandler<C>);
+
+/// Routes invocations accepted on framed transports to handlers registered by instance and
+/// function name.
+///
+/// Contrary to [Server], invocations are dispatched by [`ServeRouter::accept`] directly
+/// using the instance and function name read from the invocation, so a single accept loop
+/// serves all registered functions.
+pub struct ServeRouter<C, H = ()> {
+    routes: HashMap<Arc<str>, HashMap<Arc<str>, Route<C>>>,
+    max_incoming_bytes: Option<usize>,
+    conn_handler: PhantomData<H>,
+}
+
+impl<C, H> ServeRouter<C, H> {
+    /// Constructs a new [`ServeRouter`] without any routes
+    pub fn new() -> Self {
+        Self {
+            routes: HashMap::default(),
+            max_incoming_bytes: None,
+            conn_handler: PhantomData,
+        }
+    }
+
+    /// Sets the maximum amount of bytes received from the peer per invocation, including
+    /// parameters, after which the incoming stream of the invocation fails
+    #[must_use]
+    pub fn max_incoming_bytes(mut self, n: usize) -> Self {
+        self.max_incoming_bytes = Some(n);
+        self
+    }
+
+    /// Registers `handler` for invocations of function `func` from instance `instance`,
+    /// replacing any handler previously registered for it
+    pub fn route<F, Fut>(
+        &mut self,
+        instance: impl Into<Arc<str>>,
+        func: impl Into<Arc<str>>,
+        paths: impl Into<Arc<[Box<[Option<usize>]>]>>,
+        handler: F,
+    ) -> &mut Self
+    where
+        F: Fn(C, Outgoing, Incoming) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let handler: RouteHandler<C> =
+            Arc::new(move |cx, tx, rx| Box::pin(handler(cx, tx, rx)) as RouteFuture);
+        self.routes
+            .entry(instance.into())
+            .or_default()
+            .insert(func.into(), (paths.into(), handler));
+        self
+    }
+
+    /// Accept an invocation on an [Accept] and route it to the handler registered for the
+    /// invoked function.
+    ///
+    /// Returns the instance and function name of the invoked function and the future returned
+    /// by its handler, which must be polled to completion to handle the invocation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting the invocation has failed or no handler is registered
+    /// for the invoked function
+    #[instrument(level = "trace", skip_all)]
+    pub async fn accept<I, O>(
+        &self,
+        listener: impl Accept<Context = C, Incoming = I, Outgoing = O>,
+    ) -> Result<(Arc<str>, Arc<str>, RouteFuture), AcceptError<C, I, O>>
+    where
+        I: AsyncRead + Send + Sync + Unpin + 'static,
+        O: AsyncWrite + Send + Sync + Unpin + 'static,
+        H: ConnHandler<I, O>,
+    {
+        let (cx, tx, mut rx) = listener.accept().await.map_err(AcceptError::IO)?;
+        let (instance, name) = read_header(&mut rx).await?;
+        let route = self
+            .routes
+            .get_key_value(instance.as_str())
+            .and_then(|(instance, routes)| {
+                let (name, route) = routes.get_key_value(name.as_str())?;
+                Some((instance, name, route))
+            });
+        let Some((instance, name, (paths, handler))) = route else {
+            return Err(AcceptError::UnhandledFunction { instance, name });
+        };
+        trace!(?instance, ?name, "routing invocation");
+        let Conn { tx, rx } =
+            Conn::new::<H, _, _, _>(rx, tx, paths.iter(), self.max_incoming_bytes);
+        Ok((Arc::clone(instance), Arc::clone(name), handler(cx, tx, rx)))
+    }
+}
+
+impl<C> Default for ServeRouter<C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::Context as _;
+    use bytes::Bytes;
+    use tokio::io::{AsyncReadExt as _, AsyncWriteExt as _};
+
+    use crate::frame::memory;
+    use crate::Invoke as _;
+
+    use super::*;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn router() -> anyhow::Result<()> {
+        /// Returns a handler responding with `name` followed by the received parameters
+        fn respond(
+            name: &'static str,
+        ) -> impl Fn((), Outgoing, Incoming) -> RouteFuture + Send + Sync + 'static {
+            move |(), mut tx, mut rx| {
+                Box::pin(async move {
+                    let mut params = vec![];
+                    rx.read_to_end(&mut params)
+                        .await
+                        .context("failed to read parameters")?;
+                    tx.write_all(format!("{name}:").as_bytes()).await?;
+                    tx.write_all(&params).await?;
+                    tx.shutdown().await?;
+                    Ok(())
+                })
+            }
+        }
+
+        let (clt, lis) = memory::pair(1024);
+        let mut router = ServeRouter::default();
+        router
+            .route(
+                "test:test/foo",
+                "f",
+                Vec::<Box<[Option<usize>]>>::default(),
+                respond("foo"),
+            )
+            .route(
+                "test:test/bar",
+                "f",
+                Vec::<Box<[Option<usize>]>>::default(),
+                respond("bar"),
+            );
+        for (instance, expected) in [
+            ("test:test/bar", b"bar:test"),
+            ("test:test/foo", b"foo:test"),
+        ] {
+            let ((routed, func), res) = tokio::try_join!(
+                async {
+                    let (instance, func, handle) = router
+                        .accept(&lis)
+                        .await
+                        .context("failed to accept invocation")?;
+                    handle.await?;
+                    anyhow::Ok((instance, func))
+                },
+                async {
+                    let (mut tx, mut rx) = clt
+                        .invoke(
+                            (),
+                            instance,
+                            "f",
+                            Bytes::from("test"),
+                            [[Some(0)].as_slice(); 0],
+                        )
+                        .await?;
+                    tx.shutdown().await?;
+                    let mut buf = vec![];
+                    rx.read_to_end(&mut buf).await?;
+                    anyhow::Ok(buf)
+                },
+            )?;
+            assert_eq!(&*routed, instance);
+            assert_eq!(&*func, "f");
+            assert_eq!(res, expected);
+        }
+
+        let (res, _) = tokio::join!(router.accept(&lis), async {
+            let (mut tx, _rx) = clt
+                .invoke(
+                    (),
+                    "test:test/baz",
+                    "f",
+                    Bytes::from("test"),
+                    [[Some(0)].as_slice(); 0],
+                )
+                .await?;
+            tx.shutdown().await?;
+            anyhow::Ok(())
+        });
+        let Err(AcceptError::UnhandledFunction { instance, name }) = res else {
+            panic!("invocation of an unrouted function should fail");
+        };
+        assert_eq!(instance, "test:test/baz");
+        assert_eq!(name, "f");
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! In-memory transport using [`tokio::io::duplex`], useful for testing without a network
+
+use anyhow::anyhow;
+use bytes::Bytes;
+use tokio::io::{duplex, split, DuplexStream, ReadHalf, WriteHalf};
+use tokio::sync::{mpsc, Mutex};
+use tracing::instrument;
+
+use crate::frame::{invoke, Accept, Incoming, Outgoing};
+use crate::Invoke;
+
+/// Creates a connected in-memory [Client] and [Listener].
+///
+/// Each invocation is transmitted over a dedicated stream pair created using
+/// [`tokio::io::duplex`] with `max_buf_size`
+pub fn pair(max_buf_size: usize) -> (Client, Listener) {
+    let (tx, rx) = mpsc::unbounded_channel();
+    (Client { tx, max_buf_size }, Listener(Mutex::new(rx)))
+}
+
+/// [Invoke] implementation of an in-memory transport
+#[derive(Clone, Debug)]
+pub struct Client {
+    tx: mpsc::UnboundedSender<DuplexStream>,
+    max_buf_size: usize,
+}
+
+impl Invoke for Client {
+    type Context = ();
+    type Outgoing = Outgoing;
+    type Incoming = Incoming;
+
+    #[instrument(level = "trace", skip(self, paths, params), fields(params = format!("{params:02x?}")))]
+    async fn invoke<P>(
+        &self,
+        (): Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let (stream, conn) = duplex(self.max_buf_size);
+        self.tx
+            .send(conn)
+            .map_err(|_| anyhow!("listener was dropped"))?;
+        let (rx, tx) = split(stream);
+        invoke(tx, rx, instance, func, params, paths).await
+    }
+}
+
+/// [Accept] implementation of an in-memory transport, accepting invocations
+/// of a [Client] returned by [pair]
+#[derive(Debug)]
+pub struct Listener(Mutex<mpsc::UnboundedReceiver<DuplexStream>>);
+
+impl Accept for Listener {
+    type Context = ();
+    type Outgoing = WriteHalf<DuplexStream>;
+    type Incoming = ReadHalf<DuplexStream>;
+
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        (&self).accept().await
+    }
+}
+
+impl Accept for &Listener {
+    type Context = ();
+    type Outgoing = WriteHalf<DuplexStream>;
+    type Incoming = ReadHalf<DuplexStream>;
+
+    #[instrument(level = "trace")]
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        let stream = self.0.lock().await.recv().await.ok_or_else(|| {
+            std::io::Error::new(std::io::ErrorKind::NotConnected, "all clients were dropped")
+        })?;
+        let (rx, tx) = split(stream);
+        Ok(((), tx, rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::{pin, Pin};
+
+    use futures::{stream, Stream, StreamExt as _, TryStreamExt as _};
+    use tokio::io::AsyncReadExt as _;
+
+    use super::*;
+    use crate::{Invoke as _, InvokeExt as _, Serve as _, ServeExt as _, Server};
+
+    type Items = Pin<Box<dyn Stream<Item = Vec<u32>> + Send>>;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn round_trip() -> anyhow::Result<()> {
+        let (clt, lis) = pair(1024);
+        let srv = Server::default();
+        let doubles = srv
+            .serve_values::<(u32,), (u32,)>(
+                "test:test/iface",
+                "double",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let counts = srv
+            .serve_values::<(), (Items,)>(
+                "test:test/iface",
+                "count",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        tokio::try_join!(
+            async {
+                for _ in 0..3 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            },
+            async {
+                let mut doubles = pin!(doubles);
+                for _ in 0..2 {
+                    let ((), (n,), _, tx) =
+                        doubles.try_next().await?.expect("unexpected end of stream");
+                    tx((n * 2,)).await?;
+                }
+                let mut counts = pin!(counts);
+                let ((), (), _, tx) = counts.try_next().await?.expect("unexpected end of stream");
+                let items: Items = Box::pin(stream::iter([vec![1, 2], vec![3]]));
+                tx((items,)).await?;
+                anyhow::Ok(())
+            },
+            async {
+                for n in [1, 21] {
+                    let (res,) = clt
+                        .invoke_values_blocking::<_, _, (u32,)>(
+                            (),
+                            "test:test/iface",
+                            "double",
+                            (n,),
+                            &[[]; 0],
+                        )
+                        .await?;
+                    assert_eq!(res, n * 2);
+                }
+                let ((items,), io) = clt
+                    .invoke_values::<_, _, (Items,)>(
+                        (),
+                        "test:test/iface",
+                        "count",
+                        (),
+                        &[[Some(0)]],
+                    )
+                    .await?;
+                let (items, io) = tokio::join!(items.collect::<Vec<_>>(), async {
+                    if let Some(io) = io {
+                        io.await
+                    } else {
+                        Ok(())
+                    }
+                });
+                io?;
+                assert_eq!(items.concat(), [1, 2, 3]);
+                anyhow::Ok(())
+            },
+        )?;
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn max_incoming_bytes() -> anyhow::Result<()> {
+        let (clt, lis) = pair(1024);
+        let srv = Server::default().max_incoming_bytes(16);
+        let invocations = srv
+            .serve("test", "f", Vec::<Box<[Option<usize>]>>::default())
+            .await?;
+        let mut invocations = pin!(invocations);
+        for (params, ok) in [
+            (Bytes::from(vec![0; 16]), true),
+            (Bytes::from(vec![0; 17]), false),
+        ] {
+            let (tx, _rx) = clt
+                .invoke((), "test", "f", params.clone(), [[Some(0)].as_slice(); 0])
+                .await?;
+            // the outgoing stream is shut down once dropped
+            drop(tx);
+            srv.accept(&lis).await?;
+            let ((), _, mut rx) = invocations
+                .try_next()
+                .await?
+                .expect("unexpected end of stream");
+            let mut buf = vec![];
+            let res = rx.read_to_end(&mut buf).await;
+            if ok {
+                res?;
+                assert_eq!(buf, params);
+            } else {
+                let err = res.expect_err("invocation exceeding the limit should fail");
+                assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            }
+        }
+        Ok(())
+    }
+}
@@ -8,6 +8,8 @@ mod codec;
 mod conn;
 mod oneshot;
 
+pub mod memory;
+
 #[cfg(any(target_family = "wasm", feature = "net"))]
 pub mod tcp;
 
@@ -4,6 +4,8 @@
 pub mod tokio;
 #[cfg(feature = "net")]
 pub use tokio::*;
+#[cfg(feature = "tls")]
+pub mod tls;
 
 #[cfg(target_family = "wasm")]
 pub mod wasi;
@@ -0,0 +1,186 @@
+//! wRPC TCP transport secured with TLS using [tokio_rustls]
+
+use core::net::SocketAddr;
+use core::time::Duration;
+
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use bytes::Bytes;
+use tokio::io::{ReadHalf, WriteHalf};
+use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
+use tokio::select;
+use tokio::sync::Mutex;
+use tokio::task::JoinSet;
+use tokio_rustls::rustls::pki_types::ServerName;
+use tokio_rustls::rustls::{ClientConfig, ServerConfig};
+use tokio_rustls::{server, TlsAcceptor, TlsConnector};
+use tracing::{debug, instrument};
+
+use crate::frame::tcp::SocketOptions;
+use crate::frame::{invoke, Accept, Incoming, Outgoing};
+use crate::Invoke;
+
+pub use tokio_rustls::rustls;
+
+/// Default time a peer has to complete the TLS handshake within
+pub const DEFAULT_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+type Handshake = std::io::Result<(SocketAddr, server::TlsStream<TcpStream>)>;
+
+/// [Invoke] implementation of a TLS-over-TCP transport
+#[derive(Clone)]
+pub struct Client<T> {
+    addr: T,
+    connector: TlsConnector,
+    server_name: ServerName<'static>,
+    options: SocketOptions,
+}
+
+impl<T> Client<T> {
+    /// Constructs a new [Client] connecting to `addr` and verifying the server certificate
+    /// against `server_name`
+    pub fn new(
+        addr: T,
+        config: impl Into<Arc<ClientConfig>>,
+        server_name: ServerName<'static>,
+    ) -> Self {
+        Self {
+            addr,
+            connector: TlsConnector::from(config.into()),
+            server_name,
+            options: SocketOptions::default(),
+        }
+    }
+
+    /// Sets [`SocketOptions`] applied to connections established by this client
+    #[must_use]
+    pub fn with_socket_options(mut self, options: SocketOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
+
+impl<T> Invoke for Client<T>
+where
+    T: ToSocketAddrs + Clone + Send + Sync,
+{
+    type Context = ();
+    type Outgoing = Outgoing;
+    type Incoming = Incoming;
+
+    #[instrument(level = "trace", skip(self, paths, params), fields(params = format!("{params:02x?}")))]
+    async fn invoke<P>(
+        &self,
+        (): Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let stream = TcpStream::connect(self.addr.clone()).await?;
+        self.options
+            .apply(&stream)
+            .context("failed to set socket options")?;
+        let stream = self
+            .connector
+            .connect(self.server_name.clone(), stream)
+            .await?;
+        let (rx, tx) = tokio::io::split(stream);
+        invoke(tx, rx, instance, func, params, paths).await
+    }
+}
+
+/// [Accept] implementation of a TLS-over-TCP transport.
+///
+/// TLS handshakes are performed concurrently in tasks spawned on the current [tokio] runtime,
+/// so that a slow or unresponsive peer does not block acceptance of other connections.
+/// [`Accept::accept`] returns the first connection, for which the handshake completed.
+#[derive(Clone)]
+pub struct Listener<T = TcpListener> {
+    listener: T,
+    acceptor: TlsAcceptor,
+    options: SocketOptions,
+    handshake_timeout: Duration,
+    handshakes: Arc<Mutex<JoinSet<Handshake>>>,
+}
+
+impl<T> Listener<T> {
+    /// Constructs a new [Listener] accepting connections on `listener`
+    pub fn new(listener: T, config: impl Into<Arc<ServerConfig>>) -> Self {
+        Self {
+            listener,
+            acceptor: TlsAcceptor::from(config.into()),
+            options: SocketOptions::default(),
+            handshake_timeout: DEFAULT_HANDSHAKE_TIMEOUT,
+            handshakes: Arc::default(),
+        }
+    }
+
+    /// Sets the time a peer has to complete the TLS handshake within,
+    /// defaults to [`DEFAULT_HANDSHAKE_TIMEOUT`]
+    #[must_use]
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+        self
+    }
+
+    /// Sets [`SocketOptions`] applied to accepted connections before the TLS handshake
+    #[must_use]
+    pub fn with_socket_options(mut self, options: SocketOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    /// Returns the underlying listener
+    pub fn get_ref(&self) -> &T {
+        &self.listener
+    }
+}
+
+impl Accept for Listener {
+    type Context = SocketAddr;
+    type Outgoing = WriteHalf<server::TlsStream<TcpStream>>;
+    type Incoming = ReadHalf<server::TlsStream<TcpStream>>;
+
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        (&self).accept().await
+    }
+}
+
+impl Accept for &Listener {
+    type Context = SocketAddr;
+    type Outgoing = WriteHalf<server::TlsStream<TcpStream>>;
+    type Incoming = ReadHalf<server::TlsStream<TcpStream>>;
+
+    #[instrument(level = "trace", skip(self))]
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        let mut handshakes = self.handshakes.lock().await;
+        loop {
+            select! {
+                res = self.listener.accept() => {
+                    let (stream, addr) = res?;
+                    self.options.apply(&stream)?;
+                    let acceptor = self.acceptor.clone();
+                    let timeout = self.handshake_timeout;
+                    handshakes.spawn(async move {
+                        let stream = tokio::time::timeout(timeout, acceptor.accept(stream))
+                            .await
+                            .map_err(|_| std::io::ErrorKind::TimedOut)??;
+                        Ok((addr, stream))
+                    });
+                }
+                Some(res) = handshakes.join_next() => {
+                    let (addr, stream) = res.map_err(std::io::Error::other)?.inspect_err(
+                        |err| debug!(?err, "TLS handshake failed"),
+                    )?;
+                    let (rx, tx) = tokio::io::split(stream);
+                    return Ok((addr, tx, rx));
+                }
+            }
+        }
+    }
+}
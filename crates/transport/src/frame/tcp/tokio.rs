@@ -1,9 +1,11 @@
 //! wRPC TCP transport using [tokio]
 
 use core::net::SocketAddr;
+use core::time::Duration;
 
 use anyhow::{bail, Context as _};
 use bytes::Bytes;
+use socket2::{SockRef, TcpKeepalive};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpListener, TcpStream, ToSocketAddrs};
 use tracing::instrument;
@@ -17,16 +19,55 @@ use crate::Invoke;
 /// repeated calls with return an error
 pub struct Invocation(std::sync::Mutex<Option<TcpStream>>);
 
+/// Socket options applied to TCP connections
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SocketOptions {
+    /// Whether to set `TCP_NODELAY`, disabling Nagle's algorithm, which delays small writes
+    /// to batch them
+    pub nodelay: bool,
+    /// Time a connection has to be idle for before TCP keepalive probes are sent.
+    /// Keepalive is not configured if `None`
+    pub keepalive: Option<Duration>,
+}
+
+impl SocketOptions {
+    /// Applies the options to `stream`
+    pub fn apply(&self, stream: &TcpStream) -> std::io::Result<()> {
+        if self.nodelay {
+            stream.set_nodelay(true)?;
+        }
+        if let Some(time) = self.keepalive {
+            SockRef::from(stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+        }
+        Ok(())
+    }
+}
+
 /// [Invoke] implementation of a TCP transport using [tokio]
 #[derive(Clone, Debug)]
-pub struct Client<T>(T);
+pub struct Client<T> {
+    addr: T,
+    options: SocketOptions,
+}
+
+impl<T> Client<T> {
+    /// Sets [`SocketOptions`] applied to connections established by this client
+    #[must_use]
+    pub fn with_socket_options(mut self, options: SocketOptions) -> Self {
+        self.options = options;
+        self
+    }
+}
 
 impl<T> From<T> for Client<T>
 where
     T: ToSocketAddrs + Clone,
 {
     fn from(addr: T) -> Self {
-        Self(addr)
+        Self {
+            addr,
+            options: SocketOptions::default(),
+        }
     }
 }
 
@@ -56,7 +97,10 @@ where
     where
         P: AsRef<[Option<usize>]> + Send + Sync,
     {
-        let stream = TcpStream::connect(self.0.clone()).await?;
+        let stream = TcpStream::connect(self.addr.clone()).await?;
+        self.options
+            .apply(&stream)
+            .context("failed to set socket options")?;
         let (rx, tx) = stream.into_split();
         invoke(tx, rx, instance, func, params, paths).await
     }
@@ -112,3 +156,72 @@ impl Accept for &TcpListener {
         Ok((addr, tx, rx))
     }
 }
+
+/// [Accept] implementation, which applies [`SocketOptions`] to accepted connections
+pub struct Listener<T = TcpListener> {
+    listener: T,
+    options: SocketOptions,
+}
+
+impl<T> Listener<T> {
+    /// Constructs a new [Listener] accepting connections on `listener`
+    pub fn new(listener: T, options: SocketOptions) -> Self {
+        Self { listener, options }
+    }
+
+    /// Returns the underlying listener
+    pub fn get_ref(&self) -> &T {
+        &self.listener
+    }
+}
+
+impl Accept for Listener {
+    type Context = SocketAddr;
+    type Outgoing = OwnedWriteHalf;
+    type Incoming = OwnedReadHalf;
+
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        (&self).accept().await
+    }
+}
+
+impl Accept for &Listener {
+    type Context = SocketAddr;
+    type Outgoing = OwnedWriteHalf;
+    type Incoming = OwnedReadHalf;
+
+    #[instrument(level = "trace", skip(self))]
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        let (stream, addr) = self.listener.accept().await?;
+        self.options.apply(&stream)?;
+        let (rx, tx) = stream.into_split();
+        Ok((addr, tx, rx))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv6Addr;
+
+    use super::*;
+
+    #[test_log::test(tokio::test)]
+    async fn socket_options() -> anyhow::Result<()> {
+        let options = SocketOptions {
+            nodelay: true,
+            keepalive: Some(Duration::from_secs(60)),
+        };
+        let lis = Listener::new(TcpListener::bind((Ipv6Addr::LOCALHOST, 0)).await?, options);
+        let addr = lis.get_ref().local_addr()?;
+        let (clt, (_, tx, _)) = tokio::try_join!(TcpStream::connect(addr), lis.accept())?;
+
+        assert!(!clt.nodelay()?);
+        assert!(!SockRef::from(&clt).keepalive()?);
+        options.apply(&clt)?;
+        for stream in [&clt, tx.as_ref()] {
+            assert!(stream.nodelay()?);
+            assert!(SockRef::from(stream).keepalive()?);
+        }
+        Ok(())
+    }
+}
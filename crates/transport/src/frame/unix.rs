@@ -1,11 +1,13 @@
 //! Unix domain socket transport
 
+use core::ops::Deref;
+
 use std::path::{Path, PathBuf};
 
 use bytes::Bytes;
 use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf, SocketAddr};
 use tokio::net::{UnixListener, UnixStream};
-use tracing::instrument;
+use tracing::{debug, instrument};
 
 use crate::frame::{invoke, Accept, Incoming, Outgoing};
 use crate::Invoke;
@@ -174,3 +176,63 @@ impl Accept for &UnixListener {
         Ok((addr, tx, rx))
     }
 }
+
+/// [`UnixListener`] bound to a filesystem path, which removes the socket file on drop
+#[derive(Debug)]
+pub struct Listener {
+    listener: UnixListener,
+    path: PathBuf,
+}
+
+impl Listener {
+    /// Creates a new [Listener] bound to `path`
+    pub fn bind(path: impl Into<PathBuf>) -> std::io::Result<Self> {
+        let path = path.into();
+        let listener = UnixListener::bind(&path)?;
+        Ok(Self { listener, path })
+    }
+
+    /// Returns the path of the socket file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Deref for Listener {
+    type Target = UnixListener;
+
+    fn deref(&self) -> &Self::Target {
+        &self.listener
+    }
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        if let Err(err) = std::fs::remove_file(&self.path) {
+            debug!(?err, path = ?self.path, "failed to remove socket file");
+        }
+    }
+}
+
+impl Accept for Listener {
+    type Context = SocketAddr;
+    type Outgoing = OwnedWriteHalf;
+    type Incoming = OwnedReadHalf;
+
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        (&self).accept().await
+    }
+}
+
+impl Accept for &Listener {
+    type Context = SocketAddr;
+    type Outgoing = OwnedWriteHalf;
+    type Incoming = OwnedReadHalf;
+
+    #[instrument(level = "trace")]
+    async fn accept(&self) -> std::io::Result<(Self::Context, Self::Outgoing, Self::Incoming)> {
+        let (stream, addr) = self.listener.accept().await?;
+        let (rx, tx) = stream.into_split();
+        Ok((addr, tx, rx))
+    }
+}
@@ -145,6 +145,148 @@ impl<T: Invoke> Invoke for TimeoutOwned<T> {
     }
 }
 
+/// Wrapper struct returned by [`InvokeExt::retrying`], which retries failed calls to
+/// [`Invoke::invoke`] with exponential backoff.
+///
+/// To preserve at-most-once semantics, invocations are only retried if the error
+/// guarantees, that no data was transmitted to the peer, i.e. if the connection was refused.
+/// Invocations of idempotent functions, which are retried on any error, can be performed
+/// using [`RetryingInvoke::idempotent`].
+///
+/// Note, that only [`Invoke::invoke`] is retried, errors encountered while writing to or
+/// reading from the returned streams are never retried.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RetryingInvoke<T> {
+    /// Inner [Invoke]
+    pub inner: T,
+    /// Maximum number of retries of a single invocation
+    pub retries: usize,
+    /// Delay before the first retry, which is doubled on each subsequent retry
+    pub backoff: Duration,
+}
+
+impl<T> RetryingInvoke<T> {
+    /// Default delay before the first retry
+    pub const DEFAULT_BACKOFF: Duration = Duration::from_millis(100);
+
+    /// Sets the delay before the first retry, which is doubled on each subsequent retry
+    #[must_use]
+    pub fn backoff(self, backoff: Duration) -> Self {
+        Self { backoff, ..self }
+    }
+
+    /// Returns an [Invoke] implementation, which retries invocations on any error.
+    ///
+    /// This must only be used to invoke idempotent functions, since the invocation may have
+    /// been received by the peer even if [`Invoke::invoke`] failed
+    pub fn idempotent(&self) -> Idempotent<'_, T> {
+        Idempotent { inner: self }
+    }
+}
+
+/// Wrapper struct returned by [`RetryingInvoke::idempotent`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Idempotent<'a, T> {
+    /// Inner [`RetryingInvoke`]
+    pub inner: &'a RetryingInvoke<T>,
+}
+
+/// Returns `true` if `err` guarantees, that no data was transmitted to the peer
+fn is_transient(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| {
+        err.downcast_ref::<std::io::Error>()
+            .is_some_and(|err| err.kind() == std::io::ErrorKind::ConnectionRefused)
+    })
+}
+
+impl<T: Invoke> RetryingInvoke<T>
+where
+    T::Context: Clone,
+{
+    async fn invoke_retrying<P>(
+        &self,
+        cx: T::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: &[P],
+        idempotent: bool,
+    ) -> anyhow::Result<(T::Outgoing, T::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let mut backoff = self.backoff;
+        let mut retries = 0;
+        loop {
+            match self
+                .inner
+                .invoke(cx.clone(), instance, func, params.clone(), paths)
+                .await
+            {
+                Ok(io) => return Ok(io),
+                Err(err) if retries < self.retries && (idempotent || is_transient(&err)) => {
+                    retries += 1;
+                    debug!(?err, retries, ?backoff, "invocation failed, retrying");
+                    tokio::time::sleep(backoff).await;
+                    backoff = backoff.saturating_mul(2);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+impl<T: Invoke> Invoke for RetryingInvoke<T>
+where
+    T::Context: Clone,
+{
+    type Context = T::Context;
+    type Outgoing = T::Outgoing;
+    type Incoming = T::Incoming;
+
+    #[instrument(level = "trace", skip(self, cx, params, paths))]
+    async fn invoke<P>(
+        &self,
+        cx: Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        self.invoke_retrying(cx, instance, func, params, paths.as_ref(), false)
+            .await
+    }
+}
+
+impl<T: Invoke> Invoke for Idempotent<'_, T>
+where
+    T::Context: Clone,
+{
+    type Context = T::Context;
+    type Outgoing = T::Outgoing;
+    type Incoming = T::Incoming;
+
+    #[instrument(level = "trace", skip(self, cx, params, paths))]
+    async fn invoke<P>(
+        &self,
+        cx: Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        self.inner
+            .invoke_retrying(cx, instance, func, params, paths.as_ref(), true)
+            .await
+    }
+}
+
 /// Extension trait for [Invoke]
 pub trait InvokeExt: Invoke {
     /// Invoke function `func` on instance `instance` using typed `Params` and `Results`
@@ -317,6 +459,20 @@ pub trait InvokeExt: Invoke {
             timeout,
         }
     }
+
+    /// Returns a [`RetryingInvoke`], wrapping [Self] with an implementation of [Invoke], which
+    /// retries calls to [`Invoke::invoke`] failing before any data was transmitted up to
+    /// `retries` times
+    fn retrying(self, retries: usize) -> RetryingInvoke<Self>
+    where
+        Self: Sized,
+    {
+        RetryingInvoke {
+            inner: self,
+            retries,
+            backoff: RetryingInvoke::<Self>::DEFAULT_BACKOFF,
+        }
+    }
 }
 
 impl<T: Invoke> InvokeExt for T {}
@@ -327,6 +483,7 @@ mod tests {
     use core::future::Future;
     use core::pin::Pin;
 
+    use std::sync::atomic::{AtomicUsize, Ordering};
     use std::sync::Arc;
 
     use bytes::Bytes;
@@ -334,6 +491,7 @@ mod tests {
     use send_future::SendFuture as _;
 
     use super::*;
+    use crate::frame;
 
     #[allow(clippy::manual_async_fn)]
     fn invoke_values_send<T>() -> impl Future<
@@ -389,6 +547,115 @@ mod tests {
         Ok(r0)
     }
 
+    /// [Invoke] implementation failing the first `failures` invocations with `kind`
+    struct Flaky {
+        attempts: AtomicUsize,
+        failures: usize,
+        kind: std::io::ErrorKind,
+    }
+
+    impl Flaky {
+        fn new(failures: usize, kind: std::io::ErrorKind) -> Self {
+            Self {
+                attempts: AtomicUsize::default(),
+                failures,
+                kind,
+            }
+        }
+    }
+
+    impl Invoke for Flaky {
+        type Context = ();
+        type Outgoing = frame::Outgoing;
+        type Incoming = frame::Incoming;
+
+        async fn invoke<P>(
+            &self,
+            (): Self::Context,
+            instance: &str,
+            func: &str,
+            params: Bytes,
+            paths: impl AsRef<[P]> + Send,
+        ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+        where
+            P: AsRef<[Option<usize>]> + Send + Sync,
+        {
+            if self.attempts.fetch_add(1, Ordering::Relaxed) < self.failures {
+                return Err(anyhow::Error::new(std::io::Error::from(self.kind))
+                    .context("failed to connect"));
+            }
+            frame::invoke(
+                tokio::io::sink(),
+                tokio::io::empty(),
+                instance,
+                func,
+                params,
+                paths,
+            )
+            .await
+        }
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn retry_on_connection_refused() -> anyhow::Result<()> {
+        let clt = Flaky::new(2, std::io::ErrorKind::ConnectionRefused)
+            .retrying(2)
+            .backoff(Duration::from_millis(1));
+        clt.invoke(
+            (),
+            "foo",
+            "bar",
+            Bytes::default(),
+            [[Some(0)].as_slice(); 0],
+        )
+        .await?;
+        assert_eq!(clt.inner.attempts.load(Ordering::Relaxed), 3);
+
+        let clt = Flaky::new(3, std::io::ErrorKind::ConnectionRefused)
+            .retrying(2)
+            .backoff(Duration::from_millis(1));
+        clt.invoke(
+            (),
+            "foo",
+            "bar",
+            Bytes::default(),
+            [[Some(0)].as_slice(); 0],
+        )
+        .await
+        .expect_err("invocation should fail once retries are exhausted");
+        assert_eq!(clt.inner.attempts.load(Ordering::Relaxed), 3);
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test)]
+    async fn no_retry_after_send() -> anyhow::Result<()> {
+        let clt = Flaky::new(1, std::io::ErrorKind::BrokenPipe)
+            .retrying(2)
+            .backoff(Duration::from_millis(1));
+        clt.invoke(
+            (),
+            "foo",
+            "bar",
+            Bytes::default(),
+            [[Some(0)].as_slice(); 0],
+        )
+        .await
+        .expect_err("non-idempotent invocation should not be retried");
+        assert_eq!(clt.inner.attempts.load(Ordering::Relaxed), 1);
+
+        clt.idempotent()
+            .invoke(
+                (),
+                "foo",
+                "bar",
+                Bytes::default(),
+                [[Some(0)].as_slice(); 0],
+            )
+            .await?;
+        assert_eq!(clt.inner.attempts.load(Ordering::Relaxed), 2);
+        Ok(())
+    }
+
     trait Handler {
         fn foo() -> impl Future<Output = anyhow::Result<()>>;
     }
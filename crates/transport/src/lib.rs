@@ -14,20 +14,25 @@
 //! - [`Invoke::Incoming`] and [`Serve::Incoming`] represent the stream *incoming* from a peer.
 //! - [`Invoke::Outgoing`] and [`Serve::Outgoing`] represent the stream *outgoing* to a peer.
 
+#[cfg(feature = "zstd")]
+pub mod compress;
 pub mod frame;
 pub mod invoke;
+pub mod record;
 pub mod serve;
+pub mod trace;
 
 mod value;
 
 pub use frame::{
-    Accept, Decoder as FrameDecoder, Encoder as FrameEncoder, Frame, FrameRef, Server,
+    Accept, Decoder as FrameDecoder, Encoder as FrameEncoder, Frame, FrameRef, ServeRouter, Server,
 };
 pub use invoke::{Invoke, InvokeExt};
 pub use send_future::SendFuture;
-pub use serve::{Serve, ServeExt};
+pub use serve::{RateLimit, RateLimitExceeded, RateLimitedServe, Serve, ServeExt};
 pub use value::*;
 
+pub use frame::memory;
 #[cfg(any(target_family = "wasm", feature = "net"))]
 pub use frame::tcp;
 #[cfg(all(unix, feature = "net"))]
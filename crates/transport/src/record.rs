@@ -0,0 +1,611 @@
+//! [Invoke] wrappers recording invocations and replaying recorded invocations, e.g. to test
+//! components against captured fixtures without the invoked peer present
+
+use core::marker::PhantomData;
+use core::pin::Pin;
+use core::task::{Context, Poll};
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{anyhow, bail, ensure, Context as _};
+use bytes::{Buf, BufMut as _, Bytes, BytesMut};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tracing::{instrument, trace};
+
+use crate::{Index, Invoke};
+
+/// A recorded invocation
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Invocation {
+    /// Instance name
+    pub instance: String,
+    /// Function name
+    pub func: String,
+    /// Parameters passed to [`Invoke::invoke`]
+    pub params: Bytes,
+    /// Bytes written to the outgoing stream, keyed by the structural path of the stream.
+    /// The path of the root stream is empty
+    pub outgoing: BTreeMap<Vec<usize>, Bytes>,
+    /// Bytes read from the incoming stream, keyed by the structural path of the stream.
+    /// The path of the root stream is empty
+    pub incoming: BTreeMap<Vec<usize>, Bytes>,
+}
+
+/// Invocations recorded by [`RecordingInvoke`] in the order they were performed
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Session {
+    /// Recorded invocations
+    pub invocations: Vec<Invocation>,
+}
+
+fn put_bytes(dst: &mut BytesMut, buf: &[u8]) -> anyhow::Result<()> {
+    let n = u32::try_from(buf.len()).context("length does not fit in u32")?;
+    dst.put_u32_le(n);
+    dst.put_slice(buf);
+    Ok(())
+}
+
+fn put_streams(dst: &mut BytesMut, streams: &BTreeMap<Vec<usize>, Bytes>) -> anyhow::Result<()> {
+    let n = u32::try_from(streams.len()).context("stream count does not fit in u32")?;
+    dst.put_u32_le(n);
+    for (path, buf) in streams {
+        let n = u32::try_from(path.len()).context("path length does not fit in u32")?;
+        dst.put_u32_le(n);
+        for i in path {
+            let i = u32::try_from(*i).context("path element does not fit in u32")?;
+            dst.put_u32_le(i);
+        }
+        put_bytes(dst, buf)?;
+    }
+    Ok(())
+}
+
+fn get_u32(buf: &mut impl Buf) -> anyhow::Result<u32> {
+    ensure!(buf.remaining() >= 4, "session truncated");
+    Ok(buf.get_u32_le())
+}
+
+fn get_bytes(buf: &mut impl Buf) -> anyhow::Result<Bytes> {
+    let n = get_u32(buf)?.try_into()?;
+    ensure!(buf.remaining() >= n, "session truncated");
+    Ok(buf.copy_to_bytes(n))
+}
+
+fn get_string(buf: &mut impl Buf) -> anyhow::Result<String> {
+    let s = get_bytes(buf)?;
+    String::from_utf8(s.into()).context("string is not valid UTF-8")
+}
+
+fn get_streams(buf: &mut impl Buf) -> anyhow::Result<BTreeMap<Vec<usize>, Bytes>> {
+    let n = get_u32(buf)?;
+    let mut streams = BTreeMap::default();
+    for _ in 0..n {
+        let n = get_u32(buf)?;
+        let path = (0..n)
+            .map(|_| -> anyhow::Result<usize> { Ok(get_u32(buf)?.try_into()?) })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        streams.insert(path, get_bytes(buf)?);
+    }
+    Ok(streams)
+}
+
+impl Session {
+    /// Encodes the session into `dst`
+    pub fn encode(&self, dst: &mut BytesMut) -> anyhow::Result<()> {
+        let n = u32::try_from(self.invocations.len())
+            .context("invocation count does not fit in u32")?;
+        dst.put_u32_le(n);
+        for Invocation {
+            instance,
+            func,
+            params,
+            outgoing,
+            incoming,
+        } in &self.invocations
+        {
+            put_bytes(dst, instance.as_bytes())?;
+            put_bytes(dst, func.as_bytes())?;
+            put_bytes(dst, params)?;
+            put_streams(dst, outgoing)?;
+            put_streams(dst, incoming)?;
+        }
+        Ok(())
+    }
+
+    /// Decodes a session encoded by [`Session::encode`]
+    pub fn decode(mut buf: impl Buf) -> anyhow::Result<Self> {
+        let n = get_u32(&mut buf)?;
+        let mut invocations = Vec::default();
+        for _ in 0..n {
+            invocations.push(Invocation {
+                instance: get_string(&mut buf)?,
+                func: get_string(&mut buf)?,
+                params: get_bytes(&mut buf)?,
+                outgoing: get_streams(&mut buf)?,
+                incoming: get_streams(&mut buf)?,
+            });
+        }
+        ensure!(!buf.has_remaining(), "trailing bytes after session");
+        Ok(Self { invocations })
+    }
+
+    /// Writes the encoded session to a file at `path`
+    #[cfg(feature = "fs")]
+    pub async fn save(&self, path: impl AsRef<std::path::Path>) -> anyhow::Result<()> {
+        let path = path.as_ref();
+        let mut buf = BytesMut::default();
+        self.encode(&mut buf)?;
+        tokio::fs::write(path, buf)
+            .await
+            .with_context(|| format!("failed to write session to `{}`", path.display()))
+    }
+
+    /// Reads a session written by [`Session::save`] from a file at `path`
+    #[cfg(feature = "fs")]
+    pub async fn load(path: impl AsRef<std::path::Path>) -> anyhow::Result<Self> {
+        let path = path.as_ref();
+        let buf = tokio::fs::read(path)
+            .await
+            .with_context(|| format!("failed to read session from `{}`", path.display()))?;
+        Self::decode(Bytes::from(buf))
+            .with_context(|| format!("failed to decode session from `{}`", path.display()))
+    }
+}
+
+/// Invocation being recorded, shared by all of its streams
+#[derive(Debug, Default)]
+struct Recorded {
+    instance: String,
+    func: String,
+    params: Bytes,
+    outgoing: BTreeMap<Vec<usize>, BytesMut>,
+    incoming: BTreeMap<Vec<usize>, BytesMut>,
+}
+
+impl From<&Recorded> for Invocation {
+    fn from(
+        Recorded {
+            instance,
+            func,
+            params,
+            outgoing,
+            incoming,
+        }: &Recorded,
+    ) -> Self {
+        let streams = |streams: &BTreeMap<Vec<usize>, BytesMut>| -> BTreeMap<_, _> {
+            streams
+                .iter()
+                .map(|(path, buf)| (path.clone(), Bytes::copy_from_slice(buf)))
+                .collect()
+        };
+        Self {
+            instance: instance.clone(),
+            func: func.clone(),
+            params: params.clone(),
+            outgoing: streams(outgoing),
+            incoming: streams(incoming),
+        }
+    }
+}
+
+/// [Invoke] wrapper, which records all invocations performed using the inner [Invoke]
+/// along with all bytes transmitted over their streams.
+///
+/// Recorded invocations can be retrieved as a [`Session`] using [`RecordingInvoke::session`]
+/// and replayed using [`ReplayInvoke`].
+#[derive(Clone, Debug)]
+pub struct RecordingInvoke<T> {
+    /// Inner [Invoke]
+    pub inner: T,
+    invocations: Arc<Mutex<Vec<Arc<Mutex<Recorded>>>>>,
+}
+
+impl<T> RecordingInvoke<T> {
+    /// Wraps `inner`
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            invocations: Arc::default(),
+        }
+    }
+
+    /// Returns all invocations recorded so far.
+    ///
+    /// Bytes transmitted over streams of the invocations after this method returns are not
+    /// included, so this should be called once all streams are done.
+    pub fn session(&self) -> anyhow::Result<Session> {
+        let invocations = self
+            .invocations
+            .lock()
+            .map_err(|err| anyhow!(err.to_string()))?;
+        let invocations = invocations
+            .iter()
+            .map(|invocation| {
+                let invocation = invocation.lock().map_err(|err| anyhow!(err.to_string()))?;
+                Ok(Invocation::from(&*invocation))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        Ok(Session { invocations })
+    }
+}
+
+impl<T: Invoke> Invoke for RecordingInvoke<T> {
+    type Context = T::Context;
+    type Outgoing = RecordingOutgoing<T::Outgoing>;
+    type Incoming = RecordingIncoming<T::Incoming>;
+
+    #[instrument(level = "trace", skip(self, cx, params, paths))]
+    async fn invoke<P>(
+        &self,
+        cx: Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let (tx, rx) = self
+            .inner
+            .invoke(cx, instance, func, params.clone(), paths)
+            .await?;
+        let recorded = Arc::new(Mutex::new(Recorded {
+            instance: instance.to_string(),
+            func: func.to_string(),
+            params,
+            ..Recorded::default()
+        }));
+        self.invocations
+            .lock()
+            .map_err(|err| anyhow!(err.to_string()))?
+            .push(Arc::clone(&recorded));
+        trace!("recording invocation");
+        Ok((
+            RecordingOutgoing {
+                inner: tx,
+                path: Vec::default(),
+                recorded: Arc::clone(&recorded),
+            },
+            RecordingIncoming {
+                inner: rx,
+                path: Vec::default(),
+                recorded,
+            },
+        ))
+    }
+}
+
+/// Outgoing stream of [`RecordingInvoke`]
+pub struct RecordingOutgoing<T> {
+    inner: T,
+    path: Vec<usize>,
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+impl<T: Index<T>> Index<Self> for RecordingOutgoing<T> {
+    fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: self.inner.index(path)?,
+            path: [self.path.as_slice(), path].concat(),
+            recorded: Arc::clone(&self.recorded),
+        })
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for RecordingOutgoing<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let n = match Pin::new(&mut self.inner).poll_write(cx, buf) {
+            Poll::Ready(Ok(n)) => n,
+            other => return other,
+        };
+        let mut recorded = self
+            .recorded
+            .lock()
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+        recorded
+            .outgoing
+            .entry(self.path.clone())
+            .or_default()
+            .extend_from_slice(&buf[..n]);
+        Poll::Ready(Ok(n))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+/// Incoming stream of [`RecordingInvoke`]
+pub struct RecordingIncoming<T> {
+    inner: T,
+    path: Vec<usize>,
+    recorded: Arc<Mutex<Recorded>>,
+}
+
+impl<T: Index<T>> Index<Self> for RecordingIncoming<T> {
+    fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+        Ok(Self {
+            inner: self.inner.index(path)?,
+            path: [self.path.as_slice(), path].concat(),
+            recorded: Arc::clone(&self.recorded),
+        })
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for RecordingIncoming<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let n = buf.filled().len();
+        match Pin::new(&mut self.inner).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+        let mut recorded = self
+            .recorded
+            .lock()
+            .map_err(|err| std::io::Error::other(err.to_string()))?;
+        recorded
+            .incoming
+            .entry(self.path.clone())
+            .or_default()
+            .extend_from_slice(&buf.filled()[n..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// [Invoke] implementation, which replays invocations recorded by [`RecordingInvoke`].
+///
+/// Each invocation is matched against the first recorded invocation of the same function
+/// with identical parameters, which was not replayed yet. The incoming streams of the
+/// invocation yield the recorded bytes, while bytes written to the outgoing streams are
+/// discarded.
+#[derive(Debug)]
+pub struct ReplayInvoke<C = ()> {
+    invocations: Mutex<VecDeque<Invocation>>,
+    context: PhantomData<fn() -> C>,
+}
+
+impl<C> ReplayInvoke<C> {
+    /// Returns the number of recorded invocations, which were not replayed yet
+    #[must_use]
+    pub fn remaining(&self) -> usize {
+        self.invocations
+            .lock()
+            .map(|invocations| invocations.len())
+            .unwrap_or_default()
+    }
+}
+
+impl<C> From<Session> for ReplayInvoke<C> {
+    fn from(Session { invocations }: Session) -> Self {
+        Self {
+            invocations: Mutex::new(invocations.into()),
+            context: PhantomData,
+        }
+    }
+}
+
+impl<C: Send + Sync> Invoke for ReplayInvoke<C> {
+    type Context = C;
+    type Outgoing = ReplayOutgoing;
+    type Incoming = ReplayIncoming;
+
+    #[instrument(level = "trace", skip(self, _cx, params, _paths))]
+    async fn invoke<P>(
+        &self,
+        _cx: Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        _paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let Invocation { incoming, .. } = {
+            let mut invocations = self
+                .invocations
+                .lock()
+                .map_err(|err| anyhow!(err.to_string()))?;
+            let Some(i) = invocations.iter().position(|invocation| {
+                invocation.instance == instance
+                    && invocation.func == func
+                    && invocation.params == params
+            }) else {
+                bail!("no recorded invocation of `{instance}#{func}` matches the parameters")
+            };
+            invocations
+                .remove(i)
+                .context("recorded invocation not found")?
+        };
+        trace!("replaying invocation");
+        let buf = incoming.get::<[usize]>(&[]).cloned().unwrap_or_default();
+        Ok((
+            ReplayOutgoing,
+            ReplayIncoming {
+                incoming: Arc::new(incoming),
+                path: Vec::default(),
+                buf,
+            },
+        ))
+    }
+}
+
+/// Outgoing stream of [`ReplayInvoke`], which discards all bytes written to it
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ReplayOutgoing;
+
+impl Index<Self> for ReplayOutgoing {
+    fn index(&self, _: &[usize]) -> anyhow::Result<Self> {
+        Ok(Self)
+    }
+}
+
+impl AsyncWrite for ReplayOutgoing {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Incoming stream of [`ReplayInvoke`], which yields the recorded bytes
+#[derive(Clone, Debug)]
+pub struct ReplayIncoming {
+    incoming: Arc<BTreeMap<Vec<usize>, Bytes>>,
+    path: Vec<usize>,
+    buf: Bytes,
+}
+
+impl Index<Self> for ReplayIncoming {
+    fn index(&self, path: &[usize]) -> anyhow::Result<Self> {
+        let path = [self.path.as_slice(), path].concat();
+        let buf = self.incoming.get(&path).cloned().unwrap_or_default();
+        Ok(Self {
+            incoming: Arc::clone(&self.incoming),
+            path,
+            buf,
+        })
+    }
+}
+
+impl AsyncRead for ReplayIncoming {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let n = buf.remaining().min(self.buf.len());
+        buf.put_slice(&self.buf.split_to(n));
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::{stream, Stream, StreamExt as _, TryStreamExt as _};
+
+    use super::*;
+    use crate::{memory, InvokeExt as _, ServeExt as _, Server};
+
+    type Items = Pin<Box<dyn Stream<Item = Vec<u32>> + Send>>;
+
+    /// Invokes `double` with `21` and `count`, returning the results
+    async fn invoke(clt: &impl Invoke<Context = ()>) -> anyhow::Result<(u32, Vec<u32>)> {
+        let (n,) = clt
+            .invoke_values_blocking::<_, _, (u32,)>(
+                (),
+                "test:test/iface",
+                "double",
+                (21,),
+                &[[]; 0],
+            )
+            .await?;
+        let ((items,), io) = clt
+            .invoke_values::<_, _, (Items,)>((), "test:test/iface", "count", (), &[[Some(0)]])
+            .await?;
+        let (items, io) = tokio::join!(items.collect::<Vec<_>>(), async {
+            if let Some(io) = io {
+                io.await
+            } else {
+                Ok(())
+            }
+        });
+        io?;
+        Ok((n, items.concat()))
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn record_replay() -> anyhow::Result<()> {
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let doubles = srv
+            .serve_values::<(u32,), (u32,)>(
+                "test:test/iface",
+                "double",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let counts = srv
+            .serve_values::<(), (Items,)>(
+                "test:test/iface",
+                "count",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let clt = RecordingInvoke::new(clt);
+        let ((), (), recorded) = tokio::try_join!(
+            async {
+                for _ in 0..2 {
+                    srv.accept(&lis).await?;
+                }
+                anyhow::Ok(())
+            },
+            async {
+                let mut doubles = pin!(doubles);
+                let ((), (n,), _, tx) =
+                    doubles.try_next().await?.expect("unexpected end of stream");
+                tx((n * 2,)).await?;
+                let mut counts = pin!(counts);
+                let ((), (), _, tx) = counts.try_next().await?.expect("unexpected end of stream");
+                let items: Items = Box::pin(stream::iter([vec![1, 2], vec![3]]));
+                tx((items,)).await?;
+                anyhow::Ok(())
+            },
+            invoke(&clt),
+        )?;
+        assert_eq!(recorded, (42, vec![1, 2, 3]));
+
+        let session = clt.session()?;
+        let [double, count] = &session.invocations[..] else {
+            panic!("exactly two invocations should have been recorded");
+        };
+        assert_eq!(double.func, "double");
+        assert_eq!(count.func, "count");
+        assert!(
+            count
+                .incoming
+                .get(&vec![0])
+                .is_some_and(|buf| !buf.is_empty()),
+            "deferred stream bytes should have been recorded"
+        );
+
+        let mut buf = BytesMut::default();
+        session.encode(&mut buf)?;
+        let decoded = Session::decode(buf.freeze())?;
+        assert_eq!(decoded, session);
+
+        let replay = ReplayInvoke::from(decoded);
+        assert_eq!(invoke(&replay).await?, recorded);
+        assert_eq!(replay.remaining(), 0);
+        invoke(&replay)
+            .await
+            .expect_err("replayed invocations should be consumed");
+        Ok(())
+    }
+}
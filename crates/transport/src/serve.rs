@@ -1,14 +1,19 @@
 //! wRPC transport server handle
 
+use core::fmt;
 use core::future::Future;
+use core::hash::Hash;
 use core::mem;
 use core::pin::Pin;
+use core::time::Duration;
 
+use std::collections::HashMap;
 use std::sync::Arc;
 
-use anyhow::{bail, Context as _};
-use futures::{SinkExt as _, Stream, TryStreamExt as _};
+use anyhow::{anyhow, bail, Context as _};
+use futures::{future, SinkExt as _, Stream, TryStreamExt as _};
 use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt as _};
+use tokio::time::Instant;
 use tokio_util::codec::{FramedRead, FramedWrite};
 use tracing::{debug, instrument, trace, Instrument as _, Span};
 
@@ -141,13 +146,162 @@ pub trait ServeExt: Serve {
 
 impl<T: Serve> ServeExt for T {}
 
+/// Token bucket configuration of [`RateLimitedServe`]
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimit {
+    /// Maximum number of invocations accepted from a single peer in a burst
+    pub burst: u32,
+    /// Time it takes for a single invocation to be added back to the budget of a peer
+    pub interval: Duration,
+}
+
+/// Error yielded by [`RateLimitedServe`] for invocations exceeding the budget of the peer
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RateLimitExceeded;
+
+impl fmt::Display for RateLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("peer exceeded invocation rate limit")
+    }
+}
+
+impl std::error::Error for RateLimitExceeded {}
+
+/// Invocation budget of a single peer
+struct TokenBucket {
+    tokens: u32,
+    refilled: Instant,
+}
+
+impl TokenBucket {
+    /// Refills the bucket according to `limit` and takes a single token from it, if available
+    fn try_take(&mut self, limit: &RateLimit, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.refilled);
+        if limit.interval.is_zero() {
+            self.tokens = limit.burst;
+            self.refilled = now;
+        } else {
+            let n = elapsed.as_nanos() / limit.interval.as_nanos();
+            match u32::try_from(n) {
+                Ok(n) if self.tokens.saturating_add(n) < limit.burst => {
+                    self.tokens += n;
+                    self.refilled += limit.interval * n;
+                }
+                _ => {
+                    self.tokens = limit.burst;
+                    self.refilled = now;
+                }
+            }
+        }
+        if let Some(tokens) = self.tokens.checked_sub(1) {
+            self.tokens = tokens;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Returns `true` if the bucket would be full at `now`
+    fn is_full(&self, limit: &RateLimit, now: Instant) -> bool {
+        let missing = limit.burst.saturating_sub(self.tokens);
+        now.saturating_duration_since(self.refilled) >= limit.interval.saturating_mul(missing)
+    }
+}
+
+/// [Serve] wrapper, which limits the rate of invocations accepted from each peer using a
+/// token bucket per peer.
+///
+/// Peers are identified by a key derived from the transport-specific [`Serve::Context`] of each
+/// invocation, e.g. the address or the authenticated identity of the peer.
+/// Invocations exceeding the budget of a peer are rejected by dropping their streams and
+/// yielding a [`RateLimitExceeded`] error in the invocation stream, invocations of other peers
+/// are not affected. The budget of a peer is shared by all functions served by the wrapper.
+pub struct RateLimitedServe<T: Serve, K> {
+    inner: T,
+    limit: RateLimit,
+    key: Arc<dyn Fn(&T::Context) -> K + Send + Sync>,
+    buckets: Arc<std::sync::Mutex<HashMap<K, TokenBucket>>>,
+}
+
+impl<T: Serve, K> RateLimitedServe<T, K> {
+    /// Wraps `inner` limiting invocations of each peer identified by `key` to `limit`
+    pub fn new(
+        inner: T,
+        limit: RateLimit,
+        key: impl Fn(&T::Context) -> K + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            inner,
+            limit,
+            key: Arc::new(key),
+            buckets: Arc::default(),
+        }
+    }
+}
+
+impl<T, K> Serve for RateLimitedServe<T, K>
+where
+    T: Serve,
+    K: Eq + Hash + Send + 'static,
+{
+    type Context = T::Context;
+    type Outgoing = T::Outgoing;
+    type Incoming = T::Incoming;
+
+    #[instrument(level = "trace", skip(self, paths))]
+    async fn serve(
+        &self,
+        instance: &str,
+        func: &str,
+        paths: impl Into<Arc<[Box<[Option<usize>]>]>> + Send,
+    ) -> anyhow::Result<
+        impl Stream<Item = anyhow::Result<(Self::Context, Self::Outgoing, Self::Incoming)>>
+            + Send
+            + 'static,
+    > {
+        let invocations = self.inner.serve(instance, func, paths).await?;
+        let limit = self.limit;
+        let key = Arc::clone(&self.key);
+        let buckets = Arc::clone(&self.buckets);
+        Ok(invocations.and_then(move |(cx, tx, rx)| {
+            let peer = key(&cx);
+            let now = Instant::now();
+            let accepted = buckets.lock().map(|mut buckets| {
+                if !buckets.contains_key(&peer) {
+                    // evict budgets of idle peers, which are indistinguishable from new ones
+                    buckets.retain(|_, bucket| !bucket.is_full(&limit, now));
+                }
+                buckets
+                    .entry(peer)
+                    .or_insert_with(|| TokenBucket {
+                        tokens: limit.burst,
+                        refilled: now,
+                    })
+                    .try_take(&limit, now)
+            });
+            future::ready(match accepted {
+                Ok(true) => Ok((cx, tx, rx)),
+                Ok(false) => {
+                    debug!("rejecting invocation exceeding the peer rate limit");
+                    Err(RateLimitExceeded.into())
+                }
+                Err(err) => Err(anyhow!(err.to_string()).context("failed to lock rate limits")),
+            })
+        }))
+    }
+}
+
 #[allow(dead_code)]
 #[cfg(test)]
 mod tests {
     use bytes::Bytes;
     use futures::{stream, StreamExt as _, TryStreamExt as _};
+    use tokio::io::{DuplexStream, ReadHalf, WriteHalf};
+
+    use core::pin::pin;
 
-    use crate::Captures;
+    use crate::frame::{memory, AcceptExt as _};
+    use crate::{Captures, Invoke as _, Server};
 
     use super::*;
 
@@ -222,4 +376,67 @@ mod tests {
             })) as Pin<Box<dyn Stream<Item = _>>>)
         }
     }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn rate_limited() -> anyhow::Result<()> {
+        let (clt_a, lis_a) = memory::pair(1024);
+        let (clt_b, lis_b) = memory::pair(1024);
+        let lis_a = lis_a.map_context((|()| "a") as fn(()) -> &'static str);
+        let lis_b = lis_b.map_context((|()| "b") as fn(()) -> &'static str);
+        let srv =
+            Server::<&'static str, ReadHalf<DuplexStream>, WriteHalf<DuplexStream>>::default();
+        let limited = RateLimitedServe::new(
+            &srv,
+            RateLimit {
+                burst: 2,
+                interval: Duration::from_secs(3600),
+            },
+            |peer: &&'static str| *peer,
+        );
+        let invocations = limited
+            .serve("test:test/foo", "f", Vec::<Box<[Option<usize>]>>::default())
+            .await?;
+        let mut invocations = pin!(invocations);
+        for (peer, accepted) in [
+            ("a", true),
+            ("a", true),
+            ("a", false),
+            ("b", true),
+            ("b", true),
+            ("a", false),
+        ] {
+            let (clt, lis) = if peer == "a" {
+                (&clt_a, &lis_a)
+            } else {
+                (&clt_b, &lis_b)
+            };
+            let ((), invocation, ()) = tokio::try_join!(
+                async { srv.accept(lis).await.context("failed to accept invocation") },
+                async { invocations.next().await.context("unexpected end of stream") },
+                async {
+                    let (mut tx, _rx) = clt
+                        .invoke(
+                            (),
+                            "test:test/foo",
+                            "f",
+                            Bytes::from("test"),
+                            [[Some(0)].as_slice(); 0],
+                        )
+                        .await?;
+                    tx.shutdown().await?;
+                    anyhow::Ok(())
+                },
+            )?;
+            if accepted {
+                let (cx, _, _) = invocation?;
+                assert_eq!(cx, peer);
+            } else {
+                let err = invocation
+                    .err()
+                    .expect("invocation exceeding the rate limit should be rejected");
+                assert!(err.is::<RateLimitExceeded>());
+            }
+        }
+        Ok(())
+    }
 }
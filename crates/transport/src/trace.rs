@@ -0,0 +1,442 @@
+//! [Invoke] and [Serve] wrappers propagating [W3C trace context](https://www.w3.org/TR/trace-context/)
+//! from invokers to servers, e.g. to bridge wRPC invocations into existing distributed tracing
+//! backends, like OpenTelemetry.
+//!
+//! Transports do not necessarily provide a metadata channel, so the trace context is transmitted
+//! as a header preceding the parameters, see [`encode_trace_context`]. This changes the wire
+//! format, so both peers must be configured to use it: invocations performed using
+//! [`TracePropagatingInvoke`] can only be served using [`TracePropagatingServe`] and vice versa.
+//!
+//! The trace context of an invocation is passed along with the context of the inner transport.
+//! On the serving side, it can be used to set the parent of the span used to instrument the
+//! handling of the invocation, e.g. `wrpc_runtime_wasmtime::ServeExt::serve_function` does so
+//! for the trace context returned by `wrpc_runtime_wasmtime::WrpcView::serve_trace_context`.
+//!
+//! Within a task, the trace context of the span currently handling an invocation can be set
+//! using [`TraceContext::scope`]. [`TracePropagatingInvoke`] propagates it to the peer of nested
+//! invocations, which are performed without an explicit trace context.
+
+use core::fmt;
+use core::future::Future;
+use core::hash::{BuildHasher as _, Hasher as _};
+use core::str::FromStr;
+use core::sync::atomic::{AtomicU64, Ordering};
+use core::time::Duration;
+
+use std::collections::hash_map::RandomState;
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context as _};
+use bytes::{BufMut as _, Bytes, BytesMut};
+use futures::{Stream, StreamExt as _};
+use tokio::io::AsyncRead;
+use tokio::task::futures::TaskLocalFuture;
+use tokio_util::codec::Encoder as _;
+use tracing::instrument;
+use wasm_tokio::cm::AsyncReadValue as _;
+use wasm_tokio::{AsyncReadCore as _, CoreNameEncoder};
+
+use crate::{Invoke, Serve};
+
+/// Default [`TracePropagatingServe::header_timeout`]
+pub const DEFAULT_HEADER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Default [`TracePropagatingServe::max_pending_headers`]
+pub const DEFAULT_MAX_PENDING_HEADERS: usize = 256;
+
+tokio::task_local! {
+    static CURRENT: TraceContext;
+}
+
+/// W3C trace context of an invocation, as carried by the `traceparent` HTTP header
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct TraceContext {
+    /// ID of the whole trace, must not be zero
+    pub trace_id: u128,
+    /// ID of the parent span, i.e. the span of the invoker, must not be zero
+    pub span_id: u64,
+    /// Trace flags
+    pub flags: u8,
+}
+
+impl TraceContext {
+    /// Flag set if the invoker may have recorded the trace
+    pub const FLAG_SAMPLED: u8 = 0x01;
+
+    /// Returns whether [`Self::FLAG_SAMPLED`] is set
+    #[must_use]
+    pub fn is_sampled(&self) -> bool {
+        self.flags & Self::FLAG_SAMPLED != 0
+    }
+
+    /// Returns the trace context of the current task, if it is executed within
+    /// [`Self::scope`]
+    #[must_use]
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|cx| *cx).ok()
+    }
+
+    /// Executes `fut` with `self` as the [`Self::current`] trace context
+    pub fn scope<F: Future>(self, fut: F) -> TaskLocalFuture<Self, F> {
+        CURRENT.scope(self, fut)
+    }
+
+    /// Returns the trace context of a new span with a random ID within the same trace, which
+    /// is a child of the span of `self`
+    #[must_use]
+    pub fn child(&self) -> Self {
+        static SEQ: AtomicU64 = AtomicU64::new(0);
+
+        let mut h = RandomState::new().build_hasher();
+        h.write_u64(SEQ.fetch_add(1, Ordering::Relaxed));
+        Self {
+            span_id: h.finish().max(1),
+            ..*self
+        }
+    }
+}
+
+/// Formats the trace context as a version `00` `traceparent` header value
+impl fmt::Display for TraceContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id, self.span_id, self.flags
+        )
+    }
+}
+
+/// Parses a `traceparent` header value. Fields appended by future versions are ignored.
+impl FromStr for TraceContext {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        fn parse_hex(s: &str, len: usize, field: &str) -> anyhow::Result<u128> {
+            ensure!(
+                s.len() == len && s.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f')),
+                "`{field}` must consist of {len} lowercase hexadecimal digits"
+            );
+            u128::from_str_radix(s, 16).with_context(|| format!("failed to parse `{field}`"))
+        }
+
+        let mut fields = s.split('-');
+        let (Some(version), Some(trace_id), Some(span_id), Some(flags)) =
+            (fields.next(), fields.next(), fields.next(), fields.next())
+        else {
+            bail!("`traceparent` must consist of at least 4 fields");
+        };
+        let version = parse_hex(version, 2, "version")?;
+        ensure!(version != 0xff, "version `ff` is invalid");
+        ensure!(
+            version != 0 || fields.next().is_none(),
+            "`traceparent` of version `00` must consist of exactly 4 fields"
+        );
+        let trace_id = parse_hex(trace_id, 32, "trace-id")?;
+        ensure!(trace_id != 0, "`trace-id` must not be zero");
+        let span_id = parse_hex(span_id, 16, "parent-id")?;
+        ensure!(span_id != 0, "`parent-id` must not be zero");
+        let flags = parse_hex(flags, 2, "trace-flags")?;
+        Ok(Self {
+            trace_id,
+            span_id: span_id.try_into()?,
+            flags: flags.try_into()?,
+        })
+    }
+}
+
+/// Encodes the trace context header as an `option<string>` containing the `traceparent`
+/// header value, where `none` indicates that no trace context is propagated
+pub fn encode_trace_context(cx: Option<&TraceContext>, dst: &mut BytesMut) -> anyhow::Result<()> {
+    let Some(cx) = cx else {
+        dst.put_u8(0);
+        return Ok(());
+    };
+    dst.put_u8(1);
+    CoreNameEncoder
+        .encode(cx.to_string().as_str(), dst)
+        .context("failed to encode trace context")
+}
+
+/// Reads the trace context header encoded by [`encode_trace_context`]
+pub async fn read_trace_context(
+    mut r: impl AsyncRead + Unpin,
+) -> anyhow::Result<Option<TraceContext>> {
+    if !r.read_option_status().await? {
+        return Ok(None);
+    }
+    let mut s = String::default();
+    r.read_core_name(&mut s).await?;
+    s.parse().map(Some)
+}
+
+/// [Invoke] wrapper, which propagates the [`TraceContext`] passed along with the context of
+/// each invocation to the peer. If no trace context is passed, [`TraceContext::current`]
+/// is propagated.
+///
+/// Invocations must be served using [`TracePropagatingServe`].
+#[derive(Clone, Copy, Debug)]
+pub struct TracePropagatingInvoke<T> {
+    /// Inner [Invoke]
+    pub inner: T,
+}
+
+impl<T> TracePropagatingInvoke<T> {
+    /// Wraps `inner`
+    pub fn new(inner: T) -> Self {
+        Self { inner }
+    }
+}
+
+impl<T: Invoke> Invoke for TracePropagatingInvoke<T> {
+    type Context = (Option<TraceContext>, T::Context);
+    type Outgoing = T::Outgoing;
+    type Incoming = T::Incoming;
+
+    #[instrument(level = "trace", skip(self, cx, params, paths))]
+    async fn invoke<P>(
+        &self,
+        cx: Self::Context,
+        instance: &str,
+        func: &str,
+        params: Bytes,
+        paths: impl AsRef<[P]> + Send,
+    ) -> anyhow::Result<(Self::Outgoing, Self::Incoming)>
+    where
+        P: AsRef<[Option<usize>]> + Send + Sync,
+    {
+        let (trace, cx) = cx;
+        let trace = trace.or_else(TraceContext::current);
+        // option status, length and the 55 bytes of a version `00` `traceparent`
+        let mut buf = BytesMut::with_capacity(params.len().saturating_add(57));
+        encode_trace_context(trace.as_ref(), &mut buf)?;
+        buf.extend_from_slice(&params);
+        self.inner
+            .invoke(cx, instance, func, buf.freeze(), paths)
+            .await
+    }
+}
+
+/// [Serve] wrapper, which extracts the [`TraceContext`] propagated by the peer and passes it
+/// along with the context of each invocation.
+///
+/// The trace context header is read before an invocation is yielded by the invocation stream.
+/// Headers of up to [`Self::max_pending_headers`] invocations are read concurrently, each
+/// within [`Self::header_timeout`], so that a peer stalling the transmission of the header
+/// does not block other invocations. Invocations are yielded in the order in which the headers
+/// are received.
+///
+/// Invocations must be performed using [`TracePropagatingInvoke`].
+#[derive(Clone, Copy, Debug)]
+pub struct TracePropagatingServe<T> {
+    /// Inner [Serve]
+    pub inner: T,
+    /// Maximum duration to wait for the trace context header of an invocation, after which
+    /// the invocation stream yields an error
+    pub header_timeout: Duration,
+    /// Maximum number of invocations, for which the trace context header is read concurrently
+    pub max_pending_headers: usize,
+}
+
+impl<T> TracePropagatingServe<T> {
+    /// Wraps `inner` using [`DEFAULT_HEADER_TIMEOUT`] and [`DEFAULT_MAX_PENDING_HEADERS`]
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            header_timeout: DEFAULT_HEADER_TIMEOUT,
+            max_pending_headers: DEFAULT_MAX_PENDING_HEADERS,
+        }
+    }
+}
+
+impl<T: Serve> Serve for TracePropagatingServe<T> {
+    type Context = (Option<TraceContext>, T::Context);
+    type Outgoing = T::Outgoing;
+    type Incoming = T::Incoming;
+
+    #[instrument(level = "trace", skip(self, paths))]
+    async fn serve(
+        &self,
+        instance: &str,
+        func: &str,
+        paths: impl Into<Arc<[Box<[Option<usize>]>]>> + Send,
+    ) -> anyhow::Result<
+        impl Stream<Item = anyhow::Result<(Self::Context, Self::Outgoing, Self::Incoming)>>
+            + Send
+            + 'static,
+    > {
+        let invocations = self.inner.serve(instance, func, paths).await?;
+        let timeout = self.header_timeout;
+        Ok(invocations
+            .map(move |invocation| async move {
+                let (cx, tx, mut rx) = invocation?;
+                let trace = tokio::time::timeout(timeout, read_trace_context(&mut rx))
+                    .await
+                    .context("timed out reading trace context header")?
+                    .context("failed to read trace context header")?;
+                anyhow::Ok(((trace, cx), tx, rx))
+            })
+            .buffer_unordered(self.max_pending_headers))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use futures::TryStreamExt as _;
+
+    use super::*;
+    use crate::{memory, InvokeExt as _, ServeExt as _, Server};
+
+    #[test]
+    fn traceparent() -> anyhow::Result<()> {
+        let s = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+        let cx: TraceContext = s.parse()?;
+        assert_eq!(cx.trace_id, 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736);
+        assert_eq!(cx.span_id, 0x00f0_67aa_0ba9_02b7);
+        assert!(cx.is_sampled());
+        assert_eq!(cx.to_string(), s);
+
+        assert!("00-00000000000000000000000000000000-00f067aa0ba902b7-01"
+            .parse::<TraceContext>()
+            .is_err());
+        assert!("00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01"
+            .parse::<TraceContext>()
+            .is_err());
+        assert!("ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01"
+            .parse::<TraceContext>()
+            .is_err());
+        assert!("00-4BF92F3577B34DA6A3CE929D0E0E4736-00f067aa0ba902b7-01"
+            .parse::<TraceContext>()
+            .is_err());
+        assert!("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-00"
+            .parse::<TraceContext>()
+            .is_err());
+        let cx: TraceContext =
+            "01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00-future".parse()?;
+        assert!(!cx.is_sampled());
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn propagate() -> anyhow::Result<()> {
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let invocations = TracePropagatingServe::new(&srv)
+            .serve_values::<(String,), (String,)>(
+                "test:test/iface",
+                "echo",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let clt = TracePropagatingInvoke::new(clt);
+        let trace = TraceContext {
+            trace_id: 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736,
+            span_id: 0x00f0_67aa_0ba9_02b7,
+            flags: TraceContext::FLAG_SAMPLED,
+        };
+        let current = trace.child();
+        let mut invocations = pin!(invocations);
+        for (cx, scope, expected) in [
+            (Some(trace), None, Some(trace)),
+            (None, None, None),
+            (None, Some(current), Some(current)),
+            (Some(trace), Some(current), Some(trace)),
+        ] {
+            let invocation = clt.invoke_values_blocking::<_, _, (String,)>(
+                (cx, ()),
+                "test:test/iface",
+                "echo",
+                ("foo",),
+                &[[]; 0],
+            );
+            let ((), received, (echoed,)) = tokio::try_join!(
+                async {
+                    srv.accept(&lis).await?;
+                    anyhow::Ok(())
+                },
+                async {
+                    let ((received, ()), (s,), _, tx) = invocations
+                        .try_next()
+                        .await?
+                        .expect("unexpected end of stream");
+                    tx((s,)).await?;
+                    anyhow::Ok(received)
+                },
+                async {
+                    if let Some(scope) = scope {
+                        scope.scope(invocation).await
+                    } else {
+                        invocation.await
+                    }
+                },
+            )?;
+            assert_eq!(received, expected);
+            assert_eq!(echoed, "foo");
+        }
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn stalled_header() -> anyhow::Result<()> {
+        let (clt, lis) = memory::pair(1024);
+        let srv = Server::default();
+        let invocations = TracePropagatingServe::new(&srv)
+            .serve_values::<(String,), (String,)>(
+                "test:test/iface",
+                "echo",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let mut invocations = pin!(invocations);
+
+        // invocation, which never transmits the trace context header
+        let ((), stalled) = tokio::try_join!(
+            async {
+                srv.accept(&lis).await?;
+                anyhow::Ok(())
+            },
+            clt.invoke((), "test:test/iface", "echo", Bytes::default(), &[[]; 0]),
+        )?;
+
+        let trace = TraceContext {
+            trace_id: 0x4bf9_2f35_77b3_4da6_a3ce_929d_0e0e_4736,
+            span_id: 0x00f0_67aa_0ba9_02b7,
+            flags: TraceContext::FLAG_SAMPLED,
+        };
+        let clt = TracePropagatingInvoke::new(clt);
+        let ((), received, (echoed,)) = tokio::try_join!(
+            async {
+                srv.accept(&lis).await?;
+                anyhow::Ok(())
+            },
+            async {
+                let ((received, ()), (s,), _, tx) =
+                    tokio::time::timeout(Duration::from_secs(5), invocations.try_next())
+                        .await
+                        .context("invocation blocked by stalled header")??
+                        .expect("unexpected end of stream");
+                tx((s,)).await?;
+                anyhow::Ok(received)
+            },
+            clt.invoke_values_blocking::<_, _, (String,)>(
+                (Some(trace), ()),
+                "test:test/iface",
+                "echo",
+                ("foo",),
+                &[[]; 0],
+            ),
+        )?;
+        assert_eq!(received, Some(trace));
+        assert_eq!(echoed, "foo");
+
+        // the header of the stalled invocation cannot be read once it is closed
+        drop(stalled);
+        invocations
+            .try_next()
+            .await
+            .expect_err("reading header of closed invocation should fail");
+        Ok(())
+    }
+}
@@ -1244,6 +1244,92 @@ impl<T: ?Sized> tokio_util::codec::Decoder for ResourceOwnDecoder<T> {
     }
 }
 
+/// Component model `error-context` value, transmitted as its debug message
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+#[repr(transparent)]
+pub struct ErrorContext(pub String);
+
+impl ErrorContext {
+    /// Returns the debug message of the error context
+    #[must_use]
+    pub fn debug_message(&self) -> &str {
+        &self.0
+    }
+}
+
+impl From<String> for ErrorContext {
+    fn from(debug_message: String) -> Self {
+        Self(debug_message)
+    }
+}
+
+impl From<&str> for ErrorContext {
+    fn from(debug_message: &str) -> Self {
+        Self(debug_message.into())
+    }
+}
+
+impl From<ErrorContext> for String {
+    fn from(ErrorContext(debug_message): ErrorContext) -> Self {
+        debug_message
+    }
+}
+
+/// Codec for `error-context` types, encoding the debug message as a `string`
+#[derive(Debug, Default)]
+#[repr(transparent)]
+pub struct ErrorContextCodec(CoreNameDecoder);
+
+impl_deferred_sync!(ErrorContextCodec);
+
+impl<R> Deferred<Incoming<R>> for CoreVecDecoder<ErrorContextCodec> {
+    fn take_deferred(&mut self) -> Option<DeferredFn<Incoming<R>>> {
+        None
+    }
+}
+
+impl tokio_util::codec::Encoder<ErrorContext> for ErrorContextCodec {
+    type Error = std::io::Error;
+
+    #[instrument(level = "trace", skip(self, item), ret, fields(ty = "error-context"))]
+    fn encode(&mut self, item: ErrorContext, dst: &mut BytesMut) -> std::io::Result<()> {
+        CoreNameEncoder.encode(item.0, dst)
+    }
+}
+
+impl tokio_util::codec::Encoder<&ErrorContext> for ErrorContextCodec {
+    type Error = std::io::Error;
+
+    #[instrument(level = "trace", skip(self, item), ret, fields(ty = "error-context"))]
+    fn encode(&mut self, item: &ErrorContext, dst: &mut BytesMut) -> std::io::Result<()> {
+        CoreNameEncoder.encode(item.0.as_str(), dst)
+    }
+}
+
+impl tokio_util::codec::Decoder for ErrorContextCodec {
+    type Item = ErrorContext;
+    type Error = std::io::Error;
+
+    #[instrument(level = "trace", skip(self), fields(ty = "error-context"))]
+    fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        let debug_message = self.0.decode(src)?;
+        Ok(debug_message.map(ErrorContext))
+    }
+}
+
+impl<W> Encode<W> for ErrorContext {
+    type Encoder = ErrorContextCodec;
+}
+
+impl<W> Encode<W> for &ErrorContext {
+    type Encoder = ErrorContextCodec;
+}
+
+impl<R> Decode<R> for ErrorContext {
+    type Decoder = ErrorContextCodec;
+    type ListDecoder = CoreVecDecoder<Self::Decoder>;
+}
+
 /// Codec for `()`
 #[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
 #[repr(transparent)]
@@ -2295,4 +2381,22 @@ mod tests {
         assert_eq!(buf.as_ref(), b"\x42\x42");
         Ok(())
     }
+
+    #[test_log::test(tokio::test)]
+    async fn error_context_codec() -> anyhow::Result<()> {
+        let mut buf = BytesMut::new();
+        let mut enc = <&ErrorContext as Encode<NoopStream>>::Encoder::default();
+        let cx = ErrorContext::from("failed");
+        enc.encode(&cx, &mut buf)?;
+        if let Some(_f) = Deferred::<NoopStream>::take_deferred(&mut enc) {
+            bail!("no deferred write should have been returned");
+        }
+        assert_eq!(buf.as_ref(), b"\x06failed");
+
+        let mut dec = <ErrorContext as Decode<NoopStream>>::Decoder::default();
+        let decoded = tokio_util::codec::Decoder::decode(&mut dec, &mut buf)?;
+        assert_eq!(decoded, Some(cx));
+        assert!(buf.is_empty());
+        Ok(())
+    }
 }
@@ -0,0 +1,323 @@
+//! Codecs of `wasi:http` host resources, which allow `wasi:http/outgoing-handler` to be
+//! polyfilled over wRPC while `wasi:http/types` remains linked statically.
+//!
+//! Resources are transmitted as opaque handles containing the wRPC encoding of:
+//!
+//! - `outgoing-request`: [`OutgoingRequest`]
+//! - `request-options`: [`RequestOptions`]
+//! - `future-incoming-response`: [`IncomingResponse`]
+
+use core::time::Duration;
+
+use anyhow::{bail, ensure, Context as _};
+use bytes::{BufMut as _, Bytes, BytesMut};
+use http_body_util::{BodyExt as _, Full};
+use tokio_util::codec::{Decoder as _, Encoder as _};
+use wasm_tokio::{
+    CoreNameDecoder, CoreNameEncoder, CoreVecDecoderBytes, CoreVecEncoderBytes, Leb128DecoderU16,
+    Leb128DecoderU32, Leb128DecoderU64, Leb128Encoder,
+};
+use wasmtime_wasi_http::bindings::http::types::{Method, Scheme};
+use wasmtime_wasi_http::types::{
+    HostFutureIncomingResponse, HostOutgoingRequest, HostRequestOptions,
+};
+use wrpc_runtime_wasmtime::{HostResourceCodecs, WrpcView};
+
+/// Timeout between body frames of responses received from the wRPC peer.
+/// Response bodies are received in full, so this is never hit in practice
+const BETWEEN_BYTES_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// `wasi:http/types.outgoing-request` transmitted to the wRPC peer, encoded as
+/// `tuple<string, option<string>, option<string>, option<string>, list<tuple<string, list<u8>>>>`
+/// containing the method, scheme, authority, path with query and headers.
+///
+/// Request bodies are not supported
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct OutgoingRequest {
+    pub method: String,
+    pub scheme: Option<String>,
+    pub authority: Option<String>,
+    pub path_with_query: Option<String>,
+    pub headers: Vec<(String, Bytes)>,
+}
+
+/// `wasi:http/types.request-options` transmitted to the wRPC peer, encoded as
+/// `tuple<option<u64>, option<u64>, option<u64>>` containing the connect, first byte
+/// and between bytes timeouts in nanoseconds
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct RequestOptions {
+    pub connect_timeout: Option<Duration>,
+    pub first_byte_timeout: Option<Duration>,
+    pub between_bytes_timeout: Option<Duration>,
+}
+
+/// `wasi:http/types.future-incoming-response` received from the wRPC peer, encoded as
+/// `tuple<u16, list<tuple<string, list<u8>>>, list<u8>>` containing the status, headers
+/// and body
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct IncomingResponse {
+    pub status: u16,
+    pub headers: Vec<(String, Bytes)>,
+    pub body: Bytes,
+}
+
+fn encode_option<T>(
+    v: Option<T>,
+    dst: &mut BytesMut,
+    encode: impl FnOnce(T, &mut BytesMut) -> std::io::Result<()>,
+) -> std::io::Result<()> {
+    if let Some(v) = v {
+        dst.put_u8(1);
+        encode(v, dst)
+    } else {
+        dst.put_u8(0);
+        Ok(())
+    }
+}
+
+fn encode_headers(headers: &[(String, Bytes)], dst: &mut BytesMut) -> std::io::Result<()> {
+    let n = u32::try_from(headers.len())
+        .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidInput, err))?;
+    Leb128Encoder.encode(n, dst)?;
+    for (name, value) in headers {
+        CoreNameEncoder.encode(name.as_str(), dst)?;
+        CoreVecEncoderBytes.encode(value.as_ref(), dst)?;
+    }
+    Ok(())
+}
+
+fn decode_value<D: tokio_util::codec::Decoder>(
+    mut dec: D,
+    src: &mut BytesMut,
+) -> anyhow::Result<D::Item>
+where
+    D::Error: Into<anyhow::Error>,
+{
+    dec.decode(src)
+        .map_err(Into::into)?
+        .context("value truncated")
+}
+
+fn decode_option<T>(
+    src: &mut BytesMut,
+    decode: impl FnOnce(&mut BytesMut) -> anyhow::Result<T>,
+) -> anyhow::Result<Option<T>> {
+    ensure!(!src.is_empty(), "option truncated");
+    match src.split_to(1)[0] {
+        0 => Ok(None),
+        1 => decode(src).map(Some),
+        v => bail!("invalid option status byte {v}"),
+    }
+}
+
+fn decode_headers(src: &mut BytesMut) -> anyhow::Result<Vec<(String, Bytes)>> {
+    let n = decode_value(Leb128DecoderU32, src).context("failed to decode header count")?;
+    let mut headers = Vec::with_capacity(n.min(1024) as usize);
+    for _ in 0..n {
+        let name = decode_value(CoreNameDecoder::default(), src)
+            .context("failed to decode header name")?;
+        let value = decode_value(CoreVecDecoderBytes::default(), src)
+            .context("failed to decode header value")?;
+        headers.push((name, value));
+    }
+    Ok(headers)
+}
+
+fn encode_duration(v: Duration, dst: &mut BytesMut) -> std::io::Result<()> {
+    let ns = u64::try_from(v.as_nanos()).unwrap_or(u64::MAX);
+    Leb128Encoder.encode(ns, dst)
+}
+
+fn decode_duration(src: &mut BytesMut) -> anyhow::Result<Duration> {
+    decode_value(Leb128DecoderU64, src).map(Duration::from_nanos)
+}
+
+impl OutgoingRequest {
+    /// Encodes the request into a resource handle
+    pub fn encode(&self) -> anyhow::Result<Bytes> {
+        let mut dst = BytesMut::default();
+        CoreNameEncoder.encode(self.method.as_str(), &mut dst)?;
+        for v in [&self.scheme, &self.authority, &self.path_with_query] {
+            encode_option(v.as_deref(), &mut dst, |v, dst| {
+                CoreNameEncoder.encode(v, dst)
+            })?;
+        }
+        encode_headers(&self.headers, &mut dst)?;
+        Ok(dst.freeze())
+    }
+
+    /// Decodes the request from a resource handle
+    pub fn decode(buf: Bytes) -> anyhow::Result<Self> {
+        let mut src = BytesMut::from(buf);
+        let method = decode_value(CoreNameDecoder::default(), &mut src)
+            .context("failed to decode method")?;
+        let [scheme, authority, path_with_query] =
+            ["scheme", "authority", "path with query"].map(|name| {
+                decode_option(&mut src, |src| {
+                    decode_value(CoreNameDecoder::default(), src)
+                })
+                .with_context(|| format!("failed to decode {name}"))
+            });
+        let headers = decode_headers(&mut src)?;
+        ensure!(src.is_empty(), "trailing bytes after outgoing request");
+        Ok(Self {
+            method,
+            scheme: scheme?,
+            authority: authority?,
+            path_with_query: path_with_query?,
+            headers,
+        })
+    }
+}
+
+impl TryFrom<&HostOutgoingRequest> for OutgoingRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(req: &HostOutgoingRequest) -> anyhow::Result<Self> {
+        ensure!(
+            req.body.is_none(),
+            "transmitting outgoing request bodies over wRPC is not supported yet"
+        );
+        let method = match &req.method {
+            Method::Get => "GET",
+            Method::Head => "HEAD",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Connect => "CONNECT",
+            Method::Options => "OPTIONS",
+            Method::Trace => "TRACE",
+            Method::Patch => "PATCH",
+            Method::Other(method) => method.as_str(),
+        };
+        let scheme = req.scheme.as_ref().map(|scheme| match scheme {
+            Scheme::Http => "http".into(),
+            Scheme::Https => "https".into(),
+            Scheme::Other(scheme) => scheme.clone(),
+        });
+        Ok(Self {
+            method: method.into(),
+            scheme,
+            authority: req.authority.clone(),
+            path_with_query: req.path_with_query.clone(),
+            headers: req
+                .headers
+                .iter()
+                .map(|(name, value)| (name.to_string(), Bytes::copy_from_slice(value.as_bytes())))
+                .collect(),
+        })
+    }
+}
+
+impl RequestOptions {
+    /// Encodes the options into a resource handle
+    pub fn encode(&self) -> anyhow::Result<Bytes> {
+        let mut dst = BytesMut::default();
+        for v in [
+            self.connect_timeout,
+            self.first_byte_timeout,
+            self.between_bytes_timeout,
+        ] {
+            encode_option(v, &mut dst, encode_duration)?;
+        }
+        Ok(dst.freeze())
+    }
+
+    /// Decodes the options from a resource handle
+    pub fn decode(buf: Bytes) -> anyhow::Result<Self> {
+        let mut src = BytesMut::from(buf);
+        let connect_timeout =
+            decode_option(&mut src, decode_duration).context("failed to decode connect timeout")?;
+        let first_byte_timeout = decode_option(&mut src, decode_duration)
+            .context("failed to decode first byte timeout")?;
+        let between_bytes_timeout = decode_option(&mut src, decode_duration)
+            .context("failed to decode between bytes timeout")?;
+        ensure!(src.is_empty(), "trailing bytes after request options");
+        Ok(Self {
+            connect_timeout,
+            first_byte_timeout,
+            between_bytes_timeout,
+        })
+    }
+}
+
+impl From<&HostRequestOptions> for RequestOptions {
+    fn from(opts: &HostRequestOptions) -> Self {
+        Self {
+            connect_timeout: opts.connect_timeout,
+            first_byte_timeout: opts.first_byte_timeout,
+            between_bytes_timeout: opts.between_bytes_timeout,
+        }
+    }
+}
+
+impl IncomingResponse {
+    /// Encodes the response into a resource handle
+    pub fn encode(&self) -> anyhow::Result<Bytes> {
+        let mut dst = BytesMut::default();
+        Leb128Encoder.encode(self.status, &mut dst)?;
+        encode_headers(&self.headers, &mut dst)?;
+        CoreVecEncoderBytes.encode(self.body.as_ref(), &mut dst)?;
+        Ok(dst.freeze())
+    }
+
+    /// Decodes the response from a resource handle
+    pub fn decode(buf: Bytes) -> anyhow::Result<Self> {
+        let mut src = BytesMut::from(buf);
+        let status = decode_value(Leb128DecoderU16, &mut src).context("failed to decode status")?;
+        let headers = decode_headers(&mut src)?;
+        let body = decode_value(CoreVecDecoderBytes::default(), &mut src)
+            .context("failed to decode body")?;
+        ensure!(src.is_empty(), "trailing bytes after incoming response");
+        Ok(Self {
+            status,
+            headers,
+            body,
+        })
+    }
+}
+
+impl TryFrom<IncomingResponse> for HostFutureIncomingResponse {
+    type Error = anyhow::Error;
+
+    fn try_from(
+        IncomingResponse {
+            status,
+            headers,
+            body,
+        }: IncomingResponse,
+    ) -> anyhow::Result<Self> {
+        let mut resp = http::Response::new(Full::new(body).map_err(|err| match err {}).boxed());
+        *resp.status_mut() = http::StatusCode::from_u16(status).context("invalid status")?;
+        for (name, value) in headers {
+            let name = http::HeaderName::try_from(name).context("invalid header name")?;
+            let value =
+                http::HeaderValue::from_maybe_shared(value).context("invalid header value")?;
+            resp.headers_mut().append(name, value);
+        }
+        Ok(Self::Ready(Ok(Ok(
+            wasmtime_wasi_http::types::IncomingResponse {
+                resp,
+                worker: None,
+                between_bytes_timeout: BETWEEN_BYTES_TIMEOUT,
+            },
+        ))))
+    }
+}
+
+/// Registers codecs of `wasi:http` resources used by `wasi:http/outgoing-handler` in `codecs`
+pub fn register_codecs<T: WrpcView + 'static>(codecs: &mut HostResourceCodecs<T>) {
+    codecs
+        .register::<HostOutgoingRequest>(
+            |req| OutgoingRequest::try_from(req)?.encode(),
+            |_| bail!("decoding `wasi:http/types.outgoing-request` is not supported"),
+        )
+        .register::<HostRequestOptions>(
+            |opts| RequestOptions::from(opts).encode(),
+            |_| bail!("decoding `wasi:http/types.request-options` is not supported"),
+        )
+        .register::<HostFutureIncomingResponse>(
+            |_| bail!("encoding `wasi:http/types.future-incoming-response` is not supported"),
+            |buf| IncomingResponse::decode(buf)?.try_into(),
+        );
+}
@@ -1,39 +1,56 @@
 #![allow(clippy::type_complexity)]
 
+use core::fmt;
+use core::future::{self, Future};
 use core::iter;
+use core::net::SocketAddr;
+use core::num::NonZeroUsize;
 use core::ops::Bound;
 use core::pin::pin;
 use core::time::Duration;
 
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Instant;
 
-use anyhow::{anyhow, bail, Context as _};
-use clap::Parser;
-use futures::StreamExt as _;
+use anyhow::{anyhow, bail, ensure, Context as _};
+use clap::{ArgAction, Args, Parser, ValueEnum};
+use futures::{Stream, StreamExt as _, TryStreamExt as _};
 use tokio::fs;
-use tokio::sync::Mutex;
-use tokio::task::JoinSet;
-use tracing::{error, info, instrument, warn, Instrument as _, Span};
+use tokio::select;
+use tokio::sync::{watch, Semaphore};
+use tokio::task::{JoinError, JoinSet};
+use tokio_util::sync::CancellationToken;
+use tracing::{error, info, info_span, instrument, warn, Instrument as _, Span};
 use url::Url;
 use wasi_preview1_component_adapter_provider::{
     WASI_SNAPSHOT_PREVIEW1_ADAPTER_NAME, WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER,
     WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
 };
 use wasmtime::component::{types, Component, InstancePre, Linker, ResourceTable, ResourceType};
-use wasmtime::{Engine, Store};
-use wasmtime_wasi::{WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
+use wasmtime::{Engine, Store, StoreLimits, StoreLimitsBuilder};
+use wasmtime_wasi::p2::pipe::MemoryOutputPipe;
+use wasmtime_wasi::{DirPerms, FilePerms, WasiCtx, WasiCtxBuilder, WasiCtxView, WasiView};
 use wasmtime_wasi_http::{WasiHttpCtx, WasiHttpView};
 use wrpc_runtime_wasmtime::{
-    collect_component_resource_exports, collect_component_resource_imports, link_item, rpc,
-    RemoteResource, ServeExt as _, SharedResourceTable, WrpcCtxView, WrpcView,
+    collect_component_resource_exports, collect_component_resource_imports, component_exports,
+    link_item, rpc, Export, HostResourceCodecs, RemoteResource, ServeExt as _, SharedResourceTable,
+    WrpcCtxView, WrpcView,
 };
 use wrpc_transport::{Invoke, Serve};
 
+pub mod http;
 mod nats;
+mod oci;
 mod tcp;
+#[cfg(unix)]
+mod uds;
 
 const DEFAULT_TIMEOUT: &str = "10s";
+const DEFAULT_MAX_CONCURRENT_INVOCATIONS: &str = "1024";
+const DEFAULT_SHUTDOWN_GRACE_PERIOD: &str = "30s";
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -42,6 +59,526 @@ enum Command {
     Nats(nats::Command),
     #[command(subcommand)]
     Tcp(tcp::Command),
+    #[cfg(unix)]
+    #[command(subcommand)]
+    Uds(uds::Command),
+    Inspect(InspectArgs),
+}
+
+/// Print how imports of a component would be linked and which exports would be served,
+/// without serving it
+#[derive(Args, Debug)]
+struct InspectArgs {
+    /// Print the report as JSON
+    #[arg(long)]
+    json: bool,
+
+    #[command(flatten)]
+    interfaces: HostInterfaces,
+
+    /// Path or URL to Wasm reactor component
+    workload: String,
+}
+
+/// Guest environment configuration
+#[derive(Args, Clone, Debug, Default)]
+pub struct EnvArgs {
+    /// Environment variable to set for the guest in `KEY=VALUE` format, can be repeated
+    #[arg(long = "env", value_name = "KEY=VALUE", value_parser = parse_env_var)]
+    vars: Vec<(String, String)>,
+
+    /// Path to a dotenv-style file containing environment variables to set for the guest,
+    /// can be repeated. Variables passed via `--env` take precedence
+    #[arg(long = "env-file", value_name = "PATH")]
+    files: Vec<PathBuf>,
+
+    /// Inherit the environment of the host process.
+    /// Variables passed via `--env` and `--env-file` take precedence
+    #[arg(long)]
+    inherit_env: bool,
+
+    /// Host directory to preopen for the guest in `HOST::GUEST` format, can be repeated.
+    /// If `::GUEST` is omitted, the directory is exposed at the host path.
+    /// Append `::ro` to only grant read access. No directories are preopened by default
+    #[arg(long = "dir", value_name = "HOST::GUEST", value_parser = parse_preopen)]
+    dirs: Vec<Preopen>,
+
+    /// Inherit stdio of the host process when serving.
+    /// By default, guest stdin is empty and guest stdout and stderr are discarded
+    #[arg(long)]
+    inherit_stdio: bool,
+
+    /// Grant the guest access to the network of the host when serving.
+    /// By default, guest socket and name lookup operations are denied
+    #[arg(long)]
+    inherit_network: bool,
+}
+
+impl EnvArgs {
+    /// Reads the environment files and constructs the [GuestEnv]
+    pub async fn load(self) -> anyhow::Result<GuestEnv> {
+        let mut vars = Vec::new();
+        for path in &self.files {
+            let file = fs::read_to_string(path)
+                .await
+                .with_context(|| format!("failed to read `{}`", path.display()))?;
+            vars.extend(parse_env_file(&file, path)?);
+        }
+        vars.extend(self.vars);
+        for Preopen { host, .. } in &self.dirs {
+            let md = fs::metadata(host)
+                .await
+                .with_context(|| format!("failed to stat `{}`", host.display()))?;
+            if !md.is_dir() {
+                bail!("`{}` is not a directory", host.display())
+            }
+        }
+        Ok(GuestEnv {
+            inherit: self.inherit_env,
+            vars: vars.into(),
+            preopens: self.dirs.into(),
+            stdio: if self.inherit_stdio {
+                GuestStdio::Inherit
+            } else {
+                GuestStdio::Discard
+            },
+            network: self.inherit_network,
+        })
+    }
+}
+
+/// WASI preview1 adapter used to encode core module workloads into components
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Adapter {
+    /// Built-in `command` adapter
+    Command,
+    /// Built-in `reactor` adapter
+    Reactor,
+    /// Custom adapter read from a file
+    Path(PathBuf),
+}
+
+impl Adapter {
+    /// Returns the adapter bytes, reading them from the file for [Adapter::Path]
+    pub async fn load(&self) -> anyhow::Result<Cow<'static, [u8]>> {
+        match self {
+            Self::Command => Ok(Cow::Borrowed(WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER)),
+            Self::Reactor => Ok(Cow::Borrowed(WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER)),
+            Self::Path(path) => fs::read(path)
+                .await
+                .map(Cow::Owned)
+                .with_context(|| format!("failed to read adapter `{}`", path.display())),
+        }
+    }
+}
+
+fn parse_adapter(s: &str) -> anyhow::Result<Adapter> {
+    match s {
+        "command" => Ok(Adapter::Command),
+        "reactor" => Ok(Adapter::Reactor),
+        "" => bail!("adapter must be `command`, `reactor` or a path"),
+        path => Ok(Adapter::Path(path.into())),
+    }
+}
+
+/// Environment exposed to the guest
+#[derive(Clone, Debug, Default)]
+pub struct GuestEnv {
+    /// Whether the environment of the host process is inherited
+    pub inherit: bool,
+    /// Environment variables set for the guest, later entries take precedence
+    pub vars: Arc<[(String, String)]>,
+    /// Host directories preopened for the guest
+    pub preopens: Arc<[Preopen]>,
+    /// Guest stdio configuration
+    pub stdio: GuestStdio,
+    /// Whether the guest is granted access to the network of the host
+    pub network: bool,
+}
+
+/// Stdio exposed to the guest
+#[derive(Clone, Debug, Default)]
+pub enum GuestStdio {
+    /// Guest stdin is empty and guest stdout and stderr are discarded
+    #[default]
+    Discard,
+    /// Stdio of the host process is inherited
+    Inherit,
+    /// Guest stdin is empty and guest stdout and stderr are captured in memory
+    Capture {
+        /// Captured guest stdout
+        stdout: MemoryOutputPipe,
+        /// Captured guest stderr
+        stderr: MemoryOutputPipe,
+    },
+}
+
+/// Host directory preopened for the guest
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Preopen {
+    /// Path of the directory on the host
+    pub host: PathBuf,
+    /// Path the directory is exposed at to the guest
+    pub guest: String,
+    /// Whether the guest is only allowed to read from the directory
+    pub read_only: bool,
+}
+
+fn parse_env_var(s: &str) -> anyhow::Result<(String, String)> {
+    let (k, v) = s
+        .split_once('=')
+        .with_context(|| format!("environment variable `{s}` is not in `KEY=VALUE` format"))?;
+    if k.is_empty() {
+        bail!("environment variable `{s}` has an empty name")
+    }
+    Ok((k.to_string(), v.to_string()))
+}
+
+fn parse_preopen(s: &str) -> anyhow::Result<Preopen> {
+    let (dir, read_only) = s.strip_suffix("::ro").map_or((s, false), |dir| (dir, true));
+    let (host, guest) = dir.split_once("::").unwrap_or((dir, dir));
+    if host.is_empty() || guest.is_empty() {
+        bail!("directory `{s}` is not in `HOST::GUEST` format")
+    }
+    Ok(Preopen {
+        host: host.into(),
+        guest: guest.to_string(),
+        read_only,
+    })
+}
+
+fn parse_env_file(file: &str, path: &Path) -> anyhow::Result<Vec<(String, String)>> {
+    let mut vars = Vec::new();
+    for (i, line) in file.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (k, v) = line
+            .split_once('=')
+            .map(|(k, v)| (k.trim(), v))
+            .filter(|(k, _)| !k.is_empty())
+            .with_context(|| format!("invalid line {} in `{}`", i + 1, path.display()))?;
+        let v = v.trim();
+        let v = v
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| v.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(v);
+        vars.push((k.to_string(), v.to_string()));
+    }
+    Ok(vars)
+}
+
+/// Resource limits applied to each guest [Store].
+///
+/// Exceeding any of the limits traps the guest, which fails the invocation
+/// in progress. Note, that fuel and the epoch deadline are accounted per [Store], so for
+/// components exporting resources, which are served using a single shared [Store],
+/// they apply to all invocations in aggregate
+#[derive(Args, Clone, Copy, Debug, Default)]
+pub struct Limits {
+    /// Maximum size in bytes of each linear memory of the guest,
+    /// attempts to grow memory beyond this limit trap
+    #[arg(long)]
+    pub max_memory_bytes: Option<usize>,
+
+    /// Maximum number of component and core instances of the guest
+    #[arg(long)]
+    pub max_instances: Option<usize>,
+
+    /// Amount of fuel available to the guest, execution traps once it is exhausted
+    #[arg(long)]
+    pub fuel: Option<u64>,
+
+    /// Wall-clock time in milliseconds the guest may execute for before trapping.
+    /// The deadline is enforced with a granularity of 10ms
+    // NOTE: The trap is configured on the store, so this cannot be combined with
+    // `WrpcCtx::execution_timeout`, which replaces the epoch deadline behavior of the store
+    #[arg(long)]
+    pub epoch_deadline_ms: Option<u64>,
+}
+
+/// Host interfaces linked statically by [instantiate_pre], all interfaces are linked by default.
+///
+/// Imports of interfaces, which are not linked, are polyfilled over wRPC like any other import
+#[derive(Args, Clone, Debug)]
+pub struct HostInterfaces {
+    /// Do not link `wasi:filesystem`, polyfill it over wRPC instead
+    #[arg(long = "no-wasi-filesystem", action = ArgAction::SetFalse)]
+    wasi_filesystem: bool,
+
+    /// Do not link `wasi:sockets`, polyfill it over wRPC instead
+    #[arg(long = "no-wasi-sockets", action = ArgAction::SetFalse)]
+    wasi_sockets: bool,
+
+    /// Do not link `wasi:http`, polyfill it over wRPC instead
+    #[arg(long = "no-wasi-http", action = ArgAction::SetFalse)]
+    wasi_http: bool,
+
+    /// Implementation of `wasi:http/outgoing-handler`, if `wasi:http` is linked
+    #[arg(long = "wasi-http-outgoing", value_enum, default_value_t = HttpOutgoing::Static)]
+    wasi_http_outgoing: HttpOutgoing,
+
+    /// Do not link `wrpc:rpc`, polyfill it over wRPC instead
+    #[arg(long = "no-wrpc-rpc", action = ArgAction::SetFalse)]
+    wrpc_rpc: bool,
+
+    /// Semantic version requirement for a statically linked interface,
+    /// e.g. `wasi:http/types=>=0.2.0, <0.2.4`. Imports of versions not satisfying
+    /// the requirement are polyfilled over wRPC
+    #[arg(
+        long = "interface-version",
+        value_name = "INTERFACE=REQUIREMENT",
+        value_parser = parse_interface_version
+    )]
+    interface_versions: Vec<(String, semver::VersionReq)>,
+}
+
+impl Default for HostInterfaces {
+    fn default() -> Self {
+        Self {
+            wasi_filesystem: true,
+            wasi_sockets: true,
+            wasi_http: true,
+            wasi_http_outgoing: HttpOutgoing::Static,
+            wrpc_rpc: true,
+            interface_versions: Vec::default(),
+        }
+    }
+}
+
+impl HostInterfaces {
+    /// Sets whether `wasi:filesystem` is linked
+    #[must_use]
+    pub fn wasi_filesystem(self, wasi_filesystem: bool) -> Self {
+        Self {
+            wasi_filesystem,
+            ..self
+        }
+    }
+
+    /// Sets whether `wasi:sockets` is linked
+    #[must_use]
+    pub fn wasi_sockets(self, wasi_sockets: bool) -> Self {
+        Self {
+            wasi_sockets,
+            ..self
+        }
+    }
+
+    /// Sets whether `wasi:http` is linked
+    #[must_use]
+    pub fn wasi_http(self, wasi_http: bool) -> Self {
+        Self { wasi_http, ..self }
+    }
+
+    /// Sets the implementation of `wasi:http/outgoing-handler`, if `wasi:http` is linked
+    #[must_use]
+    pub fn wasi_http_outgoing(self, wasi_http_outgoing: HttpOutgoing) -> Self {
+        Self {
+            wasi_http_outgoing,
+            ..self
+        }
+    }
+
+    /// Sets whether `wrpc:rpc` is linked
+    #[must_use]
+    pub fn wrpc_rpc(self, wrpc_rpc: bool) -> Self {
+        Self { wrpc_rpc, ..self }
+    }
+
+    /// Sets the requirement for versions of `interface` linked statically,
+    /// overriding the [VersionPolicy] default
+    #[must_use]
+    pub fn interface_version(mut self, interface: &str, req: semver::VersionReq) -> Self {
+        self.interface_versions.push((interface.to_string(), req));
+        self
+    }
+
+    /// Links the configured interfaces into `linker`
+    fn link<C>(&self, linker: &mut Linker<Ctx<C>>) -> anyhow::Result<()>
+    where
+        C: Invoke + 'static,
+        C::Context: Clone + 'static,
+    {
+        // `wasi:filesystem` and `wasi:sockets` are linked as part of WASI and
+        // `wasi:http/outgoing-handler` as part of `wasi:http`, so they are shadowed
+        // by the polyfills if not linked
+        linker.allow_shadowing(
+            !self.wasi_filesystem
+                || !self.wasi_sockets
+                || self.wasi_http && self.wasi_http_outgoing == HttpOutgoing::Wrpc,
+        );
+        wasmtime_wasi::p2::add_to_linker_async(linker).context("failed to link WASI")?;
+        if self.wasi_http {
+            wasmtime_wasi_http::add_only_http_to_linker_async(linker)
+                .context("failed to link `wasi:http`")?;
+        }
+        if self.wrpc_rpc {
+            wrpc_runtime_wasmtime::rpc::add_to_linker(linker)
+                .context("failed to link `wrpc:rpc`")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the [VersionPolicy] of statically linked interfaces
+    fn version_policy(&self) -> anyhow::Result<VersionPolicy> {
+        let mut policy = VersionPolicy::default();
+        for (interface, req) in &self.interface_versions {
+            policy.set(interface, req.clone())?;
+        }
+        Ok(policy)
+    }
+
+    /// Returns `true` if instance `name` is linked statically and must not be polyfilled
+    fn is_linked(&self, versions: &VersionPolicy, name: &str) -> bool {
+        let Some((interface, version)) = name.split_once('@') else {
+            return false;
+        };
+        if !versions.matches(interface, version) {
+            return false;
+        }
+        match interface.split_once('/') {
+            Some(("wasi:filesystem", _)) => self.wasi_filesystem,
+            Some(("wasi:sockets", _)) => self.wasi_sockets,
+            Some(("wasi:http", "outgoing-handler")) => {
+                self.wasi_http && self.wasi_http_outgoing == HttpOutgoing::Static
+            }
+            Some(("wasi:http", _)) => self.wasi_http,
+            Some(("wrpc:rpc", _)) => self.wrpc_rpc,
+            _ => true,
+        }
+    }
+}
+
+/// Implementation of `wasi:http/outgoing-handler`
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum HttpOutgoing {
+    /// Send outgoing requests from the host
+    #[default]
+    Static,
+    /// Tunnel outgoing requests to the wRPC peer, which sends them, see [`http`]
+    Wrpc,
+}
+
+/// Default semantic version requirements of statically linked interfaces
+const DEFAULT_INTERFACE_VERSIONS: &[(&str, &str)] = &[
+    ("wasi:cli/environment", "^0.2.0"),
+    ("wasi:cli/exit", "^0.2.0"),
+    ("wasi:cli/stderr", "^0.2.0"),
+    ("wasi:cli/stdin", "^0.2.0"),
+    ("wasi:cli/stdout", "^0.2.0"),
+    ("wasi:cli/terminal-input", "^0.2.0"),
+    ("wasi:cli/terminal-output", "^0.2.0"),
+    ("wasi:cli/terminal-stderr", "^0.2.0"),
+    ("wasi:cli/terminal-stdin", "^0.2.0"),
+    ("wasi:cli/terminal-stdout", "^0.2.0"),
+    ("wasi:clocks/monotonic-clock", "^0.2.0"),
+    ("wasi:clocks/timezone", "^0.2.1"),
+    ("wasi:clocks/wall-clock", "^0.2.0"),
+    ("wasi:filesystem/preopens", "^0.2.0"),
+    ("wasi:filesystem/types", "^0.2.0"),
+    ("wasi:http/incoming-handler", "^0.2.0"),
+    ("wasi:http/outgoing-handler", "^0.2.0"),
+    ("wasi:http/types", "^0.2.0"),
+    ("wasi:io/error", "^0.2.0"),
+    ("wasi:io/poll", "^0.2.0"),
+    ("wasi:io/streams", "^0.2.0"),
+    ("wasi:random/insecure", "^0.2.0"),
+    ("wasi:random/insecure-seed", "^0.2.0"),
+    ("wasi:random/random", "^0.2.0"),
+    ("wasi:sockets/instance-network", "^0.2.0"),
+    ("wasi:sockets/ip-name-lookup", "^0.2.0"),
+    ("wasi:sockets/network", "^0.2.0"),
+    ("wasi:sockets/tcp", "^0.2.0"),
+    ("wasi:sockets/tcp-create-socket", "^0.2.0"),
+    ("wasi:sockets/udp", "^0.2.0"),
+    ("wasi:sockets/udp-create-socket", "^0.2.0"),
+    ("wrpc:rpc/context", "=0.1.0"),
+    ("wrpc:rpc/error", "=0.1.0"),
+    ("wrpc:rpc/invoker", "=0.1.0"),
+    ("wrpc:rpc/transport", "=0.1.0"),
+];
+
+/// Semantic version requirements of statically linked interfaces.
+///
+/// Imports of interfaces, which are not in the policy, or versions, which do not satisfy
+/// the requirement, are polyfilled over wRPC. Pre-release versions are only accepted
+/// if the requirement explicitly allows them
+#[derive(Clone, Debug)]
+pub struct VersionPolicy(BTreeMap<Box<str>, semver::VersionReq>);
+
+impl Default for VersionPolicy {
+    fn default() -> Self {
+        Self(
+            DEFAULT_INTERFACE_VERSIONS
+                .iter()
+                .map(|(interface, req)| {
+                    let req = req
+                        .parse()
+                        .expect("default version requirements must be valid");
+                    ((*interface).into(), req)
+                })
+                .collect(),
+        )
+    }
+}
+
+impl VersionPolicy {
+    /// Sets the requirement for versions of `interface` linked statically.
+    ///
+    /// Only interfaces, for which a host implementation is linked, can be configured
+    pub fn set(&mut self, interface: &str, req: semver::VersionReq) -> anyhow::Result<&mut Self> {
+        let Some(v) = self.0.get_mut(interface) else {
+            bail!("no host implementation of interface `{interface}` is available")
+        };
+        *v = req;
+        Ok(self)
+    }
+
+    /// Returns `true` if `version` of `interface` is linked statically
+    #[must_use]
+    pub fn matches(&self, interface: &str, version: &str) -> bool {
+        let Some(req) = self.0.get(interface) else {
+            return false;
+        };
+        version
+            .parse::<semver::Version>()
+            .is_ok_and(|version| version.build.is_empty() && req.matches(&version))
+    }
+}
+
+fn parse_interface_version(s: &str) -> anyhow::Result<(String, semver::VersionReq)> {
+    let (interface, req) = s.split_once('=').with_context(|| {
+        format!("interface version `{s}` is not in `INTERFACE=REQUIREMENT` format")
+    })?;
+    let req = req
+        .parse()
+        .map_err(|err| anyhow!("invalid version requirement `{req}`: {err}"))?;
+    Ok((interface.to_string(), req))
+}
+
+/// Interval at which the engine epoch is incremented, if an epoch deadline is set
+const EPOCH_TICK: Duration = Duration::from_millis(10);
+
+impl Limits {
+    fn store_limits(&self) -> StoreLimits {
+        let mut limits = StoreLimitsBuilder::new().trap_on_grow_failure(true);
+        if let Some(n) = self.max_memory_bytes {
+            limits = limits.memory_size(n);
+        }
+        if let Some(n) = self.max_instances {
+            limits = limits.instances(n);
+        }
+        limits.build()
+    }
+
+    fn configure(&self, config: &mut wasmtime::Config) {
+        config.consume_fuel(self.fuel.is_some());
+        config.epoch_interruption(self.epoch_deadline_ms.is_some());
+    }
 }
 
 pub enum Workload {
@@ -61,6 +598,8 @@ pub struct Ctx<C: Invoke> {
     pub wasi: WasiCtx,
     pub http: WasiHttpCtx,
     pub wrpc: WrpcCtx<C>,
+    pub limits: StoreLimits,
+    pub host_resource_codecs: Arc<HostResourceCodecs<Self>>,
 }
 
 impl<C> wrpc_runtime_wasmtime::WrpcCtx<C> for WrpcCtx<C>
@@ -83,6 +622,9 @@ where
     fn timeout(&self) -> Option<Duration> {
         Some(self.timeout)
     }
+
+    // NOTE: `execution_timeout` must not be overridden, since it would replace the trap
+    // configured for `Limits::epoch_deadline_ms`
 }
 
 impl<C> WrpcView for Ctx<C>
@@ -98,6 +640,10 @@ where
             table: &mut self.table,
         }
     }
+
+    fn host_resource_codecs(&self) -> Option<Arc<HostResourceCodecs<Self>>> {
+        Some(Arc::clone(&self.host_resource_codecs))
+    }
 }
 
 impl<C: Invoke> WasiView for Ctx<C> {
@@ -137,35 +683,14 @@ fn use_pooling_allocator_by_default() -> anyhow::Result<Option<bool>> {
     }
 }
 
-fn is_0_2(version: &str, min_patch: u64) -> bool {
-    if let Ok(semver::Version {
-        major,
-        minor,
-        patch,
-        pre,
-        build,
-    }) = version.parse()
-    {
-        major == 0 && minor == 2 && patch >= min_patch && pre.is_empty() && build.is_empty()
-    } else {
-        false
-    }
-}
-
+/// Constructs an [Engine] and compiles `workload` into a [Component], encoding core modules
+/// using `adapter`
 #[instrument(level = "trace", skip(adapter))]
-async fn instantiate_pre<C>(
+async fn load_component(
     adapter: &[u8],
     workload: &str,
-) -> anyhow::Result<(
-    InstancePre<Ctx<C>>,
-    Engine,
-    Arc<[ResourceType]>,
-    Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
-)>
-where
-    C: Invoke + Clone + 'static,
-    C::Context: Clone + 'static,
-{
+    limits: Limits,
+) -> anyhow::Result<(Engine, Component)> {
     let mut opts = wasmtime_cli_flags::CommonOptions::try_parse_from(iter::empty::<&'static str>())
         .context("failed to construct common Wasmtime options")?;
     let mut config = opts
@@ -173,7 +698,21 @@ where
         .context("failed to construct Wasmtime config")?;
     config.wasm_component_model(true);
     config.async_support(true);
+    limits.configure(&mut config);
     let engine = wasmtime::Engine::new(&config).context("failed to initialize Wasmtime engine")?;
+    if limits.epoch_deadline_ms.is_some() {
+        let engine = engine.weak();
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(EPOCH_TICK);
+            loop {
+                interval.tick().await;
+                let Some(engine) = engine.upgrade() else {
+                    return;
+                };
+                engine.increment_epoch();
+            }
+        });
+    }
 
     let wasm = if workload.starts_with('.') || workload.starts_with('/') {
         fs::read(&workload)
@@ -200,6 +739,14 @@ where
                 let wasm = wasm.bytes().await.context("failed fetch Wasm from URL")?;
                 wasm.to_vec()
             }
+            "oci" => {
+                let reference = workload
+                    .strip_prefix("oci://")
+                    .context("OCI workload reference must start with `oci://`")?;
+                oci::fetch(reference)
+                    .await
+                    .context("failed to fetch Wasm from OCI registry")?
+            }
             scheme => bail!("URL scheme `{scheme}` not supported"),
         },
         Workload::Binary(wasm) => wasm,
@@ -218,18 +765,124 @@ where
     };
 
     let component = Component::new(&engine, wasm).context("failed to compile component")?;
+    Ok((engine, component))
+}
+
+/// Resolution of a component import or export
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Resolution {
+    /// Import is linked statically to a host implementation
+    Linked,
+    /// Import is polyfilled over wRPC
+    Polyfilled,
+    /// Export is served over wRPC
+    Served,
+    /// Item can neither be linked nor served
+    Unsupported,
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Linked => f.pad("linked"),
+            Self::Polyfilled => f.pad("polyfilled"),
+            Self::Served => f.pad("served"),
+            Self::Unsupported => f.pad("unsupported"),
+        }
+    }
+}
+
+/// Resolved component import or export
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize)]
+pub struct Item {
+    /// Name of the instance, empty for root items
+    pub instance: String,
+    /// Name of the item within the instance
+    pub name: String,
+    /// How the item is resolved
+    pub resolution: Resolution,
+    /// Reason for the item being [Resolution::Unsupported]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Guest resource exported by a component
+#[derive(Clone, Debug, Eq, PartialEq, serde::Serialize)]
+pub struct GuestResource {
+    /// Name of the instance, empty for root resources
+    pub instance: String,
+    /// Name of the resource within the instance
+    pub name: String,
+}
+
+/// Report of how a component would be linked and served by [handle_serve]
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Serialize)]
+pub struct Inspection {
+    /// Resolution of component imports
+    pub imports: Vec<Item>,
+    /// Resolution of component exports
+    pub exports: Vec<Item>,
+    /// Guest resources exported by the component. If there are any, all exports are served
+    /// using a single shared [Store]
+    pub guest_resources: Vec<GuestResource>,
+}
+
+impl fmt::Display for Inspection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (section, items) in [("imports", &self.imports), ("exports", &self.exports)] {
+            writeln!(f, "{section}:")?;
+            for Item {
+                instance,
+                name,
+                resolution,
+                reason,
+            } in items
+            {
+                write!(f, "  {resolution:<11} {instance} {name}")?;
+                if let Some(reason) = reason {
+                    write!(f, " ({reason})")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        writeln!(f, "guest resources:")?;
+        for GuestResource { instance, name } in &self.guest_resources {
+            writeln!(f, "  {instance} {name}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Component with statically linked host interfaces and polyfilled imports
+struct LinkedComponent<C: Invoke> {
+    linker: Linker<Ctx<C>>,
+    guest_resources: Arc<[ResourceType]>,
+    host_resources: Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
+    /// Resolution of component imports
+    imports: Vec<Item>,
+}
 
-    let mut linker = Linker::<Ctx<C>>::new(&engine);
-    wasmtime_wasi::p2::add_to_linker_async(&mut linker).context("failed to link WASI")?;
-    wasmtime_wasi_http::add_only_http_to_linker_async(&mut linker)
-        .context("failed to link `wasi:http`")?;
-    wrpc_runtime_wasmtime::rpc::add_to_linker(&mut linker).context("failed to link `wrpc:rpc`")?;
+/// Links `interfaces` and polyfills all other imports of `component` over wRPC
+#[instrument(level = "trace", skip(engine, component))]
+fn link_component<C>(
+    engine: &Engine,
+    component: &Component,
+    interfaces: HostInterfaces,
+) -> anyhow::Result<LinkedComponent<C>>
+where
+    C: Invoke + Clone + 'static,
+    C::Context: Clone + 'static,
+{
+    let versions = interfaces.version_policy()?;
+    let mut linker = Linker::<Ctx<C>>::new(engine);
+    interfaces.link(&mut linker)?;
 
     let ty = component.component_type();
     let mut host_resources = BTreeMap::default();
     let mut guest_resources = Vec::new();
-    collect_component_resource_imports(&engine, &ty, &mut host_resources);
-    collect_component_resource_exports(&engine, &ty, &mut guest_resources);
+    collect_component_resource_imports(engine, &ty, &mut host_resources);
+    collect_component_resource_exports(engine, &ty, &mut guest_resources);
     let io_err_tys = host_resources
         .range::<str, _>((
             Bound::Included("wasi:io/error@0.2"),
@@ -265,8 +918,35 @@ where
     let rpc_err_ty = host_resources
         .get("wrpc:rpc/error@0.1.0")
         .and_then(|instance| instance.get("error"))
-        .copied();
-    // TODO: This should include `wasi:http` resources
+        .copied()
+        .filter(|_| interfaces.wrpc_rpc);
+    // Resources used by `wasi:http/outgoing-handler`, which are transmitted using the
+    // codecs registered by `http::register_codecs`
+    let http_tys = host_resources
+        .range::<str, _>((
+            Bound::Included("wasi:http/types@0.2"),
+            Bound::Excluded("wasi:http/types@0.3"),
+        ))
+        .flat_map(|(_, instance)| {
+            [
+                (
+                    "outgoing-request",
+                    ResourceType::host::<wasmtime_wasi_http::types::HostOutgoingRequest>(),
+                ),
+                (
+                    "request-options",
+                    ResourceType::host::<wasmtime_wasi_http::types::HostRequestOptions>(),
+                ),
+                (
+                    "future-incoming-response",
+                    ResourceType::host::<wasmtime_wasi_http::types::HostFutureIncomingResponse>(),
+                ),
+            ]
+            .into_iter()
+            .filter_map(|(name, host_ty)| Some((*instance.get(name)?, host_ty)))
+        })
+        .filter(|_| interfaces.wasi_http)
+        .collect::<Box<[_]>>();
     let host_resources = host_resources
         .into_iter()
         .map(|(name, instance)| {
@@ -287,7 +967,10 @@ where
                         ty if io_pollable_tys.contains(&ty) => {
                             ResourceType::host::<wasmtime_wasi::p2::bindings::io::poll::Pollable>()
                         }
-                        _ => ResourceType::host::<RemoteResource>(),
+                        _ => http_tys
+                            .iter()
+                            .find_map(|(guest_ty, host_ty)| (*guest_ty == ty).then_some(*host_ty))
+                            .unwrap_or_else(ResourceType::host::<RemoteResource>),
                     };
                     (name, (ty, host_ty))
                 })
@@ -297,57 +980,94 @@ where
         .collect::<HashMap<_, _>>();
     let host_resources = Arc::from(host_resources);
     let guest_resources = Arc::from(guest_resources);
-    for (name, item) in ty.imports(&engine) {
+    let mut imports = Vec::new();
+    for (name, item) in ty.imports(engine) {
+        let funcs = import_functions(engine, name, &item);
         // Avoid polyfilling instances, for which static bindings are linked
-        match name.split_once('/').map(|(pkg, suffix)| {
-            suffix
-                .split_once('@')
-                .map_or((pkg, suffix, None), |(iface, version)| {
-                    (pkg, iface, Some(version))
-                })
-        }) {
-            Some(("wrpc:rpc", "transport" | "error" | "context" | "invoker", Some("0.1.0"))) => {}
-            Some((
-                "wasi:cli",
-                "environment" | "exit" | "stderr" | "stdin" | "stdout" | "terminal-input"
-                | "terminal-output" | "terminal-stderr" | "terminal-stdin" | "terminal-stdout",
-                Some(version),
-            )) if is_0_2(version, 0) => {}
-            Some(("wasi:clocks", "monotonic-clock" | "wall-clock", Some(version)))
-                if is_0_2(version, 0) => {}
-            Some(("wasi:clocks", "timezone", Some(version))) if is_0_2(version, 1) => {}
-            Some(("wasi:filesystem", "preopens" | "types", Some(version)))
-                if is_0_2(version, 0) => {}
-            Some((
-                "wasi:http",
-                "incoming-handler" | "outgoing-handler" | "types",
-                Some(version),
-            )) if is_0_2(version, 0) => {}
-            Some(("wasi:io", "error" | "poll" | "streams", Some(version)))
-                if is_0_2(version, 0) => {}
-            Some(("wasi:random", "insecure-seed" | "insecure" | "random", Some(version)))
-                if is_0_2(version, 0) => {}
-            Some((
-                "wasi:sockets",
-                "instance-network" | "ip-name-lookup" | "network" | "tcp-create-socket" | "tcp"
-                | "udp-create-socket" | "udp",
-                Some(version),
-            )) if is_0_2(version, 0) => {}
-            _ => {
-                if let Err(err) = link_item(
-                    &engine,
-                    &mut linker.root(),
-                    Arc::clone(&guest_resources),
-                    Arc::clone(&host_resources),
-                    item,
-                    "",
-                    name,
-                ) {
-                    error!(?err, "failed to polyfill instance");
-                }
-            }
+        if interfaces.is_linked(&versions, name) {
+            imports.extend(funcs.into_iter().map(|(instance, name)| Item {
+                instance,
+                name,
+                resolution: Resolution::Linked,
+                reason: None,
+            }));
+            continue;
+        }
+        if let Err(err) = link_item(
+            engine,
+            &mut linker.root(),
+            Arc::clone(&guest_resources),
+            Arc::clone(&host_resources),
+            item,
+            "",
+            name,
+        ) {
+            error!(?err, "failed to polyfill instance");
+            let reason = format!("{err:#}");
+            imports.extend(funcs.into_iter().map(|(instance, name)| Item {
+                instance,
+                name,
+                resolution: Resolution::Unsupported,
+                reason: Some(reason.clone()),
+            }));
+        } else {
+            imports.extend(funcs.into_iter().map(|(instance, name)| Item {
+                instance,
+                name,
+                resolution: Resolution::Polyfilled,
+                reason: None,
+            }));
         }
     }
+    Ok(LinkedComponent {
+        linker,
+        guest_resources,
+        host_resources,
+        imports,
+    })
+}
+
+/// Returns the `(instance, name)` pairs of functions provided by import `name` of type `ty`.
+///
+/// Instance imports yield all of their functions, other items are returned as root items
+fn import_functions(
+    engine: &Engine,
+    name: &str,
+    ty: &types::ComponentItem,
+) -> Vec<(String, String)> {
+    if let types::ComponentItem::ComponentInstance(ty) = ty {
+        ty.exports(engine)
+            .filter(|(_, ty)| matches!(ty, types::ComponentItem::ComponentFunc(_)))
+            .map(|(func, _)| (name.to_string(), func.to_string()))
+            .collect()
+    } else {
+        vec![(String::new(), name.to_string())]
+    }
+}
+
+#[instrument(level = "trace", skip(adapter))]
+async fn instantiate_pre<C>(
+    adapter: &[u8],
+    workload: &str,
+    limits: Limits,
+    interfaces: HostInterfaces,
+) -> anyhow::Result<(
+    InstancePre<Ctx<C>>,
+    Engine,
+    Arc<[ResourceType]>,
+    Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
+)>
+where
+    C: Invoke + Clone + 'static,
+    C::Context: Clone + 'static,
+{
+    let (engine, component) = load_component(adapter, workload, limits).await?;
+    let LinkedComponent {
+        linker,
+        guest_resources,
+        host_resources,
+        ..
+    } = link_component(&engine, &component, interfaces)?;
 
     let pre = linker
         .instantiate_pre(&component)
@@ -355,51 +1075,161 @@ where
     Ok((pre, engine, guest_resources, host_resources))
 }
 
-fn new_store<C: Invoke>(
+/// Configuration of stores constructed for workloads
+pub struct StoreConfig<C: Invoke> {
+    /// Client used to invoke polyfilled imports
+    pub client: C,
+    /// Context passed to `client` on invocation
+    pub cx: C::Context,
+    /// Invocation timeout of polyfilled imports
+    pub timeout: Duration,
+    /// Environment exposed to the guest
+    pub env: GuestEnv,
+    /// Resource limits of the guest
+    pub limits: Limits,
+}
+
+/// Configuration of [`handle_serve`]
+#[derive(Clone, Debug)]
+pub struct ServeConfig {
+    /// Host interfaces linked to the workloads
+    pub interfaces: HostInterfaces,
+    /// Maximum number of invocations served concurrently by all workloads
+    pub max_concurrent_invocations: NonZeroUsize,
+    /// Time to wait for in-flight invocations to complete on shutdown
+    pub grace_period: Duration,
+    /// Behavior when serving an invocation panics
+    pub panic_policy: PanicPolicy,
+}
+
+/// Constructs a [Store] for a workload invoked as `arg0` with arguments `args`
+fn new_store<C>(
+    engine: &Engine,
+    config: &StoreConfig<C>,
+    arg0: &str,
+    args: &[String],
+) -> wasmtime::Store<Ctx<C>>
+where
+    C: Invoke + Clone + 'static,
+    C::Context: Clone + 'static,
+{
+    new_store_with_host_env(engine, config, arg0, args, std::env::vars())
+}
+
+/// Like [new_store], but uses `host_env` as the environment of the host process inherited
+/// by the guest if [`GuestEnv::inherit`] is set
+fn new_store_with_host_env<C>(
     engine: &Engine,
-    wrpc: C,
-    cx: C::Context,
+    StoreConfig {
+        client,
+        cx,
+        timeout,
+        env,
+        limits,
+    }: &StoreConfig<C>,
     arg0: &str,
-    timeout: Duration,
-) -> wasmtime::Store<Ctx<C>> {
-    Store::new(
+    args: &[String],
+    host_env: impl IntoIterator<Item = (String, String)>,
+) -> wasmtime::Store<Ctx<C>>
+where
+    C: Invoke + Clone + 'static,
+    C::Context: Clone + 'static,
+{
+    let mut wasi = WasiCtxBuilder::new();
+    let mut host_resource_codecs = HostResourceCodecs::default();
+    http::register_codecs(&mut host_resource_codecs);
+    if env.inherit {
+        for (k, v) in host_env {
+            wasi.env(k, v);
+        }
+    }
+    for Preopen {
+        host,
+        guest,
+        read_only,
+    } in env.preopens.iter()
+    {
+        let (dir_perms, file_perms) = if *read_only {
+            (DirPerms::READ, FilePerms::READ)
+        } else {
+            (DirPerms::all(), FilePerms::all())
+        };
+        if let Err(err) = wasi.preopened_dir(host, guest, dir_perms, file_perms) {
+            error!(?err, host = %host.display(), guest, "failed to preopen directory");
+        }
+    }
+    match &env.stdio {
+        GuestStdio::Discard => {}
+        GuestStdio::Inherit => {
+            wasi.inherit_stdio();
+        }
+        GuestStdio::Capture { stdout, stderr } => {
+            wasi.stdout(stdout.clone()).stderr(stderr.clone());
+        }
+    }
+    if env.network {
+        wasi.inherit_network()
+            .allow_ip_name_lookup(true)
+            .allow_tcp(true)
+            .allow_udp(true);
+    } else {
+        wasi.allow_ip_name_lookup(false)
+            .allow_tcp(false)
+            .allow_udp(false);
+    }
+    let mut store = Store::new(
         engine,
         Ctx {
-            wasi: WasiCtxBuilder::new()
-                .inherit_env()
-                .inherit_stdio()
-                .inherit_network()
-                .allow_ip_name_lookup(true)
-                .allow_tcp(true)
-                .allow_udp(true)
-                .args(&[arg0])
-                .build(),
+            wasi: wasi.envs(&env.vars[..]).arg(arg0).args(args).build(),
             http: WasiHttpCtx::new(),
             table: ResourceTable::new(),
             wrpc: WrpcCtx {
-                wrpc,
-                cx,
+                wrpc: client.clone(),
+                cx: cx.clone(),
                 shared_resources: SharedResourceTable::default(),
-                timeout,
+                timeout: *timeout,
             },
+            limits: limits.store_limits(),
+            host_resource_codecs: Arc::new(host_resource_codecs),
         },
-    )
+    );
+    store.limiter(|ctx| &mut ctx.limits);
+    if let Some(fuel) = limits.fuel {
+        if let Err(err) = store.set_fuel(fuel) {
+            warn!(?err, "failed to set fuel");
+        }
+    }
+    if let Some(ms) = limits.epoch_deadline_ms {
+        store.set_epoch_deadline(ms.div_ceil(EPOCH_TICK.as_millis() as u64).max(1));
+        store.epoch_deadline_trap();
+    }
+    store
 }
 
-#[instrument(level = "trace", skip(clt, cx), ret(level = "trace"))]
+#[instrument(level = "trace", skip(store), ret(level = "trace"))]
 pub async fn handle_run<C>(
-    clt: C,
-    cx: C::Context,
-    timeout: Duration,
+    store: StoreConfig<C>,
+    interfaces: HostInterfaces,
+    adapter: &Adapter,
     workload: &str,
+    args: &[String],
 ) -> anyhow::Result<()>
 where
     C: Invoke + Clone + 'static,
     C::Context: Clone + 'static,
 {
-    let (pre, engine, _, _) =
-        instantiate_pre(WASI_SNAPSHOT_PREVIEW1_COMMAND_ADAPTER, workload).await?;
-    let mut store = new_store(&engine, clt, cx, "command.wasm", timeout);
+    let adapter = adapter.load().await?;
+    let (pre, engine, _, _) = instantiate_pre(&adapter, workload, store.limits, interfaces).await?;
+    // Commands are run interactively, so they always inherit stdio and network of the host
+    let config = StoreConfig {
+        env: GuestEnv {
+            stdio: GuestStdio::Inherit,
+            network: true,
+            ..store.env
+        },
+        ..store
+    };
+    let mut store = new_store(&engine, &config, "command.wasm", args);
     let cmd = wasmtime_wasi::p2::bindings::CommandPre::new(pre)
         .context("failed to construct `command` instance")?
         .instantiate_async(&mut store)
@@ -412,352 +1242,504 @@ where
         .map_err(|()| anyhow!("component failed"))
 }
 
-#[instrument(level = "trace", skip_all, ret(level = "trace"))]
-pub async fn serve_shared<C, S>(
-    handlers: &mut JoinSet<()>,
-    srv: S,
-    mut store: wasmtime::Store<Ctx<C>>,
-    pre: InstancePre<Ctx<C>>,
-    guest_resources: Arc<[ResourceType]>,
-    host_resources: Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
-) -> anyhow::Result<()>
-where
-    C: Invoke + 'static,
-    C::Context: Clone,
-    S: Serve,
-{
-    let span = Span::current();
-    let instance = pre
-        .instantiate_async(&mut store)
-        .await
-        .context("failed to instantiate component")?;
-    let engine = store.engine().clone();
-    let store = Arc::new(Mutex::new(store));
-    for (name, ty) in pre.component().component_type().exports(&engine) {
-        match (name, ty) {
-            (name, types::ComponentItem::ComponentFunc(ty)) => {
-                info!(?name, "serving root function");
-                let invocations = srv
-                    .serve_function_shared(
-                        Arc::clone(&store),
-                        instance,
-                        Arc::clone(&guest_resources),
-                        Arc::clone(&host_resources),
-                        ty,
-                        "",
-                        name,
-                    )
-                    .await?;
-                handlers.spawn(
-                    async move {
-                        let mut invocations = pin!(invocations);
-                        while let Some(invocation) = invocations.next().await {
-                            match invocation {
-                                Ok((_, fut)) => {
-                                    info!("serving root function invocation");
-                                    if let Err(err) = fut.await {
-                                        warn!(?err, "failed to serve root function invocation");
-                                    } else {
-                                        info!("successfully served root function invocation");
-                                    }
-                                }
-                                Err(err) => {
-                                    error!(?err, "failed to accept root function invocation");
-                                }
-                            }
-                        }
-                    }
-                    .instrument(span.clone()),
-                );
-            }
-            (_, types::ComponentItem::CoreFunc(_)) => {
-                warn!(name, "serving root core function exports not supported yet");
-            }
-            (_, types::ComponentItem::Module(_)) => {
-                warn!(name, "serving root module exports not supported yet");
-            }
-            (_, types::ComponentItem::Component(_)) => {
-                warn!(name, "serving root component exports not supported yet");
-            }
-            (instance_name, types::ComponentItem::ComponentInstance(ty)) => {
-                for (name, ty) in ty.exports(&engine) {
-                    match ty {
-                        types::ComponentItem::ComponentFunc(ty) => {
-                            info!(?name, "serving instance function");
-                            let invocations = srv
-                                .serve_function_shared(
-                                    Arc::clone(&store),
-                                    instance,
-                                    Arc::clone(&guest_resources),
-                                    Arc::clone(&host_resources),
-                                    ty,
-                                    instance_name,
-                                    name,
-                                )
-                                .await?;
-                            handlers.spawn(async move {
-                                let mut invocations = pin!(invocations);
-                                while let Some(invocation) = invocations.next().await {
-                                    match invocation {
-                                        Ok((_, fut)) => {
-                                            info!("serving instance function invocation");
-                                            if let Err(err) = fut.await {
-                                                warn!(
-                                                    ?err,
-                                                    "failed to serve instance function invocation"
-                                                );
-                                            } else {
-                                                info!(
-                                                    "successfully served instance function invocation"
-                                                );
-                                            }
-                                        }
-                                        Err(err) => {
-                                            error!(
-                                                ?err,
-                                                "failed to accept instance function invocation"
-                                            );
-                                        }
-                                    }
-                                }
-                            }
-                            .instrument(span.clone()));
-                        }
-                        types::ComponentItem::CoreFunc(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving instance core function exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::Module(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving instance module exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::Component(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving instance component exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::ComponentInstance(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving nested instance exports not supported yet"
-                            );
+/// Per-function invocation metrics recorded by the serve loops
+#[derive(Clone)]
+struct InvocationMetrics {
+    invocations: metrics::Counter,
+    succeeded: metrics::Counter,
+    failed: metrics::Counter,
+    accept_errors: metrics::Counter,
+    duration: metrics::Histogram,
+}
+
+impl InvocationMetrics {
+    /// Registers metrics for function `name` exported by `instance` with the current recorder
+    fn new(instance: &str, name: &str) -> Self {
+        let labels = [
+            ("instance", instance.to_string()),
+            ("function", name.to_string()),
+        ];
+        Self {
+            invocations: metrics::counter!("wrpc_invocations_total", &labels),
+            succeeded: metrics::counter!("wrpc_invocations_succeeded_total", &labels),
+            failed: metrics::counter!("wrpc_invocations_failed_total", &labels),
+            accept_errors: metrics::counter!("wrpc_invocation_accept_errors_total", &labels),
+            duration: metrics::histogram!("wrpc_invocation_duration_seconds", &labels),
+        }
+    }
+}
+
+/// Source of [InvocationMetrics] for invocations of type `T` served by [`serve_invocations`]
+trait ServeMetrics<T> {
+    /// Returns the metrics of the function invoked by `invocation` and the span to serve it in
+    fn invocation(&mut self, invocation: &T) -> (InvocationMetrics, Span);
+
+    /// Records an invocation, which failed to be accepted
+    fn accept_error(&self);
+}
+
+/// Invocations of a single function
+impl<T> ServeMetrics<T> for InvocationMetrics {
+    fn invocation(&mut self, _: &T) -> (InvocationMetrics, Span) {
+        (self.clone(), Span::current())
+    }
+
+    fn accept_error(&self) {
+        self.accept_errors.increment(1);
+    }
+}
+
+/// [InvocationMetrics] of all functions served by
+/// [`ServeExt::serve_component`](wrpc_runtime_wasmtime::ServeExt::serve_component), keyed by
+/// instance and function name
+struct ComponentMetrics {
+    functions: HashMap<(Arc<str>, Arc<str>), InvocationMetrics>,
+    /// Invocations, which failed to be accepted, cannot be attributed to a function
+    accept_errors: metrics::Counter,
+}
+
+impl Default for ComponentMetrics {
+    fn default() -> Self {
+        Self {
+            functions: HashMap::default(),
+            accept_errors: metrics::counter!("wrpc_component_invocation_accept_errors_total"),
+        }
+    }
+}
+
+impl ComponentMetrics {
+    /// Registers metrics for function `name` exported by `instance`
+    fn register(&mut self, instance: &str, name: &str) -> InvocationMetrics {
+        self.functions
+            .entry((instance.into(), name.into()))
+            .or_insert_with(|| InvocationMetrics::new(instance, name))
+            .clone()
+    }
+}
+
+impl<C> ServeMetrics<(Arc<str>, Arc<str>, C)> for ComponentMetrics {
+    fn invocation(
+        &mut self,
+        (instance, name, _): &(Arc<str>, Arc<str>, C),
+    ) -> (InvocationMetrics, Span) {
+        let metrics = self.register(instance, name);
+        let span = info_span!("invocation", instance = &**instance, name = &**name);
+        (metrics, span)
+    }
+
+    fn accept_error(&self) {
+        self.accept_errors.increment(1);
+    }
+}
+
+/// Installs a Prometheus recorder for invocation metrics and serves them on `addr`
+pub fn install_metrics_exporter(addr: SocketAddr) -> anyhow::Result<()> {
+    metrics_exporter_prometheus::PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .install()
+        .with_context(|| format!("failed to serve Prometheus metrics on `{addr}`"))
+}
+
+/// Behavior of the serve loops once serving an invocation panics
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, ValueEnum)]
+pub enum PanicPolicy {
+    /// Fail the panicking invocation and continue serving. Instances shared by invocations
+    /// are rebuilt before serving the next invocation
+    #[default]
+    Isolate,
+    /// Stop serving all exports and fail
+    Abort,
+}
+
+/// Records a failed invocation, if dropped while the invocation task panics
+struct PanicGuard(metrics::Counter);
+
+impl Drop for PanicGuard {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            self.0.increment(1);
+        }
+    }
+}
+
+/// Handles the completion of an invocation task spawned by [`serve_invocations`].
+/// If the task panicked and `policy` is [`PanicPolicy::Abort`], the panic is propagated.
+fn handle_invocation_task(res: Result<(), JoinError>, policy: PanicPolicy) {
+    match res {
+        Ok(()) => {}
+        Err(err) if err.is_panic() => {
+            error!("invocation handler panicked");
+            if policy == PanicPolicy::Abort {
+                std::panic::resume_unwind(err.into_panic());
+            }
+        }
+        Err(err) => warn!(?err, "invocation task cancelled"),
+    }
+}
+
+/// Serves `invocations` concurrently, keeping at most as many invocations in flight
+/// as there are `permits` available. The next invocation is only accepted once a permit
+/// is acquired, permits are released when the invocation completes.
+///
+/// Once `shutdown` is cancelled, no further invocations are accepted and this function
+/// returns after all in-flight invocations complete.
+///
+/// If serving an invocation panics and `panic_policy` is [`PanicPolicy::Abort`], the panic
+/// is propagated once observed.
+async fn serve_invocations<T, F>(
+    invocations: impl Stream<Item = anyhow::Result<(T, F)>>,
+    permits: Arc<Semaphore>,
+    mut metrics: impl ServeMetrics<T>,
+    shutdown: CancellationToken,
+    panic_policy: PanicPolicy,
+) where
+    F: Future<Output = anyhow::Result<()>> + Send + 'static,
+{
+    let mut invocations = pin!(invocations.take_until(shutdown.cancelled_owned()));
+    let mut tasks = JoinSet::new();
+    loop {
+        let Some(invocation) = invocations.next().await else {
+            break;
+        };
+        match invocation {
+            Ok((invocation, fut)) => {
+                // `permits` may be shared by multiple exports, only acquire a permit once there
+                // is an invocation to serve, so that idle exports do not hold on to permits
+                let Ok(permit) = Arc::clone(&permits).acquire_owned().await else {
+                    error!("invocation semaphore closed");
+                    break;
+                };
+                let (metrics, span) = metrics.invocation(&invocation);
+                span.in_scope(|| info!("serving invocation"));
+                metrics.invocations.increment(1);
+                tasks.spawn(
+                    async move {
+                        let _panic = PanicGuard(metrics.failed.clone());
+                        let start = Instant::now();
+                        let res = fut.await;
+                        metrics.duration.record(start.elapsed());
+                        if let Err(err) = res {
+                            metrics.failed.increment(1);
+                            warn!(?err, "failed to serve invocation");
+                        } else {
+                            metrics.succeeded.increment(1);
+                            info!("successfully served invocation");
                         }
-                        types::ComponentItem::Type(_) | types::ComponentItem::Resource(_) => {}
+                        drop(permit);
                     }
-                }
+                    .instrument(span),
+                );
             }
-            (_, types::ComponentItem::Type(_) | types::ComponentItem::Resource(_)) => {}
+            Err(err) => {
+                metrics.accept_error();
+                error!(?err, "failed to accept invocation");
+            }
+        }
+        while let Some(res) = tasks.try_join_next() {
+            handle_invocation_task(res, panic_policy);
         }
     }
-    Ok(())
+    while let Some(res) = tasks.join_next().await {
+        handle_invocation_task(res, panic_policy);
+    }
 }
 
+/// Returns the [Item] describing an export of `kind`, which cannot be served
+fn unsupported_export(instance: String, name: String, kind: &str) -> Item {
+    Item {
+        instance,
+        name,
+        resolution: Resolution::Unsupported,
+        reason: Some(format!("serving {kind} exports not supported yet")),
+    }
+}
+
+/// Serves all exports of the component instantiated from `pre` using
+/// [`ServeExt::serve_component`](wrpc_runtime_wasmtime::ServeExt::serve_component).
+///
+/// If the component exports resources, all invocations share a single instance, which is
+/// re-instantiated into a fresh store constructed by `store` if it becomes unusable, for example,
+/// because the guest trapped. Otherwise, each invocation is served using a new store.
+///
+/// Returns the exports, which cannot be served and were skipped.
 #[instrument(level = "trace", skip_all, ret(level = "trace"))]
 #[allow(clippy::too_many_arguments)]
-pub async fn serve_stateless<C, S>(
+pub async fn serve_workload<C, S>(
     handlers: &mut JoinSet<()>,
     srv: S,
-    clt: C,
-    cx: C::Context,
+    store: impl Fn() -> wasmtime::Store<Ctx<C>> + Send + Sync + 'static,
     pre: InstancePre<Ctx<C>>,
+    guest_resources: Arc<[ResourceType]>,
     host_resources: Arc<HashMap<Box<str>, HashMap<Box<str>, (ResourceType, ResourceType)>>>,
-    engine: &Engine,
-    timeout: Duration,
-) -> anyhow::Result<()>
+    permits: Arc<Semaphore>,
+    shutdown: CancellationToken,
+    panic_policy: PanicPolicy,
+) -> anyhow::Result<Vec<Item>>
 where
-    C: Invoke + Clone + 'static,
-    C::Context: Clone + 'static,
+    C: Invoke + 'static,
+    C::Context: Clone,
     S: Serve,
 {
-    let span = Span::current();
-    for (name, ty) in pre.component().component_type().exports(engine) {
-        match (name, ty) {
-            (name, types::ComponentItem::ComponentFunc(ty)) => {
-                let clt = clt.clone();
-                let cx = cx.clone();
-                let engine = engine.clone();
-                info!(?name, "serving root function");
-                let invocations = srv
-                    .serve_function(
-                        move || {
-                            new_store(&engine, clt.clone(), cx.clone(), "reactor.wasm", timeout)
-                        },
-                        pre.clone(),
-                        Arc::clone(&host_resources),
-                        ty,
-                        "",
-                        name,
-                    )
-                    .await?;
-                handlers.spawn(
-                    async move {
-                        let mut invocations = pin!(invocations);
-                        while let Some(invocation) = invocations.next().await {
-                            match invocation {
-                                Ok((_, fut)) => {
-                                    info!("serving root function invocation");
-                                    if let Err(err) = fut.await {
-                                        warn!(?err, "failed to serve root function invocation");
-                                    } else {
-                                        info!("successfully served root function invocation");
-                                    }
-                                }
-                                Err(err) => {
-                                    error!(?err, "failed to accept root function invocation");
-                                }
-                            }
-                        }
-                    }
-                    .instrument(span.clone()),
-                );
-            }
-            (_, types::ComponentItem::CoreFunc(_)) => {
-                warn!(name, "serving root core function exports not supported yet");
+    let mut metrics = ComponentMetrics::default();
+    let mut skipped = Vec::new();
+    for export in component_exports(pre.component()) {
+        match export {
+            Export::Function { instance, name, .. } => {
+                info!(instance, name, "serving function");
+                metrics.register(&instance, &name);
             }
-            (_, types::ComponentItem::Module(_)) => {
-                warn!(name, "serving root module exports not supported yet");
+            Export::Resource { instance, name, .. } if !guest_resources.is_empty() => {
+                info!(instance, name, "serving resource drop");
+                metrics.register(&instance, &format!("[resource-drop]{name}"));
             }
-            (_, types::ComponentItem::Component(_)) => {
-                warn!(name, "serving root component exports not supported yet");
+            Export::Resource { .. } => {}
+            Export::Unsupported {
+                instance,
+                name,
+                kind,
+            } => {
+                warn!(instance, name, kind, "serving export not supported yet");
+                skipped.push(unsupported_export(instance, name, kind));
             }
-            (instance_name, types::ComponentItem::ComponentInstance(ty)) => {
-                for (name, ty) in ty.exports(engine) {
-                    match ty {
-                        types::ComponentItem::ComponentFunc(ty) => {
-                            let clt = clt.clone();
-                            let engine = engine.clone();
-                            let cx = cx.clone();
-                            info!(?name, "serving instance function");
-                            let invocations = srv
-                                .serve_function(
-                                    move || {
-                                        new_store(
-                                            &engine,
-                                            clt.clone(),
-                                            cx.clone(),
-                                            "reactor.wasm",
-                                            timeout,
-                                        )
-                                    },
-                                    pre.clone(),
-                                    Arc::clone(&host_resources),
-                                    ty,
-                                    instance_name,
-                                    name,
-                                )
-                                .await?;
-                            handlers.spawn(async move {
-                                let mut invocations = pin!(invocations);
-                                while let Some(invocation) = invocations.next().await {
-                                    match invocation {
-                                        Ok((_, fut)) => {
-                                            info!("serving instance function invocation");
-                                            if let Err(err) = fut.await {
-                                                warn!(
-                                                    ?err,
-                                                    "failed to serve instance function invocation"
-                                                );
-                                            } else {
-                                                info!(
-                                                    "successfully served instance function invocation"
-                                                );
-                                            }
-                                        }
-                                        Err(err) => {
-                                            error!(
-                                                ?err,
-                                                "failed to accept instance function invocation"
-                                            );
-                                        }
-                                    }
-                                }
-                            }.instrument(span.clone()));
-                        }
-                        types::ComponentItem::CoreFunc(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving instance core function exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::Module(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving instance module exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::Component(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving instance component exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::ComponentInstance(_) => {
-                            warn!(
-                                instance_name,
-                                name, "serving nested instance exports not supported yet"
-                            );
-                        }
-                        types::ComponentItem::Type(_) | types::ComponentItem::Resource(_) => {}
-                    }
+        }
+    }
+    let invocations = srv
+        .serve_component(store, pre, guest_resources, host_resources)
+        .await?
+        .map_ok(|(instance, name, cx, fut)| ((instance, name, cx), fut));
+    handlers.spawn(
+        serve_invocations(invocations, permits, metrics, shutdown, panic_policy)
+            .instrument(info_span!("serve")),
+    );
+    Ok(skipped)
+}
+
+/// Status of a reactor component served by [`handle_serve`], which can be observed to
+/// implement readiness and liveness probes
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ServeStatus {
+    /// The component is being instantiated and its exports are not served yet
+    #[default]
+    Starting,
+    /// All exports are served and invocations are accepted
+    Ready,
+    /// Shutdown was requested or all export handlers finished, no further invocations
+    /// are accepted
+    Stopped,
+}
+
+/// Waits for all `handlers` to complete or for `shutdown` to resolve.
+///
+/// On shutdown, `token` is cancelled to stop accepting new invocations and handlers are given
+/// `grace_period` to finish serving in-flight invocations before they are aborted.
+/// `status` is set to [`ServeStatus::Stopped`] once handlers stop accepting invocations.
+///
+/// If a handler panics and `panic_policy` is [`PanicPolicy::Abort`], all handlers are aborted
+/// immediately and an error is returned.
+async fn join_handlers(
+    handlers: &mut JoinSet<()>,
+    shutdown: impl Future<Output = ()>,
+    token: &CancellationToken,
+    grace_period: Duration,
+    status: &watch::Sender<ServeStatus>,
+    panic_policy: PanicPolicy,
+) -> anyhow::Result<()> {
+    let mut shutdown = pin!(shutdown);
+    loop {
+        select! {
+            res = handlers.join_next() => match res {
+                Some(Ok(())) => {}
+                Some(Err(err)) if err.is_panic() && panic_policy == PanicPolicy::Abort => {
+                    error!(?err, "handler panicked, aborting all handlers");
+                    status.send_replace(ServeStatus::Stopped);
+                    token.cancel();
+                    handlers.shutdown().await;
+                    bail!("handler panicked");
                 }
+                Some(Err(err)) => error!(?err, "handler failed"),
+                None => {
+                    status.send_replace(ServeStatus::Stopped);
+                    return Ok(());
+                }
+            },
+            () = &mut shutdown => break,
+        }
+    }
+    status.send_replace(ServeStatus::Stopped);
+    info!(
+        ?grace_period,
+        "shutting down, waiting for in-flight invocations"
+    );
+    token.cancel();
+    let drain = async {
+        while let Some(res) = handlers.join_next().await {
+            if let Err(err) = res {
+                error!(?err, "handler failed");
             }
-            (_, types::ComponentItem::Type(_) | types::ComponentItem::Resource(_)) => {}
         }
+    };
+    if tokio::time::timeout(grace_period, drain).await.is_err() {
+        warn!("shutdown grace period elapsed, aborting in-flight invocations");
+        handlers.shutdown().await;
     }
     Ok(())
 }
 
-#[instrument(level = "trace", skip(srv, clt, cx), ret(level = "trace"))]
+/// Resolves once the process receives SIGINT or, on Unix, SIGTERM
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(err) => {
+                error!(?err, "failed to install SIGTERM handler");
+                future::pending().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+    select! {
+        res = tokio::signal::ctrl_c() => {
+            if let Err(err) = res {
+                error!(?err, "failed to install SIGINT handler");
+                future::pending::<()>().await;
+            }
+        }
+        () = terminate => {}
+    }
+}
+
+/// Serves all exports of the reactor components `workloads` using `srv` until `shutdown`
+/// resolves.
+///
+/// Each workload is instantiated separately and must export instances distinct from those of
+/// all other workloads, all workloads share the [`ServeConfig::max_concurrent_invocations`] limit.
+/// Stores of all workloads are constructed using `store`.
+///
+/// `status` is set to [`ServeStatus::Ready`] once all exports are served and to
+/// [`ServeStatus::Stopped`] once invocations are no longer accepted.
+///
+/// Handler tasks are owned by the returned future, dropping it aborts all of them.
+/// [`ServeConfig::panic_policy`] determines whether a panic while serving an invocation stops
+/// serving.
+#[instrument(
+    level = "trace",
+    skip(srv, store, shutdown, status),
+    ret(level = "trace")
+)]
 pub async fn handle_serve<C, S>(
     srv: S,
-    clt: C,
-    cx: C::Context,
-    timeout: Duration,
-    workload: &str,
+    store: StoreConfig<C>,
+    ServeConfig {
+        interfaces,
+        max_concurrent_invocations,
+        grace_period,
+        panic_policy,
+    }: ServeConfig,
+    shutdown: impl Future<Output = ()>,
+    status: watch::Sender<ServeStatus>,
+    adapter: &Adapter,
+    workloads: &[String],
 ) -> anyhow::Result<()>
 where
     C: Invoke + Clone + 'static,
     C::Context: Clone + 'static,
-    S: Serve,
+    S: Serve + Clone,
 {
-    let (pre, engine, guest_resources, host_resources) =
-        instantiate_pre(WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER, workload).await?;
-
+    ensure!(!workloads.is_empty(), "no workloads specified");
+    let adapter = adapter.load().await?;
+    let store = Arc::new(store);
+    let permits = Arc::new(Semaphore::new(max_concurrent_invocations.get()));
+    let token = CancellationToken::new();
     let mut handlers = JoinSet::new();
-    if guest_resources.is_empty() {
-        serve_stateless(
-            &mut handlers,
-            srv,
-            clt,
-            cx,
-            pre,
-            host_resources,
-            &engine,
-            timeout,
-        )
-        .await?;
-    } else {
-        serve_shared(
+    // exported instances, or names of root exports, mapped to the index of the exporting workload
+    let mut namespaces = HashMap::<String, usize>::new();
+    for (i, workload) in workloads.iter().enumerate() {
+        let (pre, engine, guest_resources, host_resources) =
+            instantiate_pre(&adapter, workload, store.limits, interfaces.clone()).await?;
+        for export in component_exports(pre.component()) {
+            let (Export::Function { instance, name, .. }
+            | Export::Resource { instance, name, .. }
+            | Export::Unsupported { instance, name, .. }) = export;
+            let namespace = if instance.is_empty() { name } else { instance };
+            match namespaces.get(&namespace) {
+                Some(j) if *j != i => bail!(
+                    "`{namespace}` exported by `{workload}` is already exported by `{}`",
+                    workloads[*j]
+                ),
+                Some(_) => {}
+                None => {
+                    namespaces.insert(namespace, i);
+                }
+            }
+        }
+        let store = Arc::clone(&store);
+        serve_workload(
             &mut handlers,
-            srv,
-            new_store(&engine, clt, cx, "reactor.wasm", timeout),
+            srv.clone(),
+            move || new_store(&engine, &store, "reactor.wasm", &[]),
             pre,
             guest_resources,
             host_resources,
+            Arc::clone(&permits),
+            token.clone(),
+            panic_policy,
         )
         .await?;
     }
-    while let Some(res) = handlers.join_next().await {
-        if let Err(err) = res {
-            error!(?err, "handler failed");
+    status.send_replace(ServeStatus::Ready);
+    join_handlers(
+        &mut handlers,
+        shutdown,
+        &token,
+        grace_period,
+        &status,
+        panic_policy,
+    )
+    .await
+}
+
+/// Resolves imports and exports of the reactor component `workload` the same way
+/// [handle_serve] does, without instantiating or serving it
+#[instrument(level = "trace", ret(level = "trace"))]
+pub async fn inspect(interfaces: HostInterfaces, workload: &str) -> anyhow::Result<Inspection> {
+    let (engine, component) = load_component(
+        WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+        workload,
+        Limits::default(),
+    )
+    .await?;
+    // the transport does not affect resolution, any client type will do
+    let LinkedComponent { imports, .. } =
+        link_component::<wrpc_transport::tcp::Client<SocketAddr>>(&engine, &component, interfaces)?;
+    let mut exports = Vec::new();
+    let mut guest_resources = Vec::new();
+    for export in component_exports(&component) {
+        match export {
+            Export::Function { instance, name, .. } => exports.push(Item {
+                instance,
+                name,
+                resolution: Resolution::Served,
+                reason: None,
+            }),
+            Export::Resource { instance, name, .. } => {
+                guest_resources.push(GuestResource { instance, name });
+            }
+            Export::Unsupported {
+                instance,
+                name,
+                kind,
+            } => exports.push(unsupported_export(instance, name, kind)),
         }
     }
-    Ok(())
+    Ok(Inspection {
+        imports,
+        exports,
+        guest_resources,
+    })
 }
 
 #[instrument(level = "trace", ret(level = "trace"))]
@@ -766,5 +1748,1271 @@ pub async fn run() -> anyhow::Result<()> {
     match Command::parse() {
         Command::Nats(args) => nats::run(args).await,
         Command::Tcp(args) => tcp::run(args).await,
+        #[cfg(unix)]
+        Command::Uds(args) => uds::run(args).await,
+        Command::Inspect(InspectArgs {
+            json,
+            interfaces,
+            workload,
+        }) => {
+            let inspection = inspect(interfaces, &workload).await?;
+            if json {
+                let inspection = serde_json::to_string_pretty(&inspection)
+                    .context("failed to encode inspection as JSON")?;
+                println!("{inspection}");
+            } else {
+                print!("{inspection}");
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::net::Ipv4Addr;
+
+    use bytes::Bytes;
+    use futures::FutureExt as _;
+    use wrpc_transport::ServeExt as _;
+
+    use super::*;
+    use crate::http::{IncomingResponse, OutgoingRequest};
+
+    /// Core module `$libc` exporting `memory` and a bump allocator `realloc` followed by its
+    /// instance `$libc`. Additional fields of the module, like data segments, can be passed
+    macro_rules! libc {
+        ($($field:literal)?) => {
+            concat!(
+                r#"  (core module $libc
+    (memory (export "memory") 1)
+    (global $heap (mut i32) (i32.const 1024))
+    (func (export "realloc") (param i32 i32 i32 i32) (result i32)
+      (local $ret i32)
+      (local.set $ret
+        (i32.and
+          (i32.add (global.get $heap) (i32.sub (local.get 2) (i32.const 1)))
+          (i32.sub (i32.const 0) (local.get 2))))
+      (global.set $heap (i32.add (local.get $ret) (local.get 3)))
+      (local.get $ret))
+"#,
+                $("    ", $field, "\n",)?
+                r#"  )
+  (core instance $libc (instantiate $libc))
+"#
+            )
+        };
+    }
+
+    const ENV_COMPONENT: &str = concat!(
+        r#"(component
+  (import "wasi:cli/environment@0.2.0" (instance $env
+    (export "get-environment" (func (result (list (tuple string string)))))
+  ))
+"#,
+        libc!(),
+        r#"  (core func $get-environment
+    (canon lower (func $env "get-environment")
+      (memory $libc "memory") (realloc (func $libc "realloc"))))
+  (core module $m
+    (import "libc" "memory" (memory 1))
+    (import "env" "get-environment" (func $get-environment (param i32)))
+    (func (export "get") (result i32)
+      (call $get-environment (i32.const 16))
+      (i32.const 16))
+  )
+  (core instance $m (instantiate $m
+    (with "libc" (instance $libc))
+    (with "env" (instance (export "get-environment" (func $get-environment))))
+  ))
+  (func (export "get") (result (list (tuple string string)))
+    (canon lift (core func $m "get") (memory $libc "memory") (realloc (func $libc "realloc"))))
+)"#
+    );
+
+    #[test]
+    fn env_file() -> anyhow::Result<()> {
+        let vars = parse_env_file(
+            r#"
+# comment
+FOO=bar
+export BAZ = "quoted value"
+SINGLE='single'
+EMPTY=
+"#,
+            Path::new(".env"),
+        )?;
+        assert_eq!(
+            vars,
+            [
+                ("FOO".into(), "bar".into()),
+                ("BAZ".into(), "quoted value".into()),
+                ("SINGLE".into(), "single".into()),
+                ("EMPTY".into(), String::new()),
+            ]
+        );
+        assert!(parse_env_file("INVALID", Path::new(".env")).is_err());
+        assert!(parse_env_file("=value", Path::new(".env")).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn preopen() -> anyhow::Result<()> {
+        assert_eq!(
+            parse_preopen("/tmp/data::/data")?,
+            Preopen {
+                host: "/tmp/data".into(),
+                guest: "/data".into(),
+                read_only: false,
+            }
+        );
+        assert_eq!(
+            parse_preopen("/tmp/data::/data::ro")?,
+            Preopen {
+                host: "/tmp/data".into(),
+                guest: "/data".into(),
+                read_only: true,
+            }
+        );
+        assert_eq!(
+            parse_preopen("data")?,
+            Preopen {
+                host: "data".into(),
+                guest: "data".into(),
+                read_only: false,
+            }
+        );
+        assert!(parse_preopen("::/data").is_err());
+        assert!(parse_preopen("/tmp/data::").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn interface_versions() -> anyhow::Result<()> {
+        let interfaces = HostInterfaces::default();
+        let versions = interfaces.version_policy()?;
+        assert!(interfaces.is_linked(&versions, "wasi:http/types@0.2.0"));
+        assert!(interfaces.is_linked(&versions, "wasi:http/types@0.2.3"));
+        assert!(interfaces.is_linked(&versions, "wasi:clocks/timezone@0.2.1"));
+        assert!(interfaces.is_linked(&versions, "wrpc:rpc/error@0.1.0"));
+        assert!(!interfaces.is_linked(&versions, "wasi:http/types@0.3.0"));
+        assert!(!interfaces.is_linked(&versions, "wasi:http/types@0.2.3-rc"));
+        assert!(!interfaces.is_linked(&versions, "wasi:http/types@0.2.3+build"));
+        assert!(!interfaces.is_linked(&versions, "wasi:http/types"));
+        assert!(!interfaces.is_linked(&versions, "wasi:clocks/timezone@0.2.0"));
+        assert!(!interfaces.is_linked(&versions, "wrpc:rpc/error@0.1.1"));
+        assert!(!interfaces.is_linked(&versions, "wasi:keyvalue/store@0.2.0"));
+
+        let interfaces = HostInterfaces::default()
+            .interface_version("wasi:http/types", ">=0.2.0, <0.2.3".parse()?)
+            .interface_version("wasi:io/streams", ">=0.2.0-rc, <0.3.0".parse()?);
+        let versions = interfaces.version_policy()?;
+        assert!(interfaces.is_linked(&versions, "wasi:http/types@0.2.2"));
+        assert!(!interfaces.is_linked(&versions, "wasi:http/types@0.2.3"));
+        assert!(interfaces.is_linked(&versions, "wasi:http/outgoing-handler@0.2.3"));
+        assert!(interfaces.is_linked(&versions, "wasi:io/streams@0.2.0-rc"));
+
+        let interfaces = interfaces.wasi_http(false);
+        let versions = interfaces.version_policy()?;
+        assert!(!interfaces.is_linked(&versions, "wasi:http/types@0.2.2"));
+
+        HostInterfaces::default()
+            .interface_version("wasi:keyvalue/store", "^0.2.0".parse()?)
+            .version_policy()
+            .expect_err("unknown interface should not be configurable");
+
+        let (interface, req) = parse_interface_version("wasi:http/types=>=0.2.0, <0.2.3")?;
+        assert_eq!(interface, "wasi:http/types");
+        assert_eq!(req, ">=0.2.0, <0.2.3".parse()?);
+        assert!(parse_interface_version("wasi:http/types").is_err());
+        assert!(parse_interface_version("wasi:http/types=invalid").is_err());
+        Ok(())
+    }
+
+    /// Instantiates the component `wat` in a fresh [Store] constructed for `test.wasm` invoked
+    /// with `args` and environment `env` of a host process with environment `host_env`
+    async fn instantiate(
+        wat: &str,
+        env: GuestEnv,
+        limits: Limits,
+        args: &[&str],
+        host_env: &[(&str, &str)],
+    ) -> anyhow::Result<(
+        Store<Ctx<wrpc_transport::tcp::Client<&'static str>>>,
+        wasmtime::component::Instance,
+    )> {
+        let mut config = wasmtime::Config::new();
+        config.wasm_component_model(true);
+        config.async_support(true);
+        limits.configure(&mut config);
+        let engine = Engine::new(&config)?;
+        let component = Component::new(&engine, wat)?;
+        let mut linker = Linker::new(&engine);
+        wasmtime_wasi::p2::add_to_linker_async(&mut linker)?;
+        let args: Vec<_> = args.iter().map(ToString::to_string).collect();
+        let mut store = new_store_with_host_env(
+            &engine,
+            &StoreConfig {
+                client: wrpc_transport::tcp::Client::from("[::1]:0"),
+                cx: (),
+                timeout: Duration::from_secs(1),
+                env,
+                limits,
+            },
+            "test.wasm",
+            &args,
+            host_env.iter().map(|(k, v)| (k.to_string(), v.to_string())),
+        );
+        let instance = linker.instantiate_async(&mut store, &component).await?;
+        Ok((store, instance))
+    }
+
+    #[tokio::test]
+    async fn guest_env() -> anyhow::Result<()> {
+        const HOST_ENV: &[(&str, &str)] = &[("WRPC_TEST_HOST_SECRET", "secret")];
+
+        let env = GuestEnv {
+            inherit: false,
+            vars: [("FOO".to_string(), "bar".to_string())].into(),
+            ..GuestEnv::default()
+        };
+        let (mut store, instance) =
+            instantiate(ENV_COMPONENT, env.clone(), Limits::default(), &[], HOST_ENV).await?;
+        let get = instance.get_typed_func::<(), (Vec<(String, String)>,)>(&mut store, "get")?;
+        let (vars,) = get.call_async(&mut store, ()).await?;
+        assert_eq!(vars, [("FOO".to_string(), "bar".to_string())]);
+
+        let env = GuestEnv {
+            inherit: true,
+            ..env
+        };
+        let (mut store, instance) =
+            instantiate(ENV_COMPONENT, env, Limits::default(), &[], HOST_ENV).await?;
+        let get = instance.get_typed_func::<(), (Vec<(String, String)>,)>(&mut store, "get")?;
+        let (vars,) = get.call_async(&mut store, ()).await?;
+        assert!(vars.contains(&("WRPC_TEST_HOST_SECRET".to_string(), "secret".to_string())));
+        assert!(vars.contains(&("FOO".to_string(), "bar".to_string())));
+        Ok(())
+    }
+
+    const ARGS_COMPONENT: &str = concat!(
+        r#"(component
+  (import "wasi:cli/environment@0.2.0" (instance $env
+    (export "get-arguments" (func (result (list string))))
+  ))
+"#,
+        libc!(),
+        r#"  (core func $get-arguments
+    (canon lower (func $env "get-arguments")
+      (memory $libc "memory") (realloc (func $libc "realloc"))))
+  (core module $m
+    (import "libc" "memory" (memory 1))
+    (import "env" "get-arguments" (func $get-arguments (param i32)))
+    (func (export "get") (result i32)
+      (call $get-arguments (i32.const 16))
+      (i32.const 16))
+  )
+  (core instance $m (instantiate $m
+    (with "libc" (instance $libc))
+    (with "env" (instance (export "get-arguments" (func $get-arguments))))
+  ))
+  (func (export "get") (result (list string))
+    (canon lift (core func $m "get") (memory $libc "memory") (realloc (func $libc "realloc"))))
+)"#
+    );
+
+    #[tokio::test]
+    async fn guest_args() -> anyhow::Result<()> {
+        let (mut store, instance) = instantiate(
+            ARGS_COMPONENT,
+            GuestEnv::default(),
+            Limits::default(),
+            &["foo", "--bar"],
+            &[],
+        )
+        .await?;
+        let get = instance.get_typed_func::<(), (Vec<String>,)>(&mut store, "get")?;
+        let (args,) = get.call_async(&mut store, ()).await?;
+        assert_eq!(args, ["test.wasm", "foo", "--bar"]);
+        Ok(())
+    }
+
+    const FS_COMPONENT: &str = concat!(
+        r#"(component
+  (import "wasi:filesystem/types@0.2.0" (instance $types
+    (export "descriptor" (type $descriptor (sub resource)))
+    (type $error-code (enum
+      "access" "would-block" "already" "bad-descriptor" "busy" "deadlock" "quota" "exist"
+      "file-too-large" "illegal-byte-sequence" "in-progress" "interrupted" "invalid" "io"
+      "is-directory" "loop" "too-many-links" "message-size" "name-too-long" "no-device"
+      "no-entry" "no-lock" "insufficient-memory" "insufficient-space" "not-directory"
+      "not-empty" "not-recoverable" "unsupported" "no-tty" "no-such-device" "overflow"
+      "not-permitted" "pipe" "read-only" "invalid-seek" "text-file-busy" "cross-device"))
+    (export "error-code" (type $error-code' (eq $error-code)))
+    (type $path-flags (flags "symlink-follow"))
+    (export "path-flags" (type $path-flags' (eq $path-flags)))
+    (type $open-flags (flags "create" "directory" "exclusive" "truncate"))
+    (export "open-flags" (type $open-flags' (eq $open-flags)))
+    (type $descriptor-flags (flags
+      "read" "write" "file-integrity-sync" "data-integrity-sync" "requested-write-sync"
+      "mutate-directory"))
+    (export "descriptor-flags" (type $descriptor-flags' (eq $descriptor-flags)))
+    (export "[method]descriptor.open-at" (func
+      (param "self" (borrow $descriptor))
+      (param "path-flags" $path-flags')
+      (param "path" string)
+      (param "open-flags" $open-flags')
+      (param "flags" $descriptor-flags')
+      (result (result (own $descriptor) (error $error-code')))))
+    (export "[method]descriptor.read" (func
+      (param "self" (borrow $descriptor))
+      (param "length" u64)
+      (param "offset" u64)
+      (result (result (tuple (list u8) bool) (error $error-code')))))
+  ))
+  (alias export $types "descriptor" (type $descriptor))
+  (import "wasi:filesystem/preopens@0.2.0" (instance $preopens
+    (alias outer 1 $descriptor (type $descriptor))
+    (export "descriptor" (type $descriptor' (eq $descriptor)))
+    (export "get-directories" (func (result (list (tuple (own $descriptor') string)))))
+  ))
+"#,
+        libc!(r#"(data (i32.const 256) "test.txt")"#),
+        r#"  (core func $get-directories
+    (canon lower (func $preopens "get-directories")
+      (memory $libc "memory") (realloc (func $libc "realloc"))))
+  (core func $open-at
+    (canon lower (func $types "[method]descriptor.open-at")
+      (memory $libc "memory") (realloc (func $libc "realloc"))))
+  (core func $read
+    (canon lower (func $types "[method]descriptor.read")
+      (memory $libc "memory") (realloc (func $libc "realloc"))))
+  (core module $m
+    (import "libc" "memory" (memory 1))
+    (import "" "get-directories" (func $get-directories (param i32)))
+    (import "" "open-at" (func $open-at (param i32 i32 i32 i32 i32 i32 i32)))
+    (import "" "read" (func $read (param i32 i64 i64 i32)))
+    (func (export "read") (result i32)
+      (call $get-directories (i32.const 16))
+      (if (i32.eqz (i32.load (i32.const 20))) (then unreachable))
+      (call $open-at
+        (i32.load (i32.load (i32.const 16)))
+        (i32.const 0)
+        (i32.const 256) (i32.const 8)
+        (i32.const 0)
+        (i32.const 1)
+        (i32.const 32))
+      (if (i32.load8_u (i32.const 32)) (then unreachable))
+      (call $read (i32.load (i32.const 36)) (i64.const 1024) (i64.const 0) (i32.const 48))
+      (if (i32.load8_u (i32.const 48)) (then unreachable))
+      (i32.const 52))
+  )
+  (core instance $m (instantiate $m
+    (with "libc" (instance $libc))
+    (with "" (instance
+      (export "get-directories" (func $get-directories))
+      (export "open-at" (func $open-at))
+      (export "read" (func $read))
+    ))
+  ))
+  (func (export "read") (result (list u8))
+    (canon lift (core func $m "read") (memory $libc "memory") (realloc (func $libc "realloc"))))
+)"#
+    );
+
+    #[tokio::test]
+    async fn guest_preopen() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs::write(dir.path().join("test.txt"), "hello from the host").await?;
+
+        let env = GuestEnv {
+            preopens: [Preopen {
+                host: dir.path().into(),
+                guest: "/data".into(),
+                read_only: true,
+            }]
+            .into(),
+            ..GuestEnv::default()
+        };
+        let (mut store, instance) =
+            instantiate(FS_COMPONENT, env, Limits::default(), &[], &[]).await?;
+        let read = instance.get_typed_func::<(), (Vec<u8>,)>(&mut store, "read")?;
+        let (buf,) = read.call_async(&mut store, ()).await?;
+        assert_eq!(buf, b"hello from the host");
+
+        // no directories are preopened by default
+        let (mut store, instance) = instantiate(
+            FS_COMPONENT,
+            GuestEnv::default(),
+            Limits::default(),
+            &[],
+            &[],
+        )
+        .await?;
+        let read = instance.get_typed_func::<(), (Vec<u8>,)>(&mut store, "read")?;
+        read.call_async(&mut store, ())
+            .await
+            .expect_err("reading without preopens should trap");
+        Ok(())
+    }
+
+    const STDOUT_COMPONENT: &str = r#"(component
+  (import "wasi:io/error@0.2.0" (instance $error
+    (export "error" (type (sub resource)))
+  ))
+  (alias export $error "error" (type $error))
+  (import "wasi:io/streams@0.2.0" (instance $streams
+    (alias outer 1 $error (type $error))
+    (export "error" (type $error' (eq $error)))
+    (export "output-stream" (type $output-stream (sub resource)))
+    (type $stream-error (variant
+      (case "last-operation-failed" (own $error'))
+      (case "closed")))
+    (export "stream-error" (type $stream-error' (eq $stream-error)))
+    (export "[method]output-stream.blocking-write-and-flush" (func
+      (param "self" (borrow $output-stream))
+      (param "contents" (list u8))
+      (result (result (error $stream-error')))))
+  ))
+  (alias export $streams "output-stream" (type $output-stream))
+  (import "wasi:cli/stdout@0.2.0" (instance $stdout
+    (alias outer 1 $output-stream (type $output-stream))
+    (export "output-stream" (type $output-stream' (eq $output-stream)))
+    (export "get-stdout" (func (result (own $output-stream'))))
+  ))
+  (core module $libc
+    (memory (export "memory") 1)
+    (data (i32.const 256) "hello from the guest")
+  )
+  (core instance $libc (instantiate $libc))
+  (core func $get-stdout (canon lower (func $stdout "get-stdout")))
+  (core func $write
+    (canon lower (func $streams "[method]output-stream.blocking-write-and-flush")
+      (memory $libc "memory")))
+  (core func $drop (canon resource.drop $output-stream))
+  (core module $m
+    (import "" "get-stdout" (func $get-stdout (result i32)))
+    (import "" "write" (func $write (param i32 i32 i32 i32)))
+    (import "" "drop" (func $drop (param i32)))
+    (func (export "run")
+      (local $stdout i32)
+      (local.set $stdout (call $get-stdout))
+      (call $write (local.get $stdout) (i32.const 256) (i32.const 20) (i32.const 16))
+      (call $drop (local.get $stdout)))
+  )
+  (core instance $m (instantiate $m
+    (with "" (instance
+      (export "get-stdout" (func $get-stdout))
+      (export "write" (func $write))
+      (export "drop" (func $drop))
+    ))
+  ))
+  (func (export "run") (canon lift (core func $m "run")))
+)"#;
+
+    #[tokio::test]
+    async fn guest_stdio() -> anyhow::Result<()> {
+        let stdout = MemoryOutputPipe::new(1024);
+        let stderr = MemoryOutputPipe::new(1024);
+        let env = GuestEnv {
+            stdio: GuestStdio::Capture {
+                stdout: stdout.clone(),
+                stderr: stderr.clone(),
+            },
+            ..GuestEnv::default()
+        };
+        let (mut store, instance) =
+            instantiate(STDOUT_COMPONENT, env, Limits::default(), &[], &[]).await?;
+        let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+        run.call_async(&mut store, ()).await?;
+        assert_eq!(stdout.contents(), "hello from the guest");
+        assert!(stderr.contents().is_empty());
+
+        // stdout is discarded by default
+        let (mut store, instance) = instantiate(
+            STDOUT_COMPONENT,
+            GuestEnv::default(),
+            Limits::default(),
+            &[],
+            &[],
+        )
+        .await?;
+        let run = instance.get_typed_func::<(), ()>(&mut store, "run")?;
+        run.call_async(&mut store, ()).await?;
+        Ok(())
+    }
+
+    /// Core module importing `wasi_snapshot_preview1.proc_exit` and exporting its memory
+    const PREVIEW1_MODULE: &[u8] = b"\0asm\x01\0\0\0\
+        \x01\x05\x01\x60\x01\x7f\x00\
+        \x02\x24\x01\x16wasi_snapshot_preview1\x09proc_exit\x00\x00\
+        \x05\x03\x01\x00\x01\
+        \x07\x0a\x01\x06memory\x02\x00";
+
+    #[tokio::test]
+    async fn custom_adapter() -> anyhow::Result<()> {
+        assert_eq!(parse_adapter("command")?, Adapter::Command);
+        assert_eq!(parse_adapter("reactor")?, Adapter::Reactor);
+        assert_eq!(
+            parse_adapter("./adapter.wasm")?,
+            Adapter::Path("./adapter.wasm".into())
+        );
+        assert!(parse_adapter("").is_err());
+
+        let dir = tempfile::tempdir()?;
+        let workload = dir.path().join("module.wasm");
+        fs::write(&workload, PREVIEW1_MODULE).await?;
+        let workload = workload.to_string_lossy();
+
+        let path = dir.path().join("adapter.wasm");
+        fs::write(&path, WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER).await?;
+        let adapter = Adapter::Path(path.clone()).load().await?;
+        assert_eq!(adapter, WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER);
+        load_component(&adapter, &workload, Limits::default()).await?;
+
+        // the module is encoded using the adapter read from the file
+        fs::write(&path, b"not an adapter").await?;
+        let adapter = Adapter::Path(path).load().await?;
+        let err = load_component(&adapter, &workload, Limits::default())
+            .await
+            .expect_err("encoding the module using an invalid adapter should fail");
+        assert!(format!("{err:#}").contains("failed to add WASI adapter"));
+        Ok(())
+    }
+
+    const LIMITS_COMPONENT: &str = r#"(component
+  (core module $m
+    (memory 1)
+    (func (export "grow") (param i32) (result i32)
+      (memory.grow (local.get 0)))
+    (func (export "spin")
+      (loop $l (br $l)))
+  )
+  (core instance $m (instantiate $m))
+  (func (export "grow") (param "pages" u32) (result s32)
+    (canon lift (core func $m "grow")))
+  (func (export "spin")
+    (canon lift (core func $m "spin")))
+)"#;
+
+    async fn instantiate_limited(
+        limits: Limits,
+    ) -> anyhow::Result<(
+        Store<Ctx<wrpc_transport::tcp::Client<&'static str>>>,
+        wasmtime::component::Instance,
+    )> {
+        instantiate(LIMITS_COMPONENT, GuestEnv::default(), limits, &[], &[]).await
+    }
+
+    #[tokio::test]
+    async fn memory_limit() -> anyhow::Result<()> {
+        let (mut store, instance) = instantiate_limited(Limits {
+            max_memory_bytes: Some(4 << 16),
+            ..Limits::default()
+        })
+        .await?;
+        let grow = instance.get_typed_func::<(u32,), (i32,)>(&mut store, "grow")?;
+        let (prev,) = grow.call_async(&mut store, (2,)).await?;
+        assert_eq!(prev, 1);
+        grow.post_return_async(&mut store).await?;
+        grow.call_async(&mut store, (1 << 14,))
+            .await
+            .expect_err("growing memory beyond the limit should trap");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fuel_limit() -> anyhow::Result<()> {
+        let (mut store, instance) = instantiate_limited(Limits {
+            fuel: Some(10_000),
+            ..Limits::default()
+        })
+        .await?;
+        let spin = instance.get_typed_func::<(), ()>(&mut store, "spin")?;
+        let err = spin
+            .call_async(&mut store, ())
+            .await
+            .expect_err("spinning should exhaust fuel");
+        assert_eq!(
+            err.downcast_ref::<wasmtime::Trap>(),
+            Some(&wasmtime::Trap::OutOfFuel)
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn epoch_deadline() -> anyhow::Result<()> {
+        let (mut store, instance) = instantiate_limited(Limits {
+            epoch_deadline_ms: Some(50),
+            ..Limits::default()
+        })
+        .await?;
+        let engine = store.engine().clone();
+        let ticker = tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(EPOCH_TICK).await;
+                engine.increment_epoch();
+            }
+        });
+        let spin = instance.get_typed_func::<(), ()>(&mut store, "spin")?;
+        let err = spin
+            .call_async(&mut store, ())
+            .await
+            .expect_err("spinning should exceed the deadline");
+        ticker.abort();
+        assert_eq!(
+            err.downcast_ref::<wasmtime::Trap>(),
+            Some(&wasmtime::Trap::Interrupt)
+        );
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn max_concurrent_invocations() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        const LIMIT: usize = 3;
+
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let served = Arc::new(AtomicUsize::new(0));
+        let invocations = futures::stream::iter((0..LIMIT * 4).map(|_| {
+            let in_flight = Arc::clone(&in_flight);
+            let max_in_flight = Arc::clone(&max_in_flight);
+            let served = Arc::clone(&served);
+            anyhow::Ok(((), async move {
+                let n = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                max_in_flight.fetch_max(n, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                in_flight.fetch_sub(1, Ordering::SeqCst);
+                served.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }))
+        }));
+        serve_invocations(
+            invocations,
+            Arc::new(Semaphore::new(LIMIT)),
+            InvocationMetrics::new("", "test"),
+            CancellationToken::new(),
+            PanicPolicy::Isolate,
+        )
+        .await;
+        assert_eq!(served.load(Ordering::SeqCst), LIMIT * 4);
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), LIMIT);
+    }
+
+    #[tokio::test]
+    async fn max_concurrent_invocations_shared() -> anyhow::Result<()> {
+        let (served_tx, mut served_rx) = tokio::sync::mpsc::unbounded_channel();
+        let invocations = |name: &'static str| {
+            let served_tx = served_tx.clone();
+            futures::stream::iter([anyhow::Ok((
+                (),
+                async move {
+                    served_tx.send(name)?;
+                    Ok(())
+                }
+                .boxed(),
+            ))])
+            .chain(futures::stream::pending())
+        };
+        let permits = Arc::new(Semaphore::new(1));
+        let shutdown = CancellationToken::new();
+        let mut handlers = JoinSet::new();
+        for name in ["foo", "bar"] {
+            handlers.spawn(serve_invocations(
+                invocations(name),
+                Arc::clone(&permits),
+                InvocationMetrics::new("", name),
+                shutdown.clone(),
+                PanicPolicy::Isolate,
+            ));
+        }
+        let mut served = Vec::with_capacity(2);
+        for _ in 0..2 {
+            let name = tokio::time::timeout(Duration::from_secs(5), served_rx.recv())
+                .await
+                .context("invocation was not served, while another export was idle")?
+                .context("channel closed")?;
+            served.push(name);
+        }
+        served.sort_unstable();
+        assert_eq!(served, ["bar", "foo"]);
+        shutdown.cancel();
+        while let Some(res) = handlers.join_next().await {
+            res?;
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invocation_metrics() {
+        use metrics_util::debugging::{DebugValue, DebuggingRecorder};
+
+        let recorder = DebuggingRecorder::new();
+        let snapshotter = recorder.snapshotter();
+        let metrics = metrics::with_local_recorder(&recorder, || {
+            InvocationMetrics::new("test:test/iface", "hello")
+        });
+        let invocations = futures::stream::iter([
+            Ok(((), async { Ok(()) }.boxed())),
+            Ok(((), async { Ok(()) }.boxed())),
+            Ok(((), async { bail!("test") }.boxed())),
+            Err(anyhow!("test")),
+        ]);
+        serve_invocations(
+            invocations,
+            Arc::new(Semaphore::new(1)),
+            metrics,
+            CancellationToken::new(),
+            PanicPolicy::Isolate,
+        )
+        .await;
+
+        let snapshot = snapshotter.snapshot().into_hashmap();
+        let value = |name: &str| {
+            let (_, key, (_, _, value)) = snapshot
+                .iter()
+                .map(|(key, value)| (key.key().name(), key, value))
+                .find(|(n, ..)| *n == name)
+                .unwrap_or_else(|| panic!("metric `{name}` not recorded"));
+            let labels = key
+                .key()
+                .labels()
+                .map(|l| (l.key(), l.value()))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                labels,
+                [("instance", "test:test/iface"), ("function", "hello")]
+            );
+            value.clone()
+        };
+        assert_eq!(value("wrpc_invocations_total"), DebugValue::Counter(3));
+        assert_eq!(
+            value("wrpc_invocations_succeeded_total"),
+            DebugValue::Counter(2)
+        );
+        assert_eq!(
+            value("wrpc_invocations_failed_total"),
+            DebugValue::Counter(1)
+        );
+        assert_eq!(
+            value("wrpc_invocation_accept_errors_total"),
+            DebugValue::Counter(1)
+        );
+        let DebugValue::Histogram(durations) = value("wrpc_invocation_duration_seconds") else {
+            panic!("duration is not a histogram")
+        };
+        assert_eq!(durations.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        let served = Arc::new(AtomicUsize::new(0));
+        let (started_tx, mut started_rx) = tokio::sync::mpsc::unbounded_channel();
+        let invocations = futures::stream::iter((0..2).map({
+            let served = Arc::clone(&served);
+            move |_| {
+                let served = Arc::clone(&served);
+                let started_tx = started_tx.clone();
+                anyhow::Ok(((), async move {
+                    started_tx.send(()).unwrap();
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    served.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }))
+            }
+        }))
+        .chain(futures::stream::pending());
+
+        let token = CancellationToken::new();
+        let mut handlers = JoinSet::new();
+        handlers.spawn(serve_invocations(
+            invocations,
+            Arc::new(Semaphore::new(2)),
+            InvocationMetrics::new("", "test"),
+            token.clone(),
+            PanicPolicy::Isolate,
+        ));
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let join = tokio::spawn(async move {
+            join_handlers(
+                &mut handlers,
+                async {
+                    shutdown_rx.await.unwrap();
+                },
+                &token,
+                Duration::from_secs(10),
+                &watch::Sender::new(ServeStatus::default()),
+                PanicPolicy::Isolate,
+            )
+            .await
+            .unwrap();
+            handlers
+        });
+        started_rx.recv().await.unwrap();
+        started_rx.recv().await.unwrap();
+        shutdown_tx.send(()).unwrap();
+        let handlers = join.await.unwrap();
+        assert!(handlers.is_empty());
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn graceful_shutdown_grace_period() {
+        let token = CancellationToken::new();
+        let mut handlers = JoinSet::new();
+        handlers.spawn(serve_invocations(
+            futures::stream::iter([anyhow::Ok(((), future::pending()))]),
+            Arc::new(Semaphore::new(1)),
+            InvocationMetrics::new("", "test"),
+            token.clone(),
+            PanicPolicy::Isolate,
+        ));
+        join_handlers(
+            &mut handlers,
+            async {},
+            &token,
+            Duration::from_millis(10),
+            &watch::Sender::new(ServeStatus::default()),
+            PanicPolicy::Isolate,
+        )
+        .await
+        .unwrap();
+        assert!(handlers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn panic_policy_isolate() {
+        use core::sync::atomic::{AtomicUsize, Ordering};
+
+        let served = Arc::new(AtomicUsize::new(0));
+        let invocations = futures::stream::iter((0..3).map({
+            let served = Arc::clone(&served);
+            move |i| {
+                let served = Arc::clone(&served);
+                anyhow::Ok(((), async move {
+                    assert_ne!(i, 1, "test");
+                    served.fetch_add(1, Ordering::SeqCst);
+                    Ok(())
+                }))
+            }
+        }));
+        serve_invocations(
+            invocations,
+            Arc::new(Semaphore::new(1)),
+            InvocationMetrics::new("", "test"),
+            CancellationToken::new(),
+            PanicPolicy::Isolate,
+        )
+        .await;
+        assert_eq!(served.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn panic_policy_abort() {
+        let token = CancellationToken::new();
+        let mut handlers = JoinSet::new();
+        handlers.spawn(serve_invocations(
+            futures::stream::iter([anyhow::Ok(((), async { panic!("test") }))]),
+            Arc::new(Semaphore::new(1)),
+            InvocationMetrics::new("", "test"),
+            token.clone(),
+            PanicPolicy::Abort,
+        ));
+        handlers.spawn(serve_invocations(
+            futures::stream::iter([anyhow::Ok(((), future::pending()))]),
+            Arc::new(Semaphore::new(1)),
+            InvocationMetrics::new("", "test"),
+            token.clone(),
+            PanicPolicy::Abort,
+        ));
+        let status = watch::Sender::new(ServeStatus::Ready);
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            join_handlers(
+                &mut handlers,
+                future::pending(),
+                &token,
+                Duration::from_secs(10),
+                &status,
+                PanicPolicy::Abort,
+            ),
+        )
+        .await
+        .expect("handlers were not aborted")
+        .expect_err("handler panic not propagated");
+        assert!(handlers.is_empty());
+        assert!(token.is_cancelled());
+        assert_eq!(*status.borrow(), ServeStatus::Stopped);
+    }
+
+    const HTTP_COMPONENT: &str = r#"(component
+  (import "wasi:http/outgoing-handler@0.2.0" (instance $handler
+    (export "ping" (func (result u32)))
+  ))
+  (alias export $handler "ping" (func $ping-import))
+  (core func $ping-lower (canon lower (func $ping-import)))
+  (core module $m
+    (import "" "ping" (func $ping (result i32)))
+    (func (export "ping") (result i32) call $ping)
+  )
+  (core instance $i (instantiate $m
+    (with "" (instance (export "ping" (func $ping-lower))))
+  ))
+  (func (export "ping") (result u32) (canon lift (core func $i "ping")))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn polyfill_unlinked_http() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let workload = dir.path().join("http.wat");
+        fs::write(&workload, HTTP_COMPONENT).await?;
+        let workload = workload.to_string_lossy();
+
+        let lis = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = lis.local_addr()?;
+
+        instantiate_pre::<wrpc_transport::tcp::Client<SocketAddr>>(
+            WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+            &workload,
+            Limits::default(),
+            HostInterfaces::default(),
+        )
+        .await
+        .expect_err("statically linked `wasi:http` should not define `ping`");
+
+        let (pre, engine, _, _) = instantiate_pre(
+            WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+            &workload,
+            Limits::default(),
+            HostInterfaces::default().wasi_http(false),
+        )
+        .await?;
+        let mut store = new_store(
+            &engine,
+            &StoreConfig {
+                client: wrpc_transport::tcp::Client::from(addr),
+                cx: (),
+                timeout: Duration::from_secs(10),
+                env: GuestEnv::default(),
+                limits: Limits::default(),
+            },
+            "test.wasm",
+            &[],
+        );
+        let instance = pre.instantiate_async(&mut store).await?;
+        let ping = instance.get_typed_func::<(), (u32,)>(&mut store, "ping")?;
+
+        let srv = wrpc_transport::Server::default();
+        let invocations = srv
+            .serve_values::<(), (u32,)>(
+                "wasi:http/outgoing-handler@0.2.0",
+                "ping",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let mut invocations = pin!(invocations);
+        let (res, ()) = tokio::join!(ping.call_async(&mut store, ()), async {
+            srv.accept(&lis).await.expect("failed to accept connection");
+            let (_, (), rx, tx) = invocations
+                .next()
+                .await
+                .expect("unexpected end of stream")
+                .expect("failed to accept invocation");
+            assert!(rx.is_none());
+            tx((42,)).await.expect("failed to transmit response");
+        });
+        assert_eq!(res?, (42,));
+        Ok(())
+    }
+
+    const HTTP_OUTGOING_COMPONENT: &str = r#"(component
+  (import "wasi:http/types@0.2.0" (instance $types
+    (export "fields" (type $fields (sub resource)))
+    (export "outgoing-request" (type $request (sub resource)))
+    (export "request-options" (type $options (sub resource)))
+    (export "future-incoming-response" (type $response (sub resource)))
+    (export "[constructor]fields" (func (result (own $fields))))
+    (export "[constructor]outgoing-request"
+      (func (param "headers" (own $fields)) (result (own $request))))
+    (export "[method]outgoing-request.set-authority"
+      (func (param "self" (borrow $request)) (param "authority" (option string)) (result (result))))
+    (export "[method]outgoing-request.set-path-with-query"
+      (func (param "self" (borrow $request)) (param "path-with-query" (option string)) (result (result))))
+  ))
+  (alias export $types "outgoing-request" (type $request))
+  (alias export $types "request-options" (type $options))
+  (alias export $types "future-incoming-response" (type $response))
+  (import "wasi:http/outgoing-handler@0.2.0" (instance $handler
+    (alias outer 1 $request (type $request))
+    (export "outgoing-request" (type $request' (eq $request)))
+    (alias outer 1 $options (type $options))
+    (export "request-options" (type $options' (eq $options)))
+    (alias outer 1 $response (type $response))
+    (export "future-incoming-response" (type $response' (eq $response)))
+    (type $error-code (variant (case "internal-error")))
+    (export "error-code" (type $error-code' (eq $error-code)))
+    (export "handle" (func
+      (param "request" (own $request'))
+      (param "options" (option (own $options')))
+      (result (result (own $response') (error $error-code')))))
+  ))
+  (core module $libc
+    (memory (export "memory") 1)
+  )
+  (core instance $libc (instantiate $libc))
+  (core func $fields-new (canon lower (func $types "[constructor]fields")))
+  (core func $request-new (canon lower (func $types "[constructor]outgoing-request")))
+  (core func $set-authority
+    (canon lower (func $types "[method]outgoing-request.set-authority") (memory $libc "memory")))
+  (core func $set-path-with-query
+    (canon lower (func $types "[method]outgoing-request.set-path-with-query")
+      (memory $libc "memory")))
+  (core func $handle (canon lower (func $handler "handle") (memory $libc "memory")))
+  (core func $response-drop (canon resource.drop $response))
+  (core module $m
+    (import "libc" "memory" (memory 1))
+    (import "" "fields-new" (func $fields-new (result i32)))
+    (import "" "request-new" (func $request-new (param i32) (result i32)))
+    (import "" "set-authority" (func $set-authority (param i32 i32 i32 i32) (result i32)))
+    (import "" "set-path-with-query" (func $set-path-with-query (param i32 i32 i32 i32) (result i32)))
+    (import "" "handle" (func $handle (param i32 i32 i32 i32)))
+    (import "" "response-drop" (func $response-drop (param i32)))
+    (data (i32.const 16) "example.com")
+    (data (i32.const 32) "/test")
+    (func (export "run") (result i32)
+      (local $request i32)
+      (local.set $request (call $request-new (call $fields-new)))
+      (drop (call $set-authority (local.get $request) (i32.const 1) (i32.const 16) (i32.const 11)))
+      (drop (call $set-path-with-query (local.get $request) (i32.const 1) (i32.const 32) (i32.const 5)))
+      (call $handle (local.get $request) (i32.const 0) (i32.const 0) (i32.const 64))
+      (if (result i32) (i32.load8_u (i32.const 64))
+        (then (i32.const 0))
+        (else
+          (call $response-drop (i32.load (i32.const 68)))
+          (i32.const 1))))
+  )
+  (core instance $i (instantiate $m
+    (with "libc" (instance $libc))
+    (with "" (instance
+      (export "fields-new" (func $fields-new))
+      (export "request-new" (func $request-new))
+      (export "set-authority" (func $set-authority))
+      (export "set-path-with-query" (func $set-path-with-query))
+      (export "handle" (func $handle))
+      (export "response-drop" (func $response-drop))
+    ))
+  ))
+  (func (export "run") (result u32) (canon lift (core func $i "run")))
+)"#;
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn polyfill_http_outgoing_handler() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let workload = dir.path().join("http-outgoing.wat");
+        fs::write(&workload, HTTP_OUTGOING_COMPONENT).await?;
+        let workload = workload.to_string_lossy();
+
+        let lis = tokio::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).await?;
+        let addr = lis.local_addr()?;
+
+        let (pre, engine, _, _) = instantiate_pre(
+            WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+            &workload,
+            Limits::default(),
+            HostInterfaces::default().wasi_http_outgoing(HttpOutgoing::Wrpc),
+        )
+        .await?;
+        let mut store = new_store(
+            &engine,
+            &StoreConfig {
+                client: wrpc_transport::tcp::Client::from(addr),
+                cx: (),
+                timeout: Duration::from_secs(10),
+                env: GuestEnv::default(),
+                limits: Limits::default(),
+            },
+            "test.wasm",
+            &[],
+        );
+        let instance = pre.instantiate_async(&mut store).await?;
+        let run = instance.get_typed_func::<(), (u32,)>(&mut store, "run")?;
+
+        let srv = wrpc_transport::Server::default();
+        let invocations = srv
+            .serve_values::<(Bytes, Option<Bytes>), (Result<Bytes, u8>,)>(
+                "wasi:http/outgoing-handler@0.2.0",
+                "handle",
+                Vec::<Box<[Option<usize>]>>::default(),
+            )
+            .await?;
+        let mut invocations = pin!(invocations);
+        let (res, req) = tokio::join!(run.call_async(&mut store, ()), async {
+            srv.accept(&lis).await.expect("failed to accept connection");
+            let (_, (req, opts), rx, tx) = invocations
+                .next()
+                .await
+                .expect("unexpected end of stream")
+                .expect("failed to accept invocation");
+            assert!(rx.is_none());
+            assert!(opts.is_none());
+            let resp = IncomingResponse {
+                status: 200,
+                headers: vec![("content-type".into(), "text/plain".into())],
+                body: "test".into(),
+            };
+            tx((Ok(resp.encode().expect("failed to encode response")),))
+                .await
+                .expect("failed to transmit response");
+            OutgoingRequest::decode(req)
+        });
+        assert_eq!(res?, (1,));
+        assert_eq!(
+            req?,
+            OutgoingRequest {
+                method: "GET".into(),
+                scheme: None,
+                authority: Some("example.com".into()),
+                path_with_query: Some("/test".into()),
+                headers: vec![],
+            }
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn inspect_http() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let workload = dir.path().join("http.wat");
+        fs::write(&workload, HTTP_COMPONENT).await?;
+        let workload = workload.to_string_lossy();
+
+        let served = Item {
+            instance: String::new(),
+            name: "ping".into(),
+            resolution: Resolution::Served,
+            reason: None,
+        };
+        let inspection = inspect(HostInterfaces::default(), &workload).await?;
+        assert_eq!(
+            inspection,
+            Inspection {
+                imports: vec![Item {
+                    instance: "wasi:http/outgoing-handler@0.2.0".into(),
+                    name: "ping".into(),
+                    resolution: Resolution::Linked,
+                    reason: None,
+                }],
+                exports: vec![served.clone()],
+                guest_resources: vec![],
+            }
+        );
+
+        let inspection = inspect(HostInterfaces::default().wasi_http(false), &workload).await?;
+        assert_eq!(
+            inspection.imports,
+            [Item {
+                instance: "wasi:http/outgoing-handler@0.2.0".into(),
+                name: "ping".into(),
+                resolution: Resolution::Polyfilled,
+                reason: None,
+            }]
+        );
+        assert_eq!(inspection.exports, [served]);
+        assert_eq!(
+            serde_json::to_value(&inspection)?,
+            serde_json::json!({
+                "imports": [{
+                    "instance": "wasi:http/outgoing-handler@0.2.0",
+                    "name": "ping",
+                    "resolution": "polyfilled",
+                }],
+                "exports": [{
+                    "instance": "",
+                    "name": "ping",
+                    "resolution": "served",
+                }],
+                "guest_resources": [],
+            })
+        );
+        assert_eq!(
+            inspection.to_string(),
+            "imports:\n  polyfilled  wasi:http/outgoing-handler@0.2.0 ping\nexports:\n  served       ping\nguest resources:\n"
+        );
+        Ok(())
+    }
+
+    /// Component exporting function `ping` and core module `m`
+    const MODULE_EXPORT_COMPONENT: &str = r#"(component
+  (core module $m
+    (func (export "ping") (result i32) i32.const 42)
+  )
+  (core instance $i (instantiate $m))
+  (func (export "ping") (result u32) (canon lift (core func $i "ping")))
+  (export "m" (core module $m))
+)"#;
+
+    #[tokio::test]
+    async fn serve_skipped_exports() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let workload = dir.path().join("module.wat");
+        fs::write(&workload, MODULE_EXPORT_COMPONENT).await?;
+        let workload = workload.to_string_lossy();
+
+        let (pre, engine, guest_resources, host_resources) = instantiate_pre(
+            WASI_SNAPSHOT_PREVIEW1_REACTOR_ADAPTER,
+            &workload,
+            Limits::default(),
+            HostInterfaces::default(),
+        )
+        .await?;
+        assert!(guest_resources.is_empty());
+
+        let srv =
+            wrpc_transport::Server::<(), tokio::io::DuplexStream, tokio::io::DuplexStream>::default(
+            );
+        let token = CancellationToken::new();
+        let mut handlers = JoinSet::new();
+        let skipped = serve_workload(
+            &mut handlers,
+            &srv,
+            move || {
+                new_store(
+                    &engine,
+                    &StoreConfig {
+                        client: wrpc_transport::tcp::Client::from("[::1]:0"),
+                        cx: (),
+                        timeout: Duration::from_secs(1),
+                        env: GuestEnv::default(),
+                        limits: Limits::default(),
+                    },
+                    "reactor.wasm",
+                    &[],
+                )
+            },
+            pre,
+            guest_resources,
+            host_resources,
+            Arc::new(Semaphore::new(1)),
+            token.clone(),
+            PanicPolicy::Isolate,
+        )
+        .await?;
+        assert_eq!(
+            skipped,
+            [Item {
+                instance: String::new(),
+                name: "m".into(),
+                resolution: Resolution::Unsupported,
+                reason: Some("serving module exports not supported yet".into()),
+            }]
+        );
+        assert_eq!(handlers.len(), 1, "function `ping` should be served");
+        token.cancel();
+        while handlers.join_next().await.is_some() {}
+        Ok(())
     }
 }
@@ -1,7 +1,11 @@
+use core::net::SocketAddr;
+use core::num::NonZeroUsize;
+
 use std::sync::Arc;
 
 use anyhow::Context as _;
 use clap::Parser;
+use tokio::sync::watch;
 use tracing::instrument;
 
 /// NATS transport
@@ -22,12 +26,46 @@ pub struct RunArgs {
     #[arg(long, default_value = crate::DEFAULT_TIMEOUT)]
     timeout: humantime::Duration,
 
+    /// NATS.io request timeout, bounding the time to wait for the peer to acknowledge
+    /// an import invocation. Unlike `--timeout`, this does not limit the time the peer
+    /// takes to complete the invocation
+    #[arg(long)]
+    request_timeout: Option<humantime::Duration>,
+
+    /// Subject prefix namespacing all import and export invocations, e.g. a tenant ID.
+    /// Invoking and serving sides must use the same namespace
+    #[arg(long, default_value = "")]
+    prefix: String,
+
     /// Prefix to send import invocations to
     #[arg(long, default_value = "")]
     import: String,
 
+    #[command(flatten)]
+    env: crate::EnvArgs,
+
+    #[command(flatten)]
+    limits: crate::Limits,
+
+    #[command(flatten)]
+    interfaces: crate::HostInterfaces,
+
+    /// WASI preview1 adapter used to encode core module workloads into components,
+    /// either `command`, `reactor` or a path to a custom adapter
+    #[arg(
+        long,
+        value_name = "PATH|command|reactor",
+        default_value = "command",
+        value_parser = crate::parse_adapter
+    )]
+    adapter: crate::Adapter,
+
     /// Path or URL to Wasm command component
     workload: String,
+
+    /// Arguments passed to the command component
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
 }
 
 /// Serve a reactor component
@@ -41,10 +79,21 @@ pub struct ServeArgs {
     #[arg(long, default_value = crate::DEFAULT_TIMEOUT)]
     timeout: humantime::Duration,
 
+    /// NATS.io request timeout, bounding the time to wait for the peer to acknowledge
+    /// an import invocation. Unlike `--timeout`, this does not limit the time the peer
+    /// takes to complete the invocation
+    #[arg(long)]
+    request_timeout: Option<humantime::Duration>,
+
     /// NATS queue group to use
     #[arg(short, long)]
     group: Option<String>,
 
+    /// Subject prefix namespacing all import and export invocations, e.g. a tenant ID.
+    /// Invoking and serving sides must use the same namespace
+    #[arg(long, default_value = "")]
+    prefix: String,
+
     /// Prefix to send import invocations to
     #[arg(long, default_value = "")]
     import: String,
@@ -53,8 +102,48 @@ pub struct ServeArgs {
     #[arg(long, default_value = "")]
     export: String,
 
-    /// Path or URL to Wasm command component
+    /// Maximum number of invocations served concurrently
+    #[arg(long, default_value = crate::DEFAULT_MAX_CONCURRENT_INVOCATIONS)]
+    max_concurrent_invocations: NonZeroUsize,
+
+    /// Time to wait for in-flight invocations to complete on SIGINT or SIGTERM
+    #[arg(long, default_value = crate::DEFAULT_SHUTDOWN_GRACE_PERIOD)]
+    shutdown_grace_period: humantime::Duration,
+
+    /// Behavior when serving an invocation panics
+    #[arg(long, value_enum, default_value_t = crate::PanicPolicy::Isolate)]
+    on_handler_panic: crate::PanicPolicy,
+
+    /// Address to serve Prometheus invocation metrics on
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    #[command(flatten)]
+    env: crate::EnvArgs,
+
+    #[command(flatten)]
+    limits: crate::Limits,
+
+    #[command(flatten)]
+    interfaces: crate::HostInterfaces,
+
+    /// WASI preview1 adapter used to encode core module workloads into components,
+    /// either `command`, `reactor` or a path to a custom adapter
+    #[arg(
+        long,
+        value_name = "PATH|command|reactor",
+        default_value = "reactor",
+        value_parser = crate::parse_adapter
+    )]
+    adapter: crate::Adapter,
+
+    /// Path or URL to Wasm reactor component
     workload: String,
+
+    /// Path or URL to an additional Wasm reactor component served by the same process,
+    /// can be specified multiple times. Each component must export distinct instances
+    #[arg(long = "workload", value_name = "WORKLOAD")]
+    workloads: Vec<String>,
 }
 
 #[instrument(level = "trace", ret(level = "trace"))]
@@ -62,17 +151,42 @@ pub async fn handle_run(
     RunArgs {
         nats,
         timeout,
+        request_timeout,
+        prefix,
         import,
+        env,
+        limits,
+        interfaces,
+        adapter,
         ref workload,
+        ref args,
     }: RunArgs,
 ) -> anyhow::Result<()> {
+    let env = env.load().await?;
     let nats = wrpc_cli::nats::connect(nats)
         .await
         .context("failed to connect to NATS.io")?;
-    let nats = wrpc_transport_nats::Client::new(nats, import, None)
+    let import = wrpc_transport_nats::namespace_prefix(&prefix, &import);
+    let mut nats = wrpc_transport_nats::Client::new(nats, import, None)
         .await
         .context("failed to construct NATS.io transport client")?;
-    crate::handle_run(nats, None, *timeout, workload).await
+    if let Some(timeout) = request_timeout {
+        nats = nats.with_request_timeout(*timeout);
+    }
+    crate::handle_run(
+        crate::StoreConfig {
+            client: nats,
+            cx: None,
+            timeout: *timeout,
+            env,
+            limits,
+        },
+        interfaces,
+        &adapter,
+        workload,
+        args,
+    )
+    .await
 }
 
 #[instrument(level = "trace", ret(level = "trace"))]
@@ -80,23 +194,64 @@ pub async fn handle_serve(
     ServeArgs {
         nats,
         timeout,
+        request_timeout,
+        prefix,
         export,
         import,
         group,
-        ref workload,
+        env,
+        limits,
+        interfaces,
+        max_concurrent_invocations,
+        shutdown_grace_period,
+        on_handler_panic,
+        metrics_addr,
+        adapter,
+        workload,
+        mut workloads,
     }: ServeArgs,
 ) -> anyhow::Result<()> {
+    workloads.insert(0, workload);
+    if let Some(addr) = metrics_addr {
+        crate::install_metrics_exporter(addr)?;
+    }
+    let env = env.load().await?;
     let nats = wrpc_cli::nats::connect(nats)
         .await
         .context("failed to connect to NATS")?;
     let nats = Arc::new(nats);
+    let export = wrpc_transport_nats::namespace_prefix(&prefix, &export);
+    let import = wrpc_transport_nats::namespace_prefix(&prefix, &import);
     let exports = wrpc_transport_nats::Client::new(Arc::clone(&nats), export, group.map(Arc::from))
         .await
         .context("failed to construct NATS.io transport export client")?;
-    let imports = wrpc_transport_nats::Client::new(nats, import, None)
+    let mut imports = wrpc_transport_nats::Client::new(nats, import, None)
         .await
         .context("failed to construct NATS.io transport import client")?;
-    crate::handle_serve(exports, imports, None, *timeout, workload).await
+    if let Some(timeout) = request_timeout {
+        imports = imports.with_request_timeout(*timeout);
+    }
+    crate::handle_serve(
+        exports,
+        crate::StoreConfig {
+            client: imports,
+            cx: None,
+            timeout: *timeout,
+            env,
+            limits,
+        },
+        crate::ServeConfig {
+            interfaces,
+            max_concurrent_invocations,
+            grace_period: *shutdown_grace_period,
+            panic_policy: on_handler_panic,
+        },
+        crate::shutdown_signal(),
+        watch::Sender::new(crate::ServeStatus::default()),
+        &adapter,
+        &workloads,
+    )
+    .await
 }
 
 #[instrument(level = "trace", ret(level = "trace"))]
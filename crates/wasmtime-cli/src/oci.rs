@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context as _};
+use oci_client::client::{ClientConfig, ClientProtocol};
+use oci_client::secrets::RegistryAuth;
+use oci_client::{Client, Reference};
+use sha2::{Digest as _, Sha256};
+use tokio::fs;
+use tracing::{debug, instrument, warn};
+
+/// Layer media types of Wasm components published as OCI artifacts
+const WASM_LAYER_MEDIA_TYPES: &[&str] = &[
+    "application/wasm",
+    "application/vnd.wasm.content.layer.v1+wasm",
+    "application/vnd.module.wasm.content.layer.v1+wasm",
+];
+
+/// Returns the directory pulled artifacts are cached in, if any.
+///
+/// There is no fallback to a shared directory, like the system temporary directory, since
+/// other users could plant artifacts in it. Artifacts are not cached if no directory is found.
+fn cache_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("WRPC_OCI_CACHE_DIR") {
+        return Some(dir.into());
+    }
+    let dir = std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+    Some(dir.join("wrpc").join("oci"))
+}
+
+/// Returns whether `digest` of the form `sha256:<hex>` is the digest of `buf`.
+/// Digests using any other algorithm cannot be verified and never match.
+fn digest_matches(digest: &str, buf: &[u8]) -> bool {
+    let Some(expected) = digest.strip_prefix("sha256:") else {
+        return false;
+    };
+    format!("{:x}", Sha256::digest(buf)) == expected
+}
+
+/// Reads the artifact cached at `path`, which must match layer `digest`.
+/// Cached artifacts, which do not match the digest, are removed.
+async fn read_cached(path: &Path, digest: &str) -> Option<Vec<u8>> {
+    match fs::read(path).await {
+        Ok(wasm) if digest_matches(digest, &wasm) => {
+            debug!(?path, "using cached OCI artifact");
+            Some(wasm)
+        }
+        Ok(_) => {
+            warn!(
+                ?path,
+                digest, "cached OCI artifact does not match digest, discard it"
+            );
+            if let Err(err) = fs::remove_file(path).await {
+                warn!(?err, ?path, "failed to remove cached OCI artifact");
+            }
+            None
+        }
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+        Err(err) => {
+            warn!(?err, ?path, "failed to read cached OCI artifact");
+            None
+        }
+    }
+}
+
+/// Caches `wasm` at `path`
+async fn write_cached(path: &Path, wasm: &[u8]) {
+    if let Some(dir) = path.parent() {
+        if let Err(err) = fs::create_dir_all(dir).await {
+            warn!(?err, ?dir, "failed to create OCI cache directory");
+            return;
+        }
+    }
+    if let Err(err) = fs::write(path, wasm).await {
+        warn!(?err, ?path, "failed to cache OCI artifact");
+    }
+}
+
+/// Returns whether `registry`, optionally followed by a port, is a loopback host
+fn is_loopback_registry(registry: &str) -> bool {
+    let host = if let Some(rest) = registry.strip_prefix('[') {
+        let Some((host, _)) = rest.split_once(']') else {
+            return false;
+        };
+        host
+    } else {
+        registry.split_once(':').map_or(registry, |(host, _)| host)
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
+
+/// Looks up credentials for `registry` in the standard Docker configuration.
+///
+/// Lookup may invoke credential helper processes, so it is performed on a blocking thread.
+async fn registry_auth(registry: &str) -> RegistryAuth {
+    let res = tokio::task::spawn_blocking({
+        let registry = registry.to_string();
+        move || docker_credential::get_credential(&registry)
+    })
+    .await;
+    let res = match res {
+        Ok(res) => res,
+        Err(err) => {
+            warn!(
+                ?err,
+                registry, "credential lookup panicked, using anonymous access"
+            );
+            return RegistryAuth::Anonymous;
+        }
+    };
+    match res {
+        Ok(docker_credential::DockerCredential::UsernamePassword(username, password)) => {
+            RegistryAuth::Basic(username, password)
+        }
+        Ok(docker_credential::DockerCredential::IdentityToken(token)) => {
+            RegistryAuth::Bearer(token)
+        }
+        Err(err) => {
+            debug!(
+                ?err,
+                registry, "no credentials found, using anonymous access"
+            );
+            RegistryAuth::Anonymous
+        }
+    }
+}
+
+/// Fetches a Wasm component published as an OCI artifact at `reference`.
+///
+/// Pulled artifacts are cached on disk keyed by the digest of the Wasm layer, so repeated
+/// fetches of the same artifact only pull the manifest. Cached artifacts are verified against
+/// the layer digest before use. Registries on loopback hosts, that is `localhost`, `127.0.0.1`
+/// and `::1`, are accessed over plain HTTP.
+#[instrument(level = "trace")]
+pub async fn fetch(reference: &str) -> anyhow::Result<Vec<u8>> {
+    fetch_cached(reference, cache_dir().as_deref()).await
+}
+
+/// Like [fetch], but caches pulled artifacts in `cache`, if any
+async fn fetch_cached(reference: &str, cache: Option<&Path>) -> anyhow::Result<Vec<u8>> {
+    let reference: Reference = reference
+        .parse()
+        .with_context(|| format!("failed to parse OCI reference `{reference}`"))?;
+    let registry = reference.resolve_registry();
+    let protocol = if is_loopback_registry(registry) {
+        ClientProtocol::HttpsExcept(vec![registry.to_string()])
+    } else {
+        ClientProtocol::Https
+    };
+    let client = Client::new(ClientConfig {
+        protocol,
+        ..ClientConfig::default()
+    });
+    let auth = registry_auth(registry).await;
+
+    let (manifest, _) = client
+        .pull_image_manifest(&reference, &auth)
+        .await
+        .with_context(|| format!("failed to pull manifest of `{reference}`"))?;
+    let mut layers = manifest
+        .layers
+        .iter()
+        .filter(|layer| WASM_LAYER_MEDIA_TYPES.contains(&layer.media_type.as_str()));
+    let (Some(layer), None) = (layers.next(), layers.next()) else {
+        bail!("OCI artifact `{reference}` must contain exactly one Wasm layer")
+    };
+    let path = cache.map(|dir| {
+        dir.join(layer.digest.replace(':', "-"))
+            .with_extension("wasm")
+    });
+    if let Some(path) = &path {
+        if let Some(wasm) = read_cached(path, &layer.digest).await {
+            return Ok(wasm);
+        }
+    } else {
+        debug!("no OCI cache directory found, artifact will not be cached");
+    }
+
+    let mut wasm = Vec::default();
+    client
+        .pull_blob(&reference, layer, &mut wasm)
+        .await
+        .with_context(|| format!("failed to pull `{reference}`"))?;
+    ensure!(
+        digest_matches(&layer.digest, &wasm),
+        "Wasm layer of `{reference}` does not match digest `{}`",
+        layer.digest
+    );
+    if let Some(path) = &path {
+        write_cached(path, &wasm).await;
+    }
+    Ok(wasm)
+}
+
+#[cfg(test)]
+mod tests {
+    use oci_client::client::{Config, ImageLayer};
+    use oci_client::manifest::OciImageManifest;
+
+    use super::*;
+
+    /// Binary encoding of an empty component
+    const COMPONENT: &[u8] = b"\0asm\x0d\x00\x01\x00";
+
+    #[tokio::test]
+    async fn cache() -> anyhow::Result<()> {
+        let cache = tempfile::tempdir()?;
+        let digest = format!("sha256:{:x}", Sha256::digest(COMPONENT));
+        let path = cache.path().join("a").join("b.wasm");
+
+        assert_eq!(read_cached(&path, &digest).await, None);
+        write_cached(&path, COMPONENT).await;
+        assert_eq!(
+            read_cached(&path, &digest).await.as_deref(),
+            Some(COMPONENT)
+        );
+
+        // tampered artifacts are neither used nor kept
+        std::fs::write(&path, b"\0asm\x01\x00\x00\x00")?;
+        assert_eq!(read_cached(&path, &digest).await, None);
+        assert!(!path.exists());
+
+        // digests, which cannot be verified, never match
+        write_cached(&path, COMPONENT).await;
+        let digest = digest.replacen("sha256", "sha512", 1);
+        assert_eq!(read_cached(&path, &digest).await, None);
+        Ok(())
+    }
+
+    #[test]
+    fn loopback_registry() {
+        for registry in [
+            "localhost",
+            "localhost:5000",
+            "127.0.0.1",
+            "127.0.0.1:5000",
+            "[::1]",
+            "[::1]:5000",
+        ] {
+            assert!(is_loopback_registry(registry), "{registry}");
+        }
+        for registry in [
+            "ghcr.io",
+            "localhost.example.com",
+            "localhost.example.com:5000",
+            "127.0.0.1.example.com",
+            "127.0.0.10:5000",
+            "[::10]:5000",
+        ] {
+            assert!(!is_loopback_registry(registry), "{registry}");
+        }
+    }
+
+    /// Pushes a trivial component to a local registry and pulls it back.
+    ///
+    /// Start the registry with `docker run -d -p 5000:5000 registry:2`
+    #[tokio::test]
+    #[ignore = "requires an OCI registry listening on localhost:5000"]
+    async fn pull_local_registry() -> anyhow::Result<()> {
+        let cache = tempfile::tempdir()?;
+
+        let wasm = COMPONENT.to_vec();
+        let reference: Reference = "localhost:5000/wrpc/test:latest".parse()?;
+        let client = Client::new(ClientConfig {
+            protocol: ClientProtocol::HttpsExcept(vec!["localhost:5000".into()]),
+            ..ClientConfig::default()
+        });
+        let layers = [ImageLayer::new(
+            wasm.clone().into(),
+            WASM_LAYER_MEDIA_TYPES[0].into(),
+            None,
+        )];
+        let config = Config::new(
+            b"{}".to_vec().into(),
+            "application/vnd.wasm.config.v0+json".into(),
+            None,
+        );
+        let manifest = OciImageManifest::build(&layers, &config, None);
+        client
+            .push(
+                &reference,
+                &layers,
+                config,
+                &RegistryAuth::Anonymous,
+                Some(manifest),
+            )
+            .await?;
+
+        let pulled = fetch_cached("localhost:5000/wrpc/test:latest", Some(cache.path())).await?;
+        assert_eq!(pulled, wasm);
+        assert_eq!(std::fs::read_dir(cache.path())?.count(), 1);
+
+        let cached = fetch_cached("localhost:5000/wrpc/test:latest", Some(cache.path())).await?;
+        assert_eq!(cached, wasm);
+        Ok(())
+    }
+}
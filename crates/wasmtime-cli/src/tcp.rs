@@ -1,8 +1,22 @@
+use core::net::SocketAddr;
+use core::num::NonZeroUsize;
+
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
-use anyhow::Context as _;
-use clap::Parser;
-use tracing::{error, instrument};
+use anyhow::{bail, Context as _};
+use clap::{ArgAction, Args, Parser};
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::crypto::{verify_tls12_signature, verify_tls13_signature, CryptoProvider};
+use rustls::pki_types::pem::PemObject as _;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, ServerConfig, SignatureScheme};
+use tokio::sync::watch;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{error, instrument, warn};
+use wrpc_transport::tcp::{tls, SocketOptions};
+use wrpc_transport::{Invoke, Serve};
 
 pub const DEFAULT_ADDR: &str = "[::1]:7761";
 
@@ -24,8 +38,37 @@ pub struct RunArgs {
     #[arg(long, default_value = DEFAULT_ADDR)]
     import: String,
 
+    #[command(flatten)]
+    tls: TlsArgs,
+
+    #[command(flatten)]
+    socket: SocketArgs,
+
+    #[command(flatten)]
+    env: crate::EnvArgs,
+
+    #[command(flatten)]
+    limits: crate::Limits,
+
+    #[command(flatten)]
+    interfaces: crate::HostInterfaces,
+
+    /// WASI preview1 adapter used to encode core module workloads into components,
+    /// either `command`, `reactor` or a path to a custom adapter
+    #[arg(
+        long,
+        value_name = "PATH|command|reactor",
+        default_value = "command",
+        value_parser = crate::parse_adapter
+    )]
+    adapter: crate::Adapter,
+
     /// Path or URL to Wasm command component
     workload: String,
+
+    /// Arguments passed to the command component
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
 }
 
 /// Serve a reactor component
@@ -43,41 +86,366 @@ pub struct ServeArgs {
     #[arg(long, default_value = DEFAULT_ADDR)]
     export: String,
 
-    /// Path or URL to Wasm command component
+    #[command(flatten)]
+    tls: TlsArgs,
+
+    #[command(flatten)]
+    socket: SocketArgs,
+
+    /// Maximum number of invocations served concurrently
+    #[arg(long, default_value = crate::DEFAULT_MAX_CONCURRENT_INVOCATIONS)]
+    max_concurrent_invocations: NonZeroUsize,
+
+    /// Time to wait for in-flight invocations to complete on SIGINT or SIGTERM
+    #[arg(long, default_value = crate::DEFAULT_SHUTDOWN_GRACE_PERIOD)]
+    shutdown_grace_period: humantime::Duration,
+
+    /// Behavior when serving an invocation panics
+    #[arg(long, value_enum, default_value_t = crate::PanicPolicy::Isolate)]
+    on_handler_panic: crate::PanicPolicy,
+
+    /// Address to serve Prometheus invocation metrics on
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    #[command(flatten)]
+    env: crate::EnvArgs,
+
+    #[command(flatten)]
+    limits: crate::Limits,
+
+    #[command(flatten)]
+    interfaces: crate::HostInterfaces,
+
+    /// WASI preview1 adapter used to encode core module workloads into components,
+    /// either `command`, `reactor` or a path to a custom adapter
+    #[arg(
+        long,
+        value_name = "PATH|command|reactor",
+        default_value = "reactor",
+        value_parser = crate::parse_adapter
+    )]
+    adapter: crate::Adapter,
+
+    /// Path or URL to Wasm reactor component
     workload: String,
+
+    /// Path or URL to an additional Wasm reactor component served by the same process,
+    /// can be specified multiple times. Each component must export distinct instances
+    #[arg(long = "workload", value_name = "WORKLOAD")]
+    workloads: Vec<String>,
 }
 
-#[instrument(level = "trace", ret(level = "trace"))]
-pub async fn handle_run(
+/// TCP socket options applied to import and export connections
+#[derive(Args, Debug)]
+pub struct SocketArgs {
+    /// Do not set `TCP_NODELAY`, letting Nagle's algorithm batch small writes
+    /// at the cost of latency
+    #[arg(long = "no-tcp-nodelay", action = ArgAction::SetFalse)]
+    tcp_nodelay: bool,
+
+    /// Time a connection has to be idle for before TCP keepalive probes are sent,
+    /// keepalive is not configured by default
+    #[arg(long)]
+    tcp_keepalive: Option<humantime::Duration>,
+}
+
+impl SocketArgs {
+    fn options(&self) -> SocketOptions {
+        SocketOptions {
+            nodelay: self.tcp_nodelay,
+            keepalive: self.tcp_keepalive.map(Into::into),
+        }
+    }
+}
+
+/// TLS configuration, plaintext TCP is used unless `--tls` is set
+#[derive(Args, Debug)]
+pub struct TlsArgs {
+    /// Use TLS for import and export connections
+    #[arg(long)]
+    tls: bool,
+
+    /// Path to PEM-encoded CA certificate bundle used to verify the peer.
+    /// If set when serving, clients are required to present a certificate signed by this CA
+    #[arg(long, requires = "tls")]
+    tls_ca: Option<PathBuf>,
+
+    /// Path to PEM-encoded certificate chain presented to the peer
+    #[arg(long, requires_all = ["tls", "tls_key"])]
+    tls_cert: Option<PathBuf>,
+
+    /// Path to PEM-encoded private key of the certificate passed via `--tls-cert`
+    #[arg(long, requires_all = ["tls", "tls_cert"])]
+    tls_key: Option<PathBuf>,
+
+    /// Server name used to verify the import server certificate,
+    /// defaults to the host of the import address
+    #[arg(long, requires = "tls")]
+    tls_server_name: Option<String>,
+
+    /// Skip verification of the import server certificate.
+    /// This is insecure and should only ever be used for testing
+    #[arg(long, requires = "tls")]
+    insecure_skip_verify: bool,
+}
+
+/// [ServerCertVerifier], which accepts any server certificate
+#[derive(Debug)]
+struct SkipServerVerification(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &self.0.signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.0.signature_verification_algorithms.supported_schemes()
+    }
+}
+
+fn load_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    CertificateDer::pem_file_iter(path)
+        .with_context(|| format!("failed to open `{}`", path.display()))?
+        .collect::<Result<_, _>>()
+        .with_context(|| format!("failed to parse certificates in `{}`", path.display()))
+}
+
+fn load_roots(path: &Path) -> anyhow::Result<RootCertStore> {
+    let mut roots = RootCertStore::empty();
+    for cert in load_certs(path)? {
+        roots
+            .add(cert)
+            .with_context(|| format!("failed to add CA certificate from `{}`", path.display()))?;
+    }
+    Ok(roots)
+}
+
+fn load_key(path: &Path) -> anyhow::Result<PrivateKeyDer<'static>> {
+    PrivateKeyDer::from_pem_file(path)
+        .with_context(|| format!("failed to parse private key in `{}`", path.display()))
+}
+
+impl TlsArgs {
+    fn client(&self, addr: String, options: SocketOptions) -> anyhow::Result<tls::Client<String>> {
+        let server_name = if let Some(name) = &self.tls_server_name {
+            ServerName::try_from(name.clone())
+                .with_context(|| format!("invalid TLS server name `{name}`"))?
+        } else if let Ok(addr) = addr.parse::<SocketAddr>() {
+            ServerName::from(addr.ip())
+        } else {
+            let (host, _) = addr
+                .rsplit_once(':')
+                .with_context(|| format!("address `{addr}` is missing a port"))?;
+            ServerName::try_from(host.to_string())
+                .with_context(|| format!("failed to derive TLS server name from `{addr}`"))?
+        };
+
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ClientConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .context("failed to configure TLS protocol versions")?;
+        let builder = if self.insecure_skip_verify {
+            warn!("TLS server certificate verification is disabled");
+            builder
+                .dangerous()
+                .with_custom_certificate_verifier(Arc::new(SkipServerVerification(provider)))
+        } else {
+            let ca = self.tls_ca.as_deref().context(
+                "`--tls-ca` is required to verify the import server certificate, unless `--insecure-skip-verify` is set",
+            )?;
+            builder.with_root_certificates(load_roots(ca)?)
+        };
+        let config = match (&self.tls_cert, &self.tls_key) {
+            (Some(cert), Some(key)) => builder
+                .with_client_auth_cert(load_certs(cert)?, load_key(key)?)
+                .context("failed to configure TLS client certificate")?,
+            _ => builder.with_no_client_auth(),
+        };
+        Ok(tls::Client::new(addr, config, server_name).with_socket_options(options))
+    }
+
+    fn server_config(&self) -> anyhow::Result<ServerConfig> {
+        let (Some(cert), Some(key)) = (&self.tls_cert, &self.tls_key) else {
+            bail!("`--tls-cert` and `--tls-key` are required to serve over TLS")
+        };
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(Arc::clone(&provider))
+            .with_safe_default_protocol_versions()
+            .context("failed to configure TLS protocol versions")?;
+        let builder = if let Some(ca) = &self.tls_ca {
+            let verifier =
+                WebPkiClientVerifier::builder_with_provider(Arc::new(load_roots(ca)?), provider)
+                    .build()
+                    .context("failed to construct TLS client certificate verifier")?;
+            builder.with_client_cert_verifier(verifier)
+        } else {
+            builder.with_no_client_auth()
+        };
+        builder
+            .with_single_cert(load_certs(cert)?, load_key(key)?)
+            .context("failed to configure TLS server certificate")
+    }
+}
+
+/// Runs the command component using `clt` to send import invocations
+async fn run<C>(
+    clt: C,
     RunArgs {
         timeout,
-        import,
+        env,
+        limits,
+        interfaces,
+        adapter,
         ref workload,
+        ref args,
+        ..
     }: RunArgs,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<()>
+where
+    C: Invoke<Context = ()> + Clone + 'static,
+{
+    let env = env.load().await?;
     crate::handle_run(
-        wrpc_transport::tcp::Client::from(import),
-        (),
-        *timeout,
+        crate::StoreConfig {
+            client: clt,
+            cx: (),
+            timeout: *timeout,
+            env,
+            limits,
+        },
+        interfaces,
+        &adapter,
         workload,
+        args,
     )
     .await
 }
 
 #[instrument(level = "trace", ret(level = "trace"))]
-pub async fn handle_serve(
+pub async fn handle_run(args: RunArgs) -> anyhow::Result<()> {
+    let options = args.socket.options();
+    if args.tls.tls {
+        let clt = args.tls.client(args.import.clone(), options)?;
+        return run(clt, args).await;
+    }
+    let clt = wrpc_transport::tcp::Client::from(args.import.clone()).with_socket_options(options);
+    run(clt, args).await
+}
+
+/// Serves the reactor components on `srv` using `clt` to send import invocations
+async fn serve<S, C>(
+    srv: S,
+    clt: C,
     ServeArgs {
         timeout,
-        export,
-        import,
-        ref workload,
+        env,
+        limits,
+        interfaces,
+        max_concurrent_invocations,
+        shutdown_grace_period,
+        on_handler_panic,
+        adapter,
+        workload,
+        mut workloads,
+        ..
     }: ServeArgs,
-) -> anyhow::Result<()> {
-    let lis = tokio::net::TcpListener::bind(&export)
+) -> anyhow::Result<()>
+where
+    S: Serve + Clone,
+    C: Invoke<Context = ()> + Clone + 'static,
+{
+    workloads.insert(0, workload);
+    let env = env.load().await?;
+    crate::handle_serve(
+        srv,
+        crate::StoreConfig {
+            client: clt,
+            cx: (),
+            timeout: *timeout,
+            env,
+            limits,
+        },
+        crate::ServeConfig {
+            interfaces,
+            max_concurrent_invocations,
+            grace_period: *shutdown_grace_period,
+            panic_policy: on_handler_panic,
+        },
+        crate::shutdown_signal(),
+        watch::Sender::new(crate::ServeStatus::default()),
+        &adapter,
+        &workloads,
+    )
+    .await
+}
+
+#[instrument(level = "trace", ret(level = "trace"))]
+pub async fn handle_serve(args: ServeArgs) -> anyhow::Result<()> {
+    if let Some(addr) = args.metrics_addr {
+        crate::install_metrics_exporter(addr)?;
+    }
+    let lis = tokio::net::TcpListener::bind(&args.export)
         .await
-        .with_context(|| format!("failed to bind TCP listener on `{export}`"))?;
+        .with_context(|| format!("failed to bind TCP listener on `{}`", args.export))?;
+    let options = args.socket.options();
+    if args.tls.tls {
+        let lis = tls::Listener::new(lis, args.tls.server_config()?).with_socket_options(options);
+        let clt = args.tls.client(args.import.clone(), options)?;
+        let srv = Arc::new(wrpc_transport::Server::default());
+        // abort the accept loop even if this future is dropped before completion
+        let _accept = AbortOnDropHandle::new(tokio::spawn({
+            let srv = Arc::clone(&srv);
+            async move {
+                loop {
+                    if let Err(err) = srv.accept(&lis).await {
+                        error!(?err, "failed to accept TLS connection");
+                    }
+                }
+            }
+        }));
+        return serve(srv.as_ref(), clt, args).await;
+    }
+    let lis = wrpc_transport::tcp::Listener::new(lis, options);
+    let clt = wrpc_transport::tcp::Client::from(args.import.clone()).with_socket_options(options);
     let srv = Arc::new(wrpc_transport::Server::default());
-    let accept = tokio::spawn({
+    // abort the accept loop even if this future is dropped before completion
+    let _accept = AbortOnDropHandle::new(tokio::spawn({
         let srv = Arc::clone(&srv);
         async move {
             loop {
@@ -86,17 +454,8 @@ pub async fn handle_serve(
                 }
             }
         }
-    });
-    let res = crate::handle_serve(
-        srv.as_ref(),
-        wrpc_transport::tcp::Client::from(import),
-        (),
-        *timeout,
-        workload,
-    )
-    .await;
-    accept.abort();
-    res
+    }));
+    serve(srv.as_ref(), clt, args).await
 }
 
 #[instrument(level = "trace", ret(level = "trace"))]
@@ -0,0 +1,395 @@
+use core::net::SocketAddr;
+use core::num::NonZeroUsize;
+
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use anyhow::Context as _;
+use clap::Parser;
+use tokio::sync::watch;
+use tokio_util::task::AbortOnDropHandle;
+use tracing::{error, instrument};
+
+/// Unix domain socket transport
+#[derive(Parser, Debug)]
+pub enum Command {
+    Run(RunArgs),
+    Serve(ServeArgs),
+}
+
+/// Run a command component
+#[derive(Parser, Debug)]
+pub struct RunArgs {
+    /// Invocation timeout
+    #[arg(long, default_value = crate::DEFAULT_TIMEOUT)]
+    timeout: humantime::Duration,
+
+    /// Socket path to send import invocations to
+    #[arg(long)]
+    import: PathBuf,
+
+    #[command(flatten)]
+    env: crate::EnvArgs,
+
+    #[command(flatten)]
+    limits: crate::Limits,
+
+    #[command(flatten)]
+    interfaces: crate::HostInterfaces,
+
+    /// WASI preview1 adapter used to encode core module workloads into components,
+    /// either `command`, `reactor` or a path to a custom adapter
+    #[arg(
+        long,
+        value_name = "PATH|command|reactor",
+        default_value = "command",
+        value_parser = crate::parse_adapter
+    )]
+    adapter: crate::Adapter,
+
+    /// Path or URL to Wasm command component
+    workload: String,
+
+    /// Arguments passed to the command component
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+    args: Vec<String>,
+}
+
+/// Serve a reactor component
+#[derive(Parser, Debug)]
+pub struct ServeArgs {
+    /// Invocation timeout
+    #[arg(long, default_value = crate::DEFAULT_TIMEOUT)]
+    timeout: humantime::Duration,
+
+    /// Socket path to send import invocations to
+    #[arg(long)]
+    import: PathBuf,
+
+    /// Socket path to listen for export invocations on.
+    /// The socket file is removed once the server exits.
+    #[arg(long)]
+    export: PathBuf,
+
+    /// Maximum number of invocations served concurrently
+    #[arg(long, default_value = crate::DEFAULT_MAX_CONCURRENT_INVOCATIONS)]
+    max_concurrent_invocations: NonZeroUsize,
+
+    /// Time to wait for in-flight invocations to complete on SIGINT or SIGTERM
+    #[arg(long, default_value = crate::DEFAULT_SHUTDOWN_GRACE_PERIOD)]
+    shutdown_grace_period: humantime::Duration,
+
+    /// Behavior when serving an invocation panics
+    #[arg(long, value_enum, default_value_t = crate::PanicPolicy::Isolate)]
+    on_handler_panic: crate::PanicPolicy,
+
+    /// Address to serve Prometheus invocation metrics on
+    #[arg(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    #[command(flatten)]
+    env: crate::EnvArgs,
+
+    #[command(flatten)]
+    limits: crate::Limits,
+
+    #[command(flatten)]
+    interfaces: crate::HostInterfaces,
+
+    /// WASI preview1 adapter used to encode core module workloads into components,
+    /// either `command`, `reactor` or a path to a custom adapter
+    #[arg(
+        long,
+        value_name = "PATH|command|reactor",
+        default_value = "reactor",
+        value_parser = crate::parse_adapter
+    )]
+    adapter: crate::Adapter,
+
+    /// Path or URL to Wasm reactor component
+    workload: String,
+
+    /// Path or URL to an additional Wasm reactor component served by the same process,
+    /// can be specified multiple times. Each component must export distinct instances
+    #[arg(long = "workload", value_name = "WORKLOAD")]
+    workloads: Vec<String>,
+}
+
+#[instrument(level = "trace", ret(level = "trace"))]
+pub async fn handle_run(
+    RunArgs {
+        timeout,
+        import,
+        env,
+        limits,
+        interfaces,
+        adapter,
+        ref workload,
+        ref args,
+    }: RunArgs,
+) -> anyhow::Result<()> {
+    let env = env.load().await?;
+    crate::handle_run(
+        crate::StoreConfig {
+            client: wrpc_transport::unix::Client::from(import),
+            cx: (),
+            timeout: *timeout,
+            env,
+            limits,
+        },
+        interfaces,
+        &adapter,
+        workload,
+        args,
+    )
+    .await
+}
+
+#[instrument(level = "trace", ret(level = "trace"))]
+pub async fn handle_serve(args: ServeArgs) -> anyhow::Result<()> {
+    handle_serve_with_status(args, watch::Sender::new(crate::ServeStatus::default())).await
+}
+
+/// Like [handle_serve], but reports the status of the served component on `status`
+#[instrument(level = "trace", skip(status), ret(level = "trace"))]
+pub async fn handle_serve_with_status(
+    ServeArgs {
+        timeout,
+        export,
+        import,
+        env,
+        limits,
+        interfaces,
+        max_concurrent_invocations,
+        shutdown_grace_period,
+        on_handler_panic,
+        metrics_addr,
+        adapter,
+        workload,
+        mut workloads,
+    }: ServeArgs,
+    status: watch::Sender<crate::ServeStatus>,
+) -> anyhow::Result<()> {
+    workloads.insert(0, workload);
+    if let Some(addr) = metrics_addr {
+        crate::install_metrics_exporter(addr)?;
+    }
+    let env = env.load().await?;
+    let lis = wrpc_transport::unix::Listener::bind(&export)
+        .with_context(|| format!("failed to bind Unix listener on `{}`", export.display()))?;
+    let srv = Arc::new(wrpc_transport::Server::default());
+    // abort the accept loop even if this future is dropped before completion
+    let _accept = AbortOnDropHandle::new(tokio::spawn({
+        let srv = Arc::clone(&srv);
+        async move {
+            loop {
+                if let Err(err) = srv.accept(&lis).await {
+                    error!(?err, "failed to accept Unix domain socket connection");
+                }
+            }
+        }
+    }));
+    crate::handle_serve(
+        srv.as_ref(),
+        crate::StoreConfig {
+            client: wrpc_transport::unix::Client::from(import),
+            cx: (),
+            timeout: *timeout,
+            env,
+            limits,
+        },
+        crate::ServeConfig {
+            interfaces,
+            max_concurrent_invocations,
+            grace_period: *shutdown_grace_period,
+            panic_policy: on_handler_panic,
+        },
+        crate::shutdown_signal(),
+        status,
+        &adapter,
+        &workloads,
+    )
+    .await
+}
+
+#[instrument(level = "trace", ret(level = "trace"))]
+pub async fn run(cmd: Command) -> anyhow::Result<()> {
+    match cmd {
+        Command::Run(args) => handle_run(args).await,
+        Command::Serve(args) => handle_serve(args).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::time::Duration;
+
+    use std::path::Path;
+
+    use tokio::task::JoinHandle;
+    use wrpc_transport::InvokeExt as _;
+
+    use super::*;
+    use crate::ServeStatus;
+
+    const REACTOR: &str = r#"(component
+  (core module $m
+    (func (export "hello") (result i32) i32.const 42)
+  )
+  (core instance $i (instantiate $m))
+  (func $hello (result u32) (canon lift (core func $i "hello")))
+  (instance $iface (export "hello" (func $hello)))
+  (export "test:test/iface" (instance $iface))
+)"#;
+
+    const NESTED_REACTOR: &str = r#"(component
+  (core module $m
+    (func (export "hello") (result i32) i32.const 42)
+  )
+  (core instance $i (instantiate $m))
+  (func $hello (result u32) (canon lift (core func $i "hello")))
+  (instance $inner (export "hello" (func $hello)))
+  (instance $outer (export "inner" (instance $inner)))
+  (export "test:test/outer" (instance $outer))
+)"#;
+
+    const OTHER_REACTOR: &str = r#"(component
+  (core module $m
+    (func (export "hello") (result i32) i32.const 7)
+  )
+  (core instance $i (instantiate $m))
+  (func $hello (result u32) (canon lift (core func $i "hello")))
+  (instance $iface (export "hello" (func $hello)))
+  (export "test:test/other" (instance $iface))
+)"#;
+
+    /// Spawns a task serving `components` on a Unix domain socket in `dir`, waits for it to
+    /// become ready and returns the task handle along with the socket path
+    async fn spawn_serve(
+        dir: &Path,
+        components: &[&str],
+    ) -> anyhow::Result<(JoinHandle<anyhow::Result<()>>, PathBuf)> {
+        let mut workloads = Vec::with_capacity(components.len());
+        for (i, component) in components.iter().enumerate() {
+            let workload = dir.join(format!("reactor-{i}.wat"));
+            tokio::fs::write(&workload, component)
+                .await
+                .context("failed to write component")?;
+            workloads.push(workload.to_string_lossy().into_owned());
+        }
+        let workload = workloads.remove(0);
+        let export = dir.join("export.sock");
+        let (status, mut status_rx) = watch::channel(ServeStatus::default());
+        let srv = tokio::spawn(handle_serve_with_status(
+            ServeArgs {
+                timeout: Duration::from_secs(10).into(),
+                import: dir.join("import.sock"),
+                export: export.clone(),
+                env: crate::EnvArgs::default(),
+                limits: crate::Limits::default(),
+                interfaces: crate::HostInterfaces::default(),
+                max_concurrent_invocations: NonZeroUsize::MIN,
+                shutdown_grace_period: Duration::from_secs(1).into(),
+                on_handler_panic: crate::PanicPolicy::Isolate,
+                metrics_addr: None,
+                adapter: crate::Adapter::Reactor,
+                workload,
+                workloads,
+            },
+            status,
+        ));
+        tokio::time::timeout(
+            Duration::from_secs(10),
+            status_rx.wait_for(|status| *status == ServeStatus::Ready),
+        )
+        .await
+        .context("timed out waiting for the component to be served")?
+        .context("serve task exited before becoming ready")?;
+        Ok((srv, export))
+    }
+
+    /// Invokes `instance#name` on the server listening on `export`
+    async fn invoke(export: &Path, instance: &str, name: &str) -> Option<u32> {
+        match wrpc_transport::unix::Client::from(export.to_path_buf())
+            .invoke_values_blocking::<_, _, (u32,)>((), instance, name, (), &[[]; 0])
+            .await
+        {
+            Ok((v,)) => Some(v),
+            Err(err) => {
+                tracing::debug!(?err, "invocation failed");
+                None
+            }
+        }
+    }
+
+    /// Serves `component` over a Unix domain socket and invokes `instance#name` on it
+    async fn serve_and_invoke(
+        component: &str,
+        instance: &str,
+        name: &str,
+    ) -> anyhow::Result<Option<u32>> {
+        let dir = tempfile::tempdir().context("failed to create temporary directory")?;
+        let (srv, export) = spawn_serve(dir.path(), &[component]).await?;
+        let res = invoke(&export, instance, name).await;
+        srv.abort();
+        Ok(res)
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_round_trip() -> anyhow::Result<()> {
+        let res = serve_and_invoke(REACTOR, "test:test/iface", "hello").await?;
+        assert_eq!(res, Some(42));
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_nested_instance() -> anyhow::Result<()> {
+        let res = serve_and_invoke(NESTED_REACTOR, "test:test/outer/inner", "hello").await?;
+        assert_eq!(res, Some(42));
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_multiple_workloads() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir().context("failed to create temporary directory")?;
+        let (srv, export) = spawn_serve(dir.path(), &[REACTOR, OTHER_REACTOR]).await?;
+        assert_eq!(invoke(&export, "test:test/iface", "hello").await, Some(42));
+        assert_eq!(invoke(&export, "test:test/other", "hello").await, Some(7));
+        srv.abort();
+
+        let dir = tempfile::tempdir().context("failed to create temporary directory")?;
+        spawn_serve(dir.path(), &[REACTOR, REACTOR])
+            .await
+            .expect_err("workloads exporting the same instance should not be served");
+        Ok(())
+    }
+
+    #[test_log::test(tokio::test(flavor = "multi_thread"))]
+    async fn serve_drop_aborts_tasks() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir().context("failed to create temporary directory")?;
+        let (srv, export) = spawn_serve(dir.path(), &[REACTOR]).await?;
+        assert_eq!(invoke(&export, "test:test/iface", "hello").await, Some(42));
+
+        srv.abort();
+        assert!(srv
+            .await
+            .expect_err("serve should be aborted")
+            .is_cancelled());
+        // the listener is owned by the accept task and removes the socket once dropped
+        for _ in 0..50 {
+            if !export.exists() {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(
+            !export.exists(),
+            "accept task leaked after serve was dropped"
+        );
+        wrpc_transport::unix::Client::from(export)
+            .invoke_values_blocking::<_, _, (u32,)>((), "test:test/iface", "hello", (), &[[]; 0])
+            .await
+            .expect_err("invocation should fail after serve was dropped");
+        Ok(())
+    }
+}
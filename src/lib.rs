@@ -14,6 +14,9 @@ pub mod transport {
 
     #[cfg(feature = "web-transport")]
     pub use wrpc_transport_web as web;
+
+    #[cfg(feature = "websocket")]
+    pub use wrpc_transport_ws as ws;
 }
 
 /// wRPC runtime
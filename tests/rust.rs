@@ -1082,6 +1082,163 @@ async fn rust_dynamic_nats() -> anyhow::Result<()> {
     .await
 }
 
+#[cfg(feature = "nats")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+#[instrument(ret)]
+async fn rust_nats_namespace() -> anyhow::Result<()> {
+    use wrpc_transport_nats::namespace_prefix;
+
+    wrpc_test::with_nats(|_, nats_client| async {
+        let nats_client = Arc::new(nats_client);
+        let new_client = |namespace| {
+            wrpc_transport_nats::Client::new(
+                Arc::clone(&nats_client),
+                namespace_prefix(namespace, "rust-namespace"),
+                None,
+            )
+        };
+        let srv = new_client("tenant-a")
+            .await
+            .context("failed to construct server client")?;
+        let clt = new_client("tenant-a")
+            .await
+            .context("failed to construct client")?;
+        let other = new_client("tenant-b")
+            .await
+            .context("failed to construct client in another namespace")?;
+
+        let invocations = srv
+            .serve_values::<(String,), (String,)>("test", "echo", Box::default())
+            .await
+            .context("failed to serve `test.echo`")?;
+        let mut invocations = pin!(invocations);
+
+        info!("invoking `test.echo` in another namespace");
+        other
+            .invoke_values_blocking::<_, _, (String,)>((), "test", "echo", ("foo",), &[[]; 0])
+            .await
+            .expect_err("invocation in another namespace should not be served");
+
+        try_join!(
+            async {
+                let ((), (v,), rx, tx) = invocations
+                    .try_next()
+                    .await
+                    .context("failed to accept invocation")?
+                    .context("unexpected end of stream")?;
+                assert!(rx.is_none());
+                assert_eq!(v, "bar");
+                tx((v,)).await.context("failed to send response")
+            },
+            async {
+                info!("invoking `test.echo` in the same namespace");
+                let (v,) = clt
+                    .invoke_values_blocking::<_, _, (String,)>(
+                        (),
+                        "test",
+                        "echo",
+                        ("bar",),
+                        &[[]; 0],
+                    )
+                    .await
+                    .context("failed to invoke `test.echo`")?;
+                assert_eq!(v, "bar");
+                Ok(())
+            }
+        )?;
+        Ok(())
+    })
+    .await
+}
+
+#[cfg(feature = "nats")]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+#[instrument(ret)]
+async fn rust_nats_request_timeout() -> anyhow::Result<()> {
+    use std::time::Instant;
+
+    use wrpc_transport_nats::invocation_subject;
+
+    wrpc_test::with_nats(|_, nats_client| async {
+        let nats_client = Arc::new(nats_client);
+        let srv = wrpc_transport_nats::Client::new(
+            Arc::clone(&nats_client),
+            "rust-request-timeout",
+            None,
+        )
+        .await
+        .context("failed to construct server client")?;
+        let clt = wrpc_transport_nats::Client::new(
+            Arc::clone(&nats_client),
+            "rust-request-timeout",
+            None,
+        )
+        .await
+        .context("failed to construct client")?
+        .with_request_timeout(Duration::from_millis(100));
+        // component-level timeout, which is much larger than the NATS.io request timeout
+        let clt = clt.timeout(Duration::from_secs(10));
+
+        // responder, which receives the handshake, but never acknowledges it
+        let _slow = nats_client
+            .subscribe(invocation_subject("rust-request-timeout", "test", "slow"))
+            .await
+            .context("failed to subscribe")?;
+        nats_client.flush().await.context("failed to flush")?;
+
+        info!("invoking `test.slow`");
+        let start = Instant::now();
+        let err = clt
+            .invoke_values_blocking::<_, _, (String,)>((), "test", "slow", ("foo",), &[[]; 0])
+            .await
+            .expect_err("unacknowledged invocation should time out");
+        assert!(start.elapsed() < Duration::from_secs(10));
+        assert!(
+            err.chain().any(|err| err
+                .downcast_ref::<std::io::Error>()
+                .is_some_and(|err| err.kind() == std::io::ErrorKind::TimedOut)),
+            "{err:?}"
+        );
+
+        let invocations = srv
+            .serve_values::<(String,), (String,)>("test", "echo", Box::default())
+            .await
+            .context("failed to serve `test.echo`")?;
+        let mut invocations = pin!(invocations);
+        try_join!(
+            async {
+                let ((), (v,), rx, tx) = invocations
+                    .try_next()
+                    .await
+                    .context("failed to accept invocation")?
+                    .context("unexpected end of stream")?;
+                assert!(rx.is_none());
+                // the handshake is already acknowledged, so slow processing
+                // must not trip the NATS.io request timeout
+                sleep(Duration::from_millis(500)).await;
+                tx((v,)).await.context("failed to send response")
+            },
+            async {
+                info!("invoking `test.echo`");
+                let (v,) = clt
+                    .invoke_values_blocking::<_, _, (String,)>(
+                        (),
+                        "test",
+                        "echo",
+                        ("bar",),
+                        &[[]; 0],
+                    )
+                    .await
+                    .context("failed to invoke `test.echo`")?;
+                assert_eq!(v, "bar");
+                Ok(())
+            }
+        )?;
+        Ok(())
+    })
+    .await
+}
+
 #[cfg(feature = "quic")]
 #[test_log::test(tokio::test(flavor = "multi_thread"))]
 #[instrument(ret)]
@@ -1423,6 +1580,91 @@ async fn rust_bindgen_tcp_async() -> anyhow::Result<()> {
     }
 }
 
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+#[instrument(ret)]
+async fn rust_bindgen_tls_async() -> anyhow::Result<()> {
+    use wrpc_transport::tcp::tls;
+
+    let (srv_cnf, clt_cnf) = wrpc_test::cert_pair().context("failed to generate certificates")?;
+    let lis = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+        .await
+        .context("failed to start TCP listener")?;
+    let addr = lis.local_addr().context("failed to get server address")?;
+    let lis = tls::Listener::new(lis, srv_cnf);
+    let lis = lis.map_context(|addr| assert!(addr.ip().is_loopback()));
+
+    let srv = Arc::new(wrpc_transport::frame::Server::default());
+    let clt = tls::Client::new(
+        addr,
+        clt_cnf,
+        tls::rustls::pki_types::ServerName::try_from("localhost")
+            .context("failed to parse server name")?,
+    );
+    let span = Span::current();
+    let mut fut = pin!(
+        async { assert_bindgen_async(Arc::new(clt), Arc::clone(&srv),).await }
+            .instrument(span.clone())
+    );
+    loop {
+        select! {
+            res = &mut fut => {
+                return res
+            }
+            res = srv.accept(&lis).instrument(span.clone()) => {
+                res.expect("failed to accept connection");
+                continue
+            }
+        }
+    }
+}
+
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+#[instrument(ret)]
+async fn tls_stalled_handshake() -> anyhow::Result<()> {
+    use wrpc_transport::tcp::tls;
+    use wrpc_transport::Invoke as _;
+
+    let (srv_cnf, clt_cnf) = wrpc_test::cert_pair().context("failed to generate certificates")?;
+    let lis = tokio::net::TcpListener::bind((Ipv6Addr::LOCALHOST, 0))
+        .await
+        .context("failed to start TCP listener")?;
+    let addr = lis.local_addr().context("failed to get server address")?;
+    let lis = tls::Listener::new(lis, srv_cnf).with_handshake_timeout(Duration::from_secs(1));
+
+    // connection, which never performs the TLS handshake
+    let stalled = tokio::net::TcpStream::connect(addr)
+        .await
+        .context("failed to connect to server")?;
+    let clt = tls::Client::new(
+        addr,
+        clt_cnf,
+        tls::rustls::pki_types::ServerName::try_from("localhost")
+            .context("failed to parse server name")?,
+    );
+    let (_, (peer, _, _)) = tokio::time::timeout(Duration::from_millis(500), async {
+        try_join!(
+            async {
+                clt.invoke((), "foo", "bar", Bytes::new(), [] as [&[Option<usize>]; 0])
+                    .await
+                    .context("failed to invoke `foo.bar`")
+            },
+            async { lis.accept().await.context("failed to accept connection") },
+        )
+    })
+    .await
+    .context("stalled handshake blocked connection acceptance")??;
+    assert!(peer.ip().is_loopback());
+
+    let err = lis
+        .accept()
+        .await
+        .map(|_| ())
+        .expect_err("stalled handshake should time out");
+    assert_eq!(err.kind(), std::io::ErrorKind::TimedOut);
+    drop(stalled);
+    Ok(())
+}
+
 #[cfg(unix)]
 #[test_log::test(tokio::test(flavor = "multi_thread"))]
 #[instrument(ret)]
@@ -1490,3 +1732,39 @@ async fn rust_bindgen_uds_async() -> anyhow::Result<()> {
         }
     }
 }
+
+#[cfg(unix)]
+#[test_log::test(tokio::test(flavor = "multi_thread"))]
+#[instrument(ret)]
+async fn rust_bindgen_uds_listener() -> anyhow::Result<()> {
+    let dir = tempfile::tempdir().context("failed to create temporary directory")?;
+    let path = dir.path().join("wrpc.sock");
+
+    let lis = wrpc_transport::frame::unix::Listener::bind(&path)
+        .context("failed to bind Unix listener")?;
+    assert_eq!(lis.path(), path);
+
+    let srv = Arc::new(wrpc_transport::frame::Server::default());
+    let clt = wrpc_transport::frame::unix::Client::from(path.clone());
+    let span = Span::current();
+    let mut fut = pin!(
+        async { assert_bindgen_async(Arc::new(clt), Arc::clone(&srv),).await }
+            .instrument(span.clone())
+    );
+    loop {
+        select! {
+            res = &mut fut => {
+                res?;
+                break
+            }
+            res = srv.accept(&lis).instrument(span.clone()) => {
+                res.expect("failed to accept connection");
+                continue
+            }
+        }
+    }
+    assert!(path.exists());
+    drop(lis);
+    assert!(!path.exists(), "socket file was not removed");
+    Ok(())
+}